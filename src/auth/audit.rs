@@ -0,0 +1,205 @@
+//! Audit log of administrative actions stored in sled
+//!
+//! The auth middleware appends one entry per state changing request (anything
+//! but GET, HEAD and OPTIONS) once the handler answered, and one per rejected
+//! authorization attempt. Entries are never rewritten, keys are sled's
+//! monotonic ids so iteration order is recording order. Entries older than
+//! `RETENTION_SECS` are dropped by the cleanup task.
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+
+/// Entries older than this are dropped
+pub const RETENTION_SECS: u64 = 90 * 24 * 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The handler answered with a 2xx or 3xx
+    Success,
+    /// Authorized, but the handler answered with an error
+    Failure,
+    /// Missing or invalid credentials
+    Denied,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    /// Unix seconds
+    pub timestamp: u64,
+    /// `api-token`, `jwt:<sub>`, or `anonymous` for public routes and rejected requests
+    pub actor: String,
+    /// Method and route, e.g. `POST /containers/:id/start`
+    pub action: String,
+    /// Request path
+    pub target: String,
+    /// Internal ID when the path is under `/containers/<id>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+}
+
+/// An entry before the log assigns its id and timestamp
+pub struct AuditRecord {
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub outcome: AuditOutcome,
+    pub status: u16,
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub container: Option<String>,
+    /// Unix seconds, inclusive
+    pub since: Option<u64>,
+    pub limit: usize,
+}
+
+/// Internal ID from a `/containers/<id>[/...]` path
+pub fn container_of(path: &str) -> Option<String> {
+    let id = path.strip_prefix("/containers/")?.split('/').next()?;
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+pub struct AuditLog {
+    db: Arc<Db>,
+}
+
+impl AuditLog {
+    pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn key(id: u64) -> String {
+        // Zero padded so keys sort by id
+        format!("{:020}", id)
+    }
+
+    pub fn record(&self, record: AuditRecord) -> Result<AuditEntry, Box<dyn std::error::Error + Send + Sync>> {
+        let entry = AuditEntry {
+            id: self.db.generate_id()?,
+            timestamp: Self::now(),
+            container: container_of(&record.target),
+            actor: record.actor,
+            action: record.action,
+            target: record.target,
+            outcome: record.outcome,
+            status: record.status,
+            ip: record.ip,
+        };
+        self.db.insert(Self::key(entry.id).as_bytes(), serde_json::to_vec(&entry)?)?;
+        Ok(entry)
+    }
+
+    /// Newest first, at most `filter.limit` entries
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut entries = Vec::new();
+        for item in self.db.iter().rev() {
+            if entries.len() >= filter.limit {
+                break;
+            }
+            let (_, value) = item?;
+            let entry: AuditEntry = serde_json::from_slice(&value)?;
+            if filter.since.is_some_and(|since| entry.timestamp < since) {
+                break;
+            }
+            if filter.container.as_ref().is_some_and(|c| entry.container.as_ref() != Some(c)) {
+                continue;
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Drop entries older than `RETENTION_SECS`, returns how many
+    pub fn prune(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let cutoff = Self::now().saturating_sub(RETENTION_SECS);
+        let mut removed = 0;
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            let entry: AuditEntry = serde_json::from_slice(&value)?;
+            if entry.timestamp >= cutoff {
+                break;
+            }
+            self.db.remove(key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(action: &str, target: &str, outcome: AuditOutcome) -> AuditRecord {
+        AuditRecord {
+            actor: "api-token".to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            outcome,
+            status: 200,
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn test_query_filters_newest_first() {
+        let path = std::env::temp_dir().join(format!("lightd-audit-{}", uuid::Uuid::new_v4()));
+        let log = AuditLog::new(path.to_str().unwrap()).unwrap();
+
+        log.record(record("POST /containers/:id/start", "/containers/mc-1/start", AuditOutcome::Success)).unwrap();
+        log.record(record("POST /firewall/rules", "/firewall/rules", AuditOutcome::Success)).unwrap();
+        log.record(record("DELETE /containers/:id", "/containers/mc-1", AuditOutcome::Denied)).unwrap();
+
+        let all = log.query(&AuditFilter { limit: 10, ..Default::default() }).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].action, "DELETE /containers/:id");
+        assert!(all[0].id > all[1].id);
+
+        let container = log.query(&AuditFilter {
+            container: Some("mc-1".to_string()),
+            limit: 10,
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(container.len(), 2);
+        assert!(container.iter().all(|e| e.container.as_deref() == Some("mc-1")));
+
+        let limited = log.query(&AuditFilter { limit: 1, ..Default::default() }).unwrap();
+        assert_eq!(limited.len(), 1);
+
+        let future = log.query(&AuditFilter { since: Some(u64::MAX), limit: 10, ..Default::default() }).unwrap();
+        assert!(future.is_empty());
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+
+    #[test]
+    fn test_container_of() {
+        assert_eq!(container_of("/containers/mc-1/start").as_deref(), Some("mc-1"));
+        assert_eq!(container_of("/containers/mc-1").as_deref(), Some("mc-1"));
+        assert_eq!(container_of("/containers"), None);
+        assert_eq!(container_of("/firewall/rules"), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! JWT validation for panels that issue their own tokens
+//!
+//! Tokens are checked against a configured secret or public key instead of
+//! the token database. The `scope` claim is a space separated list like in
+//! OAuth: `api` grants the HTTP API, `websocket` grants console connections.
+//! An optional `container` claim limits the token to one container.
+
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::config::config::JwtConfig;
+
+/// Scope required for HTTP API requests
+pub const SCOPE_API: &str = "api";
+/// Scope required for WebSocket connections
+pub const SCOPE_WEBSOCKET: &str = "websocket";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtClaims {
+    /// Checked by the decoder
+    #[allow(unused)]
+    pub exp: u64,
+    #[serde(default)]
+    pub scope: String,
+    /// Internal ID of the only container this token may access
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Who the panel issued the token to, recorded in the audit log
+    #[serde(default)]
+    pub sub: Option<String>,
+}
+
+impl JwtClaims {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|s| s == scope)
+    }
+
+    pub fn allows_container(&self, internal_id: &str) -> bool {
+        self.container.as_deref().is_none_or(|c| c == internal_id)
+    }
+
+    /// Container-bound tokens may only call routes under `/containers/<id>`
+    pub fn allows_path(&self, path: &str) -> bool {
+        let Some(container) = &self.container else {
+            return true;
+        };
+        match path.strip_prefix("/containers/").and_then(|rest| rest.strip_prefix(container.as_str())) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        }
+    }
+}
+
+pub struct JwtValidator {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtValidator {
+    pub fn from_config(config: &JwtConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let algorithm = Algorithm::from_str(&config.algorithm)
+            .map_err(|_| format!("Unsupported JWT algorithm: {}", config.algorithm))?;
+
+        let key = match algorithm {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+                let secret = config.secret.as_deref().ok_or("JWT secret is required for HMAC algorithms")?;
+                DecodingKey::from_secret(secret.as_bytes())
+            }
+            _ => {
+                let path = config.public_key_path.as_deref()
+                    .ok_or("JWT public_key_path is required for asymmetric algorithms")?;
+                let pem = std::fs::read(path)
+                    .map_err(|e| format!("Failed to read JWT public key {}: {}", path, e))?;
+                match algorithm {
+                    Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(&pem)?,
+                    Algorithm::EdDSA => DecodingKey::from_ed_pem(&pem)?,
+                    _ => DecodingKey::from_rsa_pem(&pem)?,
+                }
+            }
+        };
+
+        let mut validation = Validation::new(algorithm);
+        if let Some(issuer) = &config.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &config.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        Ok(Self { key, validation })
+    }
+
+    /// Check signature, expiry, issuer and audience
+    pub fn verify(&self, token: &str) -> Result<JwtClaims, String> {
+        decode::<JwtClaims>(token, &self.key, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Opaque tokens are `lightd_` prefixed, JWTs are three dot separated parts
+pub fn looks_like_jwt(token: &str) -> bool {
+    !token.starts_with("lightd_") && token.split('.').count() == 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    fn config() -> JwtConfig {
+        JwtConfig {
+            algorithm: "HS256".to_string(),
+            secret: Some("panel-secret".to_string()),
+            public_key_path: None,
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    fn sign(claims: serde_json::Value, secret: &str) -> String {
+        encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    fn in_an_hour() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600
+    }
+
+    #[test]
+    fn test_verify_checks_signature_and_reads_claims() {
+        let validator = JwtValidator::from_config(&config()).unwrap();
+        let claims = serde_json::json!({ "exp": in_an_hour(), "scope": "websocket", "container": "abc123" });
+
+        let verified = validator.verify(&sign(claims.clone(), "panel-secret")).unwrap();
+        assert!(verified.has_scope(SCOPE_WEBSOCKET));
+        assert!(!verified.has_scope(SCOPE_API));
+        assert!(verified.allows_container("abc123"));
+        assert!(!verified.allows_container("other"));
+
+        assert!(validator.verify(&sign(claims, "wrong-secret")).is_err());
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let validator = JwtValidator::from_config(&config()).unwrap();
+        let token = sign(serde_json::json!({ "exp": 1, "scope": "api" }), "panel-secret");
+        assert!(validator.verify(&token).is_err());
+    }
+
+    #[test]
+    fn test_container_claim_limits_paths() {
+        let claims = JwtClaims {
+            exp: 0,
+            scope: "api".to_string(),
+            container: Some("abc".to_string()),
+            sub: None,
+        };
+        assert!(claims.allows_path("/containers/abc"));
+        assert!(claims.allows_path("/containers/abc/power"));
+        assert!(!claims.allows_path("/containers/abcd/power"));
+        assert!(!claims.allows_path("/volumes"));
+    }
+
+    #[test]
+    fn test_looks_like_jwt() {
+        assert!(looks_like_jwt("aaa.bbb.ccc"));
+        assert!(!looks_like_jwt("lightd_abc"));
+    }
+}
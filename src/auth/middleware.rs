@@ -1,22 +1,30 @@
 //! Authentication middleware for API routes
 //! 
-//! Validates Bearer tokens and vendor headers.
+//! Validates Bearer tokens and vendor headers, and writes the audit log.
 
 use axum::{
-    extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, Method, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use serde_json::json;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+use super::audit::{AuditLog, AuditOutcome, AuditRecord};
+use super::jwt::{looks_like_jwt, JwtValidator, SCOPE_API};
+use super::rate_limit::{constant_time_eq, RateLimiter};
 use crate::config::config::Config;
 
 #[derive(Clone)]
 pub struct AuthConfig {
     pub api_token: String,
     pub allowed_origins: Vec<String>,
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Set when `authorization.jwt` is configured
+    pub jwt: Option<Arc<JwtValidator>>,
+    pub audit: Option<Arc<AuditLog>>,
 }
 
 impl AuthConfig {
@@ -24,8 +32,21 @@ impl AuthConfig {
         Self {
             api_token: config.authorization.token.clone(),
             allowed_origins: vec!["*".to_string()], // TODO: Load from config
+            rate_limiter: Arc::new(RateLimiter::from_config(&config.authorization.rate_limit)),
+            jwt: None,
+            audit: None,
         }
     }
+    
+    pub fn with_jwt(mut self, validator: Arc<JwtValidator>) -> Self {
+        self.jwt = Some(validator);
+        self
+    }
+
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
 }
 
 /// Check if origin is allowed
@@ -38,27 +59,88 @@ fn is_origin_allowed(origin: Option<&str>, allowed_origins: &[String]) -> bool {
 
 /// Validate vendor header
 fn validate_vendor(headers: &HeaderMap) -> bool {
-    if let Some(accept) = headers.get("accept") {
-        if let Ok(accept_str) = accept.to_str() {
-            return accept_str.contains("Application/vnd.pkglat");
-        }
+    if let Some(accept) = headers.get("accept")
+        && let Ok(accept_str) = accept.to_str() {
+        return accept_str.contains("Application/vnd.pkglat");
     }
     false
 }
 
 /// Extract and validate Bearer token
 fn validate_bearer_token(headers: &HeaderMap, api_token: &str) -> bool {
-    if let Some(auth) = headers.get("authorization") {
-        if let Ok(auth_str) = auth.to_str() {
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                // if the token doesn't start with that aswell, we just fuck off.
-                return token.starts_with("lightd_") && token == api_token;
-            }
+    if let Some(auth) = headers.get("authorization")
+        && let Ok(auth_str) = auth.to_str()
+            && let Some(token) = auth_str.strip_prefix("Bearer ") {
+            // if the token doesn't start with that aswell, we just fuck off.
+            return token.starts_with("lightd_") && constant_time_eq(token.as_bytes(), api_token.as_bytes());
         }
-    }
     false
 }
 
+/// Validate a panel issued JWT Bearer token with the `api` scope for `path`,
+/// returns the actor for the audit log
+fn validate_jwt_bearer(headers: &HeaderMap, validator: &JwtValidator, path: &str) -> Option<String> {
+    let token = headers.get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))?;
+    if !looks_like_jwt(token) {
+        return None;
+    }
+    
+    match validator.verify(token) {
+        Ok(claims) if claims.has_scope(SCOPE_API) && claims.allows_path(path) => {
+            Some(match claims.sub {
+                Some(sub) => format!("jwt:{}", sub),
+                None => "jwt".to_string(),
+            })
+        }
+        Ok(_) => None,
+        Err(e) => {
+            tracing::debug!("Rejected JWT: {}", e);
+            None
+        }
+    }
+}
+
+/// Requests that change nothing are left out of the audit log
+fn is_audited(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Method and route (`POST /containers/:id/start`) plus the path of a request
+fn audit_target(request: &Request) -> (String, String) {
+    let path = request.uri().path();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str())
+        .unwrap_or(path);
+    (format!("{} {}", request.method(), route), path.to_string())
+}
+
+/// Append to the audit log if there is one, a failed write doesn't fail the request
+fn audit(auth_config: &AuthConfig, (action, target): (String, String), actor: &str, status: StatusCode, ip: Option<std::net::IpAddr>) {
+    let Some(log) = &auth_config.audit else {
+        return;
+    };
+    let outcome = match status {
+        StatusCode::UNAUTHORIZED => AuditOutcome::Denied,
+        s if s.is_success() || s.is_redirection() => AuditOutcome::Success,
+        _ => AuditOutcome::Failure,
+    };
+    let record = AuditRecord {
+        actor: actor.to_string(),
+        action,
+        target,
+        outcome,
+        status: status.as_u16(),
+        ip: ip.map(|ip| ip.to_string()),
+    };
+    if let Err(e) = log.record(record) {
+        tracing::warn!("Failed to write audit log: {}", e);
+    }
+}
+
 /// Authentication middleware
 pub async fn auth_middleware(
     State(auth_config): State<Arc<AuthConfig>>,
@@ -87,15 +169,72 @@ pub async fn auth_middleware(
         ).into_response();
     }
     
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    
+    // Reject clients that already burned through their attempts. Not audited,
+    // the failures that used up the attempts are, so a client hammering the
+    // API adds at most `max_attempts` entries per window.
+    if let Some(ip) = client_ip
+        && auth_config.rate_limiter.is_limited(ip) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            axum::Json(json!({
+                "error": "Too many failed authentication attempts, try again later"
+            }))
+        ).into_response();
+    }
+    
     // Check Bearer token
-    if !validate_bearer_token(&headers, &auth_config.api_token) {
+    let actor = if validate_bearer_token(&headers, &auth_config.api_token) {
+        Some("api-token".to_string())
+    } else {
+        auth_config.jwt.as_deref()
+            .and_then(|jwt| validate_jwt_bearer(&headers, jwt, request.uri().path()))
+    };
+    let Some(actor) = actor else {
+        if let Some(ip) = client_ip {
+            auth_config.rate_limiter.record_failure(ip);
+        }
+        audit(&auth_config, audit_target(&request), "anonymous", StatusCode::UNAUTHORIZED, client_ip);
         return (
             StatusCode::UNAUTHORIZED,
             axum::Json(json!({
                 "error": "Invalid or missing Bearer token. Format: Authorization: Bearer lightd_<token>"
             }))
         ).into_response();
-    }
+    };
     
-    next.run(request).await
+    if auth_config.audit.is_none() || !is_audited(request.method()) {
+        return next.run(request).await;
+    }
+    // The request moves into the handler, keep what the entry needs
+    let target = audit_target(&request);
+    let response = next.run(request).await;
+    audit(&auth_config, target, &actor, response.status(), client_ip);
+    response
+}
+
+/// Audit log for public routes that hand out credentials, like token issuance
+pub async fn audit_middleware(
+    State(auth_config): State<Arc<AuthConfig>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if auth_config.audit.is_none() || !is_audited(request.method()) {
+        return next.run(request).await;
+    }
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let target = audit_target(&request);
+    let response = next.run(request).await;
+    // Rate limited refreshes are left out like in `auth_middleware`
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        audit(&auth_config, target, "anonymous", response.status(), client_ip);
+    }
+    response
 }
@@ -1,2 +1,5 @@
+pub mod audit;
 pub mod middleware;
+pub mod rate_limit;
 pub mod tokens;
+pub mod jwt;
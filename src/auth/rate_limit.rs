@@ -0,0 +1,129 @@
+//! Per-IP rate limiting for token checks
+//!
+//! Token bucket keyed by client IP. Every failed auth attempt takes a token out
+//! of the bucket, and once it is empty the client gets 429 until it refills.
+
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use crate::config::config::RateLimitConfig;
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+pub struct RateLimiter {
+    buckets: DashMap<IpAddr, Bucket>,
+    /// Failed attempts allowed in one window
+    capacity: f64,
+    /// Tokens restored per second
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    pub fn new(max_attempts: u32, window_secs: u64) -> Self {
+        let capacity = max_attempts.max(1) as f64;
+        Self {
+            buckets: DashMap::new(),
+            capacity,
+            refill_per_sec: capacity / window_secs.max(1) as f64,
+        }
+    }
+
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self::new(config.max_attempts, config.window_secs)
+    }
+
+    /// Check if an IP has run out of attempts
+    pub fn is_limited(&self, ip: IpAddr) -> bool {
+        match self.buckets.get_mut(&ip) {
+            Some(mut bucket) => {
+                self.refill(&mut bucket);
+                bucket.tokens < 1.0
+            }
+            None => false,
+        }
+    }
+
+    /// Record a failed attempt for an IP
+    pub fn record_failure(&self, ip: IpAddr) {
+        let mut bucket = self.buckets.entry(ip).or_insert_with(|| Bucket {
+            tokens: self.capacity,
+            last_refill: Instant::now(),
+        });
+        self.refill(&mut bucket);
+        bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+
+        if bucket.tokens < 1.0 {
+            tracing::warn!("Auth rate limit reached for {}", ip);
+        }
+    }
+
+    /// Drop buckets that have fully refilled
+    pub fn cleanup(&self) {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * self.refill_per_sec < self.capacity
+        });
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+    }
+}
+
+/// Compare two byte strings without leaking where they differ
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limited_after_max_failures() {
+        let limiter = RateLimiter::new(3, 60);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(!limiter.is_limited(ip));
+            limiter.record_failure(ip);
+        }
+
+        assert!(limiter.is_limited(ip));
+    }
+
+    #[test]
+    fn test_limit_is_per_ip() {
+        let limiter = RateLimiter::new(1, 60);
+        let blocked: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        limiter.record_failure(blocked);
+
+        assert!(limiter.is_limited(blocked));
+        assert!(!limiter.is_limited(other));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"lightd_abc", b"lightd_abc"));
+        assert!(!constant_time_eq(b"lightd_abc", b"lightd_abd"));
+        assert!(!constant_time_eq(b"lightd_abc", b"lightd_ab"));
+    }
+}
@@ -7,23 +7,69 @@ use sled::Db;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use super::jwt::{looks_like_jwt, JwtValidator, SCOPE_WEBSOCKET};
+
+/// How long a refreshed token keeps validating, so an open WebSocket can
+/// switch to the new one before the old one stops working
+pub const REFRESH_GRACE_SECS: u64 = 10;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenData {
+    /// Public handle for listing and revoking, the token itself is the secret.
+    /// Empty on tokens stored before ids existed until they are first listed.
+    #[serde(default)]
+    pub id: String,
     pub token: String,
     pub created_at: u64,
     pub expires_at: u64,
     pub remove_on_use: bool,
     pub used: bool,
+    /// Exchanged for a new token already, only valid for the grace period
+    #[serde(default)]
+    pub refreshed: bool,
+}
+
+impl TokenData {
+    fn new(now: u64, ttl_seconds: u64, remove_on_use: bool) -> Self {
+        Self {
+            id: new_token_id(),
+            token: format!("lightd_{}", uuid::Uuid::new_v4().to_string().replace("-", "")),
+            created_at: now,
+            expires_at: now + ttl_seconds,
+            remove_on_use,
+            used: false,
+            refreshed: false,
+        }
+    }
 }
 
 pub struct TokenManager {
     db: Arc<Db>,
+    jwt: Option<Arc<JwtValidator>>,
 }
 
 impl TokenManager {
     pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let db = sled::open(db_path)?;
-        Ok(Self { db: Arc::new(db) })
+        Ok(Self { db: Arc::new(db), jwt: None })
+    }
+    
+    /// Accept panel issued JWTs for WebSocket connections as well
+    pub fn with_jwt(mut self, validator: Arc<JwtValidator>) -> Self {
+        self.jwt = Some(validator);
+        self
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    /// Cheap read to confirm the database is open and readable
+    pub fn check_db(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.get(b"__health")?;
+        Ok(())
     }
     
     /// Generate a new token
@@ -36,24 +82,15 @@ impl TokenManager {
             .duration_since(UNIX_EPOCH)?
             .as_secs();
         
-        // Generate random token
-        let token = format!("lightd_{}", uuid::Uuid::new_v4().to_string().replace("-", ""));
-        
-        let token_data = TokenData {
-            token: token.clone(),
-            created_at: now,
-            expires_at: now + ttl_seconds,
-            remove_on_use,
-            used: false,
-        };
+        let token_data = TokenData::new(now, ttl_seconds, remove_on_use);
         
         // Store in database
         let serialized = serde_json::to_vec(&token_data)?;
-        self.db.insert(token.as_bytes(), serialized)?;
+        self.db.insert(token_data.token.as_bytes(), serialized)?;
         
         tracing::info!("Generated token with TTL {}s, remove_on_use: {}", ttl_seconds, remove_on_use);
         
-        Ok(token)
+        Ok(token_data.token)
     }
     
     /// Validate a token and optionally mark as used
@@ -106,6 +143,144 @@ impl TokenManager {
         Ok(true)
     }
     
+    /// Validate a token for a WebSocket connection to `internal_id`
+    ///
+    /// JWTs need the `websocket` scope and, if they carry a `container` claim,
+    /// must name this container. Opaque tokens go through `validate_token`.
+    pub fn validate_container_token(
+        &self,
+        token: &str,
+        internal_id: &str,
+        mark_used: bool,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(jwt) = self.jwt.as_ref().filter(|_| looks_like_jwt(token)) else {
+            return self.validate_token(token, mark_used);
+        };
+        
+        match jwt.verify(token) {
+            Ok(claims) => Ok(claims.has_scope(SCOPE_WEBSOCKET) && claims.allows_container(internal_id)),
+            Err(e) => {
+                tracing::warn!("Rejected JWT for container {}: {}", internal_id, e);
+                Ok(false)
+            }
+        }
+    }
+    
+    /// Issue a fresh token for a still-valid one
+    ///
+    /// The new token keeps the original TTL, a shorter one may be given.
+    /// Single-use tokens can't be refreshed. The old token is retired in the
+    /// same transaction the new one is stored in, so it can be exchanged only
+    /// once, and stops validating after `REFRESH_GRACE_SECS`.
+    pub fn refresh_token(
+        &self,
+        token: &str,
+        ttl_seconds: Option<u64>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        
+        let data = match self.db.get(token.as_bytes())? {
+            Some(bytes) => bytes,
+            None => {
+                tracing::warn!("Refresh requested for unknown token");
+                return Ok(None);
+            }
+        };
+        
+        let token_data: TokenData = serde_json::from_slice(&data)?;
+        
+        if now > token_data.expires_at {
+            tracing::warn!("Refresh requested for expired token");
+            self.db.remove(token.as_bytes())?;
+            return Ok(None);
+        }
+        if token_data.remove_on_use {
+            tracing::warn!("Refresh requested for a single-use token");
+            return Ok(None);
+        }
+        if token_data.refreshed {
+            tracing::warn!("Refresh requested for a token that was refreshed already");
+            return Ok(None);
+        }
+        
+        let original_ttl = token_data.expires_at - token_data.created_at;
+        let ttl = ttl_seconds.map_or(original_ttl, |ttl| ttl.min(original_ttl));
+        
+        // Refreshed tokens are meant to keep a session alive, never single-use
+        let new_data = TokenData::new(now, ttl, false);
+        let retired = TokenData {
+            expires_at: token_data.expires_at.min(now + REFRESH_GRACE_SECS),
+            refreshed: true,
+            ..token_data
+        };
+        let new_bytes = serde_json::to_vec(&new_data)?;
+        let retired_bytes = serde_json::to_vec(&retired)?;
+        
+        // A concurrent refresh of the same token changed the record, only one wins
+        let exchanged = self.db.transaction(|tx| -> sled::transaction::ConflictableTransactionResult<bool, sled::Error> {
+            if tx.get(token.as_bytes())?.as_deref() != Some(&data[..]) {
+                return Ok(false);
+            }
+            tx.insert(token.as_bytes(), retired_bytes.as_slice())?;
+            tx.insert(new_data.token.as_bytes(), new_bytes.as_slice())?;
+            Ok(true)
+        })?;
+        if !exchanged {
+            tracing::warn!("Refresh lost a race with another refresh of the same token");
+            return Ok(None);
+        }
+        
+        tracing::info!("Refreshed token with TTL {}s", ttl);
+        
+        Ok(Some(new_data.token))
+    }
+    
+    /// All unexpired tokens, oldest first
+    pub fn list_tokens(&self) -> Result<Vec<TokenData>, Box<dyn std::error::Error + Send + Sync>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        
+        let mut tokens = Vec::new();
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            let Ok(mut token_data) = serde_json::from_slice::<TokenData>(&value) else {
+                continue;
+            };
+            if now > token_data.expires_at {
+                continue;
+            }
+            
+            // Give older tokens an id so they can be revoked
+            if token_data.id.is_empty() {
+                token_data.id = new_token_id();
+                self.db.insert(&key, serde_json::to_vec(&token_data)?)?;
+            }
+            
+            tokens.push(token_data);
+        }
+        
+        tokens.sort_by_key(|t| t.created_at);
+        Ok(tokens)
+    }
+    
+    /// Remove the token with the given id, false if there is none
+    pub fn revoke_token(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            if let Ok(token_data) = serde_json::from_slice::<TokenData>(&value)
+                && !token_data.id.is_empty() && token_data.id == id {
+                self.db.remove(&key)?;
+                tracing::info!("Revoked token {}", id);
+                return Ok(true);
+            }
+        }
+        
+        Ok(false)
+    }
+    
     /// Clean up expired tokens
     pub fn cleanup_expired(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
         let now = SystemTime::now()
@@ -116,11 +291,10 @@ impl TokenManager {
         
         for item in self.db.iter() {
             let (key, value) = item?;
-            if let Ok(token_data) = serde_json::from_slice::<TokenData>(&value) {
-                if now > token_data.expires_at {
-                    self.db.remove(&key)?;
-                    removed += 1;
-                }
+            if let Ok(token_data) = serde_json::from_slice::<TokenData>(&value)
+                && now > token_data.expires_at {
+                self.db.remove(&key)?;
+                removed += 1;
             }
         }
         
@@ -131,3 +305,36 @@ impl TokenManager {
         Ok(removed)
     }
 }
+
+fn new_token_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_token() {
+        let path = std::env::temp_dir().join(format!("lightd-tokens-{}", uuid::Uuid::new_v4()));
+        let manager = TokenManager::new(path.to_str().unwrap()).unwrap();
+
+        let single_use = manager.generate_token(3600, true).unwrap();
+        assert!(manager.refresh_token(&single_use, None).unwrap().is_none());
+
+        let token = manager.generate_token(60, false).unwrap();
+        let refreshed = manager.refresh_token(&token, Some(86400)).unwrap().unwrap();
+        let data: TokenData = serde_json::from_slice(&manager.db.get(&refreshed).unwrap().unwrap()).unwrap();
+        // Never longer than the token it replaces
+        assert_eq!(data.expires_at - data.created_at, 60);
+
+        // The old token is retired, it validates briefly but can't be exchanged again
+        assert!(manager.validate_token(&token, false).unwrap());
+        assert!(manager.refresh_token(&token, None).unwrap().is_none());
+        let old: TokenData = serde_json::from_slice(&manager.db.get(&token).unwrap().unwrap()).unwrap();
+        assert!(old.refreshed);
+        assert!(old.expires_at <= old.created_at + REFRESH_GRACE_SECS + 1);
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
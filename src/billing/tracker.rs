@@ -5,11 +5,13 @@
 
 use bollard::Docker;
 use bollard::container::StatsOptions;
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio::time::{interval, Duration};
+use tokio::sync::{Notify, RwLock};
+use tokio::time::{sleep_until, Duration, Instant};
 use futures::StreamExt;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,6 +43,13 @@ pub struct ResourceUsage {
     pub timestamp: u64,
 }
 
+/// Bytes sent to SFTP clients from one volume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpEgress {
+    pub bytes: u64,
+    pub timestamp: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UsageSnapshot {
     pub memory_gb: f64,
@@ -54,20 +63,26 @@ pub struct BillingTracker {
     docker: Docker,
     rates: Arc<RwLock<BillingRates>>,
     usage_data: Arc<RwLock<HashMap<String, Vec<ResourceUsage>>>>,
-    interval_ms: u64,
+    /// SFTP downloads keyed by volume_id, they bypass the container's network stats
+    sftp_egress: DashMap<String, Vec<SftpEgress>>,
+    interval_ms: AtomicU64,
+    /// Wakes the collection loop so a new interval applies to the current wait
+    interval_changed: Notify,
     remote_sync: Option<Arc<crate::remote::client::RemoteSyncManager>>,
     container_manager: Option<Arc<crate::container::manager::ContainerManager>>,
 }
 
 impl BillingTracker {
     pub fn new(rates: BillingRates, interval_ms: u64) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Docker::connect_with_local_defaults()?;
+        let docker = crate::container::docker::client()?;
         
         Ok(Self {
             docker,
             rates: Arc::new(RwLock::new(rates)),
             usage_data: Arc::new(RwLock::new(HashMap::new())),
-            interval_ms,
+            sftp_egress: DashMap::new(),
+            interval_ms: AtomicU64::new(interval_ms),
+            interval_changed: Notify::new(),
             remote_sync: None,
             container_manager: None,
         })
@@ -86,22 +101,42 @@ impl BillingTracker {
     }
     
     /// Start monitoring all containers
-    pub fn start_monitoring(self: Arc<Self>) {
+    pub fn start_monitoring(self: Arc<Self>, mut shutdown: crate::daemon::shutdown::ShutdownSignal) {
         let tracker = self.clone();
         
         tokio::spawn(async move {
-            let mut tick = interval(Duration::from_millis(tracker.interval_ms));
+            // The first collection runs right away
+            let mut last_run: Option<Instant> = None;
             
             loop {
-                tick.tick().await;
+                // Read on every pass, a config reload also shortens or stretches the wait in progress
+                let interval = Duration::from_millis(tracker.interval_ms.load(Ordering::Relaxed));
+                let due = last_run.map_or_else(Instant::now, |last| last + interval);
+                tokio::select! {
+                    _ = sleep_until(due) => {}
+                    _ = tracker.interval_changed.notified() => continue,
+                    _ = shutdown.recv() => {
+                        tracing::info!("Billing tracker stopped");
+                        break;
+                    }
+                }
                 
+                last_run = Some(Instant::now());
                 if let Err(e) = tracker.collect_metrics().await {
                     tracing::error!("Failed to collect metrics: {}", e);
                 }
             }
         });
         
-        tracing::info!("Billing tracker started with {}ms interval", self.interval_ms);
+        tracing::info!("Billing tracker started with {}ms interval", self.interval_ms.load(Ordering::Relaxed));
+    }
+    
+    /// Change the collection interval, the next collection is due one new
+    /// interval after the last one
+    pub fn set_interval(&self, interval_ms: u64) {
+        self.interval_ms.store(interval_ms, Ordering::Relaxed);
+        self.interval_changed.notify_waiters();
+        tracing::info!("Updated billing interval to {}ms", interval_ms);
     }
     
     /// Collect metrics from all running containers
@@ -112,13 +147,11 @@ impl BillingTracker {
         for container in containers {
             if let Some(id) = container.id {
                 // Only monitor lightd containers
-                if let Some(names) = container.names {
-                    if names.iter().any(|n| n.contains("lightd-")) {
-                        if let Err(e) = self.collect_container_metrics(&id).await {
-                            tracing::warn!("Failed to collect metrics for {}: {}", id, e);
-                        }
+                if let Some(names) = container.names
+                    && names.iter().any(|n| n.contains("lightd-"))
+                        && let Err(e) = self.collect_container_metrics(&id).await {
+                        tracing::warn!("Failed to collect metrics for {}: {}", id, e);
                     }
-                }
             }
         }
         
@@ -143,23 +176,29 @@ impl BillingTracker {
             }
         };
         
+        let containers = match manager.list_containers().await {
+            Ok(c) => c,
+            Err(e) => {
+                tracing::error!("Failed to list containers for billing sync: {}", e);
+                return;
+            }
+        };
+        
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        
+        let mut reports = Vec::new();
+        let mut reported_volumes = std::collections::HashSet::new();
+        
         for docker_container_id in tracked {
-            // Get all containers and find matching internal_id
-            let containers = match manager.list_containers().await {
-                Ok(c) => c,
-                Err(e) => {
-                    tracing::error!("Failed to list containers for billing sync: {}", e);
-                    continue;
-                }
-            };
-            
             // Find container with matching Docker ID
-            let internal_id = containers.iter()
-                .find(|c| c.container_id.as_ref() == Some(&docker_container_id))
-                .map(|c| c.internal_id.clone());
+            let container = containers.iter()
+                .find(|c| c.container_id.as_ref() == Some(&docker_container_id));
             
-            let internal_id = match internal_id {
-                Some(id) => id,
+            let container = match container {
+                Some(c) => c,
                 None => {
                     tracing::debug!("No internal_id found for Docker container: {}", docker_container_id);
                     continue;
@@ -168,26 +207,64 @@ impl BillingTracker {
             
             // Get hourly usage snapshot
             match self.get_usage_snapshot(&docker_container_id, 1.0).await {
-                Ok(snapshot) => {
+                Ok(mut snapshot) => {
+                    snapshot.egress_gb += self.sftp_egress_bytes(&container.volume_id, 1.0) as f64
+                        / (1024.0 * 1024.0 * 1024.0);
+                    reported_volumes.insert(container.volume_id.clone());
                     let cost = self.calculate_cost(&snapshot).await;
                     
-                    remote_sync.notify_billing(
-                        internal_id,
-                        snapshot.memory_gb,
-                        snapshot.cpu_vcpus,
-                        snapshot.storage_gb,
-                        snapshot.egress_gb,
-                        snapshot.duration_hours,
-                        cost,
-                    );
-                    
-                    tracing::debug!("Sent billing update to remote for container: {}", docker_container_id);
+                    reports.push(crate::remote::client::BillingReport {
+                        server: container.internal_id.clone(),
+                        memory_gb: snapshot.memory_gb,
+                        cpu_vcpus: snapshot.cpu_vcpus,
+                        storage_gb: snapshot.storage_gb,
+                        egress_gb: snapshot.egress_gb,
+                        duration_hours: snapshot.duration_hours,
+                        estimated_cost: cost,
+                        timestamp,
+                    });
                 }
                 Err(e) => {
                     tracing::debug!("No billing data to sync for {}: {}", docker_container_id, e);
                 }
             }
         }
+        
+        // Files can be downloaded over SFTP while the container is stopped
+        for container in &containers {
+            if reported_volumes.contains(&container.volume_id) {
+                continue;
+            }
+            let sftp_bytes = self.sftp_egress_bytes(&container.volume_id, 1.0);
+            if sftp_bytes == 0 {
+                continue;
+            }
+            
+            let snapshot = UsageSnapshot {
+                memory_gb: 0.0,
+                cpu_vcpus: 0.0,
+                storage_gb: 0.0,
+                egress_gb: sftp_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+                duration_hours: 1.0,
+            };
+            let cost = self.calculate_cost(&snapshot).await;
+            
+            reports.push(crate::remote::client::BillingReport {
+                server: container.internal_id.clone(),
+                memory_gb: snapshot.memory_gb,
+                cpu_vcpus: snapshot.cpu_vcpus,
+                storage_gb: snapshot.storage_gb,
+                egress_gb: snapshot.egress_gb,
+                duration_hours: snapshot.duration_hours,
+                estimated_cost: cost,
+                timestamp,
+            });
+        }
+        
+        if !reports.is_empty() {
+            tracing::debug!("Sending billing batch for {} containers to remote", reports.len());
+            remote_sync.notify_billing_batch(reports);
+        }
     }
     
     /// Collect metrics for a specific container
@@ -203,17 +280,8 @@ impl BillingTracker {
         if let Some(Ok(stats)) = stats_stream.next().await {
             let memory_bytes = stats.memory_stats.usage.unwrap_or(0);
             
-            // Calculate CPU usage
-            let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as f64
-                - stats.precpu_stats.cpu_usage.total_usage as f64;
-            let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as f64
-                - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as f64;
-            
-            let cpu_usage_seconds = if system_delta > 0.0 {
-                (cpu_delta / system_delta) * stats.cpu_stats.online_cpus.unwrap_or(1) as f64
-            } else {
-                0.0
-            };
+            // Cores in use, same math as the live stats
+            let cpu_usage_seconds = crate::websocket::stats::cpu_percent(&stats) / 100.0;
             
             // Network egress
             let mut egress_bytes = 0u64;
@@ -260,6 +328,43 @@ impl BillingTracker {
         Ok(())
     }
     
+    /// Record bytes sent to an SFTP client from a volume
+    pub fn record_sftp_egress(&self, volume_id: &str, bytes: u64) {
+        if bytes == 0 {
+            return;
+        }
+        
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        
+        let mut entries = self.sftp_egress.entry(volume_id.to_string()).or_default();
+        entries.push(SftpEgress { bytes, timestamp });
+        
+        // Keep only last 24 hours of data
+        let cutoff = timestamp.saturating_sub(24 * 3600);
+        entries.retain(|e| e.timestamp > cutoff);
+    }
+    
+    /// Bytes sent over SFTP from a volume within the last `duration_hours`
+    pub fn sftp_egress_bytes(&self, volume_id: &str, duration_hours: f64) -> u64 {
+        let Some(entries) = self.sftp_egress.get(volume_id) else {
+            return 0;
+        };
+        
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let cutoff = now.saturating_sub((duration_hours * 3600.0) as u64);
+        
+        entries.iter()
+            .filter(|e| e.timestamp > cutoff)
+            .map(|e| e.bytes)
+            .sum()
+    }
+    
     /// Get usage snapshot for a container over a time period
     pub async fn get_usage_snapshot(
         &self,
@@ -334,7 +439,6 @@ impl BillingTracker {
     }
     
     /// Update billing rates
-    #[allow(dead_code)]
     pub async fn update_rates(&self, rates: BillingRates) {
         let mut current_rates = self.rates.write().await;
         *current_rates = rates;
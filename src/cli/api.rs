@@ -0,0 +1,68 @@
+//! Minimal client for the local daemon, shared by the CLI commands
+
+use serde_json::{json, Value};
+
+use crate::config::config::Config;
+
+pub(crate) struct LocalApi {
+    pub(crate) host: String,
+    pub(crate) base_url: String,
+    pub(crate) token: String,
+    pub(crate) client: reqwest::Client,
+}
+
+impl LocalApi {
+    pub(crate) fn from_config() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config = Config::load("config.json")
+            .map_err(|e| format!("Failed to load config: {}", e))?;
+
+        // A wildcard listen address isn't something we can connect to
+        let host = match config.server.host.as_str() {
+            "0.0.0.0" => "127.0.0.1".to_string(),
+            "::" => "[::1]".to_string(),
+            h if h.contains(':') => format!("[{}]", h),
+            h => h.to_string(),
+        };
+        let host = format!("{}:{}", host, config.server.port);
+
+        Ok(Self {
+            base_url: format!("http://{}", host),
+            host,
+            token: config.authorization.token,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub(crate) async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let response = request
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Accept", "Application/vnd.pkglatv1+json")
+            .send()
+            .await
+            .map_err(|e| format!("Could not reach lightd at {} (is it running?): {}", self.base_url, e))?;
+
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or(Value::Null);
+
+        if !status.is_success() {
+            let message = body.get("error")
+                .and_then(|e| e.as_str())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| status.to_string());
+            return Err(message.into());
+        }
+
+        Ok(body)
+    }
+
+    /// Issue a temporary token, WebSocket connections can't use the admin token
+    pub(crate) async fn issue_token(&self, ttl: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let body = json!({ "ttl": ttl, "remove_on_use": false });
+        let response = self.send(self.client.post(format!("{}/auth/tokens", self.base_url)).json(&body)).await?;
+
+        response.get("token")
+            .and_then(|t| t.as_str())
+            .map(|t| t.to_string())
+            .ok_or_else(|| "Token response did not contain a token".into())
+    }
+}
@@ -0,0 +1,18 @@
+//! Shell completion scripts
+//!
+//! Generated from the `Cli` parser at runtime so new flags and subcommands
+//! show up without touching this file.
+
+use clap::Args;
+use clap_complete::Shell;
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the script for
+    pub shell: Shell,
+}
+
+pub fn print_completions(args: CompletionsArgs, command: &mut clap::Command) {
+    let name = command.get_name().to_string();
+    clap_complete::generate(args.shell, command, name, &mut std::io::stdout());
+}
@@ -0,0 +1,152 @@
+//! Interactive console for a container
+//!
+//! Connects to the daemon's WebSocket endpoint, prints console output as it
+//! arrives and forwards each line typed on stdin as a `send_command` event.
+
+use clap::Args;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::api::LocalApi;
+
+/// Lifetime of the tokens the console issues for itself
+const SESSION_TOKEN_TTL: &str = "1h";
+/// Refresh well before the session token expires, the daemon closes the socket once it does
+const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(45 * 60);
+
+#[derive(Args)]
+pub struct ConsoleArgs {
+    /// Internal ID of the container
+    pub internal_id: String,
+
+    /// Print the last N log lines before following
+    #[arg(long)]
+    pub tail: Option<usize>,
+
+    /// Use an existing token instead of issuing one
+    #[arg(long)]
+    pub token: Option<String>,
+}
+
+pub async fn handle_console_command(args: ConsoleArgs) {
+    if let Err(e) = run_console(args).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run_console(args: ConsoleArgs) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let api = LocalApi::from_config()?;
+
+    // Only tokens we issued ourselves get refreshed, a supplied one is the caller's to manage
+    let (token, refresh) = match args.token {
+        Some(token) => (token, false),
+        None => (api.issue_token(SESSION_TOKEN_TTL).await?, true),
+    };
+
+    let url = format!("ws://{}/ws/{}?token={}", api.host, args.internal_id, token);
+    let (socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .map_err(|e| format!("Could not connect to the console of {}: {}", args.internal_id, e))?;
+    let (mut sink, mut stream) = socket.split();
+
+    eprintln!("Connected to {}, type a command and press Enter (Ctrl-C to quit)", args.internal_id);
+
+    if let Some(tail) = args.tail {
+        let request = json!({ "logs": [tail.to_string()] });
+        sink.send(Message::Text(request.to_string())).await?;
+    }
+
+    let mut input = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdin_open = true;
+
+    let mut refresh_timer = tokio::time::interval(TOKEN_REFRESH_INTERVAL);
+    refresh_timer.tick().await;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => print_event(&text),
+                Some(Ok(Message::Close(frame))) => {
+                    match frame {
+                        Some(frame) if !frame.reason.is_empty() => eprintln!("Connection closed: {}", frame.reason),
+                        _ => eprintln!("Connection closed"),
+                    }
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(format!("Connection lost: {}", e).into()),
+                None => {
+                    eprintln!("Connection closed");
+                    break;
+                }
+            },
+            line = input.next_line(), if stdin_open => match line? {
+                Some(command) => {
+                    let event = json!({ "send_command": [command] });
+                    sink.send(Message::Text(event.to_string())).await?;
+                }
+                // Piped input ran out, keep following the output
+                None => stdin_open = false,
+            },
+            _ = refresh_timer.tick(), if refresh => {
+                match api.issue_token(SESSION_TOKEN_TTL).await {
+                    Ok(new_token) => {
+                        let event = json!({ "refresh_token": [new_token] });
+                        sink.send(Message::Text(event.to_string())).await?;
+                    }
+                    Err(e) => eprintln!("Failed to refresh session token: {}", e),
+                }
+            },
+            _ = &mut ctrl_c => {
+                let _ = sink.send(Message::Close(Some(CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: "Console closed".into(),
+                }))).await;
+                eprintln!();
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the events a console cares about, stats and the like are skipped
+fn print_event(text: &str) {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return;
+    };
+
+    let event = value.get("event").and_then(|e| e.as_str()).unwrap_or_default();
+    let args = value.get("args")
+        .and_then(|a| a.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    match event {
+        "console output" | "logs" => {
+            for line in args {
+                println!("{}", line.trim_end_matches('\n'));
+            }
+        }
+        "daemon_message" => {
+            for line in args {
+                eprintln!("[lightd] {}", line.trim_end_matches('\n'));
+            }
+        }
+        "event" => {
+            for line in args {
+                eprintln!("[event] {}", line);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,157 @@
+//! Container management CLI commands
+//!
+//! Talks to the running daemon's HTTP API with the admin token from
+//! config.json, the sled databases are locked by the daemon process.
+
+use clap::{Args, Subcommand};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+use super::api::LocalApi;
+
+#[derive(Args)]
+pub struct ContainerArgs {
+    /// Print the raw JSON response instead of a table
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub command: ContainerCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ContainerCommand {
+    /// Create a container, installation continues in the background
+    Create {
+        /// Internal ID (random UUID when omitted)
+        #[arg(long)]
+        id: Option<String>,
+        /// Docker image, e.g. ubuntu:22.04
+        #[arg(long)]
+        image: String,
+        /// Volume UUID to mount
+        #[arg(long)]
+        volume: String,
+        /// Command run when the container starts
+        #[arg(long)]
+        startup: String,
+        /// File with the install script
+        #[arg(long = "install-script")]
+        install_script: Option<PathBuf>,
+        /// Pattern marking the server as started
+        #[arg(long = "start-pattern")]
+        start_pattern: Option<String>,
+        /// Container port to publish, as PORT, PORT/udp or PORT/both (repeatable)
+        #[arg(long = "port")]
+        ports: Vec<String>,
+    },
+    /// Delete a container and return its ports to the pool
+    Delete {
+        /// Internal ID of the container
+        id: String,
+    },
+}
+
+pub async fn handle_container_command(args: ContainerArgs) {
+    let result = match args.command {
+        ContainerCommand::Create { id, image, volume, startup, install_script, start_pattern, ports } => {
+            create_container(id, image, volume, startup, install_script, start_pattern, ports, args.json).await
+        }
+        ContainerCommand::Delete { id } => delete_container(&id, args.json).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn parse_port(spec: &str) -> Result<Value, String> {
+    let (port, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let container_port: u16 = port.parse()
+        .map_err(|_| format!("Invalid port '{}'", spec))?;
+
+    match protocol.to_lowercase().as_str() {
+        "tcp" | "udp" => Ok(json!({ "container_port": container_port, "protocol": protocol.to_lowercase() })),
+        "both" => Ok(json!({ "container_port": container_port, "both_protocols": true })),
+        _ => Err(format!("Invalid protocol in '{}', expected tcp, udp or both", spec)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_container(
+    id: Option<String>,
+    image: String,
+    volume: String,
+    startup: String,
+    install_script: Option<PathBuf>,
+    start_pattern: Option<String>,
+    ports: Vec<String>,
+    as_json: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let api = LocalApi::from_config()?;
+
+    let install_script = match install_script {
+        Some(path) => Some(std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?),
+        None => None,
+    };
+
+    let ports = ports.iter()
+        .map(|p| parse_port(p))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let internal_id = id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let body = json!({
+        "internal_id": internal_id,
+        "image": image,
+        "volume_id": volume,
+        "startup_command": startup,
+        "install_script": install_script,
+        "start_pattern": start_pattern,
+        "ports": if ports.is_empty() { Value::Null } else { Value::Array(ports) },
+    });
+
+    let response = api.send(api.client.post(format!("{}/containers", api.base_url)).json(&body)).await?;
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(());
+    }
+
+    println!("╔═══════════════════════════════════════════════════════════════════╗");
+    println!("║                        Container Created                          ║");
+    println!("╠═══════════════════════════════════════════════════════════════════╣");
+    println!("║  Internal ID: {:<52} ║", internal_id);
+    println!("║  Image:       {:<52} ║", image);
+    println!("║  State:       {:<52} ║", "installing");
+    println!("╠═══════════════════════════════════════════════════════════════════╣");
+    println!("║  Follow progress with: lightd --servers                           ║");
+    println!("╚═══════════════════════════════════════════════════════════════════╝");
+    Ok(())
+}
+
+async fn delete_container(id: &str, as_json: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let api = LocalApi::from_config()?;
+
+    let response = api.send(api.client.delete(format!("{}/containers/{}", api.base_url, id))).await?;
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&response)?);
+        return Ok(());
+    }
+
+    let released = response.get("ports")
+        .and_then(|p| p.as_array())
+        .map(|p| p.len())
+        .unwrap_or(0);
+
+    println!("╔═══════════════════════════════════════════════════════════════════╗");
+    println!("║                        Container Deleted                          ║");
+    println!("╠═══════════════════════════════════════════════════════════════════╣");
+    println!("║  Internal ID:    {:<49} ║", id);
+    println!("║  Ports released: {:<49} ║", released);
+    println!("╚═══════════════════════════════════════════════════════════════════╝");
+    Ok(())
+}
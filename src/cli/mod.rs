@@ -1 +1,5 @@
+pub mod api;
+pub mod completions;
+pub mod console;
+pub mod container;
 pub mod token;
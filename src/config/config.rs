@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::fs;
 
+use crate::network::pool::AllocationStrategy;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     pub version: String,
@@ -13,6 +15,77 @@ pub struct Config {
     pub remote: Option<RemoteConfig>,
     #[serde(default)]
     pub sftp: Option<SftpConfig>,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Put /metrics behind the API token; when false it only answers loopback clients
+    pub require_auth: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            require_auth: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NetworkConfig {
+    /// How host ports are picked from the pool: "random" (default) or "sequential"
+    #[serde(default)]
+    pub allocation_strategy: AllocationStrategy,
+    /// Warn on container creation when fewer free ports than this are left per protocol, 0 disables it
+    #[serde(default = "default_low_port_threshold")]
+    pub low_port_threshold: usize,
+    /// Global CIDR blocklists applied at startup
+    #[serde(default)]
+    pub blocklists: Vec<BlocklistConfig>,
+}
+
+fn default_low_port_threshold() -> usize {
+    10
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BlocklistConfig {
+    pub name: String,
+    #[serde(default)]
+    pub cidrs: Vec<String>,
+    /// Text file with one address or range per line, merged with `cidrs`
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+impl BlocklistConfig {
+    /// Normalized entries from `cidrs` and `file`
+    pub fn load_cidrs(&self) -> Result<Vec<String>, String> {
+        let mut cidrs = self.cidrs.iter()
+            .map(|c| crate::network::blocklist::parse_cidr(c))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(file) = &self.file {
+            let text = fs::read_to_string(file).map_err(|e| format!("cannot read {}: {}", file, e))?;
+            cidrs.extend(crate::network::blocklist::parse_list(&text)?);
+        }
+        Ok(cidrs)
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            allocation_strategy: AllocationStrategy::default(),
+            low_port_threshold: default_low_port_threshold(),
+            blocklists: Vec::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -20,6 +93,51 @@ pub struct RemoteConfig {
     pub enabled: bool,
     pub url: String,
     pub token: String,
+    #[serde(default)]
+    pub retry: RemoteRetryConfig,
+    /// Max containers per billing batch request
+    #[serde(default = "default_billing_batch_size")]
+    pub billing_batch_size: usize,
+    /// Private CA and client certificate for panels behind their own PKI
+    #[serde(default)]
+    pub tls: Option<RemoteTlsConfig>,
+}
+
+/// PEM files for talking to the remote, all paths on this host
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RemoteTlsConfig {
+    /// CA bundle the remote's certificate must chain to. Replaces the system
+    /// roots, so the remote can't be impersonated with a public certificate
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// Client certificate presented for mutual TLS, needs `client_key`
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// PKCS#8 private key of `client_cert`
+    #[serde(default)]
+    pub client_key: Option<String>,
+}
+
+fn default_billing_batch_size() -> usize {
+    crate::remote::client::DEFAULT_BILLING_BATCH_SIZE
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteRetryConfig {
+    /// Total attempts per notification, including the first one
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RemoteRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -27,23 +145,347 @@ pub struct SftpConfig {
     pub enabled: bool,
     pub host: String,
     pub port: u16,
+    /// Private host key, generated on first start. Defaults to `<base_path>/sftp_host_key`
+    #[serde(default)]
+    pub host_key_path: Option<String>,
+    /// Open file and directory handles allowed per session
+    #[serde(default = "default_sftp_max_open_handles")]
+    pub max_open_handles: usize,
+    /// Handles unused for this many seconds are closed
+    #[serde(default = "default_sftp_handle_idle_timeout_secs")]
+    pub handle_idle_timeout_secs: u64,
+    /// Concurrent sessions across all clients
+    #[serde(default = "default_sftp_max_sessions")]
+    pub max_sessions: usize,
+    /// Concurrent sessions from one client IP
+    #[serde(default = "default_sftp_max_sessions_per_ip")]
+    pub max_sessions_per_ip: usize,
+    /// Failed logins an IP may have within `failed_login_window_secs` before it's banned
+    #[serde(default = "default_sftp_max_failed_logins")]
+    pub max_failed_logins: u32,
+    #[serde(default = "default_sftp_failed_login_window_secs")]
+    pub failed_login_window_secs: u64,
+    #[serde(default = "default_sftp_ban_secs")]
+    pub ban_secs: u64,
+}
+
+fn default_sftp_max_open_handles() -> usize {
+    256
+}
+
+fn default_sftp_handle_idle_timeout_secs() -> u64 {
+    300
+}
+
+fn default_sftp_max_sessions() -> usize {
+    100
+}
+
+fn default_sftp_max_sessions_per_ip() -> usize {
+    10
+}
+
+fn default_sftp_max_failed_logins() -> u32 {
+    5
+}
+
+fn default_sftp_failed_login_window_secs() -> u64 {
+    300
+}
+
+fn default_sftp_ban_secs() -> u64 {
+    900
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// How long an Idempotency-Key on container creation is remembered
+    #[serde(default = "default_idempotency_ttl_secs")]
+    pub idempotency_ttl_secs: u64,
+    /// Browser origins allowed to call the API. Unset allows any origin with
+    /// `--dev` and none otherwise.
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    /// Start in maintenance mode, refusing new containers until it is turned
+    /// off through `/maintenance/mode`
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Request timeouts and body size caps
+    #[serde(default)]
+    pub limits: HttpLimitsConfig,
+    /// Per container event buffer and handling of slow console clients
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+    86400
+}
+
+/// Timeouts answer with 408 and oversized bodies with 413. The transfer
+/// values apply to uploads, downloads, import/export and archive operations
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpLimitsConfig {
+    pub request_timeout_secs: u64,
+    pub max_body_bytes: usize,
+    pub transfer_timeout_secs: u64,
+    pub transfer_max_body_bytes: usize,
+}
+
+impl Default for HttpLimitsConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_secs: 60,
+            max_body_bytes: 8 * 1024 * 1024,
+            transfer_timeout_secs: 3600,
+            transfer_max_body_bytes: 10 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// A client that falls more than `buffer_size` events behind loses the
+/// oldest ones. One that does so `max_lags` times within `lag_window_secs`
+/// is disconnected instead of being kept behind forever
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    /// Events buffered per container for each connected client
+    pub buffer_size: usize,
+    pub max_lags: u32,
+    pub lag_window_secs: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 1024,
+            max_lags: 3,
+            lag_window_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CorsConfig {
+    /// Full origins like "https://panel.example.com", "*" allows any
+    pub allowed_origins: Vec<String>,
+    /// "*" allows any
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send, "*" allows any
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Let browsers send cookies and auth headers, can't be combined with "*"
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+fn default_cors_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE"].map(str::to_string).to_vec()
+}
+
+fn default_cors_headers() -> Vec<String> {
+    [
+        "authorization", "accept", "content-type", "idempotency-key",
+        "if-match", "if-range", "range", "content-range", "x-request-id",
+    ]
+        .map(str::to_string)
+        .to_vec()
+}
+
+impl CorsConfig {
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.allowed_origins.is_empty() {
+            problems.push("server.cors.allowed_origins: must list at least one origin".to_string());
+        }
+        for origin in &self.allowed_origins {
+            if let Err(e) = check_origin(origin) {
+                problems.push(format!("server.cors.allowed_origins: {}", e));
+            }
+        }
+        for method in &self.allowed_methods {
+            if method != "*" && axum::http::Method::from_bytes(method.to_uppercase().as_bytes()).is_err() {
+                problems.push(format!("server.cors.allowed_methods: '{}' is not an HTTP method", method));
+            }
+        }
+        for header in &self.allowed_headers {
+            if header != "*" && axum::http::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                problems.push(format!("server.cors.allowed_headers: '{}' is not a header name", header));
+            }
+        }
+        if self.allow_credentials {
+            for (field, values) in [
+                ("allowed_origins", &self.allowed_origins),
+                ("allowed_methods", &self.allowed_methods),
+                ("allowed_headers", &self.allowed_headers),
+            ] {
+                if values.iter().any(|v| v == "*") {
+                    problems.push(format!("server.cors.{}: '*' can't be used with allow_credentials", field));
+                }
+            }
+        }
+    }
+}
+
+/// An origin is a scheme and host with an optional port, no path
+fn check_origin(origin: &str) -> Result<(), String> {
+    if origin == "*" {
+        return Ok(());
+    }
+    let host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))
+        .ok_or_else(|| format!("'{}' must start with http:// or https://", origin))?;
+    if host.is_empty() || host.contains(['/', '?', '#']) || host.chars().any(|c| c.is_whitespace()) {
+        return Err(format!("'{}' must be scheme://host[:port] without a path or trailing slash", origin));
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuthConfig {
     pub enabled: bool,
     pub token: String,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Also accept JWTs signed by the panel, opaque tokens keep working
+    #[serde(default)]
+    pub jwt: Option<JwtConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct JwtConfig {
+    #[serde(default = "default_jwt_algorithm")]
+    pub algorithm: String,
+    /// Shared secret for HS256/HS384/HS512
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// PEM public key for RS*, PS*, ES256/ES384 and EdDSA
+    #[serde(default)]
+    pub public_key_path: Option<String>,
+    /// Required `iss` claim
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Required `aud` claim
+    #[serde(default)]
+    pub audience: Option<String>,
+}
+
+fn default_jwt_algorithm() -> String {
+    "HS256".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// Failed auth attempts allowed per IP within the window
+    pub max_attempts: u32,
+    pub window_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            window_secs: 60,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DockerConfig {
     pub socket_path: String,
+    /// Remove orphaned lightd containers and networks when the daemon starts
+    #[serde(default)]
+    pub reconcile_on_startup: bool,
+    /// Caps for the container that runs install scripts
+    #[serde(default)]
+    pub install_limits: InstallLimitsConfig,
+    /// json-file log rotation for every container, unless the container overrides it
+    #[serde(default)]
+    pub log_rotation: crate::container::state::LogRotation,
+    /// `uid` or `uid:gid` servers run as unless the container sets its own,
+    /// unset runs them as root. Installs always run as root.
+    #[serde(default)]
+    pub container_user: Option<String>,
+    /// How often an install interrupted by a daemon restart is run again
+    /// before the container is marked failed, 0 fails it right away
+    #[serde(default = "default_install_resume_attempts")]
+    pub install_resume_attempts: u32,
+    /// Install jobs that pull and run at once, the rest queue in order
+    #[serde(default = "default_max_concurrent_installs")]
+    pub max_concurrent_installs: usize,
+    /// Processes and threads per container, against fork bombs. -1 is unlimited.
+    /// Linux only, like the other cgroup limits
+    #[serde(default = "default_pids_limit")]
+    pub pids_limit: i64,
+    /// `nofile` and `nproc` ulimits for every container, unless the container
+    /// overrides them. Linux only
+    #[serde(default = "default_ulimits")]
+    pub ulimits: crate::container::state::Ulimits,
+    /// Remove images no container uses every this many hours, 0 only prunes
+    /// through `POST /maintenance/prune-images`
+    #[serde(default)]
+    pub image_prune_interval_hours: u64,
+    /// Retries for image pulls that fail with a transient registry error
+    #[serde(default)]
+    pub pull_retry: PullRetryConfig,
+}
+
+fn default_install_resume_attempts() -> u32 {
+    1
+}
+
+fn default_max_concurrent_installs() -> usize {
+    4
+}
+
+fn default_pids_limit() -> i64 {
+    1024
+}
+
+fn default_ulimits() -> crate::container::state::Ulimits {
+    crate::container::state::Ulimits {
+        nofile: Some(65536),
+        nproc: None,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstallLimitsConfig {
+    pub memory_mb: u64,
+    /// CPU cores, lowered to the host's core count if it has fewer
+    pub cpus: f64,
+}
+
+impl Default for InstallLimitsConfig {
+    fn default() -> Self {
+        Self {
+            memory_mb: 4096,
+            cpus: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PullRetryConfig {
+    /// Total attempts per pull, including the first one
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for PullRetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay_ms: 2_000,
+            max_delay_ms: 30_000,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -51,6 +493,30 @@ pub struct StorageConfig {
     pub base_path: String,
     pub containers_path: String,
     pub volumes_path: String,
+    /// Abort archive extraction past this multiple of the archive size
+    #[serde(default = "default_max_decompression_ratio")]
+    pub max_decompression_ratio: u64,
+    /// Trashed files are purged after this many hours, for volumes with the trash enabled
+    #[serde(default = "default_trash_retention_hours")]
+    pub trash_retention_hours: u64,
+    /// Quota for volumes created without a size, 0 makes them unlimited
+    #[serde(default = "default_volume_quota_mb")]
+    pub default_volume_quota_mb: u64,
+    /// Host directories custom mounts may come from, empty refuses custom mounts
+    #[serde(default)]
+    pub mount_allowlist: Vec<String>,
+}
+
+fn default_max_decompression_ratio() -> u64 {
+    crate::filesystem::handler::DEFAULT_MAX_DECOMPRESSION_RATIO
+}
+
+fn default_trash_retention_hours() -> u64 {
+    crate::filesystem::trash::DEFAULT_RETENTION_SECS / 3600
+}
+
+fn default_volume_quota_mb() -> u64 {
+    crate::filesystem::quota::DEFAULT_QUOTA_MB
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -68,14 +534,368 @@ pub struct BillingConfig {
     pub egress_per_gb: f64,
 }
 
+/// Every problem found while validating a config, reported together
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "config.json has {} problem(s):", self.problems.len())?;
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
     pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        // serde_json errors carry line/column, keep them but say which file
+        let config: Config = serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid {}: {}", path, e))?;
         Ok(config)
     }
 
+    /// Check values serde can't, so bad settings fail at startup with the field name
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if !is_listen_host(&self.server.host) {
+            problems.push(format!("server.host: '{}' is not an IP address or hostname", self.server.host));
+        }
+        if self.server.port == 0 {
+            problems.push("server.port: must be between 1 and 65535".to_string());
+        }
+        if self.server.idempotency_ttl_secs == 0 {
+            problems.push("server.idempotency_ttl_secs: must be greater than 0".to_string());
+        }
+        if let Some(cors) = &self.server.cors {
+            cors.validate(&mut problems);
+        }
+        let limits = &self.server.limits;
+        for (field, value) in [
+            ("request_timeout_secs", limits.request_timeout_secs),
+            ("max_body_bytes", limits.max_body_bytes as u64),
+            ("transfer_timeout_secs", limits.transfer_timeout_secs),
+            ("transfer_max_body_bytes", limits.transfer_max_body_bytes as u64),
+        ] {
+            if value == 0 {
+                problems.push(format!("server.limits.{}: must be greater than 0", field));
+            }
+        }
+        let websocket = &self.server.websocket;
+        for (field, value) in [
+            ("buffer_size", websocket.buffer_size as u64),
+            ("max_lags", websocket.max_lags as u64),
+            ("lag_window_secs", websocket.lag_window_secs),
+        ] {
+            if value == 0 {
+                problems.push(format!("server.websocket.{}: must be greater than 0", field));
+            }
+        }
+
+        if self.authorization.enabled && self.authorization.token.trim().is_empty() {
+            problems.push("authorization.token: must not be empty when authorization is enabled".to_string());
+        }
+        if self.authorization.rate_limit.max_attempts == 0 {
+            problems.push("authorization.rate_limit.max_attempts: must be at least 1".to_string());
+        }
+        if self.authorization.rate_limit.window_secs == 0 {
+            problems.push("authorization.rate_limit.window_secs: must be greater than 0".to_string());
+        }
+        if let Some(jwt) = &self.authorization.jwt {
+            match jwt.algorithm.as_str() {
+                "HS256" | "HS384" | "HS512" => {
+                    if jwt.secret.as_deref().is_none_or(|s| s.trim().is_empty()) {
+                        problems.push(format!("authorization.jwt.secret: required for {}", jwt.algorithm));
+                    }
+                }
+                "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512" | "ES256" | "ES384" | "EdDSA" => {
+                    match &jwt.public_key_path {
+                        Some(path) if std::path::Path::new(path).is_file() => {}
+                        Some(path) => problems.push(format!("authorization.jwt.public_key_path: {} does not exist", path)),
+                        None => problems.push(format!("authorization.jwt.public_key_path: required for {}", jwt.algorithm)),
+                    }
+                }
+                other => problems.push(format!("authorization.jwt.algorithm: '{}' is not supported", other)),
+            }
+        }
+
+        if self.docker.socket_path.trim().is_empty() {
+            problems.push("docker.socket_path: must not be empty".to_string());
+        }
+        if self.docker.install_limits.memory_mb < 4 {
+            problems.push("docker.install_limits.memory_mb: must be at least 4".to_string());
+        }
+        if self.docker.install_limits.cpus < 0.01 {
+            problems.push("docker.install_limits.cpus: must be at least 0.01".to_string());
+        }
+        if self.docker.max_concurrent_installs == 0 {
+            problems.push("docker.max_concurrent_installs: must be at least 1".to_string());
+        }
+        if let Err(e) = self.docker.log_rotation.validate() {
+            problems.push(format!("docker.log_rotation: {}", e));
+        }
+        if let Some(Err(e)) = self.docker.container_user.as_deref().map(crate::container::state::parse_run_as) {
+            problems.push(format!("docker.container_user: {}", e));
+        }
+        if let Err(e) = crate::container::state::validate_pids_limit(self.docker.pids_limit) {
+            problems.push(format!("docker.pids_limit: {}", e));
+        }
+        if let Err(e) = self.docker.ulimits.validate() {
+            problems.push(format!("docker.ulimits: {}", e));
+        }
+        if self.docker.pull_retry.max_attempts == 0 {
+            problems.push("docker.pull_retry.max_attempts: must be at least 1".to_string());
+        }
+        if self.docker.pull_retry.initial_delay_ms > self.docker.pull_retry.max_delay_ms {
+            problems.push("docker.pull_retry.initial_delay_ms: must not exceed docker.pull_retry.max_delay_ms".to_string());
+        }
+
+        for (field, path) in [
+            ("storage.base_path", &self.storage.base_path),
+            ("storage.containers_path", &self.storage.containers_path),
+            ("storage.volumes_path", &self.storage.volumes_path),
+        ] {
+            if let Err(problem) = check_dir_usable(path) {
+                problems.push(format!("{}: {}", field, problem));
+            }
+        }
+
+        if self.storage.max_decompression_ratio == 0 {
+            problems.push("storage.max_decompression_ratio: must be at least 1".to_string());
+        }
+        if self.storage.trash_retention_hours == 0 {
+            problems.push("storage.trash_retention_hours: must be at least 1".to_string());
+        }
+        for prefix in &self.storage.mount_allowlist {
+            let path = std::path::Path::new(prefix);
+            let plain = path.components().all(|c| matches!(c, std::path::Component::RootDir | std::path::Component::Normal(_)));
+            if !path.is_absolute() || path.parent().is_none() || !plain {
+                problems.push(format!("storage.mount_allowlist: '{}' must be an absolute path below /", prefix));
+            }
+        }
+        if self.monitoring.interval_ms == 0 {
+            problems.push("monitoring.interval_ms: must be greater than 0".to_string());
+        }
+        let billing = &self.monitoring.billing;
+        for (field, rate) in [
+            ("memory_per_gb_hour", billing.memory_per_gb_hour),
+            ("cpu_per_vcpu_hour", billing.cpu_per_vcpu_hour),
+            ("storage_per_gb_hour", billing.storage_per_gb_hour),
+            ("egress_per_gb", billing.egress_per_gb),
+        ] {
+            if !rate.is_finite() || rate < 0.0 {
+                problems.push(format!("monitoring.billing.{}: must be a non-negative number, got {}", field, rate));
+            }
+        }
+
+        if let Some(remote) = &self.remote {
+            if remote.enabled {
+                if !remote.url.starts_with("http://") && !remote.url.starts_with("https://") {
+                    problems.push(format!("remote.url: '{}' must start with http:// or https://", remote.url));
+                }
+                if remote.token.trim().is_empty() {
+                    problems.push("remote.token: must not be empty when remote sync is enabled".to_string());
+                }
+            }
+            if remote.retry.max_attempts == 0 {
+                problems.push("remote.retry.max_attempts: must be at least 1".to_string());
+            }
+            if remote.retry.initial_delay_ms > remote.retry.max_delay_ms {
+                problems.push("remote.retry.initial_delay_ms: must not exceed remote.retry.max_delay_ms".to_string());
+            }
+            if let Some(Err(e)) = remote.tls.as_ref().filter(|_| remote.enabled).map(crate::remote::client::RemoteTls::load) {
+                problems.push(format!("remote.tls: {}", e));
+            }
+            if remote.billing_batch_size == 0 {
+                problems.push("remote.billing_batch_size: must be at least 1".to_string());
+            }
+        }
+
+        if let Some(sftp) = &self.sftp
+            && sftp.enabled {
+            if !is_listen_host(&sftp.host) {
+                problems.push(format!("sftp.host: '{}' is not an IP address or hostname", sftp.host));
+            }
+            if sftp.max_open_handles == 0 {
+                problems.push("sftp.max_open_handles: must be at least 1".to_string());
+            }
+            if sftp.handle_idle_timeout_secs == 0 {
+                problems.push("sftp.handle_idle_timeout_secs: must be greater than 0".to_string());
+            }
+            if sftp.max_sessions == 0 {
+                problems.push("sftp.max_sessions: must be at least 1".to_string());
+            }
+            if sftp.max_sessions_per_ip == 0 {
+                problems.push("sftp.max_sessions_per_ip: must be at least 1".to_string());
+            }
+            if sftp.max_failed_logins == 0 {
+                problems.push("sftp.max_failed_logins: must be at least 1".to_string());
+            }
+            if sftp.failed_login_window_secs == 0 {
+                problems.push("sftp.failed_login_window_secs: must be greater than 0".to_string());
+            }
+            if sftp.port == 0 {
+                problems.push("sftp.port: must be between 1 and 65535".to_string());
+            } else if sftp.port == self.server.port {
+                problems.push(format!("sftp.port: {} is already used by server.port", sftp.port));
+            }
+        }
+
+        for list in &self.network.blocklists {
+            if let Err(e) = crate::network::blocklist::validate_name(&list.name) {
+                problems.push(format!("network.blocklists: {}", e));
+            }
+            if let Err(e) = list.load_cidrs() {
+                problems.push(format!("network.blocklists.{}: {}", list.name, e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
     pub fn get_version(&self) -> &str {
         &self.version
     }
 }
+
+/// An address to listen on, an IP address or a hostname resolved when binding
+fn is_listen_host(host: &str) -> bool {
+    if host.parse::<std::net::IpAddr>().is_ok() {
+        return true;
+    }
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// A storage path is usable if it is a directory, or could be created under a writable one
+fn check_dir_usable(path: &str) -> Result<(), String> {
+    if path.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+
+    let path = std::path::Path::new(path);
+    if path.exists() {
+        return if path.is_dir() {
+            Ok(())
+        } else {
+            Err(format!("{} exists but is not a directory", path.display()))
+        };
+    }
+
+    // Find the closest existing parent, that's where create_dir_all would start
+    match path.ancestors().skip(1).find(|p| p.exists()) {
+        Some(parent) if !parent.is_dir() => {
+            Err(format!("cannot create {}, {} is not a directory", path.display(), parent.display()))
+        }
+        Some(parent) => match fs::metadata(parent) {
+            Ok(meta) if meta.permissions().readonly() => {
+                Err(format!("cannot create {}, {} is read-only", path.display(), parent.display()))
+            }
+            _ => Ok(()),
+        },
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Config {
+        let base = std::env::temp_dir().join("lightd-config-test");
+        let base = base.to_str().unwrap();
+        serde_json::from_value(serde_json::json!({
+            "version": "0.2.1",
+            "server": { "host": "0.0.0.0", "port": 8070 },
+            "authorization": { "enabled": true, "token": "lightd_abc" },
+            "docker": { "socket_path": "/var/run/docker.sock" },
+            "storage": {
+                "base_path": base,
+                "containers_path": format!("{}/containers", base),
+                "volumes_path": format!("{}/volumes", base)
+            },
+            "monitoring": {
+                "enabled": true,
+                "interval_ms": 1000,
+                "billing": {
+                    "memory_per_gb_hour": 0.01,
+                    "cpu_per_vcpu_hour": 0.02,
+                    "storage_per_gb_hour": 0.0001,
+                    "egress_per_gb": 0.05
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_valid_config_passes() {
+        assert!(sample().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_every_problem() {
+        let mut config = sample();
+        config.server.port = 0;
+        config.storage.volumes_path = String::new();
+        config.monitoring.interval_ms = 0;
+        config.monitoring.billing.egress_per_gb = -1.0;
+
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 4);
+        assert!(err.problems.iter().any(|p| p.starts_with("server.port")));
+        assert!(err.problems.iter().any(|p| p.starts_with("storage.volumes_path")));
+        assert!(err.problems.iter().any(|p| p.starts_with("monitoring.interval_ms")));
+        assert!(err.problems.iter().any(|p| p.starts_with("monitoring.billing.egress_per_gb")));
+    }
+
+    #[test]
+    fn test_server_host_accepts_hostnames() {
+        let mut config = sample();
+        for host in ["0.0.0.0", "::", "localhost", "node-1.example.com"] {
+            config.server.host = host.to_string();
+            assert!(config.validate().is_ok(), "{} should be accepted", host);
+        }
+
+        config.server.host = "not a host".to_string();
+        let err = config.validate().unwrap_err();
+        assert!(err.problems[0].starts_with("server.host"));
+    }
+
+    #[test]
+    fn test_cors_validation() {
+        let mut config = sample();
+        config.server.cors = Some(serde_json::from_value(serde_json::json!({
+            "allowed_origins": ["https://panel.example.com", "http://localhost:3000"]
+        })).unwrap());
+        assert!(config.validate().is_ok());
+
+        config.server.cors = Some(serde_json::from_value(serde_json::json!({
+            "allowed_origins": ["*", "https://panel.example.com/"],
+            "allow_credentials": true
+        })).unwrap());
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.problems.len(), 2);
+        assert!(err.problems.iter().all(|p| p.starts_with("server.cors.allowed_origins")));
+    }
+}
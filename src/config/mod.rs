@@ -1 +1,3 @@
-pub mod config;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod config;
+pub mod reload;
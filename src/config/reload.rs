@@ -0,0 +1,185 @@
+//! Live config reloading
+//!
+//! Watches config.json and applies the settings that are safe to change while
+//! running (billing rates, monitoring interval). Anything else that changed is
+//! logged as needing a restart.
+
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use super::config::Config;
+use crate::billing::tracker::{BillingRates, BillingTracker};
+
+/// Fields that were changed in the file, split by whether they took effect
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadSummary {
+    pub applied: Vec<String>,
+    pub restart_required: Vec<String>,
+}
+
+pub struct ConfigReloader {
+    path: PathBuf,
+    current: RwLock<Config>,
+    billing_tracker: Arc<BillingTracker>,
+}
+
+impl ConfigReloader {
+    pub fn new(path: impl Into<PathBuf>, config: Config, billing_tracker: Arc<BillingTracker>) -> Self {
+        Self {
+            path: path.into(),
+            current: RwLock::new(config),
+            billing_tracker,
+        }
+    }
+
+    /// Re-read the config file and apply what can be applied live
+    pub async fn reload(&self) -> Result<ReloadSummary, Box<dyn std::error::Error + Send + Sync>> {
+        let path = self.path.to_string_lossy().to_string();
+        let new_config = Config::load(&path).map_err(|e| format!("Failed to load config: {}", e))?;
+        new_config.validate()?;
+
+        let mut current = self.current.write().await;
+
+        let mut changed = Vec::new();
+        diff_fields("", &serde_json::to_value(&*current)?, &serde_json::to_value(&new_config)?, &mut changed);
+
+        let mut summary = ReloadSummary::default();
+        let mut rates_changed = false;
+
+        for field in changed {
+            if field.starts_with("monitoring.billing.") {
+                rates_changed = true;
+                summary.applied.push(field);
+            } else if field == "monitoring.interval_ms" {
+                self.billing_tracker.set_interval(new_config.monitoring.interval_ms);
+                summary.applied.push(field);
+            } else {
+                summary.restart_required.push(field);
+            }
+        }
+
+        if rates_changed {
+            let billing = &new_config.monitoring.billing;
+            self.billing_tracker.update_rates(BillingRates {
+                memory_per_gb_hour: billing.memory_per_gb_hour,
+                cpu_per_vcpu_hour: billing.cpu_per_vcpu_hour,
+                storage_per_gb_hour: billing.storage_per_gb_hour,
+                egress_per_gb: billing.egress_per_gb,
+            }).await;
+        }
+
+        if summary.applied.is_empty() && summary.restart_required.is_empty() {
+            tracing::debug!("Config reloaded, nothing changed");
+        }
+        if !summary.applied.is_empty() {
+            tracing::info!("Config reload applied: {}", summary.applied.join(", "));
+        }
+        if !summary.restart_required.is_empty() {
+            tracing::warn!(
+                "Config reload: restart required for {}",
+                summary.restart_required.join(", ")
+            );
+        }
+
+        *current = new_config;
+        Ok(summary)
+    }
+
+    /// Reload whenever the config file changes on disk
+    pub fn watch(self: Arc<Self>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let file_name = self.path.file_name()
+            .ok_or("Config path has no file name")?
+            .to_os_string();
+        // Watch the directory, editors often replace the file instead of writing in place
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                let touches_config = event.paths.iter()
+                    .any(|p| p.file_name() == Some(file_name.as_os_str()));
+                if touches_config && (event.kind.is_modify() || event.kind.is_create()) {
+                    let _ = tx.send(());
+                }
+            }
+        })?;
+        watcher.watch(Path::new(&dir), RecursiveMode::NonRecursive)?;
+
+        tracing::info!("Watching {} for changes", self.path.display());
+
+        tokio::spawn(async move {
+            // Dropping the watcher stops it, so keep it alive with the task
+            let _watcher = watcher;
+
+            while rx.recv().await.is_some() {
+                // A save can fire several events, let it settle first
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                while rx.try_recv().is_ok() {}
+
+                if let Err(e) = self.reload().await {
+                    tracing::error!("Config reload failed, keeping current settings: {}", e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Collect dotted paths of every leaf that differs between two JSON values
+fn diff_fields(prefix: &str, old: &Value, new: &Value, out: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                diff_fields(
+                    &path,
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        _ if old != new => out.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_fields_reports_changed_leaves() {
+        let old = json!({
+            "monitoring": { "interval_ms": 1000, "billing": { "egress_per_gb": 0.05 } },
+            "server": { "port": 8070 }
+        });
+        let new = json!({
+            "monitoring": { "interval_ms": 2000, "billing": { "egress_per_gb": 0.05 } },
+            "server": { "port": 8071 }
+        });
+
+        let mut changed = Vec::new();
+        diff_fields("", &old, &new, &mut changed);
+
+        assert_eq!(changed, vec!["monitoring.interval_ms", "server.port"]);
+    }
+}
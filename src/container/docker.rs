@@ -0,0 +1,69 @@
+//! Shared Docker client
+//!
+//! Every component gets a clone of one client. bollard keeps the API version
+//! behind an `Arc`, so once `LifecycleManager::check_docker` has negotiated it
+//! at startup all clones talk the version the daemon understands, instead of
+//! failing deep inside an operation with "client version is too new".
+
+use bollard::system::Version;
+use bollard::Docker;
+use std::sync::OnceLock;
+
+/// Oldest Engine API lightd works with
+pub const MIN_API_VERSION: (usize, usize) = (1, 41);
+
+/// Docker release that shipped `MIN_API_VERSION`, for error messages
+const MIN_DOCKER_VERSION: &str = "20.10";
+
+static CLIENT: OnceLock<Docker> = OnceLock::new();
+
+/// Clone of the shared client, connecting on first use
+pub fn client() -> Result<Docker, bollard::errors::Error> {
+    if let Some(docker) = CLIENT.get() {
+        return Ok(docker.clone());
+    }
+    let docker = Docker::connect_with_local_defaults()?;
+    Ok(CLIENT.get_or_init(|| docker).clone())
+}
+
+/// Reject daemons older than `MIN_API_VERSION`, naming the version found
+pub fn check_version(version: &Version) -> Result<(), String> {
+    let docker_version = version.version.as_deref().unwrap_or("unknown");
+    let api_version = version.api_version.as_deref().unwrap_or("unknown");
+
+    let parsed = api_version
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse::<usize>().ok()?, minor.parse::<usize>().ok()?)));
+    match parsed {
+        Some(api) if api >= MIN_API_VERSION => Ok(()),
+        _ => Err(format!(
+            "Docker {} (API {}) is not supported, lightd needs Docker {} (API {}.{}) or newer",
+            docker_version, api_version, MIN_DOCKER_VERSION, MIN_API_VERSION.0, MIN_API_VERSION.1
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(docker: &str, api: &str) -> Version {
+        Version {
+            version: Some(docker.to_string()),
+            api_version: Some(api.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version(&version("20.10.24", "1.41")).is_ok());
+        assert!(check_version(&version("27.3.1", "1.47")).is_ok());
+
+        let err = check_version(&version("19.03.12", "1.40")).unwrap_err();
+        assert!(err.contains("Docker 19.03.12 (API 1.40)"), "{}", err);
+        assert!(err.contains("Docker 20.10 (API 1.41)"), "{}", err);
+
+        assert!(check_version(&Version::default()).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Container export archives for moving a container to another node
+//!
+//! An export is a plain tar holding `manifest.json` (the container state) and
+//! `volume.tar.gz`, the volume packed the same way as a backup. Host ports and
+//! the Docker ID only mean something on the source node, an import allocates
+//! fresh ports and installs a new Docker container.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::state::ContainerState;
+use crate::filesystem::backup::{restore_archive, write_archive};
+use crate::filesystem::security::is_safe_archive_path;
+
+/// Bumped when the archive layout changes in a way older nodes can't read
+pub const EXPORT_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const VOLUME_ENTRY: &str = "volume.tar.gz";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub version: u32,
+    pub exported_at: u64,
+    pub state: ContainerState,
+    /// Quota of the volume on the source node, None for unlimited or older exports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume_quota_mb: Option<u64>,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// IDs end up in host paths and Docker names, so they must be one plain path component
+fn is_plain_id(id: &str) -> bool {
+    let path = Path::new(id);
+    is_safe_archive_path(path) && path.components().count() == 1 && id != "."
+}
+
+/// Bundle the container state and its volume into `output` (blocking)
+pub fn write_export(state: &ContainerState, volume: &Path, volume_quota_mb: Option<u64>, output: &Path) -> Result<(), String> {
+    if !volume.is_dir() {
+        return Err(format!("Volume {} not found on this node", state.volume_id));
+    }
+
+    let manifest = ExportManifest {
+        version: EXPORT_VERSION,
+        exported_at: now(),
+        state: state.clone(),
+        volume_quota_mb,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+
+    let volume_archive = output.with_extension("volume.tar.gz");
+    write_archive(volume, &volume_archive, |_| {})?;
+
+    let result = (|| -> std::io::Result<()> {
+        let mut tar = tar::Builder::new(File::create(output)?);
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now());
+        header.set_cksum();
+        tar.append_data(&mut header, MANIFEST_ENTRY, manifest.as_slice())?;
+        tar.append_path_with_name(&volume_archive, VOLUME_ENTRY)?;
+
+        tar.into_inner()?;
+        Ok(())
+    })();
+
+    let _ = std::fs::remove_file(&volume_archive);
+    result.map_err(|e| e.to_string())
+}
+
+/// Read and check the manifest of an export without unpacking anything (blocking)
+pub fn read_manifest(archive: &Path) -> Result<ExportManifest, String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let mut tar = tar::Archive::new(file);
+
+    for entry in tar.entries().map_err(|e| format!("Not an export archive: {}", e))? {
+        let mut entry = entry.map_err(|e| format!("Not an export archive: {}", e))?;
+        if entry.path().map_err(|e| e.to_string())?.as_os_str() != MANIFEST_ENTRY {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        let manifest: ExportManifest = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Invalid export manifest: {}", e))?;
+
+        if manifest.version > EXPORT_VERSION {
+            return Err(format!(
+                "Export version {} is newer than this node supports ({})",
+                manifest.version, EXPORT_VERSION
+            ));
+        }
+        if !is_plain_id(&manifest.state.internal_id) || !is_plain_id(&manifest.state.volume_id) {
+            return Err("Export manifest contains an invalid internal_id or volume_id".to_string());
+        }
+
+        return Ok(manifest);
+    }
+
+    Err(format!("Export archive has no {}", MANIFEST_ENTRY))
+}
+
+/// Unpack the volume of an export into `volume` (blocking)
+pub fn unpack_volume(archive: &Path, volume: &Path) -> Result<(), String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let mut tar = tar::Archive::new(file);
+    let volume_archive = archive.with_extension("volume.tar.gz");
+
+    let mut found = false;
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        if entry.path().map_err(|e| e.to_string())?.as_os_str() == VOLUME_ENTRY {
+            let mut out = File::create(&volume_archive).map_err(|e| e.to_string())?;
+            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+            found = true;
+            break;
+        }
+    }
+    if !found {
+        return Err(format!("Export archive has no {}", VOLUME_ENTRY));
+    }
+
+    // restore_archive validates every entry before extracting
+    let result = std::fs::create_dir_all(volume)
+        .map_err(|e| e.to_string())
+        .and_then(|_| restore_archive(&volume_archive, volume, |_| {}));
+
+    let _ = std::fs::remove_file(&volume_archive);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_round_trip() {
+        let base = std::env::temp_dir().join(format!("lightd-export-{}", uuid::Uuid::new_v4()));
+        let volume = base.join("volume");
+        std::fs::create_dir_all(volume.join("world")).unwrap();
+        std::fs::write(volume.join("world/level.dat"), b"level").unwrap();
+
+        let state = ContainerState::new("abc123".to_string(), "vol1".to_string(), "./start.sh".to_string());
+        let archive = base.join("abc123.tar");
+        write_export(&state, &volume, Some(512), &archive).unwrap();
+
+        let manifest = read_manifest(&archive).unwrap();
+        assert_eq!(manifest.version, EXPORT_VERSION);
+        assert_eq!(manifest.state.internal_id, "abc123");
+        assert_eq!(manifest.volume_quota_mb, Some(512));
+
+        let target = base.join("imported");
+        unpack_volume(&archive, &target).unwrap();
+        assert_eq!(std::fs::read(target.join("world/level.dat")).unwrap(), b"level");
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_plain_id() {
+        assert!(is_plain_id("abc123"));
+        assert!(!is_plain_id("../etc"));
+        assert!(!is_plain_id("a/b"));
+        assert!(!is_plain_id(""));
+    }
+}
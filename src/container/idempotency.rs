@@ -0,0 +1,198 @@
+//! Idempotency keys for container creation
+//!
+//! A retried `POST /containers` carrying the same `Idempotency-Key` gets the
+//! stored response of the first attempt instead of creating the container
+//! again. The key is claimed before the create starts, so a retry that races
+//! the original sees it as in progress rather than running twice. A claim
+//! that is never completed, because the create failed or the request was
+//! dropped, is released again by its `ClaimGuard`.
+//!
+//! Generated secrets are not stored, a replay answers without them.
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdempotencyRecord {
+    internal_id: String,
+    /// None while the first request is still running
+    response: Option<StoredResponse>,
+    created_at: u64,
+}
+
+pub enum IdempotencyClaim {
+    /// First use of the key, go ahead and create
+    New,
+    /// The first request finished, answer with its response
+    Replay(StoredResponse),
+    /// The first request is still running
+    InProgress,
+    /// The key was already used to create this other container
+    Mismatch(String),
+}
+
+pub struct IdempotencyStore {
+    db: Arc<Db>,
+    ttl_secs: u64,
+}
+
+impl IdempotencyStore {
+    pub fn new(db_path: &str, ttl_secs: u64) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db: Arc::new(db), ttl_secs })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn is_expired(&self, record: &IdempotencyRecord, now: u64) -> bool {
+        now.saturating_sub(record.created_at) >= self.ttl_secs
+    }
+
+    /// Claim `key` for creating `internal_id`, or report what happened to it before
+    pub fn claim(&self, key: &str, internal_id: &str) -> Result<IdempotencyClaim, Box<dyn std::error::Error + Send + Sync>> {
+        let now = Self::now();
+        let claimed = serde_json::to_vec(&IdempotencyRecord {
+            internal_id: internal_id.to_string(),
+            response: None,
+            created_at: now,
+        })?;
+
+        loop {
+            let current = self.db.get(key.as_bytes())?;
+            if let Some(data) = &current {
+                let record: IdempotencyRecord = serde_json::from_slice(data)?;
+                if !self.is_expired(&record, now) {
+                    if record.internal_id != internal_id {
+                        return Ok(IdempotencyClaim::Mismatch(record.internal_id));
+                    }
+                    return Ok(match record.response {
+                        Some(response) => IdempotencyClaim::Replay(response),
+                        None => IdempotencyClaim::InProgress,
+                    });
+                }
+            }
+
+            // Another request may have claimed the key since the read
+            if self.db.compare_and_swap(key.as_bytes(), current, Some(claimed.clone()))?.is_ok() {
+                self.prune(now)?;
+                return Ok(IdempotencyClaim::New);
+            }
+        }
+    }
+
+    /// Store the response of a successful create under its key, without the SFTP password
+    pub fn complete(&self, key: &str, mut response: StoredResponse) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(data) = self.db.get(key.as_bytes())? else {
+            return Ok(());
+        };
+        if let Some(sftp) = response.body.get_mut("sftp").and_then(|s| s.as_object_mut()) {
+            sftp.remove("password");
+        }
+        let mut record: IdempotencyRecord = serde_json::from_slice(&data)?;
+        record.response = Some(response);
+        self.db.insert(key.as_bytes(), serde_json::to_vec(&record)?)?;
+        Ok(())
+    }
+
+    /// Forget a key whose create failed, so a retry runs again
+    pub fn release(&self, key: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.remove(key.as_bytes())?;
+        Ok(())
+    }
+
+    /// Release `key` on drop unless the create completed
+    pub fn guard(self: &Arc<Self>, key: &str) -> ClaimGuard {
+        ClaimGuard { store: self.clone(), key: Some(key.to_string()) }
+    }
+
+    fn prune(&self, now: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            let expired = serde_json::from_slice::<IdempotencyRecord>(&value)
+                .map(|record| self.is_expired(&record, now))
+                .unwrap_or(true);
+            if expired {
+                self.db.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A claimed key, released when dropped before `complete`. Covers failed
+/// creates as well as requests dropped by a timeout or a disconnected client.
+pub struct ClaimGuard {
+    store: Arc<IdempotencyStore>,
+    key: Option<String>,
+}
+
+impl ClaimGuard {
+    pub fn complete(mut self, response: StoredResponse) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key = self.key.take().unwrap_or_default();
+        self.store.complete(&key, response)
+    }
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take()
+            && let Err(e) = self.store.release(&key) {
+            tracing::error!("Failed to release Idempotency-Key {}: {}", key, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_replay_and_release() {
+        let path = std::env::temp_dir().join(format!("lightd-idempotency-{}", uuid::Uuid::new_v4()));
+        let store = IdempotencyStore::new(path.to_str().unwrap(), 3600).unwrap();
+
+        assert!(matches!(store.claim("key-1", "abc").unwrap(), IdempotencyClaim::New));
+        assert!(matches!(store.claim("key-1", "abc").unwrap(), IdempotencyClaim::InProgress));
+        assert!(matches!(store.claim("key-1", "other").unwrap(), IdempotencyClaim::Mismatch(id) if id == "abc"));
+
+        let body = serde_json::json!({"internal_id": "abc", "sftp": {"username": "abc", "password": "secret"}});
+        store.complete("key-1", StoredResponse { status: 200, body }).unwrap();
+        match store.claim("key-1", "abc").unwrap() {
+            IdempotencyClaim::Replay(response) => {
+                assert_eq!(response.body["internal_id"], "abc");
+                assert_eq!(response.body["sftp"], serde_json::json!({"username": "abc"}));
+            }
+            _ => panic!("expected a replay"),
+        }
+
+        store.release("key-1").unwrap();
+        assert!(matches!(store.claim("key-1", "abc").unwrap(), IdempotencyClaim::New));
+
+        // A dropped request gives the key back
+        let store = Arc::new(store);
+        drop(store.guard("key-1"));
+        assert!(matches!(store.claim("key-1", "abc").unwrap(), IdempotencyClaim::New));
+        let store = Arc::into_inner(store).unwrap();
+
+        drop(store);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
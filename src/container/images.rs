@@ -0,0 +1,202 @@
+//! Pruning of Docker images no lightd container uses
+//!
+//! Reinstalls and image changes leave the old images behind, and they take
+//! node disk outside every volume quota. An image is kept when a container in
+//! the database names it or any Docker container, lightd's or not, was created
+//! from it. Nothing is pruned while an install runs, its image may be pulled
+//! but not used by a container yet. Images of running port rebinds and image
+//! changes are kept for the same reason.
+
+use bollard::container::ListContainersOptions;
+use bollard::image::{ListImagesOptions, RemoveImageOptions};
+use bollard::Docker;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::manager::ContainerManager;
+use super::network::NetworkRebinder;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PrunedImage {
+    pub id: String,
+    /// Empty for dangling images
+    pub tags: Vec<String>,
+    pub size: i64,
+}
+
+/// What a prune run found, and removed unless it was a dry run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    pub images: Vec<PrunedImage>,
+    /// Sum of the image sizes. Layers shared with kept images are counted
+    /// too, so the disk actually freed can be less
+    pub reclaimable_bytes: i64,
+    /// Images that could not be removed, with the Docker error
+    pub errors: Vec<String>,
+}
+
+/// A prune was refused because an install is running
+#[derive(Debug)]
+pub struct InstallRunning(pub String);
+
+impl std::fmt::Display for InstallRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Container {} is installing, try again once it is done", self.0)
+    }
+}
+
+impl std::error::Error for InstallRunning {}
+
+/// `docker.io/library/node` and `node:latest` are the same image as `node`
+pub fn normalize_image_ref(image: &str) -> String {
+    let image = image.trim();
+    let image = image.strip_prefix("docker.io/").unwrap_or(image);
+    let image = image.strip_prefix("library/").unwrap_or(image);
+    let name = image.rsplit('/').next().unwrap_or(image);
+    if name.contains(':') || name.contains('@') {
+        image.to_string()
+    } else {
+        format!("{}:latest", image)
+    }
+}
+
+pub struct ImagePruner {
+    manager: Arc<ContainerManager>,
+    docker: Docker,
+    /// The timer and the API must not prune at the same time
+    running: Mutex<()>,
+    rebinder: Option<Arc<NetworkRebinder>>,
+}
+
+impl ImagePruner {
+    pub fn new(manager: Arc<ContainerManager>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let docker = crate::container::docker::client()?;
+        Ok(Self { manager, docker, running: Mutex::new(()), rebinder: None })
+    }
+
+    /// Keep the images of rebinds and image changes in progress
+    pub fn with_rebinder(mut self, rebinder: Arc<NetworkRebinder>) -> Self {
+        self.rebinder = Some(rebinder);
+        self
+    }
+
+    /// Find unused images, removing them unless `dry_run`
+    pub async fn prune(&self, dry_run: bool) -> Result<PruneReport, Box<dyn std::error::Error + Send + Sync>> {
+        let _running = self.running.lock().await;
+
+        let known = self.manager.list_containers().await?;
+        if let Some(installing) = known.iter().find(|c| c.is_installing) {
+            return Err(Box::new(InstallRunning(installing.internal_id.clone())));
+        }
+        let mut referenced: HashSet<String> = known
+            .iter()
+            .filter_map(|c| c.image.as_deref())
+            .map(normalize_image_ref)
+            .collect();
+
+        let containers = self.docker.list_containers(Some(ListContainersOptions::<String> {
+            all: true,
+            ..Default::default()
+        })).await?;
+        let in_use: HashSet<String> = containers.into_iter().filter_map(|c| c.image_id).collect();
+
+        let images = self.docker.list_images(Some(ListImagesOptions::<String> {
+            all: false,
+            ..Default::default()
+        })).await?;
+        // Read after listing, a job claims its container before it pulls, so
+        // every image in the list that a job pulled is covered here
+        if let Some(rebinder) = &self.rebinder {
+            referenced.extend(rebinder.images_in_use().iter().map(|image| normalize_image_ref(image)));
+        }
+
+        let mut report = PruneReport {
+            dry_run,
+            ..Default::default()
+        };
+        for image in images {
+            let tags: Vec<String> = image.repo_tags.into_iter().filter(|t| t != "<none>:<none>").collect();
+            let is_referenced = tags
+                .iter()
+                .chain(image.repo_digests.iter())
+                .any(|r| referenced.contains(&normalize_image_ref(r)));
+            if is_referenced || in_use.contains(&image.id) || image.containers > 0 {
+                continue;
+            }
+
+            if !dry_run {
+                // An image with several tags can only go by ID with force, so
+                // untag it one by one, the last one removes the image
+                let names = if tags.is_empty() { vec![image.id.clone()] } else { tags.clone() };
+                let mut failed = false;
+                for name in &names {
+                    let removed = self.docker.remove_image(name, Some(RemoveImageOptions {
+                        force: false,
+                        noprune: false,
+                    }), None).await;
+                    if let Err(e) = removed {
+                        report.errors.push(format!("image {}: {}", name, e));
+                        failed = true;
+                        break;
+                    }
+                }
+                if failed {
+                    continue;
+                }
+                tracing::info!("Removed unused image {} ({})", names.join(", "), image.id);
+            }
+
+            report.reclaimable_bytes += image.size;
+            report.images.push(PrunedImage { id: image.id, tags, size: image.size });
+        }
+
+        Ok(report)
+    }
+
+    /// Prune every `interval_secs` until shutdown, skipping runs while an install is going
+    pub fn start(self: Arc<Self>, interval_secs: u64, mut shutdown: crate::daemon::shutdown::ShutdownSignal) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+            // The first tick is immediate, don't prune right at startup
+            tick.tick().await;
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = shutdown.recv() => break,
+                }
+
+                match self.prune(false).await {
+                    Ok(report) if report.images.is_empty() && report.errors.is_empty() => {}
+                    Ok(report) => {
+                        tracing::info!(
+                            "Image prune removed {} image(s), up to {}MB",
+                            report.images.len(), report.reclaimable_bytes / 1024 / 1024
+                        );
+                        for e in &report.errors {
+                            tracing::warn!("Image prune: {}", e);
+                        }
+                    }
+                    Err(e) if e.is::<InstallRunning>() => tracing::debug!("Image prune skipped: {}", e),
+                    Err(e) => tracing::warn!("Image prune failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_image_ref() {
+        assert_eq!(normalize_image_ref("node"), "node:latest");
+        assert_eq!(normalize_image_ref("docker.io/library/node:18"), "node:18");
+        assert_eq!(normalize_image_ref("ghcr.io/pterodactyl/yolks:java_17"), "ghcr.io/pterodactyl/yolks:java_17");
+        assert_eq!(normalize_image_ref("localhost:5000/game"), "localhost:5000/game:latest");
+        assert_eq!(normalize_image_ref("node@sha256:abc"), "node@sha256:abc");
+    }
+}
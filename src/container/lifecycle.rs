@@ -1,17 +1,26 @@
 use super::manager::ContainerManager;
-use crate::config::config::Config as AppConfig;
+use super::state::{apply_process_limits, docker_env, docker_ports, parse_run_as, validate_mount, ContainerState, InstallState, LogRotation, MountPolicy, PortBinding, Ulimits};
+use crate::config::config::{Config as AppConfig, PullRetryConfig};
+use crate::error::{is_docker_not_found, LightdError};
+use crate::filesystem::owner::chown_recursive;
 
 use bollard::Docker;
 use bollard::container::{Config, CreateContainerOptions, StartContainerOptions, RemoveContainerOptions, LogsOptions};
 use bollard::models::{HostConfig, Mount, MountTypeEnum};
 use futures::StreamExt;
+use rand::Rng;
+use serde::Serialize;
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tracing::Instrument;
 
 #[allow(unused)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "args", rename_all = "snake_case")]
 pub enum LifecycleEvent {
     Started(String),
     DockerConnected,
@@ -26,8 +35,131 @@ pub enum LifecycleEvent {
     Error(String, String),
     ReinstallStarted(String),
     RemovingOldContainer(String),
+    VolumeWiped(String),
     RepairStarted(String),
     CorruptionDetected(String, String),
+    /// Install restarted after a daemon restart interrupted it, with the attempt number
+    InstallResumed(String, u32),
+    /// Install waits for a free slot, with the number of installs ahead of it
+    InstallQueued(String, usize),
+    /// Image pull failed with a transient error, with the attempt about to start
+    RetryingPull(String, u32),
+}
+
+impl LifecycleEvent {
+    /// Internal ID of the container the event is about
+    pub fn container_id(&self) -> Option<&str> {
+        match self {
+            Self::DockerConnected => None,
+            Self::Started(id)
+            | Self::PullingImage(id, _)
+            | Self::ImagePulled(id, _)
+            | Self::CreatingContainer(id)
+            | Self::ContainerCreated(id, _)
+            | Self::RunningInstallScript(id)
+            | Self::InstallScriptComplete(id, _)
+            | Self::SettingUpEntrypoint(id)
+            | Self::Ready(id)
+            | Self::Error(id, _)
+            | Self::ReinstallStarted(id)
+            | Self::RemovingOldContainer(id)
+            | Self::VolumeWiped(id)
+            | Self::RepairStarted(id)
+            | Self::CorruptionDetected(id, _)
+            | Self::InstallResumed(id, _)
+            | Self::InstallQueued(id, _)
+            | Self::RetryingPull(id, _) => Some(id),
+        }
+    }
+}
+
+/// Caps how many install jobs pull images and run at once. Tokio's semaphore
+/// is fair, so queued installs start in the order they came in.
+struct InstallQueue {
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+impl InstallQueue {
+    fn new(max_concurrent: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a slot, the install holds it until the permit is dropped
+    async fn enter(
+        &self,
+        internal_id: &str,
+        event_tx: &mpsc::UnboundedSender<LifecycleEvent>,
+    ) -> OwnedSemaphorePermit {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return permit;
+        }
+        let ahead = self.waiting.fetch_add(1, Ordering::SeqCst);
+        tracing::info!("Install of {} queued, {} install(s) ahead", internal_id, ahead);
+        let _ = event_tx.send(LifecycleEvent::InstallQueued(internal_id.to_string(), ahead));
+        let permit = self.slots.clone().acquire_owned().await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        // The semaphore is never closed
+        permit.expect("install semaphore closed")
+    }
+}
+
+/// Caps for the install script container, separate from the runtime limits
+#[derive(Debug, Clone, Copy)]
+pub struct InstallLimits {
+    /// Bytes
+    pub memory: i64,
+    pub cpus: f64,
+}
+
+impl InstallLimits {
+    fn host_config(&self) -> HostConfig {
+        let host_cpus = std::thread::available_parallelism()
+            .map(|n| n.get() as f64)
+            .unwrap_or(1.0);
+        HostConfig {
+            memory: Some(self.memory),
+            // No swap on top of the memory cap
+            memory_swap: Some(self.memory),
+            nano_cpus: Some((self.cpus.min(host_cpus) * 1_000_000_000.0) as i64),
+            ..Default::default()
+        }
+    }
+}
+
+/// The parts of `docker inspect` that matter when debugging a container
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerInspect {
+    pub status: Option<String>,
+    pub running: bool,
+    pub exit_code: Option<i64>,
+    pub oom_killed: bool,
+    /// Docker's error string, e.g. why the last start failed
+    pub error: Option<String>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub restart_count: Option<i64>,
+    pub mounts: Vec<DockerMount>,
+    /// From `HostConfig`, so also set for a stopped container
+    pub port_bindings: Vec<DockerPortBinding>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerMount {
+    pub source: Option<String>,
+    pub destination: Option<String>,
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DockerPortBinding {
+    /// e.g. "25565/tcp"
+    pub container_port: String,
+    pub host_ip: Option<String>,
+    pub host_port: Option<String>,
 }
 
 pub struct LifecycleManager {
@@ -35,13 +167,25 @@ pub struct LifecycleManager {
     docker: Docker,
     event_tx: mpsc::UnboundedSender<LifecycleEvent>,
     base_path: PathBuf,
+    install_limits: InstallLimits,
+    /// Used for containers without their own `log_rotation`
+    log_rotation: LogRotation,
+    /// Used for containers without their own `user`, None runs as root
+    container_user: Option<String>,
+    /// Used for containers without their own `pids_limit`
+    pids_limit: i64,
+    /// Used for the ulimits a container doesn't set itself
+    ulimits: Ulimits,
+    install_queue: Arc<InstallQueue>,
+    pull_retry: PullRetryConfig,
+    mount_policy: MountPolicy,
 }
 
 impl LifecycleManager {
     pub fn new(
         manager: Arc<ContainerManager>,
     ) -> Result<(Self, mpsc::UnboundedReceiver<LifecycleEvent>), Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Docker::connect_with_local_defaults()?;
+        let docker = crate::container::docker::client()?;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         
         let config = AppConfig::load("config.json")
@@ -49,6 +193,17 @@ impl LifecycleManager {
                 format!("Failed to load config: {}", e).into() 
             })?;
         let base_path = PathBuf::from(&config.storage.base_path);
+        let install_limits = InstallLimits {
+            memory: (config.docker.install_limits.memory_mb * 1024 * 1024) as i64,
+            cpus: config.docker.install_limits.cpus,
+        };
+        let log_rotation = config.docker.log_rotation.clone();
+        let container_user = config.docker.container_user.clone();
+        let pids_limit = config.docker.pids_limit;
+        let ulimits = config.docker.ulimits.clone();
+        let install_queue = Arc::new(InstallQueue::new(config.docker.max_concurrent_installs));
+        let pull_retry = config.docker.pull_retry.clone();
+        let mount_policy = MountPolicy::from_config(&config.storage);
         
         tracing::info!("Lifecycle manager initialized");
 
@@ -58,11 +213,34 @@ impl LifecycleManager {
                 docker,
                 event_tx,
                 base_path,
+                install_limits,
+                log_rotation,
+                container_user,
+                pids_limit,
+                ulimits,
+                install_queue,
+                pull_retry,
+                mount_policy,
             },
             event_rx,
         ))
     }
 
+    /// Storage root holding the volumes and containers directories
+    pub fn base_path(&self) -> &std::path::Path {
+        &self.base_path
+    }
+
+    /// Where custom mounts may come from, `storage.mount_allowlist`
+    pub fn mount_policy(&self) -> &MountPolicy {
+        &self.mount_policy
+    }
+
+    /// Host directory mounted as /home/container for a volume
+    pub fn volume_path(&self, volume_id: &str) -> PathBuf {
+        self.base_path.join("volumes").join(volume_id)
+    }
+
     /// Ensure Lightd network exists
     pub async fn ensure_network(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         Self::ensure_network_static(&self.docker).await
@@ -83,11 +261,8 @@ impl LifecycleManager {
                     return Ok(id);
                 }
             }
-            Err(e) => {
-                if !e.to_string().contains("404") && !e.to_string().contains("not found") {
-                    return Err(e.into());
-                }
-            }
+            Err(e) if is_docker_not_found(&e) => {}
+            Err(e) => return Err(e.into()),
         }
         
         // Create network
@@ -116,7 +291,7 @@ impl LifecycleManager {
         Ok(network_id)
     }
 
-    /// Verify Docker daemon is running and accessible
+    /// Verify Docker daemon is running, recent enough, and negotiate the API version
     pub async fn check_docker(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let ping_result = tokio::time::timeout(
             tokio::time::Duration::from_secs(5),
@@ -125,19 +300,33 @@ impl LifecycleManager {
         
         match ping_result {
             Ok(Ok(_)) => {
+                let version = self.docker.version().await?;
+                if let Err(error_msg) = super::docker::check_version(&version) {
+                    tracing::error!("{}", error_msg);
+                    return Err(error_msg.into());
+                }
+                // The version lives behind an Arc, every clone of the shared client picks it up
+                let docker = self.docker.clone().negotiate_version().await?;
+                let api = docker.client_version();
+                tracing::info!(
+                    "Docker {} accessible, using API {}.{}",
+                    version.version.as_deref().unwrap_or("unknown"),
+                    api.major_version,
+                    api.minor_version
+                );
+
                 let _ = self.event_tx.send(LifecycleEvent::DockerConnected);
-                tracing::info!("Docker daemon accessible");
                 Ok(())
             }
             Ok(Err(e)) => {
                 let error_msg = format!("Docker daemon not accessible: {}", e);
                 tracing::error!("{}", error_msg);
-                Err(error_msg.into())
+                Err(LightdError::DockerUnavailable(error_msg).into())
             }
             Err(_) => {
                 let error_msg = "Docker ping timeout after 5 seconds";
                 tracing::error!("{}", error_msg);
-                Err(error_msg.into())
+                Err(LightdError::DockerUnavailable(error_msg.to_string()).into())
             }
         }
     }
@@ -148,20 +337,16 @@ impl LifecycleManager {
         image: &str,
         internal_id: &str,
         event_tx: &mpsc::UnboundedSender<LifecycleEvent>,
+        retry: &PullRetryConfig,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        use bollard::image::CreateImageOptions;
-        
         // Check if image exists
         match docker.inspect_image(image).await {
             Ok(_) => {
                 tracing::debug!("Image {} already available", image);
                 return Ok(());
             }
-            Err(e) => {
-                if !e.to_string().contains("404") && !e.to_string().contains("No such image") {
-                    return Err(e.into());
-                }
-            }
+            Err(e) if is_docker_not_found(&e) => {}
+            Err(e) => return Err(e.into()),
         }
         
         // Image not found, pull it
@@ -172,29 +357,27 @@ impl LifecycleManager {
         
         tracing::info!("Pulling image: {}", image);
         
-        let options = Some(CreateImageOptions {
-            from_image: image,
-            ..Default::default()
-        });
-        
-        let mut stream = docker.create_image(options, None, None);
-        
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(info) => {
-                    if let Some(status) = info.status {
-                        tracing::info!("[{}] Image pull: {}", internal_id, status);
-                    }
-                    if let Some(progress) = info.progress {
-                        tracing::debug!("[{}] {}", internal_id, progress);
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!("Image pull failed: {}", e);
-                    tracing::error!("{}", error_msg);
-                    return Err(error_msg.into());
-                }
+        let max_attempts = retry.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            let e = match Self::pull_image(docker, image, internal_id).await {
+                Ok(()) => break,
+                Err(e) => e,
+            };
+            if !is_retryable_pull_error(&e) || attempt >= max_attempts {
+                let error_msg = format!("Image pull failed after {} attempt(s): {}", attempt, e);
+                tracing::error!("{}", error_msg);
+                return Err(error_msg.into());
             }
+
+            let delay = pull_backoff_delay(retry, attempt);
+            tracing::warn!(
+                "[{}] Pulling {} failed (attempt {}/{}), retrying in {}ms: {}",
+                internal_id, image, attempt, max_attempts, delay.as_millis(), e
+            );
+            attempt += 1;
+            let _ = event_tx.send(LifecycleEvent::RetryingPull(internal_id.to_string(), attempt));
+            tokio::time::sleep(delay).await;
         }
         
         let _ = event_tx.send(LifecycleEvent::ImagePulled(
@@ -206,6 +389,92 @@ impl LifecycleManager {
         Ok(())
     }
 
+    /// One pull attempt, the error is kept as is so it can be classified
+    async fn pull_image(docker: &Docker, image: &str, internal_id: &str) -> Result<(), bollard::errors::Error> {
+        use bollard::image::CreateImageOptions;
+
+        let options = Some(CreateImageOptions {
+            from_image: image,
+            ..Default::default()
+        });
+        let mut stream = docker.create_image(options, None, None);
+        while let Some(info) = stream.next().await {
+            let info = info?;
+            if let Some(status) = info.status {
+                tracing::info!("[{}] Image pull: {}", internal_id, status);
+            }
+            if let Some(progress) = info.progress {
+                tracing::debug!("[{}] {}", internal_id, progress);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the install script next to the entrypoint before the job starts,
+    /// so an install interrupted by a daemon restart can be run again
+    async fn save_install_script(
+        &self,
+        internal_id: &str,
+        install_script: Option<&str>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let container_data_path = self.base_path.join("containers").join(internal_id);
+        tokio::fs::create_dir_all(&container_data_path).await?;
+        let install_path = container_data_path.join("install.sh");
+        match install_script {
+            Some(script) => tokio::fs::write(&install_path, script).await?,
+            None => match tokio::fs::remove_file(&install_path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    /// Containers still `Installing` at startup lost their install job with the
+    /// previous daemon process. Each one is installed again, up to `max_resumes`
+    /// times, and marked failed after that. Returns how many were restarted.
+    pub async fn resume_interrupted_installs(&self, max_resumes: u32) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let mut resumed = 0;
+        for state in self.manager.list_containers().await? {
+            if state.install_state != InstallState::Installing {
+                continue;
+            }
+            let internal_id = state.internal_id;
+
+            let give_up = match &state.image {
+                None => Some("Install was interrupted by a daemon restart and the image is unknown, reinstall it".to_string()),
+                Some(_) if state.install_resumes >= max_resumes => Some(format!(
+                    "Install was interrupted by a daemon restart {} time(s), reinstall it",
+                    state.install_resumes + 1
+                )),
+                Some(_) => None,
+            };
+            if let Some(error) = give_up {
+                self.manager.mark_failed(&internal_id, &error).await?;
+                let _ = self.event_tx.send(LifecycleEvent::Error(internal_id, error));
+                continue;
+            }
+
+            let install_path = self.base_path.join("containers").join(&internal_id).join("install.sh");
+            let install_script = tokio::fs::read_to_string(&install_path).await.ok();
+
+            let attempt = self.manager.count_install_resume(&internal_id).await?;
+            tracing::warn!("Install of {} was interrupted, running it again (attempt {})", internal_id, attempt);
+            let _ = self.event_tx.send(LifecycleEvent::InstallResumed(internal_id.clone(), attempt));
+
+            // Leftover install and runtime containers are replaced by the job
+            let image = state.image.unwrap_or_default();
+            if let Err(e) = self.install_container(internal_id.clone(), image, install_script).await {
+                let error = format!("Failed to resume install: {}", e);
+                self.manager.mark_failed(&internal_id, &error).await?;
+                let _ = self.event_tx.send(LifecycleEvent::Error(internal_id, error));
+                continue;
+            }
+            resumed += 1;
+        }
+        Ok(resumed)
+    }
+
     pub async fn install_container(
         &self,
         internal_id: String,
@@ -214,11 +483,20 @@ impl LifecycleManager {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // First verify Docker is available
         self.check_docker().await?;
+        self.save_install_script(&internal_id, install_script.as_deref()).await?;
 
         let manager = self.manager.clone();
         let docker = self.docker.clone();
         let event_tx = self.event_tx.clone();
         let base_path = self.base_path.clone();
+        let install_limits = self.install_limits;
+        let log_rotation = self.log_rotation.clone();
+        let container_user = self.container_user.clone();
+        let pids_limit = self.pids_limit;
+        let ulimits = self.ulimits.clone();
+        let install_queue = self.install_queue.clone();
+        let pull_retry = self.pull_retry.clone();
+        let mount_policy = self.mount_policy.clone();
 
         // Spawn async non-blocking job
         tokio::spawn(async move {
@@ -230,6 +508,14 @@ impl LifecycleManager {
                 image,
                 install_script,
                 base_path,
+                install_limits,
+                log_rotation,
+                container_user,
+                pids_limit,
+                ulimits,
+                install_queue,
+                pull_retry,
+                mount_policy,
             )
             .await
             {
@@ -246,11 +532,12 @@ impl LifecycleManager {
                 
                 tracing::error!("Container installation failed for {}: {}", internal_id, error_msg);
             }
-        });
+        }.in_current_span());
 
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn install_container_job(
         manager: Arc<ContainerManager>,
         docker: Docker,
@@ -259,7 +546,17 @@ impl LifecycleManager {
         image: String,
         install_script: Option<String>,
         base_path: PathBuf,
+        install_limits: InstallLimits,
+        log_rotation: LogRotation,
+        container_user: Option<String>,
+        pids_limit: i64,
+        ulimits: Ulimits,
+        install_queue: Arc<InstallQueue>,
+        pull_retry: PullRetryConfig,
+        mount_policy: MountPolicy,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Held through the pull and the install script, released on return
+        let _slot = install_queue.enter(&internal_id, &event_tx).await;
         let _ = event_tx.send(LifecycleEvent::Started(internal_id.clone()));
 
         // Get container state
@@ -295,12 +592,16 @@ impl LifecycleManager {
             },
         ];
 
-        // Add custom mounts
+        // Add custom mounts, the same checks as when they were set since the
+        // stored state may predate them
         for (target, source) in &state.mount {
+            validate_mount(target, source)?;
+            mount_policy.check_source(source)?;
             mounts.push(Mount {
                 target: Some(target.clone()),
                 source: Some(source.clone()),
                 typ: Some(MountTypeEnum::BIND),
+                read_only: Some(state.read_only_mounts.contains(target)),
                 ..Default::default()
             });
         }
@@ -313,55 +614,36 @@ impl LifecycleManager {
             &image,
             &internal_id,
             &event_tx,
+            &pull_retry,
         ).await {
             return Err(format!("Failed to pull image: {}", e).into());
         }
 
         // Ensure Lightd network exists
-        let network_id = Self::ensure_network_static(&docker).await?;
+        Self::ensure_network_static(&docker).await?;
 
         // Create container config
-        let mut host_config = HostConfig {
-            mounts: Some(mounts.clone()),
-            network_mode: Some("lightd_network".to_string()),
-            ..Default::default()
-        };
-
-        // Apply limits
-        if let Some(memory) = state.limits.memory {
-            host_config.memory = Some(memory);
-        }
-        if let Some(cpu) = state.limits.cpu {
-            host_config.nano_cpus = Some((cpu * 1_000_000_000.0) as i64);
-        }
-
-        // Apply port bindings
-        let mut port_bindings = std::collections::HashMap::new();
-        let mut exposed_ports = std::collections::HashMap::new();
-        
+        let config = server_container_config(&state, &image, &state.ports, mounts.clone(), &RuntimeDefaults {
+            log_rotation: &log_rotation,
+            container_user: container_user.as_deref(),
+            pids_limit,
+            ulimits: &ulimits,
+        })?;
         for port_binding in &state.ports {
-            let container_port_key = format!("{}/{}", port_binding.container_port, port_binding.protocol);
-            let host_binding = bollard::models::PortBinding {
-                host_ip: Some("0.0.0.0".to_string()),
-                host_port: Some(port_binding.host_port.to_string()),
-            };
-            
-            port_bindings.insert(container_port_key.clone(), Some(vec![host_binding]));
-            exposed_ports.insert(container_port_key, std::collections::HashMap::new());
-            
-            tracing::info!("Binding container port {} to host port {} ({})", 
-                port_binding.container_port, port_binding.host_port, port_binding.protocol);
-        }
-        
-        if !port_bindings.is_empty() {
-            host_config.port_bindings = Some(port_bindings);
+            tracing::info!("Binding container port {} to host port {}:{}", 
+                port_binding.docker_key(), port_binding.host_ip, port_binding.host_port);
         }
 
+        // The install container gets the same log and process limits
+        let log_config = state.log_rotation.as_ref().unwrap_or(&log_rotation).log_config();
+        let pids_limit = state.pids_limit.unwrap_or(pids_limit);
+        let ulimits = state.ulimits.clone().unwrap_or_default().or(&ulimits);
+
         let container_name = format!("lightd-{}", internal_id);
 
         // Check if container already exists and remove it
         if let Ok(Some(_)) = docker.inspect_container(&container_name, None).await.map(Some).or_else(|e| {
-            if e.to_string().contains("404") || e.to_string().contains("No such container") {
+            if is_docker_not_found(&e) {
                 Ok(None)
             } else {
                 Err(e)
@@ -378,19 +660,10 @@ impl LifecycleManager {
         let entrypoint_path = container_data_path.join("entrypoint.sh");
         tokio::fs::write(&entrypoint_path, "#!/bin/sh\necho 'Container initializing...'\nsleep infinity\n").await?;
 
-        // For install phase, run as root. Will be recreated with lightd+ user after install
-        let container_user_config = None;  // Always run as root
-
-        let config = Config {
-            image: Some(image.clone()),
-            working_dir: Some("/home/container".to_string()),
-            host_config: Some(host_config),
-            entrypoint: Some(vec!["/bin/sh".to_string(), "/app/data/entrypoint.sh".to_string()]),
-            user: container_user_config,
-            tty: Some(true),
-            open_stdin: Some(true),
-            exposed_ports: if exposed_ports.is_empty() { None } else { Some(exposed_ports) },
-            ..Default::default()
+        // The install container stays root, only the server runs as this user
+        let run_as = match state.user.as_deref().or(container_user.as_deref()) {
+            Some(user) => Some(parse_run_as(user)?),
+            None => None,
         };
 
         let options = CreateContainerOptions {
@@ -425,13 +698,46 @@ impl LifecycleManager {
                 "#!/bin/sh\ncd /home/container\n/bin/sh /app/data/install.sh\n";
             tokio::fs::write(&entrypoint_path, install_entrypoint).await?;
 
-            // Start container for installation
-            docker.start_container(&container_id, None::<StartContainerOptions<String>>).await?;
+            // Install runs in its own container under the install limits, the
+            // runtime container created above has never been started
+            let install_name = format!("lightd-{}-install", internal_id);
+            let _ = docker.remove_container(&install_name, Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            })).await;
+            
+            // A fork bomb in the install script is no better than one in the server
+            let mut install_host_config = HostConfig {
+                mounts: Some(mounts.clone()),
+                network_mode: Some("lightd_network".to_string()),
+                log_config: Some(log_config.clone()),
+                ..install_limits.host_config()
+            };
+            apply_process_limits(&mut install_host_config, pids_limit, &ulimits);
+            let install_config = Config {
+                image: Some(image.clone()),
+                working_dir: Some("/home/container".to_string()),
+                host_config: Some(install_host_config),
+                entrypoint: Some(vec!["/bin/sh".to_string(), "/app/data/entrypoint.sh".to_string()]),
+                env: docker_env(&state.env),
+                tty: Some(true),
+                ..Default::default()
+            };
+            let install_id = docker.create_container(
+                Some(CreateContainerOptions { name: install_name.clone(), ..Default::default() }),
+                install_config,
+            ).await?.id;
+            
+            tracing::info!(
+                "Running install for {} with {}MB memory, {} CPUs",
+                internal_id, install_limits.memory / 1024 / 1024, install_limits.cpus
+            );
+            docker.start_container(&install_id, None::<StartContainerOptions<String>>).await?;
 
             // Allow logs to be streamed
             // Very effective
             let log_docker = docker.clone();
-            let log_container_id = container_id.clone();
+            let log_container_id = install_id.clone();
             let log_internal_id = internal_id.clone();
             
             tokio::spawn(async move {
@@ -446,7 +752,7 @@ impl LifecycleManager {
                     let line = format!("{}", log);
                     tracing::info!("[{}] {}", log_internal_id, line.trim());
                 }
-            });
+            }.in_current_span());
 
             // Wait for container to stop (install complete)
             // Kinda weird we have a timeout time period, maybe we can change this to get from the config
@@ -461,21 +767,20 @@ impl LifecycleManager {
                     break;
                 }
 
-                match docker.inspect_container(&container_id, None).await {
+                match docker.inspect_container(&install_id, None).await {
                     Ok(info) => {
-                        if let Some(state_info) = info.state {
-                            if state_info.running == Some(false) {
-                                let exit_code = state_info.exit_code.unwrap_or(-1);
-                                install_completed = true;
-                                tracing::info!("Install complete for {} (exit code: {})", internal_id, exit_code);
-                                
-                                let _ = event_tx.send(LifecycleEvent::InstallScriptComplete(
-                                    internal_id.clone(),
-                                    exit_code as i32,
-                                ));
-                                
-                                break;
-                            }
+                        if let Some(state_info) = info.state
+                            && state_info.running == Some(false) {
+                            let exit_code = state_info.exit_code.unwrap_or(-1);
+                            install_completed = true;
+                            tracing::info!("Install complete for {} (exit code: {})", internal_id, exit_code);
+                            
+                            let _ = event_tx.send(LifecycleEvent::InstallScriptComplete(
+                                internal_id.clone(),
+                                exit_code as i32,
+                            ));
+                            
+                            break;
                         }
                     }
                     Err(e) => {
@@ -490,12 +795,20 @@ impl LifecycleManager {
 
             if !install_completed {
                 tracing::error!("Install did not complete for {}", internal_id);
-                let _ = docker.stop_container(&container_id, None).await;
             }
 
-            // Don't remove the container - we'll reuse it for runtime
-            // Just stop it so we can update the entrypoint
-            docker.stop_container(&container_id, None).await?;
+            // Done with the install container, the runtime one takes over
+            docker.remove_container(&install_id, Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            })).await?;
+        }
+
+        // Files the install created belong to root, hand the volume to the server's user
+        if let Some((uid, gid)) = run_as.filter(|(uid, _)| *uid != 0) {
+            let path = volume_path.clone();
+            tokio::task::spawn_blocking(move || chown_recursive(&path, uid, gid)).await??;
+            tracing::info!("Volume of {} owned by {}:{}", internal_id, uid, gid);
         }
 
         // Setup final entrypoint with startup command
@@ -541,23 +854,34 @@ impl LifecycleManager {
     }
 
     /// Reinstall a container with a new install script
-    /// This will remove the existing Docker container and create a new one
+    /// This will remove the existing Docker container and create a new one.
+    /// With `wipe_volume` everything in the volume is deleted before the install runs.
     pub async fn reinstall_container(
         &self,
         internal_id: String,
         image: String,
         install_script: Option<String>,
+        wipe_volume: bool,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // First verify Docker is available
         self.check_docker().await?;
 
         // Mark as installing in DB first
         self.manager.mark_installing(&internal_id).await?;
+        self.save_install_script(&internal_id, install_script.as_deref()).await?;
 
         let manager = self.manager.clone();
         let docker = self.docker.clone();
         let event_tx = self.event_tx.clone();
         let base_path = self.base_path.clone();
+        let install_limits = self.install_limits;
+        let log_rotation = self.log_rotation.clone();
+        let container_user = self.container_user.clone();
+        let pids_limit = self.pids_limit;
+        let ulimits = self.ulimits.clone();
+        let install_queue = self.install_queue.clone();
+        let pull_retry = self.pull_retry.clone();
+        let mount_policy = self.mount_policy.clone();
 
         let _ = event_tx.send(LifecycleEvent::ReinstallStarted(internal_id.clone()));
 
@@ -575,6 +899,19 @@ impl LifecycleManager {
 
             tracing::info!("Removed old container {} for reinstall", container_name);
 
+            if wipe_volume {
+                if let Err(e) = Self::wipe_volume(&manager, &base_path, &internal_id).await {
+                    let error_msg = format!("Failed to wipe volume: {}", e);
+                    let _ = event_tx.send(LifecycleEvent::Error(internal_id.clone(), error_msg.clone()));
+                    if let Err(mark_err) = manager.mark_failed(&internal_id, &error_msg).await {
+                        tracing::error!("Failed to mark container {} as failed: {}", internal_id, mark_err);
+                    }
+                    tracing::error!("Container reinstall failed for {}: {}", internal_id, error_msg);
+                    return;
+                }
+                let _ = event_tx.send(LifecycleEvent::VolumeWiped(internal_id.clone()));
+            }
+
             // Now run the install job
             if let Err(e) = Self::install_container_job(
                 manager.clone(),
@@ -584,6 +921,14 @@ impl LifecycleManager {
                 image,
                 install_script,
                 base_path,
+                install_limits,
+                log_rotation,
+                container_user,
+                pids_limit,
+                ulimits,
+                install_queue,
+                pull_retry,
+                mount_policy,
             )
             .await
             {
@@ -600,8 +945,38 @@ impl LifecycleManager {
                 
                 tracing::error!("Container reinstall failed for {}: {}", internal_id, error_msg);
             }
-        });
+        }.in_current_span());
+
+        Ok(())
+    }
+
+    /// Delete everything inside a container's volume, keeping the directory
+    /// itself since it may be a mount point
+    async fn wipe_volume(
+        manager: &ContainerManager,
+        base_path: &std::path::Path,
+        internal_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let state = manager
+            .get_container(internal_id)
+            .await?
+            .ok_or_else(|| format!("Container state not found for internal_id: {}", internal_id))?;
+        let volume_path = base_path.join("volumes").join(&state.volume_id);
 
+        let mut entries = match tokio::fs::read_dir(&volume_path).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                tokio::fs::remove_dir_all(entry.path()).await?;
+            } else {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        tracing::warn!("Wiped volume {} of container {} for reinstall", state.volume_id, internal_id);
         Ok(())
     }
 
@@ -629,7 +1004,7 @@ impl LifecycleManager {
         tracing::warn!("Container {} is corrupted ({}), starting repair", internal_id, issue_msg);
 
         // Trigger a reinstall to repair
-        self.reinstall_container(internal_id, image, None).await?;
+        self.reinstall_container(internal_id, image, None, false).await?;
 
         Ok(true)
     }
@@ -648,7 +1023,7 @@ impl LifecycleManager {
                     match self.docker.inspect_container(container_id, None).await {
                         Ok(_) => Ok(true),
                         Err(e) => {
-                            if e.to_string().contains("404") || e.to_string().contains("No such container") {
+                            if is_docker_not_found(&e) {
                                 tracing::warn!("Container {} has container_id {} but Docker container doesn't exist", internal_id, container_id);
                                 Ok(false)
                             } else {
@@ -664,7 +1039,80 @@ impl LifecycleManager {
                     Ok(true)
                 }
             }
-            None => Err("Container not found".into())
+            None => Err(LightdError::NotFound("Container").into())
+        }
+    }
+
+    /// Docker's view of a container, None when Docker doesn't know it
+    pub async fn inspect_docker(
+        &self,
+        container_id: &str,
+    ) -> Result<Option<DockerInspect>, Box<dyn std::error::Error + Send + Sync>> {
+        let info = match self.docker.inspect_container(container_id, None).await {
+            Ok(info) => info,
+            Err(e) if is_docker_not_found(&e) => {
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let state = info.state.unwrap_or_default();
+        let mounts = info.mounts.unwrap_or_default()
+            .into_iter()
+            .map(|m| DockerMount {
+                source: m.source,
+                destination: m.destination,
+                read_only: m.rw == Some(false),
+            })
+            .collect();
+
+        let mut port_bindings = Vec::new();
+        // The configured bindings, network_settings only lists them while the container runs
+        let ports = info.host_config.and_then(|h| h.port_bindings).unwrap_or_default();
+        for (container_port, bindings) in ports {
+            for binding in bindings.unwrap_or_default() {
+                port_bindings.push(DockerPortBinding {
+                    container_port: container_port.clone(),
+                    host_ip: binding.host_ip,
+                    host_port: binding.host_port,
+                });
+            }
+        }
+        port_bindings.sort_by(|a, b| a.container_port.cmp(&b.container_port));
+
+        Ok(Some(DockerInspect {
+            status: state.status.map(|s| s.to_string()),
+            running: state.running.unwrap_or(false),
+            exit_code: state.exit_code,
+            oom_killed: state.oom_killed.unwrap_or(false),
+            error: state.error.filter(|e| !e.is_empty()),
+            started_at: state.started_at,
+            finished_at: state.finished_at,
+            restart_count: info.restart_count,
+            mounts,
+            port_bindings,
+        }))
+    }
+
+    /// A page of a Docker container's past output
+    pub async fn read_logs(
+        &self,
+        container_id: &str,
+        range: &super::logs::LogRange,
+    ) -> Result<super::logs::LogPage, Box<dyn std::error::Error + Send + Sync>> {
+        super::logs::read_logs(&self.docker, container_id, range).await
+    }
+
+    /// Force-remove the Docker container of `internal_id`, a missing one is fine
+    pub async fn remove_docker_container(&self, internal_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let container_name = format!("lightd-{}", internal_id);
+        match self.docker.remove_container(&container_name, Some(RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        })).await {
+            Ok(()) => Ok(()),
+            Err(bollard::errors::Error::DockerResponseServerError { status_code: 404, .. }) => Ok(()),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -681,7 +1129,7 @@ impl LifecycleManager {
                     None => Err("Pending".into()),
                 }
             }
-            None => Err("Container not found".into()),
+            None => Err(LightdError::NotFound("Container").into()),
         }
     }
 
@@ -730,3 +1178,167 @@ impl LifecycleManager {
         Ok(())
     }*/
 }
+
+/// Node-wide settings a container falls back to where its state sets nothing
+pub(crate) struct RuntimeDefaults<'a> {
+    pub log_rotation: &'a LogRotation,
+    pub container_user: Option<&'a str>,
+    pub pids_limit: i64,
+    pub ulimits: &'a Ulimits,
+}
+
+/// Docker config of the server container. Install and the recreates of a
+/// rebind or image change all build it here, so the container they leave
+/// behind is the same
+pub(crate) fn server_container_config(
+    state: &ContainerState,
+    image: &str,
+    ports: &[PortBinding],
+    mounts: Vec<Mount>,
+    defaults: &RuntimeDefaults,
+) -> Result<Config<String>, String> {
+    let log_rotation = state.log_rotation.as_ref().unwrap_or(defaults.log_rotation);
+    let mut host_config = HostConfig {
+        mounts: Some(mounts),
+        network_mode: Some("lightd_network".to_string()),
+        log_config: Some(log_rotation.log_config()),
+        ..Default::default()
+    };
+
+    // Apply limits
+    if let Some(memory) = state.limits.memory {
+        host_config.memory = Some(memory);
+    }
+    if let Some(cpu) = state.limits.cpu {
+        host_config.nano_cpus = Some((cpu * 1_000_000_000.0) as i64);
+    }
+    let ulimits = state.ulimits.clone().unwrap_or_default().or(defaults.ulimits);
+    apply_process_limits(&mut host_config, state.pids_limit.unwrap_or(defaults.pids_limit), &ulimits);
+
+    // Apply port bindings
+    let (port_bindings, exposed_ports) = docker_ports(ports);
+    if !port_bindings.is_empty() {
+        host_config.port_bindings = Some(port_bindings);
+    }
+
+    let user = match state.user.as_deref().or(defaults.container_user) {
+        Some(user) => Some(parse_run_as(user).map(|(uid, gid)| format!("{}:{}", uid, gid))?),
+        None => None,
+    };
+
+    Ok(Config {
+        image: Some(image.to_string()),
+        working_dir: Some("/home/container".to_string()),
+        host_config: Some(host_config),
+        entrypoint: Some(vec!["/bin/sh".to_string(), "/app/data/entrypoint.sh".to_string()]),
+        user,
+        env: docker_env(&state.env),
+        tty: Some(true),
+        open_stdin: Some(true),
+        exposed_ports: if exposed_ports.is_empty() { None } else { Some(exposed_ports) },
+        ..Default::default()
+    })
+}
+
+/// Registry hiccups, rate limits and timeouts are worth another pull,
+/// a missing image or refused credentials are not
+fn is_retryable_pull_error(e: &bollard::errors::Error) -> bool {
+    use bollard::errors::Error;
+    match e {
+        Error::DockerResponseServerError { status_code, message } => {
+            (*status_code == 429 || *status_code >= 500) && !is_permanent_pull_message(message)
+        }
+        // Errors from the registry arrive in the progress stream, with no status code
+        Error::DockerStreamError { error } => !is_permanent_pull_message(error),
+        Error::RequestTimeoutError | Error::HyperResponseError { .. } | Error::IOError { .. } => true,
+        _ => false,
+    }
+}
+
+/// Docker reports a missing image or bad credentials through the message,
+/// sometimes wrapped in a 500
+fn is_permanent_pull_message(message: &str) -> bool {
+    const PERMANENT: &[&str] = &[
+        "not found",
+        "manifest unknown",
+        "no matching manifest",
+        "pull access denied",
+        "access to the resource is denied",
+        "unauthorized",
+        "authentication required",
+        "invalid reference format",
+    ];
+    let message = message.to_lowercase();
+    PERMANENT.iter().any(|p| message.contains(p))
+}
+
+/// Delay before retry number `attempt` (1-based): exponential, capped, with
+/// jitter so installs hitting the same rate limit don't retry in lockstep
+fn pull_backoff_delay(policy: &PullRetryConfig, attempt: u32) -> Duration {
+    let exp = policy
+        .initial_delay_ms
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(20));
+    let capped = exp.min(policy.max_delay_ms);
+    Duration::from_millis(rand::thread_rng().gen_range(capped / 2..=capped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_install_queue() {
+        let queue = Arc::new(InstallQueue::new(1));
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+
+        let first = queue.enter("a", &event_tx).await;
+        assert!(event_rx.try_recv().is_err());
+
+        let waiter = {
+            let queue = queue.clone();
+            let event_tx = event_tx.clone();
+            tokio::spawn(async move { queue.enter("b", &event_tx).await })
+        };
+        match event_rx.recv().await {
+            Some(LifecycleEvent::InstallQueued(id, ahead)) => assert_eq!((id.as_str(), ahead), ("b", 0)),
+            other => panic!("expected InstallQueued, got {:?}", other),
+        }
+
+        drop(first);
+        let _second = waiter.await.unwrap();
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_pull_error_classification() {
+        use bollard::errors::Error;
+
+        let stream = |error: &str| Error::DockerStreamError { error: error.to_string() };
+        let server = |status_code: u16, message: &str| Error::DockerResponseServerError {
+            status_code,
+            message: message.to_string(),
+        };
+
+        assert!(is_retryable_pull_error(&stream("toomanyrequests: You have reached your pull rate limit")));
+        assert!(is_retryable_pull_error(&stream("received unexpected HTTP status: 503 Service Unavailable")));
+        assert!(is_retryable_pull_error(&server(500, "Get \"https://registry-1.docker.io/v2/\": net/http: TLS handshake timeout")));
+        assert!(is_retryable_pull_error(&server(429, "too many requests")));
+        assert!(is_retryable_pull_error(&Error::RequestTimeoutError));
+
+        assert!(!is_retryable_pull_error(&stream("manifest for nginx:nope not found: manifest unknown")));
+        assert!(!is_retryable_pull_error(&server(404, "pull access denied for nope, repository does not exist")));
+        assert!(!is_retryable_pull_error(&server(500, "unauthorized: incorrect username or password")));
+        assert!(!is_retryable_pull_error(&server(401, "authentication required")));
+    }
+
+    #[test]
+    fn test_pull_backoff_grows_and_caps() {
+        let policy = PullRetryConfig { max_attempts: 4, initial_delay_ms: 1_000, max_delay_ms: 5_000 };
+        let first = pull_backoff_delay(&policy, 1).as_millis();
+        assert!((500..=1_000).contains(&first));
+        let third = pull_backoff_delay(&policy, 3).as_millis();
+        assert!((2_000..=4_000).contains(&third));
+        let capped = pull_backoff_delay(&policy, 10).as_millis();
+        assert!((2_500..=5_000).contains(&capped));
+    }
+}
@@ -0,0 +1,233 @@
+//! Historical container output for a time range, read from Docker's logs
+//!
+//! Lines are read with Docker's timestamps and cut into pages. Docker only
+//! takes whole seconds for `since`, so the cursor of the next page is the
+//! second of the last line returned plus how many lines of that second were
+//! already returned.
+
+use bollard::Docker;
+use bollard::container::{LogOutput, LogsOptions};
+use futures::StreamExt;
+use serde::Serialize;
+
+/// Most lines in one response
+pub const MAX_LINES: usize = 5000;
+/// Most bytes of log text in one response
+pub const MAX_BYTES: usize = 1024 * 1024;
+
+/// Which lines to read, all times are unix seconds
+#[derive(Debug, Clone, Default)]
+pub struct LogRange {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    /// Only the last N lines of the range, no further pages
+    pub tail: Option<usize>,
+    /// Lines at the start of the range to leave out, from a `next` cursor
+    pub skip: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogLine {
+    /// RFC 3339 with nanoseconds, as Docker recorded it
+    pub timestamp: String,
+    pub line: String,
+}
+
+/// Query values for the page after this one
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogCursor {
+    pub since: i64,
+    pub skip: usize,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogPage {
+    pub lines: Vec<LogLine>,
+    /// Set when the range has more lines than fit in this page
+    pub next: Option<LogCursor>,
+}
+
+/// Matched lines returned by a start pattern test, the count covers all of them
+pub const MAX_PATTERN_MATCHES: usize = 100;
+
+/// Result of running a candidate start pattern over recent output
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PatternTest {
+    pub lines_checked: usize,
+    pub match_count: usize,
+    /// The line the console would have marked the server running on
+    pub first_match: Option<LogLine>,
+    /// At most `MAX_PATTERN_MATCHES`, oldest first
+    pub matches: Vec<LogLine>,
+}
+
+/// Match `pattern` against each line the way the console watcher does
+pub fn test_start_pattern(pattern: &regex::Regex, lines: Vec<LogLine>) -> PatternTest {
+    let lines_checked = lines.len();
+    let mut matched = lines.into_iter().filter(|l| pattern.is_match(&l.line));
+    let first_match = matched.next();
+    let rest: Vec<LogLine> = matched.collect();
+    let match_count = rest.len() + usize::from(first_match.is_some());
+    let matches = first_match.iter().cloned().chain(rest).take(MAX_PATTERN_MATCHES).collect();
+    PatternTest { lines_checked, match_count, first_match, matches }
+}
+
+/// Collects lines into a page until it is full
+struct Pager {
+    skip: usize,
+    limit: usize,
+    bytes: usize,
+    lines: Vec<LogLine>,
+    /// Second of the last line seen and how many lines of it were seen,
+    /// skipped ones included
+    run: Option<(i64, usize)>,
+    full: bool,
+}
+
+impl Pager {
+    fn new(skip: usize, limit: usize) -> Self {
+        Self { skip, limit, bytes: 0, lines: Vec::new(), run: None, full: false }
+    }
+
+    /// Add a line as Docker sends it with `timestamps`, false once the page is full
+    fn push(&mut self, raw: &str) -> bool {
+        let Some((timestamp, line)) = raw.split_once(' ') else {
+            return true;
+        };
+        let Ok(second) = chrono::DateTime::parse_from_rfc3339(timestamp).map(|t| t.timestamp()) else {
+            return true;
+        };
+        // A single line over the byte cap still gets a page of its own
+        if self.lines.len() >= self.limit || (!self.lines.is_empty() && self.bytes + line.len() > MAX_BYTES) {
+            self.full = true;
+            return false;
+        }
+
+        self.run = match self.run {
+            Some((run_second, count)) if run_second == second => Some((second, count + 1)),
+            _ => Some((second, 1)),
+        };
+        if self.skip > 0 {
+            self.skip -= 1;
+            return true;
+        }
+        self.bytes += line.len();
+        self.lines.push(LogLine {
+            timestamp: timestamp.to_string(),
+            line: line.to_string(),
+        });
+        true
+    }
+
+    fn finish(self) -> LogPage {
+        let next = match (self.full, self.run) {
+            (true, Some((since, skip))) => Some(LogCursor { since, skip }),
+            _ => None,
+        };
+        LogPage { lines: self.lines, next }
+    }
+}
+
+/// Read a page of a container's logs, stdout and stderr interleaved
+pub async fn read_logs(
+    docker: &Docker,
+    container_id: &str,
+    range: &LogRange,
+) -> Result<LogPage, Box<dyn std::error::Error + Send + Sync>> {
+    let limit = range.limit.clamp(1, MAX_LINES);
+    let options = LogsOptions::<String> {
+        follow: false,
+        stdout: true,
+        stderr: true,
+        timestamps: true,
+        since: range.since.unwrap_or(0),
+        until: range.until.unwrap_or(0),
+        tail: match range.tail {
+            Some(tail) => tail.min(limit).to_string(),
+            None => "all".to_string(),
+        },
+    };
+
+    let mut pager = Pager::new(if range.tail.is_some() { 0 } else { range.skip }, limit);
+    let mut stream = docker.logs(container_id, Some(options));
+    'stream: while let Some(output) = stream.next().await {
+        let message = match output? {
+            LogOutput::StdOut { message }
+            | LogOutput::StdErr { message }
+            | LogOutput::Console { message }
+            | LogOutput::StdIn { message } => message,
+        };
+        for raw in String::from_utf8_lossy(&message).lines() {
+            if !pager.push(raw.trim_end()) {
+                break 'stream;
+            }
+        }
+    }
+
+    let mut page = pager.finish();
+    if range.tail.is_some() {
+        page.next = None;
+    }
+    Ok(page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pages_continue_within_a_second() {
+        let raw = [
+            "2024-05-01T10:00:00.100000000Z first",
+            "2024-05-01T10:00:01.100000000Z second",
+            "2024-05-01T10:00:01.200000000Z third",
+            "2024-05-01T10:00:01.300000000Z fourth",
+            "2024-05-01T10:00:02.100000000Z fifth",
+        ];
+
+        let mut pager = Pager::new(0, 2);
+        assert_eq!(raw.iter().take_while(|line| pager.push(line)).count(), 2);
+        let page = pager.finish();
+        assert_eq!(page.lines.iter().map(|l| l.line.as_str()).collect::<Vec<_>>(), ["first", "second"]);
+        let next = page.next.unwrap();
+        assert_eq!(next, LogCursor { since: 1714557601, skip: 1 });
+
+        // Docker starts the next page at the cursor's second
+        let mut pager = Pager::new(next.skip, 2);
+        for line in &raw[1..] {
+            if !pager.push(line) {
+                break;
+            }
+        }
+        let page = pager.finish();
+        assert_eq!(page.lines.iter().map(|l| l.line.as_str()).collect::<Vec<_>>(), ["third", "fourth"]);
+        assert_eq!(page.next, Some(LogCursor { since: 1714557601, skip: 3 }));
+
+        let mut pager = Pager::new(3, 2);
+        for line in &raw[1..] {
+            assert!(pager.push(line));
+        }
+        let page = pager.finish();
+        assert_eq!(page.lines.len(), 1);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn test_start_pattern_matches() {
+        let line = |line: &str| LogLine { timestamp: "2024-05-01T10:00:00Z".to_string(), line: line.to_string() };
+        let lines = vec![line("Loading world"), line("Done (3.2s)! For help, type \"help\""), line("Done saving")];
+
+        let result = test_start_pattern(&regex::Regex::new(r"^Done \(").unwrap(), lines.clone());
+        assert_eq!(result.lines_checked, 3);
+        assert_eq!(result.match_count, 1);
+        assert_eq!(result.first_match.unwrap().line, "Done (3.2s)! For help, type \"help\"");
+
+        let result = test_start_pattern(&regex::Regex::new("Done").unwrap(), lines.clone());
+        assert_eq!(result.match_count, 2);
+        assert_eq!(result.matches.len(), 2);
+
+        let result = test_start_pattern(&regex::Regex::new("Started").unwrap(), lines);
+        assert_eq!((result.match_count, result.first_match), (0, None));
+    }
+}
@@ -1,22 +1,173 @@
-use super::state::{ContainerState, InstallState};
+use super::state::{validate_label_key, ContainerState, InstallState};
+use crate::error::LightdError;
+use dashmap::DashMap;
+use serde::Serialize;
 use sled::Db;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
+/// How long past its stop timeout a requested stop still explains an exit
+const STOP_REQUEST_SLACK: Duration = Duration::from_secs(60);
+
+/// Criteria for listing containers, unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct ContainerFilter {
+    pub install_state: Option<InstallState>,
+    /// Exact image, or a repository without a tag to match every tag
+    pub image: Option<String>,
+    /// Every label must be present, with the given value when one is set
+    pub labels: Vec<(String, Option<String>)>,
+}
+
+impl ContainerFilter {
+    /// Parse a `customer:acme,tier` label query into filter entries
+    pub fn parse_labels(query: &str) -> Vec<(String, Option<String>)> {
+        query.split(',')
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| match l.split_once(':') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (l.to_string(), None),
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, state: &ContainerState) -> bool {
+        if let Some(ref wanted) = self.install_state
+            && &state.install_state != wanted {
+            return false;
+        }
+
+        if let Some(ref wanted) = self.image {
+            let matches_image = state.image.as_deref().is_some_and(|image| {
+                image == wanted || image.strip_prefix(wanted.as_str()).is_some_and(|rest| rest.starts_with(':'))
+            });
+            if !matches_image {
+                return false;
+            }
+        }
+
+        self.labels.iter().all(|(key, value)| match (state.labels.get(key), value) {
+            (Some(actual), Some(wanted)) => actual == wanted,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+    }
+}
+
+/// One page of a container listing
+#[derive(Debug, Serialize)]
+pub struct ContainerPage {
+    pub containers: Vec<ContainerState>,
+    /// Containers matching the filter across all pages
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
+}
+
 pub struct ContainerManager {
     db: Arc<Db>,
     states: Arc<RwLock<()>>, // Mutex for state updates
+    /// When a stop, kill or restart was last requested, so its exit isn't counted as a crash
+    stop_requests: DashMap<String, Instant>,
+    /// Last runtime state per container ("running", "offline", ...), kept
+    /// apart from the states so listing containers doesn't see it
+    runtime_states: sled::Tree,
 }
 
 impl ContainerManager {
     pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let db = sled::open(db_path)?;
+        let runtime_states = db.open_tree("runtime_states")?;
         Ok(Self {
             db: Arc::new(db),
             states: Arc::new(RwLock::new(())),
+            stop_requests: DashMap::new(),
+            runtime_states,
         })
     }
 
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    /// Last persisted runtime state, None if it was never recorded
+    pub fn runtime_state(&self, internal_id: &str) -> Option<String> {
+        self.runtime_states
+            .get(internal_id.as_bytes())
+            .ok()
+            .flatten()
+            .map(|v| String::from_utf8_lossy(&v).to_string())
+    }
+
+    /// Persist the runtime state, read back after a daemon restart
+    pub fn set_runtime_state(&self, internal_id: &str, state: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime_states.insert(internal_id.as_bytes(), state.as_bytes())?;
+        Ok(())
+    }
+
+    /// Note that the next exit was asked for, `false` clears that again on start
+    pub fn expect_stop(&self, internal_id: &str, expected: bool) {
+        if expected {
+            self.stop_requests.insert(internal_id.to_string(), Instant::now());
+        } else {
+            self.stop_requests.remove(internal_id);
+        }
+    }
+
+    /// Record that the container exited. Exits with an error that weren't
+    /// requested through `expect_stop` count as crashes. Returns whether it was one.
+    pub async fn record_exit(
+        &self,
+        internal_id: &str,
+        exit_code: i64,
+        error: Option<String>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.states.write().await;
+
+        let Some(mut state) = self.get_container(internal_id).await? else {
+            return Err(LightdError::NotFound("Container").into());
+        };
+
+        let window = Duration::from_secs(state.stop_timeout.unwrap_or(super::power::DEFAULT_STOP_TIMEOUT_SECS)) + STOP_REQUEST_SLACK;
+        let requested = self
+            .stop_requests
+            .remove(internal_id)
+            .is_some_and(|(_, at)| at.elapsed() <= window);
+        let crashed = !requested && error.is_some();
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        state.last_exit_code = Some(exit_code);
+        state.last_exit_at = Some(now);
+        if crashed {
+            state.restart_count += 1;
+            state.last_error = error;
+        }
+        state.update_timestamp();
+
+        let serialized = serde_json::to_vec(&state)?;
+        self.db.insert(internal_id.as_bytes(), serialized)?;
+
+        if crashed {
+            tracing::warn!("Container {} crashed with exit code {} ({} crashes)", internal_id, exit_code, state.restart_count);
+        }
+        Ok(crashed)
+    }
+
+    /// Cheap read to confirm the database is open and readable
+    pub fn check_db(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.get(b"__health")?;
+        Ok(())
+    }
+
     pub async fn create_container(
         &self,
         internal_id: String,
@@ -73,6 +224,8 @@ impl ContainerManager {
             state.is_installing = false;
             state.install_state = InstallState::Ready;
             state.container_id = Some(container_id);
+            state.install_error = None;
+            state.install_resumes = 0;
             state.update_timestamp();
 
             let serialized = serde_json::to_vec(&state)?;
@@ -81,7 +234,7 @@ impl ContainerManager {
             tracing::info!("Marked container {} as ready", internal_id);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
@@ -96,6 +249,7 @@ impl ContainerManager {
         if let Some(mut state) = self.get_container(internal_id).await? {
             state.is_installing = false;
             state.install_state = InstallState::Failed;
+            state.install_error = Some(error_message.to_string());
             state.update_timestamp();
 
             let serialized = serde_json::to_vec(&state)?;
@@ -104,7 +258,7 @@ impl ContainerManager {
             tracing::error!("Container {} installation failed: {}", internal_id, error_message);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
@@ -119,6 +273,8 @@ impl ContainerManager {
             state.is_installing = true;
             state.install_state = InstallState::Installing;
             state.container_id = None; // Clear old container ID
+            state.install_error = None;
+            state.install_resumes = 0;
             state.update_timestamp();
 
             let serialized = serde_json::to_vec(&state)?;
@@ -127,10 +283,24 @@ impl ContainerManager {
             tracing::info!("Marked container {} as installing", internal_id);
             Ok(state)
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
+    /// Count a restart of an install a daemon restart interrupted, returns the new count
+    pub async fn count_install_resume(
+        &self,
+        internal_id: &str,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.states.write().await;
+
+        let mut state = self.get_container(internal_id).await?.ok_or(LightdError::NotFound("Container"))?;
+        state.install_resumes += 1;
+        state.update_timestamp();
+        self.db.insert(internal_id.as_bytes(), serde_json::to_vec(&state)?)?;
+        Ok(state.install_resumes)
+    }
+
     /// Validate container state and check for corruption
     /// Returns Ok(true) if container is healthy, Ok(false) if corrupted
     pub async fn validate_container(
@@ -178,7 +348,7 @@ impl ContainerManager {
                 }
             }
             Ok(None) => {
-                Err("Container not found".into())
+                Err(LightdError::NotFound("Container").into())
             }
             Err(e) => {
                 // DB corruption or parse error
@@ -206,20 +376,22 @@ impl ContainerManager {
             tracing::info!("Updated startup command for container {}", internal_id);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
-    /// Update start pattern for a container (for detecting when server is fully started)
-    pub async fn update_start_pattern(
+    /// Replace the start patterns of a container (for detecting when server is fully started)
+    pub async fn update_start_patterns(
         &self,
         internal_id: &str,
-        start_pattern: Option<String>,
+        start_patterns: Vec<String>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let _lock = self.states.write().await;
 
         if let Some(mut state) = self.get_container(internal_id).await? {
-            state.start_pattern = start_pattern;
+            // The list takes over, the old single field would come back when it is emptied
+            state.start_pattern = None;
+            state.start_patterns = start_patterns;
             state.update_timestamp();
 
             let serialized = serde_json::to_vec(&state)?;
@@ -228,7 +400,62 @@ impl ContainerManager {
             tracing::info!("Updated start pattern for container {}", internal_id);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
+        }
+    }
+
+    /// Update the graceful stop sequence for a container
+    pub async fn update_stop_config(
+        &self,
+        internal_id: &str,
+        stop_command: Option<String>,
+        stop_timeout: Option<u64>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.states.write().await;
+
+        if let Some(mut state) = self.get_container(internal_id).await? {
+            state.stop_command = stop_command;
+            state.stop_timeout = stop_timeout;
+            state.update_timestamp();
+
+            let serialized = serde_json::to_vec(&state)?;
+            self.db.insert(internal_id.as_bytes(), serialized)?;
+
+            tracing::info!("Updated stop config for container {}", internal_id);
+            Ok(())
+        } else {
+            Err(LightdError::NotFound("Container").into())
+        }
+    }
+
+    /// Apply label changes, a `None` value removes the label
+    pub async fn update_labels(
+        &self,
+        internal_id: &str,
+        changes: HashMap<String, Option<String>>,
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error + Send + Sync>> {
+        for key in changes.keys() {
+            validate_label_key(key)?;
+        }
+
+        let _lock = self.states.write().await;
+
+        if let Some(mut state) = self.get_container(internal_id).await? {
+            for (key, value) in changes {
+                match value {
+                    Some(value) => state.labels.insert(key, value),
+                    None => state.labels.remove(&key),
+                };
+            }
+            state.update_timestamp();
+
+            let serialized = serde_json::to_vec(&state)?;
+            self.db.insert(internal_id.as_bytes(), serialized)?;
+
+            tracing::info!("Updated labels for container {}", internal_id);
+            Ok(state.labels)
+        } else {
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
@@ -244,6 +471,45 @@ impl ContainerManager {
         Ok(containers)
     }
 
+    /// List one page of the containers matching `filter`, pages start at 1
+    ///
+    /// Entries are in internal_id order, so pages stay stable between calls.
+    pub async fn list_containers_page(
+        &self,
+        filter: &ContainerFilter,
+        page: usize,
+        per_page: usize,
+    ) -> Result<ContainerPage, Box<dyn std::error::Error + Send + Sync>> {
+        let per_page = per_page.max(1);
+        let page = page.max(1);
+        // A huge page from the query string lands past the end instead of overflowing
+        let skip = (page - 1).saturating_mul(per_page);
+
+        let mut containers = Vec::new();
+        let mut total = 0;
+
+        for item in self.db.iter() {
+            let (_, value) = item?;
+            let state: ContainerState = serde_json::from_slice(&value)?;
+            if !filter.matches(&state) {
+                continue;
+            }
+
+            if total >= skip && containers.len() < per_page {
+                containers.push(state);
+            }
+            total += 1;
+        }
+
+        Ok(ContainerPage {
+            containers,
+            total,
+            page,
+            per_page,
+            total_pages: total.div_ceil(per_page),
+        })
+    }
+
     pub async fn delete_container(
         &self,
         internal_id: &str,
@@ -252,10 +518,11 @@ impl ContainerManager {
 
         if let Some(state) = self.get_container(internal_id).await? {
             self.db.remove(internal_id.as_bytes())?;
+            self.runtime_states.remove(internal_id.as_bytes())?;
             tracing::info!("Deleted container state for internal_id: {}", internal_id);
             Ok(state)
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
@@ -276,6 +543,10 @@ impl ContainerManager {
                 // Convert CPU shares to CPU cores (approximate)
                 state.limits.cpu = Some(cpu as f64 / 1024.0);
             }
+            if let Some(cpus) = limits.cpus {
+                // Same field install_container_job turns into nano_cpus
+                state.limits.cpu = Some(cpus);
+            }
             
             state.update_timestamp();
 
@@ -285,7 +556,7 @@ impl ContainerManager {
             tracing::info!("Updated resource limits for container {}", internal_id);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 
@@ -294,11 +565,13 @@ impl ContainerManager {
         &self,
         internal_id: &str,
         volumes: std::collections::HashMap<String, String>,
+        read_only: Vec<String>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let _lock = self.states.write().await;
 
         if let Some(mut state) = self.get_container(internal_id).await? {
             state.mount = volumes;
+            state.read_only_mounts = read_only;
             state.update_timestamp();
 
             let serialized = serde_json::to_vec(&state)?;
@@ -307,7 +580,109 @@ impl ContainerManager {
             tracing::info!("Updated volumes for container {}", internal_id);
             Ok(())
         } else {
-            Err("Container not found".into())
+            Err(LightdError::NotFound("Container").into())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matches_image_with_or_without_tag() {
+        let mut state = ContainerState::new("a".to_string(), "v".to_string(), "run".to_string());
+        state.image = Some("ubuntu:22.04".to_string());
+
+        let by_image = |image: &str| ContainerFilter {
+            image: Some(image.to_string()),
+            ..Default::default()
+        };
+
+        assert!(by_image("ubuntu:22.04").matches(&state));
+        assert!(by_image("ubuntu").matches(&state));
+        assert!(!by_image("ubuntu:24.04").matches(&state));
+        assert!(!by_image("ubunt").matches(&state));
+
+        let failed = ContainerFilter {
+            install_state: Some(InstallState::Failed),
+            ..Default::default()
+        };
+        assert!(!failed.matches(&state));
+    }
+
+    #[test]
+    fn test_filter_matches_labels() {
+        let mut state = ContainerState::new("a".to_string(), "v".to_string(), "run".to_string());
+        state.labels.insert("customer".to_string(), "acme".to_string());
+        state.labels.insert("tier".to_string(), "gold".to_string());
+
+        let by_labels = |query: &str| ContainerFilter {
+            labels: ContainerFilter::parse_labels(query),
+            ..Default::default()
+        };
+
+        assert!(by_labels("customer:acme").matches(&state));
+        assert!(by_labels("customer:acme,tier").matches(&state));
+        assert!(!by_labels("customer:globex").matches(&state));
+        assert!(!by_labels("region").matches(&state));
+    }
+
+    #[tokio::test]
+    async fn test_record_exit_counts_only_crashes() {
+        let path = std::env::temp_dir().join(format!("lightd-manager-{}", uuid::Uuid::new_v4()));
+        let manager = ContainerManager::new(path.to_str().unwrap()).unwrap();
+        manager.create_container("a".to_string(), "v".to_string(), "run".to_string()).await.unwrap();
+
+        // Requested kill, exit code but no crash
+        manager.expect_stop("a", true);
+        assert!(!manager.record_exit("a", 137, Some("Exited with code 137".to_string())).await.unwrap());
+
+        assert!(manager.record_exit("a", 1, Some("Exited with code 1".to_string())).await.unwrap());
+        assert!(!manager.record_exit("a", 0, None).await.unwrap());
+
+        let state = manager.get_container("a").await.unwrap().unwrap();
+        assert_eq!(state.restart_count, 1);
+        assert_eq!(state.last_exit_code, Some(0));
+        assert_eq!(state.last_error.as_deref(), Some("Exited with code 1"));
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_state_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("lightd-manager-{}", uuid::Uuid::new_v4()));
+        let manager = ContainerManager::new(path.to_str().unwrap()).unwrap();
+        manager.create_container("a".to_string(), "v".to_string(), "run".to_string()).await.unwrap();
+        assert_eq!(manager.runtime_state("a"), None);
+        manager.set_runtime_state("a", "running").unwrap();
+        drop(manager);
+
+        let manager = ContainerManager::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(manager.runtime_state("a").as_deref(), Some("running"));
+        // Kept out of the container states
+        assert_eq!(manager.list_containers().await.unwrap().len(), 1);
+
+        manager.delete_container("a").await.unwrap();
+        assert_eq!(manager.runtime_state("a"), None);
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn test_page_past_the_end_is_empty() {
+        let path = std::env::temp_dir().join(format!("lightd-manager-{}", uuid::Uuid::new_v4()));
+        let manager = ContainerManager::new(path.to_str().unwrap()).unwrap();
+        manager.create_container("a".to_string(), "v".to_string(), "run".to_string()).await.unwrap();
+
+        let page = manager.list_containers_page(&ContainerFilter::default(), usize::MAX, 50).await.unwrap();
+        assert!(page.containers.is_empty());
+        assert_eq!(page.total, 1);
+        assert_eq!(page.total_pages, 1);
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -5,3 +5,12 @@ pub mod power;
 pub mod network;
 pub mod update;
 pub mod user;
+pub mod reconcile;
+pub mod export;
+pub mod transfer;
+pub mod uptime;
+pub mod idempotency;
+pub mod images;
+pub mod logs;
+pub mod template;
+pub mod docker;
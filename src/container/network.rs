@@ -1,15 +1,25 @@
 use super::manager::ContainerManager;
-use super::state::PortBinding;
+use super::lifecycle::{server_container_config, RuntimeDefaults};
+use super::state::{normalize_protocol, validate_mount, ContainerState, MountPolicy, PortBinding};
 use crate::config::config::Config;
+use crate::network::pool::{check_host_port_free, NetworkPool};
+use crate::error::LightdError;
 use bollard::Docker;
-use bollard::container::{RemoveContainerOptions, Config as ContainerConfig, CreateContainerOptions};
-use bollard::models::{HostConfig, Mount, MountTypeEnum, PortBinding as DockerPortBinding, PortMap};
+use bollard::container::{RemoveContainerOptions, CreateContainerOptions};
+use bollard::image::CreateImageOptions;
+use bollard::models::{HostConfig, Mount, MountTypeEnum};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::Serialize;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::timeout;
+use tracing::Instrument;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "args", rename_all = "snake_case")]
 #[allow(unused)]
 pub enum NetworkEvent {
     RebindingStarted(String),
@@ -17,39 +27,118 @@ pub enum NetworkEvent {
     CreatingNewContainer(String),
     UpdatingDatabase(String),
     RebindingComplete(String),
+    /// Image change requested, with the new image
+    ImageChangeStarted(String, String),
+    PullingImage(String, String),
+    /// Container recreated on the new image
+    ImageChanged(String, String),
     Error(String, String),
 }
 
+impl NetworkEvent {
+    /// Internal ID of the container the event is about
+    pub fn container_id(&self) -> &str {
+        match self {
+            Self::RebindingStarted(id)
+            | Self::RemovingOldContainer(id)
+            | Self::CreatingNewContainer(id)
+            | Self::UpdatingDatabase(id)
+            | Self::RebindingComplete(id)
+            | Self::ImageChangeStarted(id, _)
+            | Self::PullingImage(id, _)
+            | Self::ImageChanged(id, _)
+            | Self::Error(id, _) => id,
+        }
+    }
+}
+
+/// An image change was refused because the container is running
+#[derive(Debug)]
+pub struct ContainerRunning(pub String);
+
+impl std::fmt::Display for ContainerRunning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Container {} is running, stop it before changing its image", self.0)
+    }
+}
+
+impl std::error::Error for ContainerRunning {}
+
+/// A rebind or image change in progress
+struct RecreateJob {
+    kind: &'static str,
+    /// Image the new container is created from
+    image: String,
+}
+
+/// A container being recreated by a rebind or image change, released on drop
+struct RecreateClaim {
+    recreating: Arc<DashMap<String, RecreateJob>>,
+    internal_id: String,
+}
+
+impl Drop for RecreateClaim {
+    fn drop(&mut self) {
+        self.recreating.remove(&self.internal_id);
+    }
+}
+
 pub struct NetworkRebinder {
     manager: Arc<ContainerManager>,
+    pool: Arc<NetworkPool>,
     docker: Docker,
     event_tx: mpsc::UnboundedSender<NetworkEvent>,
     config: Config,
+    /// Containers with a rebind or image change running, and which one
+    recreating: Arc<DashMap<String, RecreateJob>>,
 }
 
 impl NetworkRebinder {
     pub fn new(
         manager: Arc<ContainerManager>,
+        pool: Arc<NetworkPool>,
     ) -> Result<(Self, mpsc::UnboundedReceiver<NetworkEvent>), Box<dyn std::error::Error>> {
-        let docker = Docker::connect_with_local_defaults()?;
+        let docker = crate::container::docker::client()?;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         let config = Config::load("config.json")?;
 
         Ok((
             Self {
                 manager,
+                pool,
                 docker,
                 event_tx,
                 config,
+                recreating: Arc::new(DashMap::new()),
             },
             event_rx,
         ))
     }
 
+    /// Only one job may recreate a container at a time, the second would
+    /// remove the container the first just created
+    fn claim(&self, internal_id: &str, kind: &'static str, image: &str) -> Result<RecreateClaim, LightdError> {
+        match self.recreating.entry(internal_id.to_string()) {
+            Entry::Occupied(running) => Err(LightdError::Conflict(format!(
+                "Container {} is already being recreated by a {}", internal_id, running.get().kind
+            ))),
+            Entry::Vacant(slot) => {
+                slot.insert(RecreateJob { kind, image: image.to_string() });
+                Ok(RecreateClaim { recreating: self.recreating.clone(), internal_id: internal_id.to_string() })
+            }
+        }
+    }
+
+    /// Images running jobs create containers from. They may be pulled without
+    /// any container using them yet, so the image pruner has to keep them.
+    pub fn images_in_use(&self) -> Vec<String> {
+        self.recreating.iter().map(|job| job.image.clone()).collect()
+    }
+
     pub async fn rebind_ports(
         &self,
         internal_id: String,
-        new_ports: Vec<PortBinding>,
+        mut new_ports: Vec<PortBinding>,
         image: String,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Validate inputs
@@ -62,28 +151,47 @@ impl NetworkRebinder {
         }
 
         // Validate ports
-        for port in &new_ports {
+        for port in &mut new_ports {
             if port.container_port == 0 {
                 return Err("Container port cannot be 0".into());
             }
             if port.host_port == 0 {
                 return Err("Host port cannot be 0".into());
             }
-            let protocol = port.protocol.to_lowercase();
-            if protocol != "tcp" && protocol != "udp" {
-                return Err(format!("Invalid protocol '{}', must be 'tcp' or 'udp'", port.protocol).into());
+            port.protocol = normalize_protocol(&port.protocol)?;
+            crate::network::pool::validate_bind_ip(&port.host_ip).map_err(|e| e.to_string())?;
+            // Entries are carried over from the current bindings, never taken from the request
+            port.pool_id = None;
+        }
+
+        // Ports the container already publishes are held by its own Docker proxy,
+        // anything else must be free on the host or the new container won't start
+        let current_ports = self.manager.get_container(&internal_id).await
+            .ok()
+            .flatten()
+            .map(|state| state.ports)
+            .unwrap_or_default();
+        for port in &new_ports {
+            let own = current_ports.iter()
+                .any(|p| p.host_port == port.host_port && p.protocol.eq_ignore_ascii_case(&port.protocol));
+            if !own {
+                check_host_port_free(&port.host_ip, port.host_port, &port.protocol)?;
             }
         }
 
+        let claim = self.claim(&internal_id, "port rebind", &image)?;
         let manager = self.manager.clone();
+        let pool = self.pool.clone();
         let docker = self.docker.clone();
         let event_tx = self.event_tx.clone();
         let config = self.config.clone();
 
         // Spawn async non-blocking job
         tokio::spawn(async move {
+            let _claim = claim;
             if let Err(e) = Self::rebind_ports_job(
                 manager,
+                pool,
                 docker,
                 event_tx.clone(),
                 internal_id.clone(),
@@ -96,17 +204,19 @@ impl NetworkRebinder {
                 let _ = event_tx.send(NetworkEvent::Error(internal_id.clone(), e.to_string()));
                 tracing::error!("Network rebinding failed for {}: {}", internal_id, e);
             }
-        });
+        }.in_current_span());
 
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn rebind_ports_job(
         manager: Arc<ContainerManager>,
+        pool: Arc<NetworkPool>,
         docker: Docker,
         event_tx: mpsc::UnboundedSender<NetworkEvent>,
         internal_id: String,
-        new_ports: Vec<PortBinding>,
+        mut new_ports: Vec<PortBinding>,
         image: String,
         config: Config,
     ) -> Result<(), Box<dyn std::error::Error>> {
@@ -130,9 +240,199 @@ impl NetworkRebinder {
             return Err("Cannot rebind network while container is installing".into());
         }
 
+        let container_id = Self::recreate(&docker, &event_tx, &state, &new_ports, &image, &config).await?;
+
+        // Update state with new ports and container ID
+        let _ = event_tx.send(NetworkEvent::UpdatingDatabase(internal_id.clone()));
+        
+        // A binding that stays keeps its pool entry, the others go back to the pool
+        let mut released_ports = Vec::new();
+        for old in &state.ports {
+            let kept = new_ports.iter_mut().find(|p| {
+                p.pool_id.is_none()
+                    && p.host_port == old.host_port
+                    && p.protocol.eq_ignore_ascii_case(&old.protocol)
+                    && p.host_ip == old.host_ip
+            });
+            match kept {
+                Some(port) => port.pool_id = old.pool_id.clone(),
+                None => released_ports.push(old.clone()),
+            }
+        }
+
+        state.ports = new_ports;
+        state.container_id = Some(container_id.clone());
+        state.update_timestamp();
+        Self::save_state(&manager, state).await?;
+
+        if let Err(e) = pool.release_ports(&released_ports).await {
+            tracing::warn!("Failed to return old ports of {} to pool: {}", internal_id, e);
+        }
+
+        let _ = event_tx.send(NetworkEvent::RebindingComplete(internal_id.clone()));
+
+        tracing::info!("Network rebinding complete for {} with container ID {}", internal_id, container_id);
+        Ok(())
+    }
+
+    /// Recreate the container on `image`, keeping volume, ports, env, limits
+    /// and the install result. The install script is not run again.
+    ///
+    /// The image is pulled before anything is removed, a failed pull leaves
+    /// the old container as it was.
+    pub async fn change_image(
+        &self,
+        internal_id: String,
+        image: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let image = image.trim().to_string();
+        if image.is_empty() {
+            return Err("Image cannot be empty".into());
+        }
+
+        let state = self.manager.get_container(&internal_id).await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Container '{}' not found", internal_id))?;
+        if state.is_installing {
+            return Err(super::images::InstallRunning(internal_id).into());
+        }
+        if let Some(ref container_id) = state.container_id
+            && let Ok(inspect) = self.docker.inspect_container(container_id, None).await
+                && inspect.state.and_then(|s| s.running).unwrap_or(false) {
+                return Err(ContainerRunning(internal_id).into());
+            }
+
+        let claim = self.claim(&internal_id, "image change", &image)?;
+        let manager = self.manager.clone();
+        let docker = self.docker.clone();
+        let event_tx = self.event_tx.clone();
+        let config = self.config.clone();
+
+        tokio::spawn(async move {
+            let _claim = claim;
+            if let Err(e) = Self::change_image_job(
+                manager,
+                docker,
+                event_tx.clone(),
+                internal_id.clone(),
+                image,
+                config,
+            )
+            .await
+            {
+                let _ = event_tx.send(NetworkEvent::Error(internal_id.clone(), e.to_string()));
+                tracing::error!("Image change failed for {}: {}", internal_id, e);
+            }
+        }.in_current_span());
+
+        Ok(())
+    }
+
+    async fn change_image_job(
+        manager: Arc<ContainerManager>,
+        docker: Docker,
+        event_tx: mpsc::UnboundedSender<NetworkEvent>,
+        internal_id: String,
+        image: String,
+        config: Config,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let _ = event_tx.send(NetworkEvent::ImageChangeStarted(internal_id.clone(), image.clone()));
+
+        let _ = event_tx.send(NetworkEvent::PullingImage(internal_id.clone(), image.clone()));
+        Self::pull_image(&docker, &image).await?;
+
+        // Read again, the pull can take a while
+        let mut state = manager.get_container(&internal_id).await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Container '{}' not found", internal_id))?;
+        if state.is_installing {
+            return Err("Cannot change the image while the container is installing".into());
+        }
+        // It may have been started while the image was pulled
+        if let Some(ref container_id) = state.container_id
+            && let Ok(inspect) = docker.inspect_container(container_id, None).await
+                && inspect.state.and_then(|s| s.running).unwrap_or(false) {
+                return Err(ContainerRunning(internal_id).into());
+            }
+
+        let ports = state.ports.clone();
+        let container_id = Self::recreate(&docker, &event_tx, &state, &ports, &image, &config).await?;
+
+        let _ = event_tx.send(NetworkEvent::UpdatingDatabase(internal_id.clone()));
+        let old_image = state.image.replace(image.clone());
+        state.container_id = Some(container_id.clone());
+        state.update_timestamp();
+        Self::save_state(&manager, state).await?;
+
+        let _ = event_tx.send(NetworkEvent::ImageChanged(internal_id.clone(), image.clone()));
+        tracing::info!(
+            "Changed image of {} from {} to {}, container ID {}",
+            internal_id, old_image.as_deref().unwrap_or("unknown"), image, container_id
+        );
+        Ok(())
+    }
+
+    /// Pull `image` for its latest version. A local image is used when the
+    /// registry can't be reached, an image that exists nowhere is an error
+    async fn pull_image(docker: &Docker, image: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let options = Some(CreateImageOptions {
+            from_image: image,
+            ..Default::default()
+        });
+        let mut stream = docker.create_image(options, None, None);
+        let mut pull_error = None;
+        while let Some(result) = stream.next().await {
+            if let Err(e) = result {
+                pull_error = Some(e);
+                break;
+            }
+        }
+
+        match pull_error {
+            None => Ok(()),
+            Some(e) => match docker.inspect_image(image).await {
+                Ok(_) => {
+                    tracing::warn!("Pulling {} failed, using the local image: {}", image, e);
+                    Ok(())
+                }
+                Err(_) => Err(format!("Image {} could not be pulled: {}", image, e).into()),
+            },
+        }
+    }
+
+    async fn save_state(manager: &ContainerManager, state: ContainerState) -> Result<(), Box<dyn std::error::Error>> {
+        let internal_id = state.internal_id.clone();
+        match timeout(Duration::from_secs(5), manager.update_container(state)).await {
+            Ok(Ok(_)) => {
+                tracing::info!("Updated container state in database for {}", internal_id);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(format!("Failed to update database: {}", e).into()),
+            Err(_) => Err("Timeout while updating database".into()),
+        }
+    }
+
+    /// Remove the Docker container and create it again with `ports` and
+    /// `image`, everything else from `state`. Returns the new container ID
+    async fn recreate(
+        docker: &Docker,
+        event_tx: &mpsc::UnboundedSender<NetworkEvent>,
+        state: &ContainerState,
+        new_ports: &[PortBinding],
+        image: &str,
+        config: &Config,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let internal_id = state.internal_id.clone();
+        let mut old_host_config = None;
+
         // Remove old container if exists
         if let Some(old_container_id) = &state.container_id {
             let _ = event_tx.send(NetworkEvent::RemovingOldContainer(internal_id.clone()));
+
+            // Limits set through the update endpoint live only on the container
+            old_host_config = docker.inspect_container(old_container_id, None).await
+                .ok()
+                .and_then(|inspect| inspect.host_config);
             
             // Try to remove with timeout
             let remove_result = timeout(
@@ -190,9 +490,10 @@ impl NetworkRebinder {
         ];
 
         // Add custom mounts
+        let mount_policy = MountPolicy::from_config(&config.storage);
         for (target, source) in &state.mount {
-            if target.trim().is_empty() || source.trim().is_empty() {
-                tracing::warn!("Skipping invalid mount: {} -> {}", target, source);
+            if let Err(e) = validate_mount(target, source).and_then(|_| mount_policy.check_source(source)) {
+                tracing::warn!("Skipping invalid mount {} -> {}: {}", target, source, e);
                 continue;
             }
             
@@ -200,54 +501,22 @@ impl NetworkRebinder {
                 target: Some(target.clone()),
                 source: Some(source.clone()),
                 typ: Some(MountTypeEnum::BIND),
-                read_only: Some(false),
+                read_only: Some(state.read_only_mounts.contains(target)),
                 ..Default::default()
             });
         }
 
-        // Build port bindings with validation
-        let mut port_bindings = PortMap::new();
-        for port in &new_ports {
-            let protocol = port.protocol.to_lowercase();
-            let key = format!("{}/{}", port.container_port, protocol);
-            
-            port_bindings.insert(
-                key,
-                Some(vec![DockerPortBinding {
-                    host_ip: Some("0.0.0.0".to_string()),
-                    host_port: Some(port.host_port.to_string()),
-                }]),
-            );
-        }
-
-        let mut host_config = HostConfig {
-            mounts: Some(mounts),
-            port_bindings: Some(port_bindings),
-            auto_remove: Some(false),
-            ..Default::default()
-        };
-
-        // Apply resource limits with validation
-        if let Some(memory) = state.limits.memory {
-            if memory > 0 {
-                host_config.memory = Some(memory);
-            }
-        }
-        
-        if let Some(cpu) = state.limits.cpu {
-            if cpu > 0.0 && cpu <= 1024.0 {
-                host_config.nano_cpus = Some((cpu * 1_000_000_000.0) as i64);
-            }
+        // Built like at install, so console stdin, the network and the limits carry over
+        let mut container_config = server_container_config(state, image, new_ports, mounts, &RuntimeDefaults {
+            log_rotation: &config.docker.log_rotation,
+            container_user: config.docker.container_user.as_deref(),
+            pids_limit: config.docker.pids_limit,
+            ulimits: &config.docker.ulimits,
+        })?;
+        if let (Some(old), Some(host_config)) = (old_host_config, container_config.host_config.as_mut()) {
+            carry_over_limits(&old, host_config);
         }
 
-        let container_config = ContainerConfig {
-            image: Some(image.clone()),
-            working_dir: Some("/home/container".to_string()),
-            host_config: Some(host_config),
-            entrypoint: Some(vec!["/bin/sh".to_string(), "/app/data/entrypoint.sh".to_string()]),
-            ..Default::default()
-        };
-
         let options = CreateContainerOptions {
             name: format!("lightd-{}", internal_id),
             ..Default::default()
@@ -265,36 +534,18 @@ impl NetworkRebinder {
             Err(_) => return Err("Timeout while creating container".into()),
         };
 
-        let container_id = container.id;
-
-        // Update state with new ports and container ID
-        let _ = event_tx.send(NetworkEvent::UpdatingDatabase(internal_id.clone()));
-        
-        state.ports = new_ports;
-        state.container_id = Some(container_id.clone());
-        state.update_timestamp();
-
-        // Update database with timeout
-        let update_result = timeout(
-            Duration::from_secs(5),
-            manager.update_container(state)
-        ).await;
-
-        match update_result {
-            Ok(Ok(_)) => {
-                tracing::info!("Updated container state in database for {}", internal_id);
-            }
-            Ok(Err(e)) => {
-                return Err(format!("Failed to update database: {}", e).into());
-            }
-            Err(_) => {
-                return Err("Timeout while updating database".into());
-            }
-        }
-
-        let _ = event_tx.send(NetworkEvent::RebindingComplete(internal_id.clone()));
-
-        tracing::info!("Network rebinding complete for {} with container ID {}", internal_id, container_id);
-        Ok(())
+        Ok(container.id)
     }
 }
+
+/// Copy the limits the state doesn't record (swap, reservation, CPU shares,
+/// CFS period/quota, cpuset, block IO weight) from the old container
+fn carry_over_limits(old: &HostConfig, host_config: &mut HostConfig) {
+    host_config.memory_swap = old.memory_swap;
+    host_config.memory_reservation = old.memory_reservation;
+    host_config.cpu_shares = old.cpu_shares;
+    host_config.cpu_period = old.cpu_period;
+    host_config.cpu_quota = old.cpu_quota;
+    host_config.cpuset_cpus = old.cpuset_cpus.clone();
+    host_config.blkio_weight = old.blkio_weight;
+}
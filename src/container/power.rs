@@ -1,38 +1,180 @@
 use super::manager::ContainerManager;
+use crate::error::LightdError;
 use bollard::Docker;
-use bollard::container::{StartContainerOptions, KillContainerOptions, RestartContainerOptions};
+use super::state::ContainerState;
+use bollard::container::{
+    AttachContainerOptions, KillContainerOptions, StartContainerOptions, StopContainerOptions,
+};
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{mpsc, Mutex, OwnedMutexGuard};
+use tracing::Instrument;
+
+/// Grace period used when a container has no `stop_timeout` (same as Docker's default)
+pub const DEFAULT_STOP_TIMEOUT_SECS: u64 = 10;
+
+/// How often `wait_until_ready` reads the runtime state
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where a start ended up after `wait_until_ready`
+#[derive(Debug, Clone, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Runtime state when the wait ended: offline, starting, running, ...
+    pub state: String,
+    pub timed_out: bool,
+    pub waited_ms: u64,
+}
+
+/// `Some(ready)` once `state` settles a start. Without start patterns nothing
+/// moves a container past `starting`, so that already counts as ready, and
+/// going offline after it was up means the server exited.
+fn start_outcome(state: &str, has_patterns: bool, seen_up: bool) -> Option<bool> {
+    match state {
+        "running" => Some(true),
+        "starting" if !has_patterns => Some(true),
+        "offline" if seen_up => Some(false),
+        _ => None,
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum PowerAction {
     Start,
+    /// Graceful stop: stop command, grace period, SIGTERM, then SIGKILL
+    Stop,
     Kill,
     Restart,
+    /// Deliver a signal without stopping the container (e.g. SIGHUP to reload)
+    Signal(String),
+    /// Freeze every process in the container (cgroup freezer), memory stays allocated
+    Pause,
+    Unpause,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "args", rename_all = "snake_case")]
 pub enum PowerEvent {
     Starting(String),
     Started(String),
+    Stopping(String),
+    Stopped(String),
     Killing(String),
     Killed(String),
     Restarting(String),
     Restarted(String),
+    Signaled(String, String),
+    Paused(String),
+    Unpaused(String),
+    /// Action rejected because another one is still running for this container
+    Busy(String),
     Error(String, String),
 }
 
+impl PowerEvent {
+    /// Internal ID of the container the event is about
+    pub fn container_id(&self) -> &str {
+        match self {
+            Self::Starting(id)
+            | Self::Started(id)
+            | Self::Stopping(id)
+            | Self::Stopped(id)
+            | Self::Killing(id)
+            | Self::Killed(id)
+            | Self::Restarting(id)
+            | Self::Restarted(id)
+            | Self::Signaled(id, _)
+            | Self::Paused(id)
+            | Self::Unpaused(id)
+            | Self::Busy(id)
+            | Self::Error(id, _) => id,
+        }
+    }
+}
+
+/// Signals that may be sent through `PowerAction::Signal`, with their numbers
+const ALLOWED_SIGNALS: &[(&str, u8)] = &[
+    ("SIGHUP", 1),
+    ("SIGINT", 2),
+    ("SIGQUIT", 3),
+    ("SIGKILL", 9),
+    ("SIGUSR1", 10),
+    ("SIGUSR2", 12),
+    ("SIGTERM", 15),
+    ("SIGCONT", 18),
+    ("SIGSTOP", 19),
+    ("SIGWINCH", 28),
+];
+
+/// Normalize a signal name or number ("hup", "SIGHUP", "1") to its canonical name
+pub fn normalize_signal(input: &str) -> Option<String> {
+    let input = input.trim();
+
+    if let Ok(number) = input.parse::<u8>() {
+        return ALLOWED_SIGNALS
+            .iter()
+            .find(|(_, n)| *n == number)
+            .map(|(name, _)| name.to_string());
+    }
+
+    let upper = input.to_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+
+    ALLOWED_SIGNALS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(name, _)| name.to_string())
+}
+
+/// Returned when a power action is already in flight for the container
+#[derive(Debug)]
+pub struct PowerBusy;
+
+impl std::fmt::Display for PowerBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Another power action is already in progress")
+    }
+}
+
+impl std::error::Error for PowerBusy {}
+
+/// Power lock of one container
+#[derive(Default)]
+struct PowerLock {
+    mutex: Arc<Mutex<()>>,
+    /// Set by a Kill that cut into the running action, which then stops short
+    preempted: Arc<AtomicBool>,
+}
+
+/// Held by an action while it runs
+struct PowerGuard {
+    _guard: Option<OwnedMutexGuard<()>>,
+    preempted: Arc<AtomicBool>,
+}
+
+/// Executes power actions one at a time per container.
+///
+/// Each container has its own lock that is held for the whole Docker call.
+/// Actions are not queued: if one is still running, the new one is rejected
+/// with `PowerBusy` and a `Busy` event, so callers never act on stale state.
+/// Kill is the exception, it cuts into a running action (a graceful stop
+/// stuck in its grace period) and that action ends without going further.
 pub struct PowerManager {
     manager: Arc<ContainerManager>,
     docker: Docker,
     event_tx: mpsc::UnboundedSender<PowerEvent>,
+    locks: DashMap<String, Arc<PowerLock>>,
 }
 
 impl PowerManager {
     pub fn new(
         manager: Arc<ContainerManager>,
     ) -> Result<(Self, mpsc::UnboundedReceiver<PowerEvent>), Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Docker::connect_with_local_defaults()?;
+        let docker = crate::container::docker::client()?;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
         Ok((
@@ -40,57 +182,167 @@ impl PowerManager {
                 manager,
                 docker,
                 event_tx,
+                locks: DashMap::new(),
             },
             event_rx,
         ))
     }
 
+    /// Take the container's power lock, or fail with `PowerBusy`. A Kill
+    /// gets through without it and preempts the action holding it.
+    fn try_lock(
+        &self,
+        internal_id: &str,
+        action: &PowerAction,
+    ) -> Result<PowerGuard, Box<dyn std::error::Error + Send + Sync>> {
+        let lock = self
+            .locks
+            .entry(internal_id.to_string())
+            .or_default()
+            .clone();
+
+        match lock.mutex.clone().try_lock_owned() {
+            Ok(guard) => {
+                lock.preempted.store(false, Ordering::SeqCst);
+                Ok(PowerGuard { _guard: Some(guard), preempted: lock.preempted.clone() })
+            }
+            // A hung graceful stop must not lock out the way to end it
+            Err(_) if matches!(action, PowerAction::Kill) => {
+                lock.preempted.store(true, Ordering::SeqCst);
+                tracing::warn!("Kill preempts the running power action of {}", internal_id);
+                Ok(PowerGuard { _guard: None, preempted: Arc::default() })
+            }
+            // Reject instead of queueing so a restart can't start while a stop is in flight
+            Err(_) => {
+                let _ = self.event_tx.send(PowerEvent::Busy(internal_id.to_string()));
+                tracing::warn!("Power action {:?} rejected for {}: busy", action, internal_id);
+                Err(Box::new(PowerBusy))
+            }
+        }
+    }
+
+    /// Run an action and wait for Docker to finish it, for callers that
+    /// need the outcome (bulk actions) rather than a fire-and-forget start
+    pub async fn run_action(
+        &self,
+        internal_id: String,
+        action: PowerAction,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guard = self.try_lock(&internal_id, &action)?;
+        let result = Self::execute_power_action(
+            self.manager.clone(),
+            self.docker.clone(),
+            self.event_tx.clone(),
+            internal_id.clone(),
+            action,
+            &guard.preempted,
+        )
+        .await;
+        if let Err(ref e) = result {
+            let _ = self.event_tx.send(PowerEvent::Error(internal_id.clone(), e.to_string()));
+            tracing::error!("Power action failed for {}: {}", internal_id, e);
+        }
+        result
+    }
+
     pub async fn execute_action(
         &self,
         internal_id: String,
         action: PowerAction,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let guard = self.try_lock(&internal_id, &action)?;
+
         let manager = self.manager.clone();
         let docker = self.docker.clone();
         let event_tx = self.event_tx.clone();
 
         // Spawn async non-blocking job
         tokio::spawn(async move {
+            // Hold the container lock until the Docker call finishes
             if let Err(e) = Self::execute_power_action(
                 manager,
                 docker,
                 event_tx.clone(),
                 internal_id.clone(),
                 action,
+                &guard.preempted,
             )
             .await
             {
                 let _ = event_tx.send(PowerEvent::Error(internal_id.clone(), e.to_string()));
                 tracing::error!("Power action failed for {}: {}", internal_id, e);
             }
-        });
+        }.in_current_span());
 
         Ok(())
     }
 
+    /// Poll the runtime state after a start until the server is ready, it went
+    /// offline again or `timeout` passed
+    pub async fn wait_until_ready(
+        &self,
+        internal_id: &str,
+        timeout: Duration,
+    ) -> Result<Readiness, Box<dyn std::error::Error + Send + Sync>> {
+        let has_patterns = !self
+            .manager
+            .get_container(internal_id)
+            .await?
+            .ok_or(LightdError::NotFound("Container"))?
+            .start_pattern_list()
+            .is_empty();
+
+        let started = Instant::now();
+        let mut seen_up = false;
+        loop {
+            // The log streamer moves the state along, it notices a start within ~2s
+            let state = self
+                .manager
+                .runtime_state(internal_id)
+                .unwrap_or_else(|| "offline".to_string());
+            seen_up |= state == "starting" || state == "running";
+
+            let outcome = start_outcome(&state, has_patterns, seen_up);
+            let timed_out = outcome.is_none() && started.elapsed() >= timeout;
+            if outcome.is_some() || timed_out {
+                return Ok(Readiness {
+                    ready: outcome.unwrap_or(false),
+                    state,
+                    timed_out,
+                    waited_ms: started.elapsed().as_millis() as u64,
+                });
+            }
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
     async fn execute_power_action(
         manager: Arc<ContainerManager>,
         docker: Docker,
         event_tx: mpsc::UnboundedSender<PowerEvent>,
         internal_id: String,
         action: PowerAction,
+        preempted: &AtomicBool,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Get container state
         let state = match manager.get_container(&internal_id).await {
             Ok(Some(state)) => state,
-            Ok(None) => return Err("Container not found".into()),
+            Ok(None) => return Err(LightdError::NotFound("Container").into()),
             Err(e) => return Err(format!("Failed to get container: {}", e).into()),
         };
 
         let container_id = state
             .container_id
+            .clone()
             .ok_or("Pending")?;
 
+        // The exit this causes is not a crash
+        match action {
+            PowerAction::Start => manager.expect_stop(&internal_id, false),
+            PowerAction::Stop | PowerAction::Kill | PowerAction::Restart => manager.expect_stop(&internal_id, true),
+            PowerAction::Signal(_) | PowerAction::Pause | PowerAction::Unpause => {}
+        }
+
         match action {
             PowerAction::Start => {
                 let _ = event_tx.send(PowerEvent::Starting(internal_id.clone()));
@@ -103,6 +355,19 @@ impl PowerManager {
                 let _ = event_tx.send(PowerEvent::Started(internal_id.clone()));
                 tracing::info!("Container started: {}", internal_id);
             }
+            PowerAction::Stop => {
+                let _ = event_tx.send(PowerEvent::Stopping(internal_id.clone()));
+                tracing::info!("Stopping container: {}", internal_id);
+
+                Self::graceful_stop(&docker, &state, &container_id, preempted).await?;
+                if preempted.load(Ordering::SeqCst) {
+                    tracing::info!("Stop of {} ended by a kill", internal_id);
+                    return Ok(());
+                }
+
+                let _ = event_tx.send(PowerEvent::Stopped(internal_id.clone()));
+                tracing::info!("Container stopped: {}", internal_id);
+            }
             PowerAction::Kill => {
                 let _ = event_tx.send(PowerEvent::Killing(internal_id.clone()));
                 tracing::info!("Killing container: {}", internal_id);
@@ -118,20 +383,154 @@ impl PowerManager {
                 let _ = event_tx.send(PowerEvent::Killed(internal_id.clone()));
                 tracing::info!("Container killed: {}", internal_id);
             }
+            PowerAction::Signal(signal) => {
+                let signal = normalize_signal(&signal)
+                    .ok_or_else(|| format!("Signal not allowed: {}", signal))?;
+                tracing::info!("Sending {} to container: {}", signal, internal_id);
+
+                docker
+                    .kill_container(
+                        &container_id,
+                        Some(KillContainerOptions { signal: signal.as_str() }),
+                    )
+                    .await?;
+
+                let _ = event_tx.send(PowerEvent::Signaled(internal_id.clone(), signal));
+            }
             PowerAction::Restart => {
                 let _ = event_tx.send(PowerEvent::Restarting(internal_id.clone()));
                 tracing::info!("Restarting container: {}", internal_id);
 
+                // The same save sequence as a stop, then a plain start
+                Self::graceful_stop(&docker, &state, &container_id, preempted).await?;
+                if preempted.load(Ordering::SeqCst) {
+                    tracing::info!("Restart of {} ended by a kill, not starting again", internal_id);
+                    return Ok(());
+                }
                 docker
-                    .restart_container(&container_id, None::<RestartContainerOptions>)
+                    .start_container(&container_id, None::<StartContainerOptions<String>>)
                     .await?;
 
                 let _ = event_tx.send(PowerEvent::Restarted(internal_id.clone()));
                 tracing::info!("Container restarted: {}", internal_id);
             }
+            PowerAction::Pause => {
+                tracing::info!("Pausing container: {}", internal_id);
+                docker.pause_container(&container_id).await?;
+
+                let _ = event_tx.send(PowerEvent::Paused(internal_id.clone()));
+                tracing::info!("Container paused: {}", internal_id);
+            }
+            PowerAction::Unpause => {
+                tracing::info!("Resuming container: {}", internal_id);
+                docker.unpause_container(&container_id).await?;
+
+                let _ = event_tx.send(PowerEvent::Unpaused(internal_id.clone()));
+                tracing::info!("Container resumed: {}", internal_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop command, grace period, SIGTERM, then SIGKILL. Returns early once
+    /// a Kill preempted the action.
+    async fn graceful_stop(
+        docker: &Docker,
+        state: &ContainerState,
+        container_id: &str,
+        preempted: &AtomicBool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let internal_id = &state.internal_id;
+        let grace = state.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT_SECS);
+
+        // Ask the server to shut itself down first so it can save
+        if let Some(ref command) = state.stop_command {
+            match Self::send_stdin(docker, container_id, command).await {
+                Ok(()) => {
+                    tracing::info!("Sent stop command to {}, waiting up to {}s", internal_id, grace);
+                    Self::wait_for_exit(docker, container_id, Duration::from_secs(grace), preempted).await;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to send stop command to {}: {}", internal_id, e);
+                }
+            }
+        }
+        if preempted.load(Ordering::SeqCst) {
+            return Ok(());
         }
 
+        // SIGTERM, then SIGKILL from Docker after the grace period
+        if Self::is_running(docker, container_id).await {
+            docker
+                .stop_container(container_id, Some(StopContainerOptions { t: grace as i64 }))
+                .await?;
+        }
+
+        // Docker stop can return before the process is really gone
+        if Self::is_running(docker, container_id).await {
+            tracing::warn!("Container {} survived stop, forcing kill", internal_id);
+            docker
+                .kill_container(
+                    container_id,
+                    Some(KillContainerOptions { signal: "SIGKILL" }),
+                )
+                .await?;
+        }
         Ok(())
     }
+
+    /// Write a single line to the container's stdin
+    async fn send_stdin(
+        docker: &Docker,
+        container_id: &str,
+        command: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let attach_opts = AttachContainerOptions::<String> {
+            stdin: Some(true),
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        let attached = docker.attach_container(container_id, Some(attach_opts)).await?;
+        let mut input = attached.input;
+        input.write_all(format!("{}\n", command).as_bytes()).await?;
+        input.flush().await?;
+
+        Ok(())
+    }
+
+    async fn is_running(docker: &Docker, container_id: &str) -> bool {
+        match docker.inspect_container(container_id, None).await {
+            Ok(info) => info.state.and_then(|s| s.running).unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// Poll until the container exits, the timeout passes or a Kill preempts
+    async fn wait_for_exit(docker: &Docker, container_id: &str, timeout: Duration, preempted: &AtomicBool) {
+        let start = std::time::Instant::now();
+        while start.elapsed() < timeout {
+            if preempted.load(Ordering::SeqCst) || !Self::is_running(docker, container_id).await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_outcome() {
+        assert_eq!(start_outcome("running", true, true), Some(true));
+        assert_eq!(start_outcome("starting", false, true), Some(true));
+        assert_eq!(start_outcome("starting", true, true), None);
+
+        // Still offline from before the start, the log streamer hasn't caught up
+        assert_eq!(start_outcome("offline", true, false), None);
+        assert_eq!(start_outcome("offline", true, true), Some(false));
+    }
+}
@@ -0,0 +1,203 @@
+//! Cleanup of Docker resources Lightd no longer tracks
+//!
+//! Failed installs and crashed daemons can leave `lightd-<internal_id>`
+//! containers and `lightd-net-<id>` networks behind without a database record.
+//! The reconciler lists them and removes the ones nothing references. The
+//! shared `lightd_network` is never touched. An install container
+//! (`lightd-<internal_id>-install`) is left alone while its container is
+//! still installing.
+//!
+//! At startup it also records each known container's runtime state as Docker
+//! reports it, so status is right before any WebSocket client reconnects.
+
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::network::ListNetworksOptions;
+use bollard::Docker;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::manager::ContainerManager;
+
+const CONTAINER_PREFIX: &str = "lightd-";
+const NETWORK_PREFIX: &str = "lightd-net-";
+const INSTALL_SUFFIX: &str = "-install";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanContainer {
+    pub name: String,
+    pub docker_id: String,
+    pub internal_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanNetwork {
+    pub name: String,
+    pub network_id: String,
+}
+
+/// What a reconcile run found, and removed unless it was a dry run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReconcileReport {
+    pub dry_run: bool,
+    pub containers: Vec<OrphanContainer>,
+    pub networks: Vec<OrphanNetwork>,
+    /// Orphans that could not be removed, with the Docker error
+    pub errors: Vec<String>,
+}
+
+impl ReconcileReport {
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty() && self.networks.is_empty()
+    }
+}
+
+pub struct Reconciler {
+    manager: Arc<ContainerManager>,
+    docker: Docker,
+}
+
+impl Reconciler {
+    pub fn new(manager: Arc<ContainerManager>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let docker = crate::container::docker::client()?;
+        Ok(Self { manager, docker })
+    }
+
+    /// Find orphaned containers and networks, removing them unless `dry_run`
+    pub async fn reconcile(&self, dry_run: bool) -> Result<ReconcileReport, Box<dyn std::error::Error + Send + Sync>> {
+        let known = self.manager.list_containers().await?;
+        let internal_ids: HashSet<String> = known.iter().map(|c| c.internal_id.clone()).collect();
+        let docker_ids: HashSet<String> = known.iter().filter_map(|c| c.container_id.clone()).collect();
+        let installing: HashSet<String> = known.iter()
+            .filter(|c| c.is_installing)
+            .map(|c| c.internal_id.clone())
+            .collect();
+
+        let mut report = ReconcileReport {
+            dry_run,
+            ..Default::default()
+        };
+
+        // Containers first, a network can't be removed while one is attached
+        let mut filters = HashMap::new();
+        filters.insert("name".to_string(), vec![CONTAINER_PREFIX.to_string()]);
+        let containers = self.docker.list_containers(Some(ListContainersOptions {
+            all: true,
+            filters,
+            ..Default::default()
+        })).await?;
+
+        for container in containers {
+            let docker_id = container.id.unwrap_or_default();
+            let Some(name) = container.names.unwrap_or_default().into_iter()
+                .map(|n| n.trim_start_matches('/').to_string())
+                .find(|n| internal_id_from_name(n).is_some())
+            else {
+                continue;
+            };
+            let internal_id = internal_id_from_name(&name).unwrap_or_default().to_string();
+
+            // The install container is only needed while the install runs
+            let in_use = if is_install_container(&name) {
+                installing.contains(&internal_id)
+            } else {
+                internal_ids.contains(&internal_id) || docker_ids.contains(&docker_id)
+            };
+            if in_use {
+                continue;
+            }
+
+            if !dry_run {
+                let removed = self.docker.remove_container(&docker_id, Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                })).await;
+                if let Err(e) = removed {
+                    report.errors.push(format!("container {}: {}", name, e));
+                    continue;
+                }
+                tracing::info!("Removed orphaned container {} ({})", name, docker_id);
+            }
+
+            report.containers.push(OrphanContainer { name, docker_id, internal_id });
+        }
+
+        let mut filters = HashMap::new();
+        filters.insert("name".to_string(), vec![NETWORK_PREFIX.to_string()]);
+        let networks = self.docker.list_networks(Some(ListNetworksOptions { filters })).await?;
+
+        for network in networks {
+            let name = network.name.unwrap_or_default();
+            let Some(owner) = name.strip_prefix(NETWORK_PREFIX) else {
+                continue;
+            };
+
+            if internal_ids.contains(owner) || docker_ids.contains(owner) {
+                continue;
+            }
+
+            let network_id = network.id.unwrap_or_default();
+            if !dry_run {
+                if let Err(e) = self.docker.remove_network(&network_id).await {
+                    report.errors.push(format!("network {}: {}", name, e));
+                    continue;
+                }
+                tracing::info!("Removed orphaned network {}", name);
+            }
+
+            report.networks.push(OrphanNetwork { name, network_id });
+        }
+
+        Ok(report)
+    }
+
+    /// Persist running/paused/offline for every known container from `docker inspect`.
+    /// Returns how many were running or paused.
+    pub async fn sync_runtime_states(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let mut active = 0;
+        for container in self.manager.list_containers().await? {
+            let docker_state = match &container.container_id {
+                Some(id) => self.docker.inspect_container(id, None).await.ok().and_then(|info| info.state),
+                None => None,
+            };
+            let runtime_state = match docker_state {
+                Some(s) if s.paused == Some(true) => "paused",
+                Some(s) if s.running == Some(true) => "running",
+                _ => "offline",
+            };
+            if runtime_state != "offline" {
+                active += 1;
+            }
+            self.manager.set_runtime_state(&container.internal_id, runtime_state)?;
+        }
+        Ok(active)
+    }
+}
+
+/// Internal ID from a `lightd-<internal_id>` or `lightd-<internal_id>-install` container name
+fn internal_id_from_name(name: &str) -> Option<&str> {
+    // Docker's name filter is a substring match, so skip anything else it returns
+    let id = name.strip_prefix(CONTAINER_PREFIX)?;
+    Some(id.strip_suffix(INSTALL_SUFFIX).unwrap_or(id)).filter(|id| !id.is_empty())
+}
+
+fn is_install_container(name: &str) -> bool {
+    internal_id_from_name(name).is_some() && name.ends_with(INSTALL_SUFFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_id_from_name() {
+        assert_eq!(internal_id_from_name("lightd-abc123"), Some("abc123"));
+        assert_eq!(internal_id_from_name("lightd-"), None);
+        assert_eq!(internal_id_from_name("my-lightd-abc"), None);
+        assert_eq!(internal_id_from_name("postgres"), None);
+        assert_eq!(internal_id_from_name("lightd-abc123-install"), Some("abc123"));
+        assert_eq!(internal_id_from_name("lightd--install"), None);
+        assert!(is_install_container("lightd-abc123-install"));
+        assert!(!is_install_container("lightd-abc123"));
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum InstallState {
@@ -8,11 +9,29 @@ pub enum InstallState {
     Failed,
 }
 
+impl std::str::FromStr for InstallState {
+    type Err = String;
+
+    /// Parse a list filter value, "running" is accepted for installed containers
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ready" | "running" => Ok(InstallState::Ready),
+            "installing" => Ok(InstallState::Installing),
+            "failed" => Ok(InstallState::Failed),
+            _ => Err(format!("Unknown state '{}', expected running, installing or failed", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContainerState {
     pub internal_id: String,
     pub volume_id: String,
+    /// Custom bind mounts, container path -> host path
     pub mount: HashMap<String, String>,
+    /// Container paths of custom mounts that are bound read-only
+    #[serde(default)]
+    pub read_only_mounts: Vec<String>,
     pub limits: ContainerLimits,
     pub container_id: Option<String>,
     pub ports: Vec<PortBinding>, // Changed to Vec of PortBinding
@@ -24,6 +43,87 @@ pub struct ContainerState {
     /// Pattern to detect when server is fully started (string or regex)
     #[serde(default)]
     pub start_pattern: Option<String>,
+    /// Regexes that mark the server as started, the first match wins. Set
+    /// through the start-pattern endpoint, takes over from `start_pattern`
+    #[serde(default)]
+    pub start_patterns: Vec<String>,
+    /// Command written to stdin to shut the server down gracefully (e.g. "stop")
+    #[serde(default)]
+    pub stop_command: Option<String>,
+    /// Seconds to wait for a graceful stop before Docker sends SIGKILL
+    #[serde(default)]
+    pub stop_timeout: Option<u64>,
+    /// Docker image the container was created from
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Free-form metadata for grouping, e.g. customer=acme
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Overrides `docker.log_rotation` from the config for this container
+    #[serde(default)]
+    pub log_rotation: Option<LogRotation>,
+    /// Times the server exited with an error without a stop, kill or restart
+    /// from Lightd, i.e. crashed
+    #[serde(default)]
+    pub restart_count: u64,
+    /// Exit code of the last exit, requested or not
+    #[serde(default)]
+    pub last_exit_code: Option<i64>,
+    #[serde(default)]
+    pub last_exit_at: Option<u64>,
+    /// Why the server last crashed, e.g. "Container killed: out of memory"
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// `uid` or `uid:gid` the server runs as, overrides `docker.container_user`
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Why the last install failed
+    #[serde(default)]
+    pub install_error: Option<String>,
+    /// Times this install was restarted after a daemon restart interrupted it
+    #[serde(default)]
+    pub install_resumes: u32,
+    /// Overrides `docker.pids_limit` from the config for this container
+    #[serde(default)]
+    pub pids_limit: Option<i64>,
+    /// Overrides single ulimits of `docker.ulimits` for this container
+    #[serde(default)]
+    pub ulimits: Option<Ulimits>,
+    /// Environment of the server and install script, e.g. {"EULA": "true"}
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+/// Check a label key, ':' and ',' are reserved by the list filter syntax
+pub fn validate_label_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > 64 {
+        return Err(format!("Label key '{}' must be 1-64 characters", key));
+    }
+    if key.contains([':', ',']) || key.chars().any(char::is_whitespace) {
+        return Err(format!("Label key '{}' must not contain ':', ',' or whitespace", key));
+    }
+    Ok(())
+}
+
+/// Environment variable names, a shell identifier so install scripts can use them
+pub fn validate_env_key(key: &str) -> Result<(), String> {
+    let mut chars = key.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("Environment variable '{}' must be letters, digits and '_', not starting with a digit", key));
+    }
+    Ok(())
+}
+
+/// `KEY=value` list for the Docker config, sorted so recreates compare equal
+pub fn docker_env(env: &HashMap<String, String>) -> Option<Vec<String>> {
+    if env.is_empty() {
+        return None;
+    }
+    let mut vars: Vec<String> = env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    vars.sort();
+    Some(vars)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +131,295 @@ pub struct PortBinding {
     pub container_port: u16,
     pub host_port: u16,
     pub protocol: String,
+    /// Host address the port is published on
+    #[serde(default = "default_host_ip")]
+    pub host_ip: String,
+    /// Pool entry the port was allocated from, freed when the binding goes away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_id: Option<String>,
+}
+
+fn default_host_ip() -> String {
+    "0.0.0.0".to_string()
+}
+
+/// Lowercase a port protocol, only tcp and udp can be published
+pub fn normalize_protocol(protocol: &str) -> Result<String, String> {
+    match protocol.trim().to_lowercase().as_str() {
+        "tcp" => Ok("tcp".to_string()),
+        "udp" => Ok("udp".to_string()),
+        _ => Err(format!("Invalid protocol '{}', must be 'tcp' or 'udp'", protocol)),
+    }
+}
+
+impl PortBinding {
+    /// Docker's key for the container side, e.g. "27015/udp"
+    pub fn docker_key(&self) -> String {
+        format!("{}/{}", self.container_port, self.protocol.to_lowercase())
+    }
+}
+
+/// `HostConfig.port_bindings` and `Config.exposed_ports` for a set of bindings.
+/// A port bound on both protocols gets one entry per protocol.
+pub fn docker_ports(ports: &[PortBinding]) -> (bollard::models::PortMap, HashMap<String, HashMap<(), ()>>) {
+    let mut port_bindings = bollard::models::PortMap::new();
+    let mut exposed_ports = HashMap::new();
+
+    for port in ports {
+        let key = port.docker_key();
+        port_bindings
+            .entry(key.clone())
+            .or_insert_with(|| Some(Vec::new()))
+            .get_or_insert_with(Vec::new)
+            .push(bollard::models::PortBinding {
+                host_ip: Some(port.host_ip.clone()),
+                host_port: Some(port.host_port.to_string()),
+            });
+        exposed_ports.insert(key, HashMap::new());
+    }
+
+    (port_bindings, exposed_ports)
+}
+
+/// Parse a run-as user, `uid` or `uid:gid` with the gid defaulting to the uid.
+/// Only numeric ids, names would be resolved inside the image, not on the host
+/// where the volume is chowned.
+pub fn parse_run_as(user: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid user '{}', expected a numeric uid or uid:gid", user);
+    let (uid, gid) = match user.trim().split_once(':') {
+        Some((uid, gid)) => (uid, gid),
+        None => (user.trim(), user.trim()),
+    };
+    Ok((uid.parse().map_err(|_| invalid())?, gid.parse().map_err(|_| invalid())?))
+}
+
+/// Container paths a custom mount may not cover
+const RESERVED_MOUNT_TARGETS: [&str; 10] = [
+    "/", "/bin", "/boot", "/dev", "/etc", "/lib", "/proc", "/sys",
+    "/home/container", "/app/data",
+];
+
+/// Host paths that must never be bound into a container, nor anything below
+/// them. `/` is only refused as itself, every other path is below it.
+const DANGEROUS_MOUNT_SOURCES: [&str; 15] = [
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/root", "/run",
+    "/sbin", "/sys", "/usr", "/var/lib/docker", "/var/run", "/var/log",
+];
+
+/// Check a custom mount before it is handed to Docker
+pub fn validate_mount(target: &str, source: &str) -> Result<(), String> {
+    use std::path::Component;
+
+    if target.is_empty() || !target.starts_with('/') {
+        return Err(format!("Invalid target path: {}", target));
+    }
+    let trimmed = match target.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    if RESERVED_MOUNT_TARGETS.contains(&trimmed) {
+        return Err(format!("Cannot mount to system path: {}", target));
+    }
+
+    let path = Path::new(source);
+    if source.is_empty()
+        || !path.is_absolute()
+        || path.components().any(|c| matches!(c, Component::ParentDir | Component::CurDir))
+    {
+        return Err(format!("Invalid source path for target {}: {}", target, source));
+    }
+    if path == Path::new("/") || DANGEROUS_MOUNT_SOURCES.iter().any(|dangerous| path.starts_with(dangerous)) {
+        return Err(format!("Cannot mount host system path: {}", source));
+    }
+    Ok(())
+}
+
+/// Where custom mount sources may live on the host. Sources must be below one
+/// of `allowed` and outside lightd's own storage, which holds every
+/// container's volume. Symlinks are resolved first, so a link inside an
+/// allowed directory can't point Docker at e.g. the Docker socket.
+#[derive(Debug, Clone)]
+pub struct MountPolicy {
+    /// `storage.mount_allowlist`, empty refuses every custom mount
+    allowed: Vec<PathBuf>,
+    /// `storage.base_path`, `volumes_path` and `containers_path`
+    storage: Vec<PathBuf>,
+}
+
+/// The path with links resolved, as given when it doesn't exist
+fn resolve_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl MountPolicy {
+    /// Both lists keep each path as given and resolved
+    pub fn new(allowed: &[String], storage: &[&str]) -> Self {
+        let with_resolved = |path: &str| [PathBuf::from(path), resolve_path(Path::new(path))];
+        Self {
+            allowed: allowed.iter().flat_map(|p| with_resolved(p)).collect(),
+            storage: storage.iter().flat_map(|p| with_resolved(p)).collect(),
+        }
+    }
+
+    pub fn from_config(storage: &crate::config::config::StorageConfig) -> Self {
+        Self::new(
+            &storage.mount_allowlist,
+            &[&storage.base_path, &storage.volumes_path, &storage.containers_path],
+        )
+    }
+
+    /// Check a source that already passed `validate_mount`
+    pub fn check_source(&self, source: &str) -> Result<(), String> {
+        let path = Path::new(source);
+        let resolved = resolve_path(path);
+        if [path, resolved.as_path()].iter().any(|p| self.storage.iter().any(|storage| p.starts_with(storage))) {
+            return Err(format!("Cannot mount lightd storage into a container: {}", source));
+        }
+        if !self.allowed.iter().any(|allowed| resolved.starts_with(allowed)) {
+            return Err(format!("Mount source {} is outside storage.mount_allowlist", source));
+        }
+        Ok(())
+    }
+}
+
+/// Check every start pattern compiles, naming the first one that doesn't
+pub fn validate_start_patterns(patterns: &[String]) -> Result<(), String> {
+    for (index, pattern) in patterns.iter().enumerate() {
+        if pattern.is_empty() {
+            return Err(format!("Start pattern {} is empty", index));
+        }
+        if let Err(e) = regex::Regex::new(pattern) {
+            return Err(format!("Start pattern {} ('{}') does not compile: {}", index, pattern, e));
+        }
+    }
+    Ok(())
+}
+
+/// Rotation of Docker's json-file logs, which live outside the volume quota
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LogRotation {
+    /// Size a log file grows to before rotating, in Docker units, e.g. "10m"
+    pub max_size: String,
+    /// Log files kept, including the current one
+    pub max_file: u32,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        Self {
+            max_size: "10m".to_string(),
+            max_file: 3,
+        }
+    }
+}
+
+impl LogRotation {
+    pub fn validate(&self) -> Result<(), String> {
+        let size = self.max_size.trim();
+        let digits = size.trim_end_matches(['k', 'm', 'g']);
+        if !digits.chars().all(|c| c.is_ascii_digit()) || !digits.parse::<u64>().is_ok_and(|n| n > 0) {
+            return Err(format!("max_size '{}' must be a positive number with an optional k, m or g suffix", self.max_size));
+        }
+        if self.max_file == 0 {
+            return Err("max_file must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// `HostConfig.log_config` for the json-file driver
+    pub fn log_config(&self) -> bollard::models::HostConfigLogConfig {
+        bollard::models::HostConfigLogConfig {
+            typ: Some("json-file".to_string()),
+            config: Some(HashMap::from([
+                ("max-size".to_string(), self.max_size.trim().to_string()),
+                ("max-file".to_string(), self.max_file.to_string()),
+            ])),
+        }
+    }
+}
+
+/// Highest pid count Linux hands out (`pid_max` on 64-bit)
+const MAX_PIDS: i64 = 4_194_304;
+
+/// Check a `pids_limit`, -1 means unlimited
+pub fn validate_pids_limit(limit: i64) -> Result<(), String> {
+    if limit != -1 && !(16..=MAX_PIDS).contains(&limit) {
+        return Err(format!("pids_limit must be between 16 and {} or -1 for unlimited", MAX_PIDS));
+    }
+    Ok(())
+}
+
+/// `nofile` and `nproc` ulimits, soft and hard limit get the same value
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Ulimits {
+    /// Open file descriptors per process
+    #[serde(default)]
+    pub nofile: Option<u64>,
+    /// Processes of the container's user. Counted per uid across the host,
+    /// not per container, and not enforced for root
+    #[serde(default)]
+    pub nproc: Option<u64>,
+}
+
+impl Ulimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(nofile) = self.nofile
+            && !(64..=1_048_576).contains(&nofile) {
+            return Err("nofile must be between 64 and 1048576".to_string());
+        }
+        if let Some(nproc) = self.nproc
+            && !(16..=MAX_PIDS as u64).contains(&nproc) {
+            return Err(format!("nproc must be between 16 and {}", MAX_PIDS));
+        }
+        Ok(())
+    }
+
+    /// These ulimits, with the ones not set taken from `defaults`
+    pub fn or(&self, defaults: &Ulimits) -> Ulimits {
+        Ulimits {
+            nofile: self.nofile.or(defaults.nofile),
+            nproc: self.nproc.or(defaults.nproc),
+        }
+    }
+
+    /// `HostConfig.ulimits`, empty when nothing is set
+    pub fn docker_ulimits(&self) -> Vec<bollard::models::ResourcesUlimits> {
+        [("nofile", self.nofile), ("nproc", self.nproc)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let value = value? as i64;
+                Some(bollard::models::ResourcesUlimits {
+                    name: Some(name.to_string()),
+                    soft: Some(value),
+                    hard: Some(value),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Set `pids_limit` and `ulimits` on a host config. Both are cgroup/rlimit
+/// features Docker only has on Linux, elsewhere they are skipped with a warning
+pub fn apply_process_limits(
+    host_config: &mut bollard::models::HostConfig,
+    pids_limit: i64,
+    ulimits: &Ulimits,
+) {
+    #[cfg(target_os = "linux")]
+    {
+        host_config.pids_limit = Some(pids_limit);
+        let ulimits = ulimits.docker_ulimits();
+        if !ulimits.is_empty() {
+            host_config.ulimits = Some(ulimits);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (host_config, pids_limit, ulimits);
+        tracing::warn!("pids_limit and ulimits not supported on this platform, skipping");
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +444,7 @@ impl ContainerState {
             internal_id,
             volume_id,
             mount: HashMap::new(),
+            read_only_mounts: Vec::new(),
             limits: ContainerLimits {
                 memory: None,
                 cpu: None,
@@ -68,6 +458,32 @@ impl ContainerState {
             created_at: now,
             updated_at: now,
             start_pattern: None,
+            start_patterns: Vec::new(),
+            stop_command: None,
+            stop_timeout: None,
+            image: None,
+            labels: HashMap::new(),
+            log_rotation: None,
+            user: None,
+            restart_count: 0,
+            last_exit_code: None,
+            last_exit_at: None,
+            last_error: None,
+            install_error: None,
+            install_resumes: 0,
+            pids_limit: None,
+            ulimits: None,
+            env: HashMap::new(),
+        }
+    }
+
+    /// Start patterns in effect, the single `start_pattern` of older records
+    /// and creates when no list was set
+    pub fn start_pattern_list(&self) -> Vec<String> {
+        if self.start_patterns.is_empty() {
+            self.start_pattern.iter().cloned().collect()
+        } else {
+            self.start_patterns.clone()
         }
     }
 
@@ -79,3 +495,113 @@ impl ContainerState {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udp_binding_is_published_as_udp() {
+        let ports = vec![
+            PortBinding { container_port: 27015, host_port: 30015, protocol: "UDP".to_string(), host_ip: "0.0.0.0".to_string(), pool_id: None },
+            PortBinding { container_port: 27015, host_port: 30015, protocol: "tcp".to_string(), host_ip: "0.0.0.0".to_string(), pool_id: None },
+        ];
+        let (bindings, exposed) = docker_ports(&ports);
+
+        let udp = bindings["27015/udp"].as_ref().unwrap();
+        assert_eq!(udp.len(), 1);
+        assert_eq!(udp[0].host_port.as_deref(), Some("30015"));
+        assert!(bindings.contains_key("27015/tcp"));
+        assert!(exposed.contains_key("27015/udp") && exposed.contains_key("27015/tcp"));
+
+        assert_eq!(normalize_protocol(" Udp ").unwrap(), "udp");
+        assert!(normalize_protocol("sctp").is_err());
+    }
+
+    #[test]
+    fn test_start_patterns() {
+        let mut state = ContainerState::new("abc".to_string(), "vol".to_string(), "./start.sh".to_string());
+        assert!(state.start_pattern_list().is_empty());
+        state.start_pattern = Some("Done".to_string());
+        assert_eq!(state.start_pattern_list(), vec!["Done"]);
+        state.start_patterns = vec!["Ready".to_string(), "Listening on .*".to_string()];
+        assert_eq!(state.start_pattern_list().len(), 2);
+
+        assert!(validate_start_patterns(&state.start_patterns).is_ok());
+        let err = validate_start_patterns(&["Done".to_string(), "Done (".to_string()]).unwrap_err();
+        assert!(err.starts_with("Start pattern 1 ('Done (')"));
+    }
+
+    #[test]
+    fn test_validate_mount() {
+        assert!(validate_mount("/data", "/srv/shared").is_ok());
+        assert!(validate_mount("/etc", "/srv/shared").is_err());
+        assert!(validate_mount("/home/container/", "/srv/shared").is_err());
+        assert!(validate_mount("/data", "/").is_err());
+        assert!(validate_mount("/data", "/etc/ssh").is_err());
+        assert!(validate_mount("/data", "/var/run/docker.sock").is_err());
+        assert!(validate_mount("/data", "/srv/../etc").is_err());
+        assert!(validate_mount("/data", "srv").is_err());
+        assert!(validate_mount("/data", "/etcetera").is_ok());
+    }
+
+    #[test]
+    fn test_mount_policy() {
+        let policy = MountPolicy::new(&["/srv/shared".to_string()], &["/var/lib/lightd"]);
+        assert!(policy.check_source("/srv/shared/maps").is_ok());
+        assert!(policy.check_source("/srv/sharedother").is_err());
+        assert!(policy.check_source("/var/run/docker.sock").is_err());
+        assert!(policy.check_source("/var/lib/lightd/volumes/neighbour").is_err());
+
+        // Storage stays refused even inside an allowed prefix
+        let policy = MountPolicy::new(&["/var/lib".to_string()], &["/var/lib/lightd"]);
+        assert!(policy.check_source("/var/lib/lightd/volumes/neighbour").is_err());
+
+        let nothing = MountPolicy::new(&[], &["/var/lib/lightd"]);
+        assert!(nothing.check_source("/srv/shared").is_err());
+    }
+
+    #[test]
+    fn test_mount_policy_resolves_links() {
+        let dir = std::env::temp_dir().join(format!("lightd-mounts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let forbidden = std::env::temp_dir().join(format!("lightd-forbidden-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&forbidden).unwrap();
+        std::os::unix::fs::symlink(&forbidden, dir.join("link")).unwrap();
+
+        let policy = MountPolicy::new(&[dir.to_string_lossy().to_string()], &["/var/lib/lightd"]);
+        assert!(policy.check_source(&dir.join("link").to_string_lossy()).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_dir_all(&forbidden);
+    }
+
+    #[test]
+    fn test_process_limits() {
+        assert!(validate_pids_limit(-1).is_ok());
+        assert!(validate_pids_limit(1024).is_ok());
+        assert!(validate_pids_limit(0).is_err());
+        assert!(validate_pids_limit(8).is_err());
+
+        let defaults = Ulimits { nofile: Some(65536), nproc: None };
+        let own = Ulimits { nofile: None, nproc: Some(512) };
+        assert!(own.validate().is_ok());
+        assert!(Ulimits { nofile: Some(10), nproc: None }.validate().is_err());
+
+        let merged = own.or(&defaults);
+        assert_eq!(merged, Ulimits { nofile: Some(65536), nproc: Some(512) });
+        let docker = merged.docker_ulimits();
+        assert_eq!(docker.len(), 2);
+        assert_eq!(docker[0].name.as_deref(), Some("nofile"));
+        assert_eq!((docker[0].soft, docker[0].hard), (Some(65536), Some(65536)));
+        assert!(Ulimits::default().docker_ulimits().is_empty());
+    }
+
+    #[test]
+    fn test_parse_run_as() {
+        assert_eq!(parse_run_as("1000"), Ok((1000, 1000)));
+        assert_eq!(parse_run_as("1000:100"), Ok((1000, 100)));
+        assert!(parse_run_as("container").is_err());
+        assert!(parse_run_as("1000:").is_err());
+    }
+}
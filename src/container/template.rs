@@ -0,0 +1,253 @@
+//! Reusable container templates stored in sled
+//!
+//! A template ("egg") holds what a kind of server needs: image, startup
+//! command, install script, environment, ports, limits and the default files
+//! for its volume. Creates that name a `template_id` start from it and only
+//! send what differs. Values are copied into the container or volume at
+//! creation, editing or deleting a template later doesn't change them.
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::state::{normalize_protocol, validate_env_key};
+use crate::filesystem::skeleton::{validate_skeleton, SkeletonFile};
+
+/// Template ids are chosen by the caller, e.g. "minecraft-paper"
+const MAX_ID_LEN: usize = 64;
+
+/// Port a create asks for, the host port is assigned from the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortRequest {
+    pub container_port: u16,
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
+    /// Bind the same host port number on both TCP and UDP (protocol is ignored)
+    #[serde(default)]
+    pub both_protocols: bool,
+}
+
+fn default_protocol() -> String {
+    "tcp".to_string()
+}
+
+/// Limits that can be set at creation or in a template, the ones `install_container` applies
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateLimits {
+    /// Memory limit in bytes
+    pub memory: Option<i64>,
+    /// CPU cores, e.g. 2.0. `cpus` like on the resources endpoint works too
+    #[serde(alias = "cpus")]
+    pub cpu: Option<f64>,
+}
+
+impl CreateLimits {
+    /// Own values, falling back to `defaults` for the unset ones
+    pub fn or(&self, defaults: &CreateLimits) -> CreateLimits {
+        CreateLimits {
+            memory: self.memory.or(defaults.memory),
+            cpu: self.cpu.or(defaults.cpu),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerTemplate {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub image: String,
+    pub startup_command: String,
+    #[serde(default)]
+    pub install_script: Option<String>,
+    #[serde(default)]
+    pub start_pattern: Option<String>,
+    #[serde(default)]
+    pub stop_command: Option<String>,
+    #[serde(default)]
+    pub stop_timeout: Option<u64>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub ports: Vec<PortRequest>,
+    #[serde(default)]
+    pub limits: Option<CreateLimits>,
+    /// Default files for volumes created from this template
+    #[serde(default)]
+    pub files: Vec<SkeletonFile>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl ContainerTemplate {
+    /// Check the fields and normalize port protocols
+    pub fn validate(&mut self) -> Result<(), String> {
+        validate_template_id(&self.id)?;
+        if self.name.trim().is_empty() {
+            return Err("name cannot be empty".to_string());
+        }
+        if self.image.trim().is_empty() {
+            return Err("image cannot be empty".to_string());
+        }
+        if self.startup_command.trim().is_empty() {
+            return Err("startup_command cannot be empty".to_string());
+        }
+        self.env.keys().try_for_each(|k| validate_env_key(k))?;
+        validate_skeleton(&self.files)?;
+        for port in &mut self.ports {
+            port.protocol = normalize_protocol(&port.protocol)?;
+        }
+        if let Some(ref limits) = self.limits
+            && (limits.memory.is_some_and(|m| m <= 0) || limits.cpu.is_some_and(|c| c <= 0.0)) {
+            return Err("limits must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn validate_template_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ID_LEN {
+        return Err(format!("Template id '{}' must be 1-{} characters", id, MAX_ID_LEN));
+    }
+    if !id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(format!("Template id '{}' may only contain letters, digits, '-', '_' and '.'", id));
+    }
+    Ok(())
+}
+
+pub struct TemplateManager {
+    db: Arc<Db>,
+}
+
+impl TemplateManager {
+    pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// Store a new template, `Ok(None)` when the id is taken
+    pub fn create(&self, mut template: ContainerTemplate) -> Result<Option<ContainerTemplate>, Box<dyn std::error::Error + Send + Sync>> {
+        template.validate()?;
+        let now = Self::now();
+        template.created_at = now;
+        template.updated_at = now;
+
+        let serialized = serde_json::to_vec(&template)?;
+        if self.db.compare_and_swap(template.id.as_bytes(), None as Option<&[u8]>, Some(serialized))?.is_err() {
+            return Ok(None);
+        }
+        tracing::info!("Created template {} ({})", template.id, template.name);
+        Ok(Some(template))
+    }
+
+    /// Replace an existing template, `Ok(None)` when there is none with that id
+    pub fn replace(&self, mut template: ContainerTemplate) -> Result<Option<ContainerTemplate>, Box<dyn std::error::Error + Send + Sync>> {
+        template.validate()?;
+        let Some(existing) = self.get(&template.id)? else {
+            return Ok(None);
+        };
+        template.created_at = existing.created_at;
+        template.updated_at = Self::now();
+
+        self.db.insert(template.id.as_bytes(), serde_json::to_vec(&template)?)?;
+        tracing::info!("Updated template {}", template.id);
+        Ok(Some(template))
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<ContainerTemplate>, Box<dyn std::error::Error + Send + Sync>> {
+        match self.db.get(id.as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All templates, ordered by id
+    pub fn list(&self) -> Result<Vec<ContainerTemplate>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut templates = Vec::new();
+
+        for item in self.db.iter() {
+            let (_, value) = item?;
+            templates.push(serde_json::from_slice(&value)?);
+        }
+
+        Ok(templates)
+    }
+
+    pub fn delete(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let removed = self.db.remove(id.as_bytes())?.is_some();
+        if removed {
+            tracing::info!("Deleted template {}", id);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(id: &str) -> ContainerTemplate {
+        ContainerTemplate {
+            id: id.to_string(),
+            name: "Paper".to_string(),
+            description: None,
+            image: "eclipse-temurin:21-jre".to_string(),
+            startup_command: "java -jar server.jar nogui".to_string(),
+            install_script: None,
+            start_pattern: Some("Done".to_string()),
+            stop_command: Some("stop".to_string()),
+            stop_timeout: None,
+            env: HashMap::from([("EULA".to_string(), "true".to_string())]),
+            ports: vec![PortRequest { container_port: 25565, protocol: "TCP".to_string(), both_protocols: false }],
+            limits: None,
+            files: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_templates_are_stored_once_per_id() {
+        let path = std::env::temp_dir().join(format!("lightd-templates-{}", uuid::Uuid::new_v4()));
+        let templates = TemplateManager::new(path.to_str().unwrap()).unwrap();
+
+        let created = templates.create(template("minecraft-paper")).unwrap().unwrap();
+        assert_eq!(created.ports[0].protocol, "tcp");
+        assert!(created.created_at > 0);
+        assert!(templates.create(template("minecraft-paper")).unwrap().is_none());
+        assert!(templates.create(template("no spaces")).is_err());
+
+        let mut changed = template("minecraft-paper");
+        changed.image = "eclipse-temurin:17-jre".to_string();
+        let replaced = templates.replace(changed).unwrap().unwrap();
+        assert_eq!(replaced.created_at, created.created_at);
+        assert!(templates.replace(template("missing")).unwrap().is_none());
+
+        assert_eq!(templates.list().unwrap().len(), 1);
+        assert_eq!(templates.get("minecraft-paper").unwrap().unwrap().image, "eclipse-temurin:17-jre");
+        assert!(templates.delete("minecraft-paper").unwrap());
+        assert!(templates.get("minecraft-paper").unwrap().is_none());
+
+        let mut bad_env = template("bad-env");
+        bad_env.env.insert("1ST".to_string(), "x".to_string());
+        assert!(bad_env.validate().is_err());
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
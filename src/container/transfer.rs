@@ -0,0 +1,378 @@
+//! Moving a container to another node
+//!
+//! A transfer stops the container, writes the same archive `/export` serves
+//! and streams it to the `/containers/import` endpoint of the target node,
+//! together with the archive's sha256 so the target can tell a cut off upload
+//! from a complete one. Only when the target answers with a created container
+//! is the local one removed. The volume is left in place for the panel to
+//! delete once it has checked the new copy.
+//!
+//! Jobs are kept in memory and polled by id like hash jobs, progress also
+//! goes out as `transfer progress` events on the container's WebSocket.
+
+use dashmap::DashMap;
+use futures::StreamExt;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio_util::io::ReaderStream;
+
+use super::export;
+use super::lifecycle::LifecycleManager;
+use super::manager::ContainerManager;
+use super::power::{PowerAction, PowerManager};
+use super::state::ContainerState;
+use crate::filesystem::handler::VolumeHandler;
+use crate::filesystem::hash::{hash_reader, HashAlgorithm};
+use crate::network::firewall::FirewallManager;
+use crate::network::pool::NetworkPool;
+use crate::remote::client::RemoteClient;
+use crate::sftp::credentials::CredentialsManager;
+use crate::websocket::EventHub;
+
+/// Finished jobs are forgotten after this long
+const JOB_RETENTION_SECS: u64 = 24 * 3600;
+
+const PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStage {
+    Stopping,
+    Exporting,
+    Uploading,
+    Removing,
+    Completed,
+    Failed,
+}
+
+impl TransferStage {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransferStage::Stopping => "stopping",
+            TransferStage::Exporting => "exporting",
+            TransferStage::Uploading => "uploading",
+            TransferStage::Removing => "removing",
+            TransferStage::Completed => "completed",
+            TransferStage::Failed => "failed",
+        }
+    }
+
+    fn is_finished(self) -> bool {
+        matches!(self, TransferStage::Completed | TransferStage::Failed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferJob {
+    pub id: String,
+    pub internal_id: String,
+    pub target_url: String,
+    pub stage: TransferStage,
+    /// Percentage of the archive uploaded
+    pub progress: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Import response of the target node
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_response: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Only plain http(s) base URLs, the import path is appended
+pub fn validate_target_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| "target_url must start with http:// or https://".to_string())?;
+    if rest.is_empty() || rest.contains(char::is_whitespace) {
+        return Err("target_url has no host".to_string());
+    }
+    Ok(url.to_string())
+}
+
+pub struct TransferManager {
+    manager: Arc<ContainerManager>,
+    lifecycle: Arc<LifecycleManager>,
+    power: Arc<PowerManager>,
+    pool: Arc<NetworkPool>,
+    credentials: Arc<CredentialsManager>,
+    firewall: Arc<FirewallManager>,
+    volumes: Arc<VolumeHandler>,
+    event_hub: Arc<EventHub>,
+    /// Plain HTTP client, without the remote sync's CA or client certificate
+    client: Arc<RemoteClient>,
+    jobs: Arc<DashMap<String, TransferJob>>,
+}
+
+impl TransferManager {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        manager: Arc<ContainerManager>,
+        lifecycle: Arc<LifecycleManager>,
+        power: Arc<PowerManager>,
+        pool: Arc<NetworkPool>,
+        credentials: Arc<CredentialsManager>,
+        firewall: Arc<FirewallManager>,
+        volumes: Arc<VolumeHandler>,
+        event_hub: Arc<EventHub>,
+        client: Arc<RemoteClient>,
+    ) -> Self {
+        Self {
+            manager,
+            lifecycle,
+            power,
+            pool,
+            credentials,
+            firewall,
+            volumes,
+            event_hub,
+            client,
+            jobs: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Transfer still running for the container, if any
+    pub fn active(&self, internal_id: &str) -> Option<TransferJob> {
+        self.jobs
+            .iter()
+            .find(|job| job.internal_id == internal_id && !job.stage.is_finished())
+            .map(|job| job.clone())
+    }
+
+    pub fn get(&self, internal_id: &str, job_id: &str) -> Option<TransferJob> {
+        self.jobs
+            .get(job_id)
+            .filter(|job| job.internal_id == internal_id)
+            .map(|job| job.clone())
+    }
+
+    /// Start moving `container` to the node at `target_url` in the background
+    pub fn start(self: &Arc<Self>, container: ContainerState, target_url: String, token: String) -> TransferJob {
+        let created_at = now();
+        self.jobs.retain(|_, job| job.finished_at.is_none_or(|at| created_at.saturating_sub(at) < JOB_RETENTION_SECS));
+
+        let job = TransferJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            internal_id: container.internal_id.clone(),
+            target_url,
+            stage: TransferStage::Stopping,
+            progress: 0,
+            size_bytes: None,
+            sha256: None,
+            target_response: None,
+            error: None,
+            created_at,
+            finished_at: None,
+        };
+        self.jobs.insert(job.id.clone(), job.clone());
+
+        let this = self.clone();
+        let job_id = job.id.clone();
+        tokio::spawn(async move {
+            let internal_id = container.internal_id.clone();
+            let archive = this.lifecycle.base_path()
+                .join("exports")
+                .join(format!("{}-{}.tar", internal_id, job_id));
+
+            let result = this.run(&job_id, container, &archive, &token).await;
+            let _ = tokio::fs::remove_file(&archive).await;
+
+            match result {
+                Ok(()) => {
+                    this.update(&job_id, |job| job.stage = TransferStage::Completed).await;
+                    tracing::info!("Transferred container {} to {}", internal_id, this.target(&job_id));
+                }
+                Err(e) => {
+                    this.update(&job_id, |job| {
+                        job.stage = TransferStage::Failed;
+                        job.error = Some(e.to_string());
+                    }).await;
+                    tracing::error!("Transfer of {} failed: {}", internal_id, e);
+                }
+            }
+        });
+
+        job
+    }
+
+    fn target(&self, job_id: &str) -> String {
+        self.jobs.get(job_id).map(|job| job.target_url.clone()).unwrap_or_default()
+    }
+
+    /// Change the job and tell WebSocket clients about it
+    async fn update(&self, job_id: &str, change: impl FnOnce(&mut TransferJob)) {
+        let Some((internal_id, stage, progress)) = self.jobs.get_mut(job_id).map(|mut job| {
+            change(&mut job);
+            if job.stage.is_finished() {
+                job.finished_at = Some(now());
+            }
+            (job.internal_id.clone(), job.stage, job.progress)
+        }) else {
+            return;
+        };
+        self.event_hub.broadcast_transfer_progress(&internal_id, stage.as_str(), progress).await;
+    }
+
+    async fn run(
+        &self,
+        job_id: &str,
+        container: ContainerState,
+        archive: &std::path::Path,
+        token: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let internal_id = container.internal_id.clone();
+        let was_running = match container.container_id.as_deref() {
+            Some(container_id) => self.lifecycle.inspect_docker(container_id).await
+                .ok()
+                .flatten()
+                .is_some_and(|docker| docker.running),
+            None => false,
+        };
+
+        // The volume must not change while it is packed
+        self.power.run_action(internal_id.clone(), PowerAction::Stop).await?;
+
+        let response = match self.send(job_id, container, archive, token).await {
+            Ok(response) => response,
+            Err(e) => {
+                // The container stays here, bring it back the way it was
+                if was_running
+                    && let Err(start) = self.power.run_action(internal_id.clone(), PowerAction::Start).await {
+                    tracing::error!("Failed to restart {} after the failed transfer: {}", internal_id, start);
+                }
+                return Err(e);
+            }
+        };
+
+        // The target owns the container now
+        self.update(job_id, |job| {
+            job.stage = TransferStage::Removing;
+            job.progress = 100;
+            job.target_response = Some(response);
+        }).await;
+        self.remove_local(&internal_id).await
+    }
+
+    /// Export the stopped container and upload it, returns the target's import response
+    async fn send(
+        &self,
+        job_id: &str,
+        container: ContainerState,
+        archive: &std::path::Path,
+        token: &str,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let internal_id = container.internal_id.clone();
+        self.update(job_id, |job| job.stage = TransferStage::Exporting).await;
+        let volume = self.lifecycle.volume_path(&container.volume_id);
+        let quota_mb = self.volumes.get_volume(&container.volume_id).await.and_then(|v| v.quota_mb);
+        let (size, sha256) = {
+            let archive = archive.to_path_buf();
+            tokio::task::spawn_blocking(move || -> Result<(u64, String), String> {
+                if let Some(dir) = archive.parent() {
+                    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                export::write_export(&container, &volume, quota_mb, &archive)?;
+                let size = std::fs::metadata(&archive).map_err(|e| e.to_string())?.len();
+                let file = std::fs::File::open(&archive).map_err(|e| e.to_string())?;
+                let sha256 = hash_reader(file, HashAlgorithm::Sha256, |_| {}).map_err(|e| e.to_string())?;
+                Ok((size, sha256))
+            }).await??
+        };
+
+        self.update(job_id, |job| {
+            job.stage = TransferStage::Uploading;
+            job.size_bytes = Some(size);
+            job.sha256 = Some(sha256.clone());
+        }).await;
+
+        let body = self.upload_body(job_id, archive.to_path_buf(), size).await?;
+        let target_url = self.target(job_id);
+        let upload = self.client.send_export(&target_url, token, body, size, &sha256);
+        tokio::pin!(upload);
+
+        // The body only counts bytes, progress goes out at most once per interval
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        let mut reported = 0;
+        loop {
+            tokio::select! {
+                result = &mut upload => return result,
+                _ = ticker.tick() => {
+                    let progress = self.jobs.get(job_id).map(|job| job.progress).unwrap_or(0);
+                    if progress != reported {
+                        reported = progress;
+                        self.event_hub.broadcast_transfer_progress(&internal_id, TransferStage::Uploading.as_str(), progress).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Archive as a streamed request body that records upload progress on the job
+    async fn upload_body(
+        &self,
+        job_id: &str,
+        archive: PathBuf,
+        size: u64,
+    ) -> Result<reqwest::Body, Box<dyn std::error::Error + Send + Sync>> {
+        let file = tokio::fs::File::open(&archive).await?;
+        let jobs = self.jobs.clone();
+        let job_id = job_id.to_string();
+        let mut sent = 0u64;
+
+        let stream = ReaderStream::new(file).inspect(move |chunk| {
+            if let Ok(bytes) = chunk {
+                sent += bytes.len() as u64;
+            }
+            if let Some(mut job) = jobs.get_mut(&job_id) {
+                job.progress = (sent.saturating_mul(100) / size.max(1)).min(99) as u8;
+            }
+        });
+
+        Ok(reqwest::Body::wrap_stream(stream))
+    }
+
+    /// Drop everything the container held on this node except its volume
+    async fn remove_local(&self, internal_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.lifecycle.remove_docker_container(internal_id).await?;
+
+        let container = self.manager.delete_container(internal_id).await?;
+        if let Err(e) = self.pool.release_ports(&container.ports).await {
+            tracing::error!("Failed to return ports of {} to pool: {}", internal_id, e);
+        }
+        if let Err(e) = self.credentials.delete_credentials(internal_id) {
+            tracing::error!("Failed to delete SFTP credentials of {}: {}", internal_id, e);
+        }
+        // Rules, DDoS chains, bandwidth limit and blocklists of the container
+        if let Err(e) = self.firewall.cleanup_container_rules(internal_id).await {
+            tracing::error!("Failed to clean up firewall rules of {}: {}", internal_id, e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_target_url() {
+        assert_eq!(validate_target_url("https://node2.example.com:8070/").unwrap(), "https://node2.example.com:8070");
+        assert!(validate_target_url("node2.example.com").is_err());
+        assert!(validate_target_url("http://").is_err());
+    }
+}
@@ -3,24 +3,82 @@
 //! Uses Bollard's update_container to modify running containers without downtime
 
 use super::manager::ContainerManager;
+use super::state::{validate_mount, MountPolicy};
+use crate::error::LightdError;
 use bollard::Docker;
 use bollard::container::UpdateContainerOptions;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::mpsc;
+use tracing::Instrument;
 
 #[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "args", rename_all = "snake_case")]
 pub enum UpdateEvent {
     UpdateStarted { container_id: String },
     ResourcesUpdated { container_id: String },
+    /// Memory and CPU caps in effect after a resources update, None is unlimited
+    LimitsUpdated { container_id: String, memory: Option<i64>, cpus: Option<f64> },
     VolumesUpdated { container_id: String },
     DatabaseUpdated { container_id: String },
     UpdateComplete { container_id: String },
     Error { container_id: String, message: String },
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl UpdateEvent {
+    /// Internal ID of the container the event is about
+    pub fn container_id(&self) -> &str {
+        match self {
+            Self::UpdateStarted { container_id }
+            | Self::ResourcesUpdated { container_id }
+            | Self::LimitsUpdated { container_id, .. }
+            | Self::VolumesUpdated { container_id }
+            | Self::DatabaseUpdated { container_id }
+            | Self::UpdateComplete { container_id }
+            | Self::Error { container_id, .. } => container_id,
+        }
+    }
+}
+
+/// One custom mount: just the host path, or the host path with options
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum MountSpec {
+    Source(String),
+    Options {
+        source: String,
+        #[serde(default)]
+        read_only: Option<bool>,
+    },
+}
+
+/// Check requested mounts and split them into what `ContainerState` stores:
+/// container path -> host path, and the container paths bound read-only
+pub fn split_mounts(
+    mounts: HashMap<String, MountSpec>,
+    policy: &MountPolicy,
+) -> Result<(HashMap<String, String>, Vec<String>), String> {
+    let mut volumes = HashMap::new();
+    let mut read_only = Vec::new();
+    for (target, spec) in mounts {
+        let (source, is_read_only) = match spec {
+            MountSpec::Source(source) => (source, false),
+            MountSpec::Options { source, read_only } => (source, read_only.unwrap_or(false)),
+        };
+        // Lightd is secure by default mate.
+        validate_mount(&target, &source)?;
+        policy.check_source(&source)?;
+        if is_read_only {
+            read_only.push(target.clone());
+        }
+        volumes.insert(target, source);
+    }
+    read_only.sort();
+    Ok((volumes, read_only))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct ResourceLimits {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub memory: Option<i64>, // Memory limit in bytes
@@ -38,6 +96,8 @@ pub struct ResourceLimits {
     pub cpuset_cpus: Option<String>, // CPUs to use (e.g., "0-3")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub blkio_weight: Option<u16>, // Block IO weight (10-1000)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>, // CPU cores (e.g., 2.0), applied as nano_cpus
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -58,7 +118,7 @@ impl ContainerUpdater {
     pub fn new(
         manager: Arc<ContainerManager>,
     ) -> Result<(Self, mpsc::UnboundedReceiver<UpdateEvent>), Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Docker::connect_with_local_defaults()?;
+        let docker = crate::container::docker::client()?;
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
         Ok((
@@ -71,6 +131,18 @@ impl ContainerUpdater {
         ))
     }
 
+    /// Check limits without applying them, e.g. before a container is created
+    pub async fn validate_limits(
+        &self,
+        limits: &ResourceLimits,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Self::validate_resource_limits(limits)?;
+        if let Some(cpus) = limits.cpus {
+            Self::validate_cpus(&self.docker, cpus).await?;
+        }
+        Ok(())
+    }
+
     /// Update container resource limits (live, no restart required)
     pub async fn update_resources(
         &self,
@@ -98,7 +170,7 @@ impl ContainerUpdater {
                 });
                 tracing::error!("Failed to update resources for {}: {}", internal_id, e);
             }
-        });
+        }.in_current_span());
 
         Ok(())
     }
@@ -118,7 +190,7 @@ impl ContainerUpdater {
         let state = manager
             .get_container(&internal_id)
             .await?
-            .ok_or_else(|| format!("Container not found: {}", internal_id))?;
+            .ok_or(LightdError::NotFound("Container"))?;
 
         let container_id = state
             .container_id
@@ -126,6 +198,9 @@ impl ContainerUpdater {
 
         // Validate limits
         Self::validate_resource_limits(&limits)?;
+        if let Some(cpus) = limits.cpus {
+            Self::validate_cpus(&docker, cpus).await?;
+        }
 
         // Build update options
         let mut update_opts = UpdateContainerOptions::<String>::default();
@@ -154,6 +229,10 @@ impl ContainerUpdater {
             update_opts.cpu_quota = Some(cpu_quota);
         }
 
+        if let Some(cpus) = limits.cpus {
+            update_opts.nano_cpus = Some((cpus * 1_000_000_000.0) as i64);
+        }
+
         if let Some(ref cpuset_cpus) = limits.cpuset_cpus {
             update_opts.cpuset_cpus = Some(cpuset_cpus.clone());
         }
@@ -186,6 +265,14 @@ impl ContainerUpdater {
         let _ = event_tx.send(UpdateEvent::DatabaseUpdated { 
             container_id: internal_id.clone() 
         });
+        // Stored values, a partial update keeps the other cap
+        if let Some(state) = manager.get_container(&internal_id).await? {
+            let _ = event_tx.send(UpdateEvent::LimitsUpdated {
+                container_id: internal_id.clone(),
+                memory: state.limits.memory,
+                cpus: state.limits.cpu,
+            });
+        }
         let _ = event_tx.send(UpdateEvent::UpdateComplete { 
             container_id: internal_id.clone() 
         });
@@ -194,12 +281,16 @@ impl ContainerUpdater {
         Ok(())
     }
 
-    /// Update container volumes (requires restart)
+    /// Update container volumes (requires restart). The mounts are checked
+    /// before anything is queued so a refused one fails the request.
     pub async fn update_volumes(
         &self,
         internal_id: String,
-        volumes: HashMap<String, String>,
+        mounts: HashMap<String, MountSpec>,
+        policy: &MountPolicy,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (volumes, read_only) = split_mounts(mounts, policy)?;
+
         let manager = self.manager.clone();
         let event_tx = self.event_tx.clone();
 
@@ -210,6 +301,7 @@ impl ContainerUpdater {
                 event_tx.clone(),
                 internal_id.clone(),
                 volumes,
+                read_only,
             )
             .await
             {
@@ -219,7 +311,7 @@ impl ContainerUpdater {
                 });
                 tracing::error!("Failed to update volumes for {}: {}", internal_id, e);
             }
-        });
+        }.in_current_span());
 
         Ok(())
     }
@@ -229,16 +321,14 @@ impl ContainerUpdater {
         event_tx: mpsc::UnboundedSender<UpdateEvent>,
         internal_id: String,
         volumes: HashMap<String, String>,
+        read_only: Vec<String>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let _ = event_tx.send(UpdateEvent::UpdateStarted { 
             container_id: internal_id.clone() 
         });
 
-        // Validate volumes
-        Self::validate_volumes(&volumes)?;
-
         // Update database
-        manager.update_volumes(&internal_id, volumes).await?;
+        manager.update_volumes(&internal_id, volumes, read_only).await?;
 
         let _ = event_tx.send(UpdateEvent::VolumesUpdated { 
             container_id: internal_id.clone() 
@@ -262,7 +352,7 @@ impl ContainerUpdater {
         let state = self.manager
             .get_container(internal_id)
             .await?
-            .ok_or_else(|| format!("Container not found: {}", internal_id))?;
+            .ok_or(LightdError::NotFound("Container"))?;
 
         let container_id = state
             .container_id
@@ -282,9 +372,37 @@ impl ContainerUpdater {
             cpu_quota: host_config.cpu_quota,
             cpuset_cpus: host_config.cpuset_cpus,
             blkio_weight: host_config.blkio_weight,
+            cpus: host_config
+                .nano_cpus
+                .filter(|n| *n > 0)
+                .map(|n| n as f64 / 1_000_000_000.0),
         })
     }
 
+    /// Validate a CPU core count against what the host actually has
+    async fn validate_cpus(
+        docker: &Docker,
+        cpus: f64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if cpus < 0.01 {
+            return Err("CPU count must be at least 0.01".into());
+        }
+
+        // Ask Docker first, it knows the cores available to containers
+        let host_cpus = match docker.info().await.ok().and_then(|info| info.ncpu) {
+            Some(n) if n > 0 => n as f64,
+            _ => std::thread::available_parallelism()
+                .map(|n| n.get() as f64)
+                .unwrap_or(1.0),
+        };
+
+        if cpus > host_cpus {
+            return Err(format!("CPU count {} exceeds host core count {}", cpus, host_cpus).into());
+        }
+
+        Ok(())
+    }
+
     /// Validate resource limits
     fn validate_resource_limits(
         limits: &ResourceLimits,
@@ -302,31 +420,32 @@ impl ContainerUpdater {
         }
 
         // Memory swap validation
-        if let (Some(memory), Some(memory_swap)) = (limits.memory, limits.memory_swap) {
-            if memory_swap != -1 && memory_swap < memory {
-                return Err("Memory swap must be greater than or equal to memory limit".into());
-            }
+        if let (Some(memory), Some(memory_swap)) = (limits.memory, limits.memory_swap)
+            && memory_swap != -1 && memory_swap < memory {
+            return Err("Memory swap must be greater than or equal to memory limit".into());
         }
 
         // CPU shares validation
-        if let Some(cpu_shares) = limits.cpu_shares {
-            if cpu_shares < 2 || cpu_shares > 262144 {
-                return Err("CPU shares must be between 2 and 262144".into());
-            }
+        if let Some(cpu_shares) = limits.cpu_shares
+            && (!(2..=262144).contains(&cpu_shares)) {
+            return Err("CPU shares must be between 2 and 262144".into());
+        }
+
+        // Docker rejects nano_cpus combined with CFS period/quota
+        if limits.cpus.is_some() && (limits.cpu_period.is_some() || limits.cpu_quota.is_some()) {
+            return Err("cpus cannot be combined with cpu_period or cpu_quota".into());
         }
 
         // CPU period validation
-        if let Some(cpu_period) = limits.cpu_period {
-            if cpu_period < 1000 || cpu_period > 1000000 {
-                return Err("CPU period must be between 1000 and 1000000 microseconds".into());
-            }
+        if let Some(cpu_period) = limits.cpu_period
+            && (!(1000..=1000000).contains(&cpu_period)) {
+            return Err("CPU period must be between 1000 and 1000000 microseconds".into());
         }
 
         // CPU quota validation
-        if let Some(cpu_quota) = limits.cpu_quota {
-            if cpu_quota < 1000 && cpu_quota != -1 {
-                return Err("CPU quota must be at least 1000 microseconds or -1 for unlimited".into());
-            }
+        if let Some(cpu_quota) = limits.cpu_quota
+            && cpu_quota < 1000 && cpu_quota != -1 {
+            return Err("CPU quota must be at least 1000 microseconds or -1 for unlimited".into());
         }
 
         // Block IO weight validation
@@ -336,37 +455,31 @@ impl ContainerUpdater {
                 tracing::warn!("Block I/O weight is only supported on Linux and will be ignored");
             }
             
-            if blkio_weight < 10 || blkio_weight > 1000 {
+            if !(10..=1000).contains(&blkio_weight) {
                 return Err("Block IO weight must be between 10 and 1000".into());
             }
         }
 
         Ok(())
     }
+}
 
-    /// Validate volumes
-    fn validate_volumes(
-        volumes: &HashMap<String, String>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        for (target, source) in volumes {
-            // Validate target path
-            if target.is_empty() || !target.starts_with('/') {
-                return Err(format!("Invalid target path: {}", target).into());
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Validate source path
-            if source.is_empty() {
-                return Err(format!("Invalid source path for target: {}", target).into());
-            }
+    #[test]
+    fn test_split_mounts_refuses_docker_socket() {
+        let policy = MountPolicy::new(&["/srv/shared".to_string(), "/var/run".to_string()], &["/var/lib/lightd"]);
 
-            // Check for dangerous paths
-            // Lightd is secure by default mate.
-            let dangerous_paths = ["/", "/bin", "/boot", "/dev", "/etc", "/lib", "/proc", "/sys"];
-            if dangerous_paths.contains(&target.as_str()) {
-                return Err(format!("Cannot mount to system path: {}", target).into());
-            }
-        }
+        let ok = HashMap::from([("/data".to_string(), MountSpec::Source("/srv/shared/maps".to_string()))]);
+        assert!(split_mounts(ok, &policy).is_ok());
 
-        Ok(())
+        // Refused even when an allowlist entry covers it
+        let socket = HashMap::from([("/var/run/docker.sock".to_string(), MountSpec::Source("/var/run/docker.sock".to_string()))]);
+        assert!(split_mounts(socket, &policy).is_err());
+
+        let neighbour = HashMap::from([("/data".to_string(), MountSpec::Source("/var/lib/lightd/volumes/other".to_string()))]);
+        assert!(split_mounts(neighbour, &policy).is_err());
     }
 }
@@ -0,0 +1,238 @@
+//! Container uptime history stored in sled
+//!
+//! Every observed run of a container is one span keyed
+//! `<internal_id>/<started_at>`, so a container's history is a prefix scan in
+//! start order. Spans are written from the power events of every start, stop
+//! and kill lightd performs, and by the console watcher as it sees the
+//! container start and stop on its own (e.g. a crash). Transitions that happen
+//! while nothing is watching are only noticed on the next observation, so an
+//! unobserved crash counts as uptime until then. Both use Docker's start time,
+//! so the same run seen twice stays one span.
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::sync::Arc;
+
+/// Spans that ended longer ago than this are dropped
+pub const RETENTION_SECS: u64 = 90 * 24 * 3600;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UptimeSpan {
+    pub started_at: u64,
+    /// None while the container is still running
+    pub stopped_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UptimeReport {
+    pub running: bool,
+    /// Start of the current run
+    pub started_at: Option<u64>,
+    /// Seconds since the current run started
+    pub current_uptime_secs: Option<u64>,
+    pub window_start: u64,
+    pub window_secs: u64,
+    pub uptime_secs: u64,
+    pub downtime_secs: u64,
+    /// uptime_secs / window_secs, 0.0 to 1.0
+    pub availability: f64,
+}
+
+/// Seconds covered by `spans` between `from` and `to`, open spans run until `to`
+fn running_secs(spans: &[UptimeSpan], from: u64, to: u64) -> u64 {
+    spans
+        .iter()
+        .map(|span| {
+            let start = span.started_at.max(from);
+            let end = span.stopped_at.unwrap_or(to).min(to);
+            end.saturating_sub(start)
+        })
+        .sum()
+}
+
+pub struct UptimeTracker {
+    db: Arc<Db>,
+}
+
+impl UptimeTracker {
+    pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    fn key(internal_id: &str, started_at: u64) -> String {
+        // Zero padded so keys sort by start time
+        format!("{}/{:020}", internal_id, started_at)
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn save(&self, internal_id: &str, span: &UptimeSpan) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let serialized = serde_json::to_vec(span)?;
+        self.db.insert(Self::key(internal_id, span.started_at).as_bytes(), serialized)?;
+        Ok(())
+    }
+
+    fn last_span(&self, internal_id: &str) -> Result<Option<UptimeSpan>, Box<dyn std::error::Error + Send + Sync>> {
+        let prefix = format!("{}/", internal_id);
+        match self.db.scan_prefix(prefix.as_bytes()).next_back() {
+            Some(item) => {
+                let (_, value) = item?;
+                Ok(Some(serde_json::from_slice(&value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn spans(&self, internal_id: &str) -> Result<Vec<UptimeSpan>, Box<dyn std::error::Error + Send + Sync>> {
+        let prefix = format!("{}/", internal_id);
+        let mut spans = Vec::new();
+
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = item?;
+            spans.push(serde_json::from_slice(&value)?);
+        }
+
+        Ok(spans)
+    }
+
+    /// Record that the container started at `at`. Seeing the same run again,
+    /// e.g. after a daemon restart, is a no-op.
+    pub fn record_start(&self, internal_id: &str, at: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(mut last) = self.last_span(internal_id)? {
+            if last.started_at == at {
+                return Ok(());
+            }
+            // The stop of the previous run was missed, end it where the new one begins
+            if last.stopped_at.is_none() {
+                last.stopped_at = Some(at.max(last.started_at));
+                self.save(internal_id, &last)?;
+            }
+        }
+
+        self.save(internal_id, &UptimeSpan { started_at: at, stopped_at: None })?;
+        self.prune(internal_id, at)
+    }
+
+    /// Close the open span of the container, if any
+    pub fn record_stop(&self, internal_id: &str, at: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(mut last) = self.last_span(internal_id)?
+            && last.stopped_at.is_none() {
+            last.stopped_at = Some(at.max(last.started_at));
+            self.save(internal_id, &last)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the whole history of a container
+    pub fn delete(&self, internal_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let prefix = format!("{}/", internal_id);
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, _) = item?;
+            self.db.remove(key)?;
+        }
+        Ok(())
+    }
+
+    fn prune(&self, internal_id: &str, now: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let cutoff = now.saturating_sub(RETENTION_SECS);
+        for span in self.spans(internal_id)? {
+            match span.stopped_at {
+                Some(stopped_at) if stopped_at < cutoff => {
+                    self.db.remove(Self::key(internal_id, span.started_at).as_bytes())?;
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Current run and cumulative uptime over the last `window_secs`, never
+    /// reaching back before the container was created
+    pub fn report(
+        &self,
+        internal_id: &str,
+        window_secs: u64,
+        created_at: u64,
+    ) -> Result<UptimeReport, Box<dyn std::error::Error + Send + Sync>> {
+        let now = Self::now();
+        let spans = self.spans(internal_id)?;
+
+        let window_start = now.saturating_sub(window_secs).max(created_at.min(now));
+        let window_secs = now - window_start;
+        let uptime_secs = running_secs(&spans, window_start, now);
+        let downtime_secs = window_secs.saturating_sub(uptime_secs);
+
+        let started_at = spans
+            .last()
+            .filter(|span| span.stopped_at.is_none())
+            .map(|span| span.started_at);
+
+        Ok(UptimeReport {
+            running: started_at.is_some(),
+            started_at,
+            current_uptime_secs: started_at.map(|at| now.saturating_sub(at)),
+            window_start,
+            window_secs,
+            uptime_secs,
+            downtime_secs,
+            availability: if window_secs == 0 { 0.0 } else { uptime_secs as f64 / window_secs as f64 },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_secs_clips_to_window() {
+        let spans = vec![
+            UptimeSpan { started_at: 0, stopped_at: Some(100) },
+            UptimeSpan { started_at: 150, stopped_at: Some(200) },
+            UptimeSpan { started_at: 300, stopped_at: None },
+        ];
+
+        assert_eq!(running_secs(&spans, 50, 400), 50 + 50 + 100);
+        assert_eq!(running_secs(&spans, 210, 290), 0);
+        assert_eq!(running_secs(&spans, 0, 350), 100 + 50 + 50);
+    }
+
+    #[test]
+    fn test_record_start_and_stop() {
+        let path = std::env::temp_dir().join(format!("lightd-uptime-{}", uuid::Uuid::new_v4()));
+        let tracker = UptimeTracker::new(path.to_str().unwrap()).unwrap();
+
+        tracker.record_start("abc", 100).unwrap();
+        tracker.record_start("abc", 100).unwrap();
+        tracker.record_stop("abc", 160).unwrap();
+        tracker.record_start("abc", 200).unwrap();
+        // Missed stop, the previous run ends where the new one starts
+        tracker.record_start("abc", 300).unwrap();
+
+        assert_eq!(tracker.spans("abc").unwrap(), vec![
+            UptimeSpan { started_at: 100, stopped_at: Some(160) },
+            UptimeSpan { started_at: 200, stopped_at: Some(300) },
+            UptimeSpan { started_at: 300, stopped_at: None },
+        ]);
+
+        tracker.record_start("abcd", 100).unwrap();
+        tracker.delete("abc").unwrap();
+        assert!(tracker.spans("abc").unwrap().is_empty());
+        assert_eq!(tracker.spans("abcd").unwrap().len(), 1);
+
+        drop(tracker);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -68,25 +68,24 @@ impl UserManager {
         
         // Create group first
         let group_output = Command::new("groupadd")
-            .args(&[
+            .args([
                 "-g", &user.gid.to_string(),
                 &user.username,
             ])
             .output();
         
-        if let Ok(output) = group_output {
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                // Ignore "group already exists" error
-                if !stderr.contains("already exists") {
-                    tracing::warn!("Failed to create group: {}", stderr);
-                }
+        if let Ok(output) = group_output
+            && !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // Ignore "group already exists" error
+            if !stderr.contains("already exists") {
+                tracing::warn!("Failed to create group: {}", stderr);
             }
         }
         
         // Create user
         let output = Command::new("useradd")
-            .args(&[
+            .args([
                 "-u", &user.uid.to_string(),
                 "-g", &user.gid.to_string(),
                 "-M", // No home directory
@@ -182,7 +181,7 @@ impl UserManager {
         #[cfg(unix)]
         {
             let output = Command::new("chown")
-                .args(&[
+                .args([
                     "-R",
                     &format!("{}:{}", user.uid, user.gid),
                     path,
@@ -206,7 +205,7 @@ impl UserManager {
         #[cfg(unix)]
         {
             let output = Command::new("id")
-                .args(&["-u", username])
+                .args(["-u", username])
                 .output()?;
             
             if !output.status.success() {
@@ -217,7 +216,7 @@ impl UserManager {
             let uid: u32 = uid_str.trim().parse()?;
             
             let output = Command::new("id")
-                .args(&["-g", username])
+                .args(["-g", username])
                 .output()?;
             
             let gid_str = String::from_utf8_lossy(&output.stdout);
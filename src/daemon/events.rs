@@ -0,0 +1,103 @@
+//! Node-wide event feed
+//!
+//! The lifecycle, power, network and update listeners in main publish every
+//! event they receive here, and `GET /events` streams the feed as
+//! Server-Sent Events. Ids are handed out under the same lock as the send,
+//! so subscribers see events in id order.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Events a slow subscriber may fall behind before it skips ahead
+const BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    Lifecycle,
+    Power,
+    Network,
+    Update,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeEvent {
+    /// Increases by one per event, restarts with the daemon
+    pub id: u64,
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    pub source: EventSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// The manager's event as `{"type", "args"}`
+    pub event: serde_json::Value,
+}
+
+pub struct EventBus {
+    tx: broadcast::Sender<NodeEvent>,
+    next_id: Mutex<u64>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(BUS_CAPACITY);
+        Self {
+            tx,
+            next_id: Mutex::new(1),
+        }
+    }
+
+    /// Publish to every subscriber, dropped when nobody is listening
+    pub fn publish(&self, source: EventSource, container_id: Option<&str>, event: &impl Serialize) {
+        let event = match serde_json::to_value(event) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("Failed to serialize {:?} event: {}", source, e);
+                return;
+            }
+        };
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+
+        let mut next_id = self.next_id.lock().unwrap();
+        let _ = self.tx.send(NodeEvent {
+            id: *next_id,
+            timestamp,
+            source,
+            container_id: container_id.map(str::to_string),
+            event,
+        });
+        *next_id += 1;
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::power::PowerEvent;
+
+    #[tokio::test]
+    async fn test_events_arrive_in_order() {
+        let bus = EventBus::new();
+        let mut rx = bus.subscribe();
+
+        bus.publish(EventSource::Power, Some("abc"), &PowerEvent::Starting("abc".to_string()));
+        bus.publish(EventSource::Power, Some("abc"), &PowerEvent::Signaled("abc".to_string(), "SIGHUP".to_string()));
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(first.container_id.as_deref(), Some("abc"));
+        assert_eq!(first.event, serde_json::json!({ "type": "starting", "args": "abc" }));
+
+        let second = rx.recv().await.unwrap();
+        assert_eq!(second.id, 2);
+        assert_eq!(second.event, serde_json::json!({ "type": "signaled", "args": ["abc", "SIGHUP"] }));
+    }
+}
@@ -0,0 +1,116 @@
+//! Node maintenance mode
+//!
+//! While it is on the node refuses new work, creates, imports and reinstalls,
+//! but everything already on it keeps working: power actions, files, consoles.
+//! The panel reads the flag from `/ready` and sends new deployments elsewhere.
+//!
+//! Toggles made through the API are written to `maintenance.json` in the
+//! storage directory so they survive the restart of an upgrade.
+//! `server.maintenance` in the config turns it on at startup regardless.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Unix time it was turned on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+}
+
+pub struct Maintenance {
+    status: RwLock<MaintenanceStatus>,
+    path: PathBuf,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl Maintenance {
+    /// Load the stored flag from `path`, `force` turns it on either way
+    pub fn load(path: impl Into<PathBuf>, force: bool) -> Self {
+        let path = path.into();
+        let mut status: MaintenanceStatus = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        if force && !status.enabled {
+            status = MaintenanceStatus {
+                enabled: true,
+                reason: Some("Enabled in config".to_string()),
+                since: Some(now()),
+            };
+        }
+        if status.enabled {
+            tracing::warn!("Node is in maintenance, new containers are refused");
+        }
+        Self { status: RwLock::new(status), path }
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        self.status.read().unwrap().clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status.read().unwrap().enabled
+    }
+
+    /// Turn maintenance on or off. Turning it on again only updates the reason.
+    pub fn set(&self, enabled: bool, reason: Option<String>) -> Result<MaintenanceStatus, std::io::Error> {
+        let mut status = self.status.write().unwrap();
+        let next = if enabled {
+            MaintenanceStatus {
+                enabled: true,
+                reason,
+                since: status.since.filter(|_| status.enabled).or(Some(now())),
+            }
+        } else {
+            MaintenanceStatus::default()
+        };
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&next)?)?;
+        if next.enabled != status.enabled {
+            tracing::info!("Maintenance mode {}", if next.enabled { "enabled" } else { "disabled" });
+        }
+        *status = next;
+        Ok(status.clone())
+    }
+
+    /// Error message for refused requests
+    pub fn message(&self) -> String {
+        match self.status().reason {
+            Some(reason) => format!("Node is in maintenance, not accepting new containers: {}", reason),
+            None => "Node is in maintenance, not accepting new containers".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_survives_reload() {
+        let path = std::env::temp_dir().join(format!("lightd-maintenance-{}.json", uuid::Uuid::new_v4()));
+
+        let maintenance = Maintenance::load(&path, false);
+        assert!(!maintenance.is_enabled());
+        maintenance.set(true, Some("kernel upgrade".to_string())).unwrap();
+        assert!(maintenance.message().ends_with("kernel upgrade"));
+
+        let reloaded = Maintenance::load(&path, false);
+        assert_eq!(reloaded.status(), maintenance.status());
+        reloaded.set(false, None).unwrap();
+        assert!(!Maintenance::load(&path, false).is_enabled());
+        assert!(Maintenance::load(&path, true).is_enabled());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,2 +1,5 @@
+pub mod events;
+pub mod maintenance;
+pub mod shutdown;
 pub mod start;
 pub mod timer;
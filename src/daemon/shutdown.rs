@@ -0,0 +1,74 @@
+//! Shutdown coordination
+//!
+//! One `Shutdown` is created in main_app and every long-running task gets a
+//! `ShutdownSignal` from it, so SIGINT/SIGTERM can stop them before the sled
+//! databases are flushed.
+
+use tokio::sync::watch;
+
+pub struct Shutdown {
+    tx: watch::Sender<bool>,
+}
+
+/// Cheap handle tasks wait on to learn the daemon is stopping
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(false);
+        Self { tx }
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        ShutdownSignal {
+            rx: self.tx.subscribe(),
+        }
+    }
+
+    /// Tell every task holding a signal to stop
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+}
+
+impl ShutdownSignal {
+    /// Resolves once shutdown has been triggered
+    pub async fn recv(&mut self) {
+        // Err means the Shutdown was dropped, treat that as shutting down too
+        let _ = self.rx.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Wait for SIGINT (Ctrl+C) or, on Unix, SIGTERM
+pub async fn wait_for_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT, shutting down"),
+        _ = terminate => tracing::info!("Received SIGTERM, shutting down"),
+    }
+}
@@ -0,0 +1,106 @@
+//! Errors callers need to tell apart
+//!
+//! Managers still return `Box<dyn Error>`. Where the kind of failure matters
+//! to the caller they box a `LightdError`, and routers turn it into a status
+//! with `status_code` instead of comparing messages.
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::filesystem::quota::InsufficientSpace;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LightdError {
+    /// `NotFound("Container")` reads "Container not found"
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    DockerUnavailable(String),
+    #[error("{0}")]
+    Validation(String),
+    /// The resource is busy or in a state that doesn't allow the request
+    #[error("{0}")]
+    Conflict(String),
+    #[error(transparent)]
+    QuotaExceeded(#[from] InsufficientSpace),
+}
+
+impl LightdError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            LightdError::NotFound(_) => StatusCode::NOT_FOUND,
+            LightdError::DockerUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            LightdError::Validation(_) => StatusCode::BAD_REQUEST,
+            LightdError::Conflict(_) => StatusCode::CONFLICT,
+            LightdError::QuotaExceeded(_) => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for LightdError {
+    fn into_response(self) -> Response {
+        (self.status_code(), Json(ErrorBody { error: self.to_string() })).into_response()
+    }
+}
+
+/// Status for a boxed error, 500 unless it is a `LightdError` or says Docker is gone
+pub fn status_code(e: &(dyn std::error::Error + 'static)) -> StatusCode {
+    if let Some(e) = e.downcast_ref::<LightdError>() {
+        return e.status_code();
+    }
+    match e.downcast_ref::<bollard::errors::Error>() {
+        Some(e) if is_docker_not_found(e) => StatusCode::NOT_FOUND,
+        Some(e) if is_docker_unreachable(e) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Docker answered 404 (no such container, image or network)
+pub fn is_docker_not_found(e: &bollard::errors::Error) -> bool {
+    matches!(e, bollard::errors::Error::DockerResponseServerError { status_code: 404, .. })
+}
+
+/// The request never got an answer from the daemon
+fn is_docker_unreachable(e: &bollard::errors::Error) -> bool {
+    use bollard::errors::Error;
+    matches!(
+        e,
+        Error::IOError { .. } | Error::HyperResponseError { .. } | Error::RequestTimeoutError | Error::SocketNotFoundError(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(LightdError::NotFound("Container"));
+        assert_eq!(boxed.to_string(), "Container not found");
+        assert_eq!(status_code(&*boxed), StatusCode::NOT_FOUND);
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(LightdError::Conflict("busy".to_string()));
+        assert_eq!(status_code(&*boxed), StatusCode::CONFLICT);
+
+        let docker = bollard::errors::Error::DockerResponseServerError {
+            status_code: 404,
+            message: "No such container: abc".to_string(),
+        };
+        assert!(is_docker_not_found(&docker));
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(docker);
+        assert_eq!(status_code(&*boxed), StatusCode::NOT_FOUND);
+
+        // Plain messages carry no meaning any more
+        let boxed: Box<dyn std::error::Error + Send + Sync> = "Container not found".into();
+        assert_eq!(status_code(&*boxed), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,508 @@
+//! Volume backups
+//!
+//! A backup is a tar.gz of the whole volume stored as
+//! `<backups>/<volume_id>/<backup_id>.tar.gz`, next to a JSON file with its
+//! metadata. Creating and restoring run in the background, one job per volume
+//! at a time, progress of running jobs is kept in memory so the list endpoint
+//! can report it. A restore unpacks into a staging directory and only swaps
+//! the volume contents once that worked, its outcome is saved with the backup.
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tar::{Archive, EntryType};
+use tokio::sync::RwLock;
+use tracing::Instrument;
+
+use super::security::is_safe_archive_path;
+use crate::error::LightdError;
+
+/// Staging directories of restores, inside the volume so the swap is a rename
+const RESTORE_STAGING_PREFIX: &str = ".lightd-restore-";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupStatus {
+    Creating,
+    Restoring,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub id: String,
+    pub volume_id: String,
+    pub name: String,
+    /// Size of the compressed archive, 0 until the backup is completed
+    pub size_bytes: u64,
+    pub status: BackupStatus,
+    /// Percentage of the running job, 100 once it is done
+    pub progress: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: u64,
+    /// Outcome of the last restore of this backup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_restore: Option<RestoreResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreResult {
+    /// `completed` or `failed`
+    pub status: BackupStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub finished_at: u64,
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub struct BackupStore {
+    root: PathBuf,
+    /// Running jobs by backup id
+    active: Arc<DashMap<String, BackupInfo>>,
+    /// Volume id -> backup id of the job running on it
+    volumes: Arc<DashMap<String, String>>,
+}
+
+impl BackupStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            active: Arc::new(DashMap::new()),
+            volumes: Arc::new(DashMap::new()),
+        }
+    }
+
+    fn archive_path(root: &Path, volume_id: &str, backup_id: &str) -> PathBuf {
+        root.join(volume_id).join(format!("{}.tar.gz", backup_id))
+    }
+
+    fn metadata_path(root: &Path, volume_id: &str, backup_id: &str) -> PathBuf {
+        root.join(volume_id).join(format!("{}.json", backup_id))
+    }
+
+    /// Reserve the volume for a job, checked and taken in one step
+    fn claim(&self, volume_id: &str, backup_id: &str) -> Result<(), LightdError> {
+        match self.volumes.entry(volume_id.to_string()) {
+            Entry::Occupied(_) => Err(LightdError::Conflict("Another backup job is running for this volume".to_string())),
+            Entry::Vacant(entry) => {
+                entry.insert(backup_id.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    async fn write_metadata(root: &Path, info: &BackupInfo) {
+        let metadata = Self::metadata_path(root, &info.volume_id, &info.id);
+        match serde_json::to_vec_pretty(info) {
+            Ok(bytes) => {
+                if let Err(e) = tokio::fs::write(&metadata, bytes).await {
+                    tracing::error!("Failed to write backup metadata {}: {}", metadata.display(), e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to serialize backup metadata: {}", e),
+        }
+    }
+
+    /// Start archiving `volume_path` in the background
+    pub async fn create(
+        &self,
+        volume_id: &str,
+        volume_path: PathBuf,
+        name: Option<String>,
+        lock: Arc<RwLock<()>>,
+    ) -> Result<BackupInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let info = BackupInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            volume_id: volume_id.to_string(),
+            name: name.unwrap_or_else(|| format!("backup-{}", created_at)),
+            size_bytes: 0,
+            status: BackupStatus::Creating,
+            progress: 0,
+            error: None,
+            created_at,
+            last_restore: None,
+        };
+
+        self.claim(volume_id, &info.id)?;
+        if let Err(e) = tokio::fs::create_dir_all(self.root.join(volume_id)).await {
+            self.volumes.remove(volume_id);
+            return Err(e.into());
+        }
+        self.active.insert(info.id.clone(), info.clone());
+
+        let root = self.root.clone();
+        let active = self.active.clone();
+        let volumes = self.volumes.clone();
+        let mut job = info.clone();
+
+        tokio::spawn(async move {
+            // Nothing may change the files while they are archived
+            let _exclusive = lock.write_owned().await;
+            let backup_id = job.id.clone();
+            let archive = Self::archive_path(&root, &job.volume_id, &backup_id);
+            let progress = active.clone();
+
+            let result = tokio::task::spawn_blocking(move || {
+                write_archive(&volume_path, &archive, |percent| {
+                    if let Some(mut entry) = progress.get_mut(&backup_id) {
+                        entry.progress = percent;
+                    }
+                })
+            }).await;
+
+            match result {
+                Ok(Ok(size)) => {
+                    job.size_bytes = size;
+                    job.status = BackupStatus::Completed;
+                    job.progress = 100;
+                    tracing::info!("Backup {} of volume {} completed ({} bytes)", job.id, job.volume_id, size);
+                }
+                Ok(Err(e)) => {
+                    job.status = BackupStatus::Failed;
+                    job.error = Some(e.clone());
+                    tracing::error!("Backup {} of volume {} failed: {}", job.id, job.volume_id, e);
+                }
+                Err(e) => {
+                    job.status = BackupStatus::Failed;
+                    job.error = Some(e.to_string());
+                    tracing::error!("Backup {} of volume {} panicked: {}", job.id, job.volume_id, e);
+                }
+            }
+
+            Self::write_metadata(&root, &job).await;
+
+            active.remove(&job.id);
+            volumes.remove(&job.volume_id);
+        }.in_current_span());
+
+        tracing::info!("Started backup {} of volume {}", info.id, volume_id);
+        Ok(info)
+    }
+
+    /// Backups of a volume, newest first, including running jobs
+    pub async fn list(&self, volume_id: &str) -> Result<Vec<BackupInfo>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut backups: Vec<BackupInfo> = self.active.iter()
+            .filter(|job| job.volume_id == volume_id)
+            .map(|job| job.clone())
+            .collect();
+
+        let dir = self.root.join(volume_id);
+        if dir.exists() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+
+                let info: BackupInfo = match serde_json::from_slice(&tokio::fs::read(&path).await?) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        tracing::warn!("Skipping unreadable backup metadata {}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+
+                // A restore in progress already shows up from the active jobs
+                if !backups.iter().any(|b| b.id == info.id) {
+                    backups.push(info);
+                }
+            }
+        }
+
+        backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+        Ok(backups)
+    }
+
+    async fn get(&self, volume_id: &str, backup_id: &str) -> Result<BackupInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let metadata = Self::metadata_path(&self.root, volume_id, backup_id);
+        if uuid::Uuid::parse_str(backup_id).is_err() || !metadata.exists() {
+            return Err(LightdError::NotFound("Backup").into());
+        }
+        Ok(serde_json::from_slice(&tokio::fs::read(&metadata).await?)?)
+    }
+
+    /// Replace the contents of `volume_path` with a completed backup, in the background
+    pub async fn restore(
+        &self,
+        volume_id: &str,
+        volume_path: PathBuf,
+        backup_id: &str,
+        lock: Arc<RwLock<()>>,
+    ) -> Result<BackupInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let backup = self.get(volume_id, backup_id).await?;
+        if backup.status != BackupStatus::Completed {
+            return Err(LightdError::Validation("Only completed backups can be restored".to_string()).into());
+        }
+        self.claim(volume_id, backup_id)?;
+
+        let mut job = backup.clone();
+        job.status = BackupStatus::Restoring;
+        job.progress = 0;
+        self.active.insert(job.id.clone(), job.clone());
+
+        let root = self.root.clone();
+        let archive = Self::archive_path(&self.root, volume_id, backup_id);
+        let active = self.active.clone();
+        let volumes = self.volumes.clone();
+        let started = job.clone();
+        let mut backup = backup;
+
+        tokio::spawn(async move {
+            let _exclusive = lock.write_owned().await;
+            let progress = active.clone();
+            let id = job.id.clone();
+
+            let result = tokio::task::spawn_blocking(move || {
+                restore_archive(&archive, &volume_path, |percent| {
+                    if let Some(mut entry) = progress.get_mut(&id) {
+                        entry.progress = percent;
+                    }
+                })
+            }).await;
+
+            let error = match result {
+                Ok(Ok(())) => {
+                    tracing::info!("Restored backup {} into volume {}", job.id, job.volume_id);
+                    None
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("Restoring backup {} into volume {} failed: {}", job.id, job.volume_id, e);
+                    Some(e)
+                }
+                Err(e) => {
+                    tracing::error!("Restoring backup {} into volume {} panicked: {}", job.id, job.volume_id, e);
+                    Some(e.to_string())
+                }
+            };
+
+            // The backup itself stays restorable, only the attempt is recorded
+            backup.last_restore = Some(RestoreResult {
+                status: if error.is_some() { BackupStatus::Failed } else { BackupStatus::Completed },
+                error,
+                finished_at: unix_now(),
+            });
+            Self::write_metadata(&root, &backup).await;
+
+            active.remove(&job.id);
+            volumes.remove(&job.volume_id);
+        }.in_current_span());
+
+        tracing::info!("Started restore of backup {} into volume {}", backup_id, volume_id);
+        Ok(started)
+    }
+
+    pub async fn delete(&self, volume_id: &str, backup_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.get(volume_id, backup_id).await?;
+        if self.active.contains_key(backup_id) {
+            return Err(LightdError::Conflict("Backup is in use by a running job".to_string()).into());
+        }
+
+        let archive = Self::archive_path(&self.root, volume_id, backup_id);
+        if archive.exists() {
+            tokio::fs::remove_file(&archive).await?;
+        }
+        tokio::fs::remove_file(Self::metadata_path(&self.root, volume_id, backup_id)).await?;
+
+        tracing::info!("Deleted backup {} of volume {}", backup_id, volume_id);
+        Ok(())
+    }
+}
+
+/// Collect every path under `dir` with the total size of regular files
+fn walk_volume(dir: &Path, out: &mut Vec<PathBuf>, total: &mut u64) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| e.to_string())?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let metadata = std::fs::symlink_metadata(&path).map_err(|e| e.to_string())?;
+
+        out.push(path.clone());
+        if metadata.is_dir() {
+            walk_volume(&path, out, total)?;
+        } else if metadata.is_file() {
+            *total += metadata.len();
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a tar.gz of `volume` to `output`, returns the archive size
+pub(crate) fn write_archive(volume: &Path, output: &Path, progress: impl Fn(u8)) -> Result<u64, String> {
+    let mut paths = Vec::new();
+    let mut total = 0u64;
+    walk_volume(volume, &mut paths, &mut total)?;
+
+    // Written under a temporary name so a crash never leaves a truncated "completed" archive
+    let partial = output.with_extension("partial");
+    let file = File::create(&partial).map_err(|e| e.to_string())?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    // Store symlinks as links, following them could pull in files outside the volume
+    tar.follow_symlinks(false);
+
+    let mut done = 0u64;
+    let mut last_percent = 0u8;
+
+    for path in paths {
+        let rel_path = path.strip_prefix(volume).map_err(|e| e.to_string())?;
+        let metadata = std::fs::symlink_metadata(&path).map_err(|e| e.to_string())?;
+
+        if metadata.is_dir() {
+            tar.append_dir(rel_path, &path).map_err(|e| e.to_string())?;
+        } else {
+            tar.append_path_with_name(&path, rel_path).map_err(|e| e.to_string())?;
+            if metadata.is_file() {
+                done += metadata.len();
+            }
+        }
+
+        let percent = (done * 100).checked_div(total).map_or(100, |p| p as u8);
+        if percent != last_percent {
+            last_percent = percent;
+            progress(percent);
+        }
+    }
+
+    tar.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| e.to_string())?;
+    std::fs::rename(&partial, output).map_err(|e| e.to_string())?;
+
+    std::fs::metadata(output).map(|m| m.len()).map_err(|e| e.to_string())
+}
+
+/// Check every entry of a backup before anything in the volume is touched
+fn validate_archive(archive: &Path) -> Result<u64, String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let mut tar = Archive::new(GzDecoder::new(file));
+    let mut count = 0u64;
+
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path().map_err(|e| e.to_string())?;
+        if !is_safe_archive_path(&path) {
+            return Err(format!("Backup contains an unsafe path: {}", path.display()));
+        }
+
+        if matches!(entry.header().entry_type(), EntryType::Symlink | EntryType::Link) {
+            let target = entry.link_name().map_err(|e| e.to_string())?.unwrap_or_default();
+            if !is_safe_archive_path(&target) {
+                return Err(format!("Backup contains a link escaping the volume: {}", path.display()));
+            }
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Replace the volume contents with the archive. It is unpacked into a
+/// staging directory first, a failed extraction leaves the volume as it was.
+pub(crate) fn restore_archive(archive: &Path, volume: &Path, progress: impl Fn(u8)) -> Result<(), String> {
+    let total = validate_archive(archive)?;
+
+    let staging = volume.join(format!("{}{}", RESTORE_STAGING_PREFIX, uuid::Uuid::new_v4()));
+    std::fs::create_dir(&staging).map_err(|e| e.to_string())?;
+    let result = unpack_archive(archive, &staging, total, progress)
+        .and_then(|_| super::owner::adopt(volume, &staging).map_err(|e| e.to_string()))
+        .and_then(|_| swap_in(volume, &staging));
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+fn unpack_archive(archive: &Path, dest: &Path, total: u64, progress: impl Fn(u8)) -> Result<(), String> {
+    let file = File::open(archive).map_err(|e| e.to_string())?;
+    let mut tar = Archive::new(GzDecoder::new(file));
+    let mut done = 0u64;
+
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        // unpack_in refuses paths that would land outside the destination
+        if !entry.unpack_in(dest).map_err(|e| e.to_string())? {
+            return Err("Backup entry escaped the volume".to_string());
+        }
+
+        done += 1;
+        progress((done * 100 / total.max(1)) as u8);
+    }
+
+    Ok(())
+}
+
+/// Clear the volume except `staging` and move the staged entries up. The
+/// contents are replaced, not the directory itself, it may be a quota mount point.
+fn swap_in(volume: &Path, staging: &Path) -> Result<(), String> {
+    for entry in std::fs::read_dir(volume).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path == staging {
+            continue;
+        }
+        let metadata = std::fs::symlink_metadata(&path).map_err(|e| e.to_string())?;
+        if metadata.is_dir() {
+            std::fs::remove_dir_all(&path).map_err(|e| e.to_string())?;
+        } else {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    }
+
+    for entry in std::fs::read_dir(staging).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        std::fs::rename(entry.path(), volume.join(entry.file_name())).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_replaces_contents() {
+        let base = std::env::temp_dir().join(format!("lightd-backup-{}", uuid::Uuid::new_v4()));
+        let volume = base.join("volume");
+        std::fs::create_dir_all(volume.join("world")).unwrap();
+        std::fs::write(volume.join("world/level.dat"), b"level").unwrap();
+        let archive = base.join("backup.tar.gz");
+        write_archive(&volume, &archive, |_| {}).unwrap();
+
+        std::fs::write(volume.join("world/level.dat"), b"changed").unwrap();
+        std::fs::write(volume.join("new.txt"), b"new").unwrap();
+
+        // A broken archive fails before the volume is touched
+        let broken = base.join("broken.tar.gz");
+        std::fs::write(&broken, b"not an archive").unwrap();
+        assert!(restore_archive(&broken, &volume, |_| {}).is_err());
+        assert!(volume.join("new.txt").exists());
+
+        restore_archive(&archive, &volume, |_| {}).unwrap();
+        assert_eq!(std::fs::read(volume.join("world/level.dat")).unwrap(), b"level");
+        assert!(!volume.join("new.txt").exists());
+        let leftovers = std::fs::read_dir(&volume).unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with(RESTORE_STAGING_PREFIX))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
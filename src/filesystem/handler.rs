@@ -1,8 +1,17 @@
 use super::volume::{Volume};
 use super::security;
-use super::quota::QuotaManager;
+use super::quota::{InsufficientSpace, QuotaManager};
+use super::skeleton::{validate_skeleton, SkeletonFile};
+use super::backup::{BackupInfo, BackupStore};
+use super::hash::{self, HashAlgorithm, HashJob, HashJobs};
+use super::lock::VolumeLocks;
+use super::owner;
+use super::trash::{self, TrashItem, TrashSettings};
+use super::transfer::{self, ContentRange, FileVersion, UnexpectedOffset};
 use super::fileinfo::{FileObject, list_directory_detailed};
-use std::path::PathBuf;
+use crate::error::LightdError;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::fs::File;
@@ -21,31 +30,75 @@ pub struct VolumeHandler {
     volumes: Arc<RwLock<Vec<Volume>>>,
     base_path: String,
     quota_manager: Arc<QuotaManager>,
+    backups: Arc<BackupStore>,
+    hash_jobs: HashJobs,
+    /// Bulk archive operations hold a volume exclusively, see `lock`
+    locks: Arc<VolumeLocks>,
+    max_decompression_ratio: u64,
+    trash_retention_secs: u64,
+    /// Quota for volumes created without a size, None leaves them unlimited
+    default_quota_mb: Option<u64>,
 }
 
 impl VolumeHandler {
     pub fn new(base_path: String) -> Self {
         let quota_manager = Arc::new(QuotaManager::new(PathBuf::from(&base_path)));
+        // Next to the volumes directory unless configured otherwise
+        let backups = Arc::new(BackupStore::new(PathBuf::from(&base_path).with_file_name("backups")));
         Self {
             volumes: Arc::new(RwLock::new(Vec::new())),
             base_path,
             quota_manager,
+            backups,
+            hash_jobs: HashJobs::new(),
+            locks: Arc::new(VolumeLocks::new()),
+            max_decompression_ratio: DEFAULT_MAX_DECOMPRESSION_RATIO,
+            trash_retention_secs: trash::DEFAULT_RETENTION_SECS,
+            default_quota_mb: Some(super::quota::DEFAULT_QUOTA_MB),
         }
     }
 
-    pub async fn create_volume(&self) -> Result<Volume, Box<dyn std::error::Error>> {
+    /// Quota for volumes created without a size, 0 opts into unlimited volumes
+    pub fn with_default_quota(mut self, size_mb: u64) -> Self {
+        self.default_quota_mb = (size_mb > 0).then_some(size_mb);
+        self
+    }
+
+    /// Abort extractions that unpack to more than `ratio` times the archive size
+    pub fn with_max_decompression_ratio(mut self, ratio: u64) -> Self {
+        self.max_decompression_ratio = ratio;
+        self
+    }
+
+    /// Purge trashed items once they are older than `secs`
+    pub fn with_trash_retention(mut self, secs: u64) -> Self {
+        self.trash_retention_secs = secs;
+        self
+    }
+
+    /// Store volume backups under `path`
+    pub fn with_backup_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.backups = Arc::new(BackupStore::new(path.into()));
+        self
+    }
+
+    /// Only reached when the default quota is configured as unlimited
+    async fn create_unlimited_volume(&self) -> Result<Volume, Box<dyn std::error::Error>> {
         let volume = Volume::new(&self.base_path)?;
         volume.create().await?;
 
         let mut volumes = self.volumes.write().await;
         volumes.push(volume.clone());
 
-        tracing::info!("Volume created with ID: {}", volume.id);
+        tracing::info!("Volume created with ID: {} and no quota", volume.id);
         Ok(volume)
     }
     
+    /// `size_mb` falls back to the default quota
     pub async fn create_volume_with_quota(&self, size_mb: Option<u64>) -> Result<Volume, Box<dyn std::error::Error>> {
-        let quota_size = size_mb.unwrap_or(1024); // Default 1GB
+        let Some(quota_size) = size_mb.or(self.default_quota_mb) else {
+            return self.create_unlimited_volume().await;
+        };
         let volume = Volume::new_with_quota(&self.base_path, quota_size)?;
         
         // Create volume with OS-level quota
@@ -60,6 +113,42 @@ impl VolumeHandler {
         Ok(volume)
     }
     
+    /// Register a volume under a given id, for containers imported from another node.
+    /// `size_mb` falls back to the default quota.
+    pub async fn create_volume_with_id(&self, id: &str, size_mb: Option<u64>) -> Result<Volume, Box<dyn std::error::Error>> {
+        let mut volumes = self.volumes.write().await;
+        let path = PathBuf::from(&self.base_path).join(id);
+        let on_disk = std::fs::read_dir(&path).map(|mut entries| entries.next().is_some()).unwrap_or(false);
+        if on_disk || volumes.iter().any(|v| v.id == id) {
+            return Err(LightdError::Conflict(format!("Volume {} already exists on this node", id)).into());
+        }
+
+        let quota = size_mb.or(self.default_quota_mb);
+        let volume = Volume::with_id(&self.base_path, id, quota)?;
+        match quota {
+            Some(size) => {
+                self.quota_manager.create_volume_with_quota(id, Some(size))
+                    .await
+                    .map_err(|e| -> Box<dyn std::error::Error> { e.to_string().into() })?;
+            }
+            None => volume.create().await?,
+        }
+        volumes.push(volume.clone());
+
+        tracing::info!("Volume created with ID: {} and {:?}MB quota", id, quota);
+        Ok(volume)
+    }
+    
+    /// Shared with the stats collector for the disk gauge
+    pub fn quota_manager(&self) -> Arc<QuotaManager> {
+        self.quota_manager.clone()
+    }
+    
+    /// Shared with the SFTP server, whose requests hold a volume like file writes do
+    pub fn volume_locks(&self) -> Arc<VolumeLocks> {
+        self.locks.clone()
+    }
+    
     pub async fn get_volume_quota(&self, id: &str) -> Result<super::quota::DiskQuota, Box<dyn std::error::Error>> {
         self.quota_manager.get_quota_usage(id)
             .await
@@ -79,6 +168,16 @@ impl VolumeHandler {
             .map_err(|e| e.to_string().into())
     }
 
+    /// Reject a write of `bytes` that won't fit, volumes without a quota aren't checked
+    async fn ensure_space(&self, volume: &Volume, bytes: u64) -> Result<(), Box<dyn std::error::Error>> {
+        if volume.quota_mb.is_none() {
+            return Ok(());
+        }
+        self.quota_manager.ensure_space(&volume.id, bytes)
+            .await
+            .map_err(|e| -> Box<dyn std::error::Error> { e })
+    }
+
     pub async fn get_volume(&self, id: &str) -> Option<Volume> {
         let volumes = self.volumes.read().await;
         volumes.iter().find(|v| v.id == id).cloned()
@@ -104,23 +203,26 @@ impl VolumeHandler {
                 tokio::fs::remove_dir_all(&volume.path).await?;
             }
             
+            self.locks.remove(id);
             tracing::info!("Deleted volume: {}", id);
             Ok(())
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
     pub async fn list_volume_files(&self, id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _shared = self.locks.shared(id).await;
             volume.list_files().await
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
     
     pub async fn list_volume_files_detailed(&self, id: &str, path: Option<&str>) -> Result<Vec<FileObject>, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _shared = self.locks.shared(id).await;
             let target_path = if let Some(p) = path {
                 volume.get_path().join(p.trim_start_matches('/'))
             } else {
@@ -137,30 +239,360 @@ impl VolumeHandler {
             
             list_directory_detailed(&target_path).await.map_err(|e| e.to_string().into())
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
-    pub async fn write_file(&self, id: &str, filename: &str, content: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    /// Disk usage of each immediate child of `path`, directories summed
+    /// recursively, largest first
+    pub async fn directory_sizes(&self, id: &str, path: Option<&str>) -> Result<DirectoryUsage, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let _shared = self.locks.shared(id).await;
+        let path = path.unwrap_or("/").to_string();
+        let target = if path.trim_start_matches('/').is_empty() {
+            volume.get_path().to_path_buf()
+        } else {
+            security::validate_read_path(volume.get_path(), path.trim_start_matches('/'))?
+        };
+        if !target.is_dir() {
+            return Err("Path is not a directory".into());
+        }
+        
+        // Big trees take a while to walk, keep it off the async workers
+        let entries = tokio::task::spawn_blocking(move || -> std::io::Result<Vec<EntryUsage>> {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(&target)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let size_bytes = if metadata.is_dir() {
+                    super::quota::directory_size(&entry.path())?
+                } else if metadata.is_file() {
+                    metadata.len()
+                } else {
+                    // Symlinks and special files take no space worth reporting
+                    continue;
+                };
+                entries.push(EntryUsage {
+                    name: entry.file_name().to_string_lossy().to_string(),
+                    is_dir: metadata.is_dir(),
+                    size_bytes,
+                });
+            }
+            entries.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then_with(|| a.name.cmp(&b.name)));
+            Ok(entries)
+        }).await.map_err(|e| e.to_string())??;
+        
+        Ok(DirectoryUsage {
+            path,
+            total_bytes: entries.iter().map(|e| e.size_bytes).sum(),
+            entries,
+        })
+    }
+
+    /// Delete a file or folder, into the trash when the volume has it enabled.
+    /// Returns the trash item in that case.
+    pub async fn delete_path(&self, id: &str, path: &str) -> Result<Option<TrashItem>, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let _shared = self.locks.shared(id).await;
+        let target = security::validate_read_path(volume.get_path(), path.trim_start_matches('/'))?;
+        let root = volume.get_path().to_path_buf();
+        
+        let item = tokio::task::spawn_blocking(move || trash::delete(&root, &target))
+            .await
+            .map_err(|e| e.to_string())??;
+        tracing::info!("Deleted {} in volume {}{}", path, id, if item.is_some() { " (to trash)" } else { "" });
+        Ok(item)
+    }
+
+    pub async fn trash_settings(&self, id: &str) -> Result<TrashSettings, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let root = volume.get_path().to_path_buf();
+        Ok(tokio::task::spawn_blocking(move || trash::settings(&root)).await.map_err(|e| e.to_string())?)
+    }
+
+    pub async fn set_trash_settings(&self, id: &str, settings: TrashSettings) -> Result<(), Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let root = volume.get_path().to_path_buf();
+        let enabled = settings.enabled;
+        tokio::task::spawn_blocking(move || trash::set_settings(&root, &settings))
+            .await
+            .map_err(|e| e.to_string())??;
+        tracing::info!("Trash {} for volume {}", if enabled { "enabled" } else { "disabled" }, id);
+        Ok(())
+    }
+
+    pub fn trash_retention_secs(&self) -> u64 {
+        self.trash_retention_secs
+    }
+
+    pub async fn list_trash(&self, id: &str) -> Result<Vec<TrashItem>, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let root = volume.get_path().to_path_buf();
+        Ok(tokio::task::spawn_blocking(move || trash::list(&root)).await.map_err(|e| e.to_string())??)
+    }
+
+    pub async fn restore_trash(&self, id: &str, item_id: &str) -> Result<TrashItem, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let _shared = self.locks.shared(id).await;
+        let root = volume.get_path().to_path_buf();
+        let item_id = item_id.to_string();
+        Ok(tokio::task::spawn_blocking(move || trash::restore(&root, &item_id)).await.map_err(|e| e.to_string())??)
+    }
+
+    /// Delete one trashed item for good, or the whole trash with `None`
+    pub async fn purge_trash(&self, id: &str, item_id: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let _shared = self.locks.shared(id).await;
+        let root = volume.get_path().to_path_buf();
+        let purged = match item_id {
+            Some(item_id) => {
+                let item_id = item_id.to_string();
+                tokio::task::spawn_blocking(move || trash::purge(&root, &item_id).map(|_| 1))
+                    .await
+                    .map_err(|e| e.to_string())??
+            }
+            None => tokio::task::spawn_blocking(move || trash::purge_older_than(&root, None))
+                .await
+                .map_err(|e| e.to_string())??,
+        };
+        Ok(purged)
+    }
+
+    /// Purge expired trash of every volume once an hour, until shutdown
+    pub fn start_trash_purge(self: Arc<Self>, mut shutdown: crate::daemon::shutdown::ShutdownSignal) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = shutdown.recv() => break,
+                }
+                
+                let roots: Vec<PathBuf> = self.volumes.read().await.iter().map(|v| v.path.clone()).collect();
+                let retention = self.trash_retention_secs;
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut purged = 0;
+                    for root in roots {
+                        match trash::purge_older_than(&root, Some(retention)) {
+                            Ok(count) => purged += count,
+                            Err(e) => tracing::warn!("Failed to purge trash of {}: {}", root.display(), e),
+                        }
+                    }
+                    purged
+                }).await;
+                match result {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::info!("Purged {} expired trash items", purged),
+                    Err(e) => tracing::error!("Trash purge failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Write `content` to `filename`. With `if_match` the write only happens
+    /// while the file still has that ETag.
+    pub async fn write_file(&self, id: &str, filename: &str, content: &str, if_match: Option<&str>) -> Result<FileVersion, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _shared = self.locks.shared(id).await;
             // Validate path to prevent traversal
             let safe_path = security::validate_write_path(volume.get_path(), filename)?;
+            self.ensure_space(&volume, content.len() as u64).await?;
+            transfer::check_if_match(&safe_path, if_match)?;
             
             // Ensure parent directory exists
             if let Some(parent) = safe_path.parent() {
-                tokio::fs::create_dir_all(parent).await?;
+                owner::create_dirs(volume.get_path(), parent)?;
             }
             
             tokio::fs::write(&safe_path, content).await?;
+            owner::adopt(volume.get_path(), &safe_path)?;
+            self.quota_manager.record_written(id, content.len() as u64);
             tracing::info!("Wrote file {} to volume {}", filename, id);
-            Ok(safe_path)
+            let meta = tokio::fs::metadata(&safe_path).await?;
+            Ok(FileVersion { etag: transfer::weak_etag(&meta), size: meta.len(), path: safe_path })
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
+    /// Write default files into a volume, each through `write_file`
+    pub async fn seed_files(&self, id: &str, files: &[SkeletonFile]) -> Result<(), Box<dyn std::error::Error>> {
+        validate_skeleton(files)?;
+        for file in files {
+            self.write_file(id, &file.path, &file.content, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Open a file for download, with the metadata its ETag comes from
+    pub async fn open_file(&self, id: &str, path: &str) -> Result<(tokio::fs::File, std::fs::Metadata), Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        // Only while opening, the open file reads the same data afterwards
+        let _shared = self.locks.shared(id).await;
+        let safe_path = security::validate_read_path(volume.get_path(), path)?;
+        let file = tokio::fs::File::open(&safe_path).await?;
+        let meta = file.metadata().await?;
+        if !meta.is_file() {
+            return Err("Path is not a file".into());
+        }
+        Ok((file, meta))
+    }
+
+    /// Checksum of a file, read in chunks on a blocking thread
+    pub async fn hash_file(&self, id: &str, path: &str, algorithm: HashAlgorithm) -> Result<String, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let _shared = self.locks.shared(id).await;
+        let safe_path = security::validate_read_path(volume.get_path(), path)?;
+        if !safe_path.is_file() {
+            return Err("Path is not a file".into());
+        }
+        
+        let digest = tokio::task::spawn_blocking(move || {
+            hash::hash_reader(File::open(&safe_path)?, algorithm, |_| {})
+        }).await.map_err(|e| e.to_string())??;
+        Ok(digest)
+    }
+
+    /// Hash a file in the background, poll the job with `hash_job`
+    pub async fn start_hash_job(&self, id: &str, path: &str, algorithm: HashAlgorithm) -> Result<HashJob, Box<dyn std::error::Error>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        // Held while the file is opened, the job keeps reading that file like a download
+        let _shared = self.locks.shared(id).await;
+        let safe_path = security::validate_read_path(volume.get_path(), path)?;
+        let meta = tokio::fs::metadata(&safe_path).await?;
+        if !meta.is_file() {
+            return Err("Path is not a file".into());
+        }
+        let file = std::fs::File::open(&safe_path)?;
+        
+        let job = self.hash_jobs.start(id, path, file, meta.len(), algorithm);
+        tracing::info!("Started hash job {} for {} in volume {}", job.id, path, id);
+        Ok(job)
+    }
+
+    pub fn hash_job(&self, id: &str, job_id: &str) -> Option<HashJob> {
+        self.hash_jobs.get(id, job_id)
+    }
+
+    /// Stream `body` into `filename`.
+    ///
+    /// Without `range` the file is replaced once the whole body arrived.
+    /// With it the body is one chunk written at its offset into the existing
+    /// file, so an interrupted upload continues from the size it reached.
+    /// `size_hint` (Content-Length) is checked against the quota up front.
+    ///
+    /// The body arrives into a temporary file without holding the volume
+    /// lock, a slow client must not stall the volume. The lock is only held
+    /// to check the target and to move the data into place.
+    pub async fn upload_file<S, E>(
+        &self,
+        id: &str,
+        filename: &str,
+        mut body: S,
+        range: Option<ContentRange>,
+        if_match: Option<&str>,
+        size_hint: Option<u64>,
+    ) -> Result<FileVersion, Box<dyn std::error::Error>>
+    where
+        S: futures::Stream<Item = Result<bytes::Bytes, E>> + Unpin,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        use futures::StreamExt;
+        use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        let (safe_path, parent, budget) = {
+            let _shared = self.locks.shared(id).await;
+            let safe_path = security::validate_write_path(volume.get_path(), filename)?;
+            let expected = range.map(|r| r.range.end - r.range.start + 1).or(size_hint);
+            self.ensure_space(&volume, expected.unwrap_or(0)).await?;
+            // Bodies without a length, or longer than announced, are held to the free space while they stream
+            let budget = match volume.quota_mb {
+                Some(_) => Some(self.quota_manager.available_bytes(id).await.map_err(|e| -> Box<dyn std::error::Error> { e })?),
+                None => None,
+            };
+            transfer::check_if_match(&safe_path, if_match)?;
+            if let Some(range) = range {
+                Self::check_offset(&safe_path, range.range.start).await?;
+            }
+
+            let parent = safe_path.parent().ok_or("Invalid path")?.to_path_buf();
+            owner::create_dirs(volume.get_path(), &parent)?;
+            (safe_path, parent, budget)
+        };
+
+        // Next to the target so the final rename stays on the same filesystem
+        let name = safe_path.file_name().ok_or("Invalid path")?.to_string_lossy().to_string();
+        let temp = parent.join(format!(".{}.upload-{}", name, uuid::Uuid::new_v4()));
+        let mut written = 0u64;
+        let result = async {
+            let mut file = tokio::fs::File::create(&temp).await?;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(std::io::Error::other)?;
+                written += chunk.len() as u64;
+                if let Some(available_bytes) = budget.filter(|available| written > *available) {
+                    tracing::warn!("Aborted upload to {} in volume {} at {} bytes, {} bytes available", filename, id, written, available_bytes);
+                    return Err(LightdError::QuotaExceeded(InsufficientSpace {
+                        volume_id: id.to_string(),
+                        needed_bytes: written,
+                        available_bytes,
+                    }).into());
+                }
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+
+            let _shared = self.locks.shared(id).await;
+            // A bulk operation may have replaced the directories meanwhile
+            let safe_path = security::validate_write_path(volume.get_path(), filename)?;
+            if let Some(range) = range {
+                if written != range.range.end - range.range.start + 1 {
+                    return Err(format!("Chunk has {} bytes, Content-Range announced {}", written, range.range.end - range.range.start + 1).into());
+                }
+                Self::check_offset(&safe_path, range.range.start).await?;
+
+                let mut target = tokio::fs::OpenOptions::new().write(true).create(true).truncate(false).open(&safe_path).await?;
+                target.seek(std::io::SeekFrom::Start(range.range.start)).await?;
+                let mut chunk = tokio::fs::File::open(&temp).await?;
+                tokio::io::copy(&mut chunk, &mut target).await?;
+                // The last chunk fixes the final size, e.g. when overwriting a longer file
+                if let Some(total) = range.total.filter(|total| range.range.end + 1 == *total) {
+                    target.set_len(total).await?;
+                }
+                target.flush().await?;
+                tokio::fs::remove_file(&temp).await?;
+            } else {
+                // Someone may have changed the file while the body was arriving
+                transfer::check_if_match(&safe_path, if_match)?;
+                // Renamed over the target, readers never see half a file
+                tokio::fs::rename(&temp, &safe_path).await?;
+            }
+            owner::adopt(volume.get_path(), &safe_path)?;
+            Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+        }.await;
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e as Box<dyn std::error::Error>);
+        }
+        
+        self.quota_manager.record_written(id, written);
+        tracing::info!("Uploaded {} bytes to {} in volume {}", written, filename, id);
+        let meta = tokio::fs::metadata(&safe_path).await?;
+        Ok(FileVersion { etag: transfer::weak_etag(&meta), size: meta.len(), path: safe_path })
+    }
+
+    /// A chunk may continue the file or rewrite part of it, not leave a gap
+    async fn check_offset(path: &Path, offset: u64) -> Result<(), UnexpectedOffset> {
+        let size = tokio::fs::metadata(path).await.map(|m| m.len()).unwrap_or(0);
+        if offset > size {
+            return Err(UnexpectedOffset { offset, size });
+        }
+        Ok(())
+    }
+
     pub async fn create_folder(&self, id: &str, root: &str, name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _shared = self.locks.shared(id).await;
             // Combine root and name for validation
             let full_path = if root == "/" {
                 name.to_string()
@@ -170,17 +602,20 @@ impl VolumeHandler {
             
             // Validate path to prevent traversal
             let safe_path = security::validate_write_path(volume.get_path(), &full_path)?;
+            // One block per directory
+            self.ensure_space(&volume, DIR_ENTRY_BYTES).await?;
             
-            tokio::fs::create_dir_all(&safe_path).await?;
+            owner::create_dirs(volume.get_path(), &safe_path)?;
             tracing::info!("Created folder {} at {} in volume {}", name, root, id);
             Ok(safe_path)
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
     pub async fn copy(&self, id: &str, source: &str, destination: &str, is_folder: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _exclusive = self.locks.exclusive(id).await;
             // Validate both source and destination paths
             let source_path = security::validate_read_path(volume.get_path(), source.trim_start_matches('/'))?;
             let dest_path = security::validate_write_path(volume.get_path(), destination.trim_start_matches('/'))?;
@@ -189,94 +624,117 @@ impl VolumeHandler {
                 return Err("Source path does not exist".into());
             }
             
+            let size = if is_folder {
+                self.quota_manager.calculate_directory_size(&source_path)
+                    .await
+                    .map_err(|e| -> Box<dyn std::error::Error> { e.to_string().into() })?
+            } else {
+                tokio::fs::metadata(&source_path).await?.len()
+            };
+            self.ensure_space(&volume, size).await?;
+            
+            if let Some(parent) = dest_path.parent() {
+                owner::create_dirs(volume.get_path(), parent)?;
+            }
             if is_folder {
                 Box::pin(copy_dir_recursive(&source_path, &dest_path)).await?;
                 tracing::info!("Copied folder from {} to {} in volume {}", source, destination, id);
             } else {
-                if let Some(parent) = dest_path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
-                }
                 tokio::fs::copy(&source_path, &dest_path).await?;
                 tracing::info!("Copied file from {} to {} in volume {}", source, destination, id);
             }
+            owner::adopt(volume.get_path(), &dest_path)?;
+            self.quota_manager.record_written(id, size);
             
             Ok(dest_path)
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
     pub async fn decompress(&self, id: &str, root: &str, file: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
-            let base_path = if root == "/" {
-                volume.get_path().to_path_buf()
+            let _exclusive = self.locks.exclusive(id).await;
+            let volume_root = volume.get_path().to_path_buf();
+            let base_path = if root.trim_matches('/').is_empty() {
+                volume_root.clone()
             } else {
-                volume.get_path().join(root.trim_start_matches('/'))
+                security::validate_read_path(&volume_root, root.trim_matches('/'))?
             };
             
-            let archive_path = base_path.join(file);
-            
-            if !archive_path.exists() {
-                return Err("Archive file does not exist".into());
-            }
+            let archive_relative = format!("{}/{}", root.trim_matches('/'), file.trim_start_matches('/'));
+            let archive_path = security::validate_read_path(&volume_root, archive_relative.trim_start_matches('/'))
+                .map_err(|_| "Archive file does not exist")?;
             
             let extract_path = base_path.clone();
             
-            // Determine archive type by extension
-            if file.ends_with(".zip") {
-                let extract_clone = extract_path.clone();
-                let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
-                    let file = File::open(&archive_path).map_err(|e| e.to_string())?;
-                    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
-                    archive.extract(&extract_clone).map_err(|e| e.to_string())?;
-                    Ok(())
-                }).await.map_err(|e| e.to_string())?;
-                result.map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
-                tracing::info!("Extracted ZIP archive {} in volume {}", file, id);
-            } else if file.ends_with(".tar.gz") || file.ends_with(".tgz") {
-                let extract_clone = extract_path.clone();
-                let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
-                    let file = File::open(&archive_path).map_err(|e| e.to_string())?;
-                    let decoder = GzDecoder::new(file);
-                    let mut archive = Archive::new(decoder);
-                    archive.unpack(&extract_clone).map_err(|e| e.to_string())?;
-                    Ok(())
-                }).await.map_err(|e| e.to_string())?;
-                result.map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
-                tracing::info!("Extracted TAR.GZ archive {} in volume {}", file, id);
-            } else if file.ends_with(".tar.bz2") || file.ends_with(".tbz2") {
-                let extract_clone = extract_path.clone();
-                let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
-                    let file = File::open(&archive_path).map_err(|e| e.to_string())?;
-                    let decoder = BzDecoder::new(file);
-                    let mut archive = Archive::new(decoder);
-                    archive.unpack(&extract_clone).map_err(|e| e.to_string())?;
-                    Ok(())
-                }).await.map_err(|e| e.to_string())?;
-                result.map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
-                tracing::info!("Extracted TAR.BZ2 archive {} in volume {}", file, id);
-            } else if file.ends_with(".tar") {
-                let extract_clone = extract_path.clone();
-                let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
-                    let file = File::open(&archive_path).map_err(|e| e.to_string())?;
-                    let mut archive = Archive::new(file);
-                    archive.unpack(&extract_clone).map_err(|e| e.to_string())?;
-                    Ok(())
-                }).await.map_err(|e| e.to_string())?;
-                result.map_err(|e| -> Box<dyn std::error::Error> { e.into() })?;
-                tracing::info!("Extracted TAR archive {} in volume {}", file, id);
-            } else {
-                return Err("Unsupported archive format".into());
-            }
+            let estimate_path = archive_path.clone();
+            let estimate_name = file.to_string();
+            let size = tokio::task::spawn_blocking(move || estimate_extracted_size(&estimate_path, &estimate_name))
+                .await
+                .map_err(|e| e.to_string())??;
+            self.ensure_space(&volume, size).await?;
+            
+            // The estimate trusts the archive's headers, the limit is enforced
+            // on the bytes actually unpacked
+            let archive_bytes = std::fs::metadata(&archive_path)?.len();
+            let ratio_limit = archive_bytes
+                .saturating_mul(self.max_decompression_ratio)
+                .max(MIN_EXTRACT_LIMIT_BYTES);
+            let available = match volume.quota_mb {
+                Some(_) => Some(self.quota_manager.available_bytes(id).await.map_err(|e| e.to_string())?),
+                None => None,
+            };
+            let limit = available.map_or(ratio_limit, |available| available.min(ratio_limit));
+            
+            // Unpack next to the destination and move into place only once
+            // everything fit, so an aborted extraction leaves nothing behind
+            let staging = base_path.join(format!(".lightd-extract-{}", uuid::Uuid::new_v4()));
+            let name = file.to_string();
+            let staging_clone = staging.clone();
+            let extract_clone = extract_path.clone();
+            let result = tokio::task::spawn_blocking(move || -> std::io::Result<u64> {
+                std::fs::create_dir_all(&staging_clone)?;
+                let written = extract_archive(&archive_path, &name, &staging_clone, limit)?;
+                owner::adopt(&volume_root, &staging_clone)?;
+                merge_dir(&staging_clone, &extract_clone, &volume_root)?;
+                Ok(written)
+            }).await.map_err(|e| e.to_string())?;
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            
+            let written = match result {
+                Ok(written) => written,
+                Err(e) if e.get_ref().is_some_and(|inner| inner.is::<ExtractionLimit>()) => {
+                    tracing::warn!("Aborted extraction of {} in volume {} past its limit of {} bytes", file, id, limit);
+                    // Report whichever limit was the tighter one
+                    let err: Box<dyn std::error::Error> = match available {
+                        Some(available) if available <= ratio_limit => Box::new(LightdError::QuotaExceeded(super::quota::InsufficientSpace {
+                            volume_id: id.to_string(),
+                            needed_bytes: limit + 1,
+                            available_bytes: available,
+                        })),
+                        _ => Box::new(ArchiveTooLarge {
+                            archive_bytes,
+                            limit_bytes: ratio_limit,
+                            max_ratio: self.max_decompression_ratio,
+                        }),
+                    };
+                    return Err(err);
+                }
+                Err(e) => return Err(e.into()),
+            };
+            tracing::info!("Extracted archive {} in volume {} ({} bytes)", file, id, written);
+            self.quota_manager.record_written(id, written);
             
             Ok(extract_path)
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
         }
     }
 
     pub async fn compress(&self, id: &str, sources: Vec<String>, output: &str, format: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
         if let Some(volume) = self.get_volume(id).await {
+            let _exclusive = self.locks.exclusive(id).await;
             let volume_path = volume.get_path().to_path_buf();
             
             // Validate all source paths exist
@@ -390,12 +848,240 @@ impl VolumeHandler {
                 }
                 _ => return Err("Unsupported compression format".into()),
             }
+            owner::adopt(&volume_path, &output_path)?;
             
             Ok(output_path)
         } else {
-            Err("Volume not found".into())
+            Err(LightdError::NotFound("Volume").into())
+        }
+    }
+
+    /// Start a tar.gz backup of the whole volume, runs in the background
+    pub async fn create_backup(&self, id: &str, name: Option<String>) -> Result<BackupInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        self.backups.create(id, volume.path.clone(), name, self.locks.lock(id)).await
+    }
+
+    pub async fn list_backups(&self, id: &str) -> Result<Vec<BackupInfo>, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        self.backups.list(id).await
+    }
+
+    /// Replace the volume contents with a backup, runs in the background
+    pub async fn restore_backup(&self, id: &str, backup_id: &str) -> Result<BackupInfo, Box<dyn std::error::Error + Send + Sync>> {
+        let volume = self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        self.backups.restore(id, volume.path.clone(), backup_id, self.locks.lock(id)).await
+    }
+
+    pub async fn delete_backup(&self, id: &str, backup_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.get_volume(id).await.ok_or(LightdError::NotFound("Volume"))?;
+        self.backups.delete(id, backup_id).await
+    }
+}
+
+/// Reserved for a new directory entry
+const DIR_ENTRY_BYTES: u64 = 4096;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryUsage {
+    pub name: String,
+    pub is_dir: bool,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirectoryUsage {
+    pub path: String,
+    pub total_bytes: u64,
+    /// Largest first
+    pub entries: Vec<EntryUsage>,
+}
+
+/// Default for `max_decompression_ratio`
+pub const DEFAULT_MAX_DECOMPRESSION_RATIO: u64 = 100;
+
+/// Archives may always expand to this much, whatever their ratio
+const MIN_EXTRACT_LIMIT_BYTES: u64 = 1024 * 1024;
+
+/// Returned when an archive unpacks to more than `max_ratio` times its size
+#[derive(Debug)]
+pub struct ArchiveTooLarge {
+    pub archive_bytes: u64,
+    pub limit_bytes: u64,
+    pub max_ratio: u64,
+}
+
+impl std::fmt::Display for ArchiveTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Archive expands beyond {} MB ({}x its size of {} bytes), extraction aborted",
+            self.limit_bytes / (1024 * 1024),
+            self.max_ratio,
+            self.archive_bytes
+        )
+    }
+}
+
+impl std::error::Error for ArchiveTooLarge {}
+
+/// Marker inside the io::Error of an extraction stopped by its limit
+#[derive(Debug)]
+struct ExtractionLimit;
+
+impl std::fmt::Display for ExtractionLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "extraction limit exceeded")
+    }
+}
+
+impl std::error::Error for ExtractionLimit {}
+
+/// Counts the bytes read through it and fails once more than `limit` were read
+struct LimitedReader<R> {
+    inner: R,
+    read: u64,
+    limit: u64,
+}
+
+impl<R: Read> LimitedReader<R> {
+    fn new(inner: R, limit: u64) -> Self {
+        Self { inner, read: 0, limit }
+    }
+
+    fn exceeded(&self) -> bool {
+        self.read > self.limit
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        if self.exceeded() {
+            return Err(std::io::Error::other(ExtractionLimit));
+        }
+        Ok(n)
+    }
+}
+
+/// Unpack `archive` into `dest` (blocking), failing with `ExtractionLimit`
+/// once more than `limit` bytes come out. Returns the bytes unpacked.
+fn extract_archive(archive: &Path, name: &str, dest: &Path, limit: u64) -> std::io::Result<u64> {
+    if name.ends_with(".zip") {
+        let mut zip = ZipArchive::new(File::open(archive)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut written = 0u64;
+        for i in 0..zip.len() {
+            let entry = zip.by_index(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let out = match entry.enclosed_name() {
+                Some(path) => dest.join(path),
+                None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid file path in archive")),
+            };
+            if entry.is_dir() {
+                std::fs::create_dir_all(&out)?;
+                continue;
+            }
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let mode = entry.unix_mode();
+            let mut reader = LimitedReader::new(entry, limit - written);
+            let result = std::io::copy(&mut reader, &mut File::create(&out)?);
+            written += reader.read;
+            if reader.exceeded() {
+                return Err(std::io::Error::other(ExtractionLimit));
+            }
+            result?;
+
+            #[cfg(unix)]
+            if let Some(mode) = mode {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&out, std::fs::Permissions::from_mode(mode))?;
+            }
+        }
+        Ok(written)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        unpack_tar(GzDecoder::new(File::open(archive)?), dest, limit)
+    } else if name.ends_with(".tar.bz2") || name.ends_with(".tbz2") {
+        unpack_tar(BzDecoder::new(File::open(archive)?), dest, limit)
+    } else if name.ends_with(".tar") {
+        unpack_tar(File::open(archive)?, dest, limit)
+    } else {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "Unsupported archive format"))
+    }
+}
+
+/// Counts the decompressed tar stream, which is what ends up on disk
+fn unpack_tar<R: Read>(reader: R, dest: &Path, limit: u64) -> std::io::Result<u64> {
+    let mut archive = Archive::new(LimitedReader::new(reader, limit));
+    let result = archive.unpack(dest);
+    let reader = archive.into_inner();
+    if reader.exceeded() {
+        return Err(std::io::Error::other(ExtractionLimit));
+    }
+    result.map(|_| reader.read)
+}
+
+/// Move everything under `src` into `dst`, descending into directories that
+/// exist on both sides and replacing files (blocking). Symlinks already in
+/// the volume are replaced, never followed, and every directory written into
+/// must resolve inside `volume_root`.
+fn merge_dir(src: &Path, dst: &Path, volume_root: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    if !dst.canonicalize()?.starts_with(volume_root.canonicalize()?) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Extraction target escapes volume boundary",
+        ));
+    }
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        let existing = std::fs::symlink_metadata(&target).ok();
+        if entry.file_type()?.is_dir() && existing.as_ref().is_some_and(|m| m.is_dir()) {
+            merge_dir(&entry.path(), &target, volume_root)?;
+        } else {
+            if existing.is_some_and(|m| m.file_type().is_symlink()) {
+                std::fs::remove_file(&target)?;
+            }
+            std::fs::rename(entry.path(), &target)?;
         }
     }
+    Ok(())
+}
+
+/// Bytes an archive will take once extracted, as far as the format tells
+/// without unpacking it (blocking). ZIP lists every entry's size, gzip stores
+/// the input size modulo 4 GiB in its trailer, bzip2 has no such field so the
+/// archive size is used as a lower bound.
+fn estimate_extracted_size(archive: &Path, name: &str) -> std::io::Result<u64> {
+    let compressed = std::fs::metadata(archive)?.len();
+
+    if name.ends_with(".zip") {
+        let mut zip = ZipArchive::new(File::open(archive)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut total = 0u64;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            total = total.saturating_add(entry.size());
+        }
+        Ok(total)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut file = File::open(archive)?;
+        if compressed < 4 {
+            return Ok(compressed);
+        }
+        file.seek(std::io::SeekFrom::End(-4))?;
+        let mut isize = [0u8; 4];
+        file.read_exact(&mut isize)?;
+        Ok((u32::from_le_bytes(isize) as u64).max(compressed))
+    } else {
+        Ok(compressed)
+    }
 }
 
 async fn copy_dir_recursive(src: &PathBuf, dst: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
@@ -457,3 +1143,130 @@ fn add_dir_to_zip<W: Write + Seek>(
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_over_quota_is_rejected() {
+        let base = std::env::temp_dir().join(format!("lightd-quota-{}", uuid::Uuid::new_v4()));
+        let handler = VolumeHandler::new(base.to_string_lossy().to_string());
+        let volume = Volume::new_with_quota(&handler.base_path, 1).unwrap();
+        volume.create().await.unwrap();
+        handler.volumes.write().await.push(volume.clone());
+        handler.quota_manager.set_cached_available(&volume.id, 10);
+
+        let err = handler.write_file(&volume.id, "big.txt", "more than ten bytes", None).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<LightdError>(), Some(LightdError::QuotaExceeded(_))));
+        assert!(!volume.path.join("big.txt").exists());
+
+        handler.write_file(&volume.id, "small.txt", "tiny", None).await.unwrap();
+        assert!(volume.path.join("small.txt").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_merge_dir_does_not_follow_symlinks() {
+        let base = std::env::temp_dir().join(format!("lightd-merge-{}", uuid::Uuid::new_v4()));
+        let volume = base.join("volume");
+        let outside = base.join("outside");
+        let staging = volume.join(".staging");
+        std::fs::create_dir_all(staging.join("plugins")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(staging.join("plugins/evil.jar"), b"jar").unwrap();
+        std::os::unix::fs::symlink(&outside, volume.join("plugins")).unwrap();
+
+        merge_dir(&staging, &volume, &volume).unwrap();
+        assert!(!outside.join("evil.jar").exists());
+        assert!(volume.join("plugins").symlink_metadata().unwrap().is_dir());
+        assert!(volume.join("plugins/evil.jar").exists());
+
+        // The destination itself must stay inside the volume
+        std::fs::create_dir_all(&staging).unwrap();
+        std::os::unix::fs::symlink(&outside, volume.join("link")).unwrap();
+        assert!(merge_dir(&staging, &volume.join("link"), &volume).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_directory_sizes() {
+        let base = std::env::temp_dir().join(format!("lightd-usage-{}", uuid::Uuid::new_v4()));
+        let handler = VolumeHandler::new(base.to_string_lossy().to_string());
+        let volume = Volume::new(&handler.base_path).unwrap();
+        volume.create().await.unwrap();
+        handler.volumes.write().await.push(volume.clone());
+
+        std::fs::create_dir_all(volume.path.join("world/region")).unwrap();
+        std::fs::write(volume.path.join("world/level.dat"), vec![0u8; 100]).unwrap();
+        std::fs::write(volume.path.join("world/region/r.0.0.mca"), vec![0u8; 400]).unwrap();
+        std::fs::write(volume.path.join("server.jar"), vec![0u8; 200]).unwrap();
+
+        let usage = handler.directory_sizes(&volume.id, None).await.unwrap();
+        assert_eq!(usage.total_bytes, 700);
+        let entries: Vec<_> = usage.entries.iter().map(|e| (e.name.as_str(), e.is_dir, e.size_bytes)).collect();
+        assert_eq!(entries, vec![("world", true, 500), ("server.jar", false, 200)]);
+
+        let usage = handler.directory_sizes(&volume.id, Some("/world")).await.unwrap();
+        assert_eq!(usage.total_bytes, 500);
+        assert!(handler.directory_sizes(&volume.id, Some("../")).await.is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_decompression_bomb_is_aborted() {
+        let base = std::env::temp_dir().join(format!("lightd-bomb-{}", uuid::Uuid::new_v4()));
+        let handler = VolumeHandler::new(base.to_string_lossy().to_string())
+            .with_max_decompression_ratio(10);
+        let volume = Volume::new(&handler.base_path).unwrap();
+        volume.create().await.unwrap();
+        handler.volumes.write().await.push(volume.clone());
+
+        // 8 MiB of zeros compress to a few KB, far past 10x and the 1 MiB floor
+        let zeros = vec![0u8; 8 * 1024 * 1024];
+        let mut zip = ZipWriter::new(File::create(volume.path.join("bomb.zip")).unwrap());
+        zip.start_file("small.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.start_file("zeros.bin", FileOptions::default()).unwrap();
+        zip.write_all(&zeros).unwrap();
+        zip.finish().unwrap();
+
+        let mut tar = tar::Builder::new(GzEncoder::new(File::create(volume.path.join("bomb.tar.gz")).unwrap(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(zeros.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "zeros.bin", zeros.as_slice()).unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+
+        for archive in ["bomb.zip", "bomb.tar.gz"] {
+            let err = handler.decompress(&volume.id, "/", archive).await.unwrap_err();
+            assert!(err.downcast_ref::<ArchiveTooLarge>().is_some(), "{}: {}", archive, err);
+        }
+
+        // Nothing from the aborted extractions is left behind, not even the small file
+        let mut left: Vec<String> = std::fs::read_dir(&volume.path).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["bomb.tar.gz", "bomb.zip"]);
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_default_quota_is_opt_in() {
+        let base = std::env::temp_dir().join(format!("lightd-default-quota-{}", uuid::Uuid::new_v4()));
+        let handler = VolumeHandler::new(base.to_string_lossy().to_string());
+        assert_eq!(handler.default_quota_mb, Some(crate::filesystem::quota::DEFAULT_QUOTA_MB));
+
+        let handler = handler.with_default_quota(0);
+        let volume = handler.create_volume_with_quota(None).await.unwrap();
+        assert_eq!(volume.quota_mb, None);
+        assert!(volume.path.is_dir());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
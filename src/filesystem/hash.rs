@@ -0,0 +1,211 @@
+//! File checksums for integrity checks
+//!
+//! Files are hashed in fixed size chunks so even multi-GB backups never sit in
+//! memory. Small files are answered right away, large ones run as a background
+//! job like backups do, and the job is polled by id until the digest is there.
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::io::Read;
+use std::sync::Arc;
+
+/// Files up to this size are hashed within the request
+pub const INLINE_LIMIT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Finished jobs are forgotten after this long
+const JOB_RETENTION_SECS: u64 = 3600;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+    Crc32,
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "md5" => Ok(Self::Md5),
+            "crc32" => Ok(Self::Crc32),
+            other => Err(format!("Unsupported hash algorithm '{}', use sha256, md5 or crc32", other)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hex digest of everything `reader` yields (blocking). `progress` gets the
+/// bytes read so far after every chunk.
+pub fn hash_reader<R: Read>(
+    mut reader: R,
+    algorithm: HashAlgorithm,
+    mut progress: impl FnMut(u64),
+) -> std::io::Result<String> {
+    enum State {
+        Sha256(sha2::Sha256),
+        Md5(md5::Md5),
+        Crc32(crc32fast::Hasher),
+    }
+
+    let mut state = match algorithm {
+        HashAlgorithm::Sha256 => State::Sha256(sha2::Sha256::new()),
+        HashAlgorithm::Md5 => State::Md5(md5::Md5::new()),
+        HashAlgorithm::Crc32 => State::Crc32(crc32fast::Hasher::new()),
+    };
+
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut total = 0u64;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        match &mut state {
+            State::Sha256(hasher) => hasher.update(&buffer[..n]),
+            State::Md5(hasher) => hasher.update(&buffer[..n]),
+            State::Crc32(hasher) => hasher.update(&buffer[..n]),
+        }
+        total += n as u64;
+        progress(total);
+    }
+
+    Ok(match state {
+        State::Sha256(hasher) => hex(&hasher.finalize()),
+        State::Md5(hasher) => hex(&hasher.finalize()),
+        State::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HashStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HashJob {
+    pub id: String,
+    pub volume_id: String,
+    pub path: String,
+    pub algorithm: HashAlgorithm,
+    pub size_bytes: u64,
+    pub status: HashStatus,
+    /// Percentage of the file read, 100 once it is done
+    pub progress: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub created_at: u64,
+    #[serde(skip)]
+    finished_at: Option<u64>,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Background hash jobs, kept in memory
+#[derive(Default)]
+pub struct HashJobs {
+    jobs: Arc<DashMap<String, HashJob>>,
+}
+
+impl HashJobs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start hashing `file` in the background
+    pub fn start(&self, volume_id: &str, path: &str, file: std::fs::File, size_bytes: u64, algorithm: HashAlgorithm) -> HashJob {
+        let created_at = now();
+        self.jobs.retain(|_, job| job.finished_at.is_none_or(|at| created_at.saturating_sub(at) < JOB_RETENTION_SECS));
+
+        let job = HashJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            volume_id: volume_id.to_string(),
+            path: path.to_string(),
+            algorithm,
+            size_bytes,
+            status: HashStatus::Running,
+            progress: 0,
+            digest: None,
+            error: None,
+            created_at,
+            finished_at: None,
+        };
+        self.jobs.insert(job.id.clone(), job.clone());
+
+        let jobs = self.jobs.clone();
+        let job_id = job.id.clone();
+        tokio::spawn(async move {
+            let progress = jobs.clone();
+            let progress_id = job_id.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                hash_reader(file, algorithm, |read| {
+                    let percent = (read.saturating_mul(100) / size_bytes.max(1)).min(99) as u8;
+                    if let Some(mut entry) = progress.get_mut(&progress_id) {
+                        entry.progress = percent;
+                    }
+                })
+            }).await;
+
+            if let Some(mut job) = jobs.get_mut(&job_id) {
+                match result {
+                    Ok(Ok(digest)) => {
+                        job.digest = Some(digest);
+                        job.status = HashStatus::Completed;
+                        job.progress = 100;
+                    }
+                    Ok(Err(e)) => {
+                        job.status = HashStatus::Failed;
+                        job.error = Some(e.to_string());
+                    }
+                    Err(e) => {
+                        job.status = HashStatus::Failed;
+                        job.error = Some(e.to_string());
+                    }
+                }
+                job.finished_at = Some(now());
+                tracing::info!("Hash job {} for {} in volume {} {:?}", job.id, job.path, job.volume_id, job.status);
+            }
+        });
+
+        job
+    }
+
+    pub fn get(&self, volume_id: &str, job_id: &str) -> Option<HashJob> {
+        self.jobs
+            .get(job_id)
+            .filter(|job| job.volume_id == volume_id)
+            .map(|job| job.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        let hash = |algorithm| hash_reader(&b"hello world"[..], algorithm, |_| {}).unwrap();
+        assert_eq!(hash(HashAlgorithm::Sha256), "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+        assert_eq!(hash(HashAlgorithm::Md5), "5eb63bbbe01eeed093cb22bb8f5acdc3");
+        assert_eq!(hash(HashAlgorithm::Crc32), "0d4a1185");
+        assert!("sha1".parse::<HashAlgorithm>().is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Per-volume locks that keep bulk archive operations apart from other work
+//!
+//! Compress, decompress, copy, backups and restores read or write many files in
+//! one go, so each holds its volume's lock exclusively while it runs. Everything else that
+//! touches a volume's files holds the lock shared: listings, opening downloads,
+//! hashing, file writes, uploads, deletes, trash operations and every mutating
+//! SFTP request. Shared holders run alongside each other, and wait while a bulk
+//! operation has the volume; a bulk operation waits for them to finish first.
+//!
+//! Contention stays within a volume, an archive job on one never blocks
+//! another. Tokio's RwLock is fair, a waiting bulk operation keeps new shared
+//! holders from starving it. That also means a task must never take a
+//! volume's lock while it already holds it, it would wait on itself.
+//!
+//! Downloads hold the lock only while the file is opened, the open file keeps
+//! reading the same data afterwards. SFTP holds it per request, so an archive
+//! can land between two WRITEs of a long SFTP upload.
+
+use dashmap::DashMap;
+use std::sync::Arc;
+use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
+
+#[derive(Default)]
+pub struct VolumeLocks {
+    locks: DashMap<String, Arc<RwLock<()>>>,
+}
+
+impl VolumeLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lock of one volume, created on first use
+    pub fn lock(&self, volume_id: &str) -> Arc<RwLock<()>> {
+        self.locks.entry(volume_id.to_string()).or_default().clone()
+    }
+
+    /// Hold the volume alongside other shared holders, waits for bulk operations
+    pub async fn shared(&self, volume_id: &str) -> OwnedRwLockReadGuard<()> {
+        self.lock(volume_id).read_owned().await
+    }
+
+    /// Hold the volume alone, for compress, decompress and copy
+    pub async fn exclusive(&self, volume_id: &str) -> OwnedRwLockWriteGuard<()> {
+        self.lock(volume_id).write_owned().await
+    }
+
+    /// Forget a deleted volume's lock, holders keep their own reference
+    pub fn remove(&self, volume_id: &str) {
+        self.locks.remove(volume_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_exclusive_waits_only_for_its_own_volume() {
+        let locks = Arc::new(VolumeLocks::new());
+        let reading = locks.shared("a").await;
+        let _other = locks.shared("a").await;
+
+        // Another volume is not held up
+        let _b = tokio::time::timeout(Duration::from_millis(100), locks.exclusive("b"))
+            .await
+            .expect("volume b should not wait for volume a");
+
+        let waiter = {
+            let locks = locks.clone();
+            tokio::spawn(async move { locks.exclusive("a").await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiter.is_finished());
+
+        drop(reading);
+        drop(_other);
+        let _exclusive = tokio::time::timeout(Duration::from_millis(500), waiter)
+            .await
+            .expect("exclusive lock after shared holders left")
+            .unwrap();
+        assert!(locks.lock("a").try_read().is_err());
+    }
+}
@@ -3,3 +3,10 @@ pub mod handler;
 pub mod security;
 pub mod quota;
 pub mod fileinfo;
+pub mod backup;
+pub mod transfer;
+pub mod hash;
+pub mod trash;
+pub mod lock;
+pub mod owner;
+pub mod skeleton;
@@ -0,0 +1,75 @@
+//! Ownership of files lightd creates in a volume
+//!
+//! The install hands the volume to the uid:gid the server runs as. lightd runs
+//! as root, so files it writes afterwards (uploads, SFTP, extracted archives,
+//! restored backups, skeleton files) would belong to root and the server
+//! couldn't change them. The owner of the volume root stands for the server's
+//! user and is passed on to every path created below it. Volumes still owned
+//! by root are left alone.
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// chown a directory tree without following symlinks (blocking)
+pub fn chown_recursive(path: &Path, uid: u32, gid: u32) -> std::io::Result<()> {
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_recursive(&entry?.path(), uid, gid)?;
+        }
+    }
+    Ok(())
+}
+
+/// uid and gid of the volume root, None while it belongs to root
+pub fn volume_owner(volume_root: &Path) -> Option<(u32, u32)> {
+    let meta = std::fs::metadata(volume_root).ok()?;
+    (meta.uid() != 0).then(|| (meta.uid(), meta.gid()))
+}
+
+/// Give `path` and everything below it the owner of the volume (blocking)
+pub fn adopt(volume_root: &Path, path: &Path) -> std::io::Result<()> {
+    match volume_owner(volume_root) {
+        Some((uid, gid)) => chown_recursive(path, uid, gid),
+        None => Ok(()),
+    }
+}
+
+/// `create_dir_all` that gives the directories it creates the owner of the volume (blocking)
+pub fn create_dirs(volume_root: &Path, dir: &Path) -> std::io::Result<()> {
+    // The topmost directory that is missing, everything new sits below it
+    let mut first_missing = None;
+    let mut current = Some(dir);
+    while let Some(path) = current.filter(|path| path.starts_with(volume_root) && !path.exists()) {
+        first_missing = Some(path);
+        current = path.parent();
+    }
+
+    std::fs::create_dir_all(dir)?;
+    match first_missing {
+        Some(path) => adopt(volume_root, path),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_owned_volume_is_left_alone() {
+        let root = std::env::temp_dir().join(format!("lightd-owner-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let before = std::fs::metadata(&root).unwrap().uid();
+
+        create_dirs(&root, &root.join("a/b")).unwrap();
+        assert!(root.join("a/b").is_dir());
+        if before == 0 {
+            assert_eq!(volume_owner(&root), None);
+        } else {
+            assert_eq!(std::fs::metadata(root.join("a/b")).unwrap().uid(), before);
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -4,12 +4,70 @@
 //! - macOS: Disk images (DMG) with fixed size
 //! - Linux: Filesystem quotas or loop devices
 
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
 use tokio::fs;
 
-const DEFAULT_QUOTA_MB: u64 = 1024; // 1GB default
+use crate::error::LightdError;
+
+/// Default for `storage.default_volume_quota_mb`
+pub const DEFAULT_QUOTA_MB: u64 = 1024; // 1GB default
+
+/// How long a `df` reading is trusted by write checks
+const USAGE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// Returned when a write would not fit in the volume's quota
+#[derive(Debug)]
+pub struct InsufficientSpace {
+    pub volume_id: String,
+    pub needed_bytes: u64,
+    pub available_bytes: u64,
+}
+
+impl std::fmt::Display for InsufficientSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Insufficient space in volume {}: {} MB needed, {} MB available",
+            self.volume_id,
+            self.needed_bytes.div_ceil(1024 * 1024),
+            self.available_bytes / (1024 * 1024)
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSpace {}
+
+/// Bytes of all files under `path` (blocking). Symlinks are not followed,
+/// a missing path counts as empty.
+pub fn directory_size(path: &Path) -> std::io::Result<u64> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut total_size = 0u64;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            total_size += metadata.len();
+        } else if metadata.is_dir() {
+            total_size += directory_size(&entry.path())?;
+        }
+    }
+    Ok(total_size)
+}
+
+/// Free space of a volume as of `read_at`, minus what was written since
+struct CachedUsage {
+    read_at: Instant,
+    available_bytes: u64,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiskQuota {
@@ -39,11 +97,15 @@ pub enum QuotaType {
 
 pub struct QuotaManager {
     base_path: PathBuf,
+    usage_cache: DashMap<String, CachedUsage>,
 }
 
 impl QuotaManager {
     pub fn new(base_path: PathBuf) -> Self {
-        Self { base_path }
+        Self {
+            base_path,
+            usage_cache: DashMap::new(),
+        }
     }
 
     /// Create a volume with disk quota
@@ -151,11 +213,11 @@ impl QuotaManager {
 
         // Create sparse file
         let output = Command::new("dd")
-            .args(&[
+            .args([
                 "if=/dev/zero",
                 &format!("of={}", img_path.to_str().unwrap()),
                 "bs=1M",
-                &format!("count=0"),
+                "count=0",
                 &format!("seek={}", size_mb),
             ])
             .output()?;
@@ -167,7 +229,7 @@ impl QuotaManager {
 
         // Create ext4 filesystem
         let output = Command::new("mkfs.ext4")
-            .args(&["-F", img_path.to_str().unwrap()])
+            .args(["-F", img_path.to_str().unwrap()])
             .output()?;
 
         if !output.status.success() {
@@ -180,7 +242,7 @@ impl QuotaManager {
 
         // Mount the loop device
         let output = Command::new("mount")
-            .args(&[
+            .args([
                 "-o",
                 "loop",
                 img_path.to_str().unwrap(),
@@ -212,7 +274,7 @@ impl QuotaManager {
         #[cfg(any(target_os = "macos", target_os = "linux"))]
         {
             let output = Command::new("df")
-                .args(&["-m", volume_path.to_str().unwrap()])
+                .args(["-m", volume_path.to_str().unwrap()])
                 .output()?;
 
             if !output.status.success() {
@@ -255,6 +317,63 @@ impl QuotaManager {
         }
     }
 
+    /// Free bytes of a volume, `df` is read at most every `USAGE_CACHE_TTL`
+    pub async fn available_bytes(
+        &self,
+        volume_id: &str,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(cached) = self.usage_cache.get(volume_id)
+            && cached.read_at.elapsed() < USAGE_CACHE_TTL {
+            return Ok(cached.available_bytes);
+        }
+
+        let quota = self.get_quota_usage(volume_id).await?;
+        let available_bytes = quota.available_mb * 1024 * 1024;
+        self.usage_cache.insert(volume_id.to_string(), CachedUsage {
+            read_at: Instant::now(),
+            available_bytes,
+        });
+        Ok(available_bytes)
+    }
+
+    /// Fail with `LightdError::QuotaExceeded` if `bytes` don't fit in the volume
+    pub async fn ensure_space(
+        &self,
+        volume_id: &str,
+        bytes: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let available_bytes = self.available_bytes(volume_id).await?;
+        if bytes > available_bytes {
+            tracing::warn!(
+                "Rejected write of {} bytes to volume {}, {} bytes available",
+                bytes,
+                volume_id,
+                available_bytes
+            );
+            return Err(Box::new(LightdError::QuotaExceeded(InsufficientSpace {
+                volume_id: volume_id.to_string(),
+                needed_bytes: bytes,
+                available_bytes,
+            })));
+        }
+        Ok(())
+    }
+
+    /// Count a finished write against the cached free space until the next `df`
+    pub fn record_written(&self, volume_id: &str, bytes: u64) {
+        if let Some(mut cached) = self.usage_cache.get_mut(volume_id) {
+            cached.available_bytes = cached.available_bytes.saturating_sub(bytes);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_cached_available(&self, volume_id: &str, available_bytes: u64) {
+        self.usage_cache.insert(volume_id.to_string(), CachedUsage {
+            read_at: Instant::now(),
+            available_bytes,
+        });
+    }
+
     /// Check if volume is out of space
     #[allow(dead_code)]
     pub async fn check_quota_exceeded(
@@ -279,31 +398,13 @@ impl QuotaManager {
         Ok(is_exceeded)
     }
 
-    /// Calculate directory size recursively
-    #[allow(dead_code)]
-    async fn calculate_directory_size(
+    /// Calculate directory size recursively, on a blocking thread
+    pub async fn calculate_directory_size(
         &self,
         path: &Path,
     ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
-        let mut total_size = 0u64;
-
-        if !path.exists() {
-            return Ok(0);
-        }
-
-        let mut entries = fs::read_dir(path).await?;
-
-        while let Some(entry) = entries.next_entry().await? {
-            let metadata = entry.metadata().await?;
-
-            if metadata.is_file() {
-                total_size += metadata.len();
-            } else if metadata.is_dir() {
-                total_size += Box::pin(self.calculate_directory_size(&entry.path())).await?;
-            }
-        }
-
-        Ok(total_size)
+        let path = path.to_path_buf();
+        Ok(tokio::task::spawn_blocking(move || directory_size(&path)).await??)
     }
 
     /// Unmount and delete volume
@@ -312,6 +413,7 @@ impl QuotaManager {
         volume_id: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let volume_path = self.base_path.join(volume_id);
+        self.usage_cache.remove(volume_id);
 
         #[cfg(target_os = "macos")]
         {
@@ -330,7 +432,7 @@ impl QuotaManager {
         {
             // Unmount loop device
             let _ = Command::new("umount")
-                .args(&["-f", volume_path.to_str().unwrap()])
+                .args(["-f", volume_path.to_str().unwrap()])
                 .output();
 
             // Delete image file
@@ -353,6 +455,8 @@ impl QuotaManager {
         volume_id: &str,
         new_size_mb: u64,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.usage_cache.remove(volume_id);
+
         #[cfg(target_os = "macos")]
         {
             let volume_path = self.base_path.join(volume_id);
@@ -399,16 +503,16 @@ impl QuotaManager {
 
             // Unmount
             let _ = Command::new("umount")
-                .args(&[volume_path.to_str().unwrap()])
+                .args([volume_path.to_str().unwrap()])
                 .output();
 
             // Resize image file
             let output = Command::new("dd")
-                .args(&[
+                .args([
                     "if=/dev/zero",
                     &format!("of={}", img_path.to_str().unwrap()),
                     "bs=1M",
-                    &format!("count=0"),
+                    "count=0",
                     &format!("seek={}", new_size_mb),
                 ])
                 .output()?;
@@ -420,7 +524,7 @@ impl QuotaManager {
 
             // Resize filesystem
             let output = Command::new("resize2fs")
-                .args(&[img_path.to_str().unwrap()])
+                .args([img_path.to_str().unwrap()])
                 .output()?;
 
             if !output.status.success() {
@@ -430,7 +534,7 @@ impl QuotaManager {
 
             // Remount
             let _ = Command::new("mount")
-                .args(&[
+                .args([
                     "-o",
                     "loop",
                     img_path.to_str().unwrap(),
@@ -449,3 +553,24 @@ impl QuotaManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ensure_space_uses_cached_usage() {
+        let quota = QuotaManager::new(std::env::temp_dir());
+        quota.set_cached_available("vol1", 1024 * 1024);
+
+        assert!(quota.ensure_space("vol1", 1000).await.is_ok());
+
+        quota.record_written("vol1", 1024 * 1024 - 500);
+        let err = quota.ensure_space("vol1", 1000).await.unwrap_err();
+        let Some(LightdError::QuotaExceeded(err)) = err.downcast_ref::<LightdError>() else {
+            panic!("expected QuotaExceeded, got {}", err);
+        };
+        assert_eq!(err.needed_bytes, 1000);
+        assert_eq!(err.available_bytes, 500);
+    }
+}
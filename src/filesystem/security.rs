@@ -4,6 +4,8 @@
 
 use std::path::{Path, PathBuf};
 
+use crate::error::LightdError;
+
 /// Validate and sanitize a path to prevent directory traversal attacks
 /// 
 /// This function ensures that:
@@ -74,21 +76,21 @@ pub fn validate_path(volume_root: &Path, user_path: &str) -> Result<PathBuf, Str
 }
 
 /// Validate a path for reading (must exist and be within volume)
-pub fn validate_read_path(volume_root: &Path, user_path: &str) -> Result<PathBuf, String> {
-    let path = validate_path(volume_root, user_path)?;
+pub fn validate_read_path(volume_root: &Path, user_path: &str) -> Result<PathBuf, LightdError> {
+    let path = validate_path(volume_root, user_path).map_err(LightdError::Validation)?;
     
     if !path.exists() {
-        return Err("Path does not exist".to_string());
+        return Err(LightdError::NotFound("Path"));
     }
     
     // Double-check with canonical path
     let canonical_root = volume_root.canonicalize()
-        .map_err(|e| format!("Failed to resolve volume root: {}", e))?;
+        .map_err(|e| LightdError::Validation(format!("Failed to resolve volume root: {}", e)))?;
     let canonical_path = path.canonicalize()
-        .map_err(|e| format!("Failed to resolve path: {}", e))?;
+        .map_err(|e| LightdError::Validation(format!("Failed to resolve path: {}", e)))?;
     
     if !canonical_path.starts_with(&canonical_root) {
-        return Err("Path escapes volume boundary (symlink detected)".to_string());
+        return Err(LightdError::Validation("Path escapes volume boundary (symlink detected)".to_string()));
     }
     
     Ok(path)
@@ -99,22 +101,32 @@ pub fn validate_write_path(volume_root: &Path, user_path: &str) -> Result<PathBu
     let path = validate_path(volume_root, user_path)?;
     
     // Check parent directory
-    if let Some(parent) = path.parent() {
-        if parent.exists() {
-            let canonical_root = volume_root.canonicalize()
-                .map_err(|e| format!("Failed to resolve volume root: {}", e))?;
-            let canonical_parent = parent.canonicalize()
-                .map_err(|e| format!("Failed to resolve parent: {}", e))?;
-            
-            if !canonical_parent.starts_with(&canonical_root) {
-                return Err("Parent directory escapes volume boundary".to_string());
-            }
+    if let Some(parent) = path.parent()
+        && parent.exists() {
+        let canonical_root = volume_root.canonicalize()
+            .map_err(|e| format!("Failed to resolve volume root: {}", e))?;
+        let canonical_parent = parent.canonicalize()
+            .map_err(|e| format!("Failed to resolve parent: {}", e))?;
+        
+        if !canonical_parent.starts_with(&canonical_root) {
+            return Err("Parent directory escapes volume boundary".to_string());
         }
     }
     
     Ok(path)
 }
 
+/// Check a path stored inside an archive before extracting it (zip-slip)
+///
+/// Entry names and link targets must be relative and must not contain `..`,
+/// otherwise extraction could write outside the destination directory.
+pub fn is_safe_archive_path(path: &Path) -> bool {
+    use std::path::Component;
+
+    !path.as_os_str().is_empty()
+        && path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,15 +150,40 @@ mod tests {
     #[test]
     fn test_accept_valid_paths() {
         let root = PathBuf::from("/tmp/test_volume");
+        // The root is canonicalized, it has to exist
+        std::fs::create_dir_all(&root).unwrap();
         assert!(validate_path(&root, "foo/bar.txt").is_ok());
         assert!(validate_path(&root, "data/config.json").is_ok());
         assert!(validate_path(&root, "test.txt").is_ok());
     }
     
+    #[test]
+    fn test_archive_paths() {
+        assert!(is_safe_archive_path(Path::new("world/level.dat")));
+        assert!(is_safe_archive_path(Path::new("./server.properties")));
+        assert!(!is_safe_archive_path(Path::new("../outside")));
+        assert!(!is_safe_archive_path(Path::new("data/../../outside")));
+        assert!(!is_safe_archive_path(Path::new("/etc/passwd")));
+        assert!(!is_safe_archive_path(Path::new("")));
+    }
+    
     #[test]
     fn test_reject_empty_path() {
         let root = PathBuf::from("/tmp/test_volume");
         assert!(validate_path(&root, "").is_err());
         assert!(validate_path(&root, "   ").is_err());
     }
+    
+    #[test]
+    fn test_missing_read_path_is_not_found() {
+        let root = std::env::temp_dir().join(format!("lightd-security-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("present.txt"), "x").unwrap();
+        
+        assert!(validate_read_path(&root, "present.txt").is_ok());
+        assert!(matches!(validate_read_path(&root, "missing.txt"), Err(LightdError::NotFound("Path"))));
+        assert!(matches!(validate_read_path(&root, "../outside"), Err(LightdError::Validation(_))));
+        
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
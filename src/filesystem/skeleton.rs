@@ -0,0 +1,87 @@
+//! Default files written into a fresh volume
+//!
+//! A skeleton is a list of path + content pairs, given inline when a volume
+//! is created or stored on a container template. The paths are checked here
+//! before the volume exists, and once more by `write_file` when each file is
+//! written, so nothing can land outside the volume.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Component, Path};
+
+/// Files per skeleton
+pub const MAX_SKELETON_FILES: usize = 256;
+
+/// Content of all files together, larger seeds belong in an upload
+pub const MAX_SKELETON_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SkeletonFile {
+    /// Relative to the volume root, parent directories are created
+    pub path: String,
+    pub content: String,
+}
+
+/// Reject paths leaving the volume, duplicates and oversized skeletons
+pub fn validate_skeleton(files: &[SkeletonFile]) -> Result<(), String> {
+    if files.len() > MAX_SKELETON_FILES {
+        return Err(format!("files: at most {} files are allowed", MAX_SKELETON_FILES));
+    }
+    let total: usize = files.iter().map(|f| f.content.len()).sum();
+    if total > MAX_SKELETON_BYTES {
+        return Err(format!("files: content may be at most {} bytes in total", MAX_SKELETON_BYTES));
+    }
+
+    let mut seen = HashSet::new();
+    for file in files {
+        let relative = Path::new(file.path.trim_start_matches('/'));
+        let safe = !relative.as_os_str().is_empty()
+            && relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        if !safe {
+            return Err(format!("files: '{}' is not a path inside the volume", file.path));
+        }
+        if !seen.insert(relative.to_path_buf()) {
+            return Err(format!("files: '{}' is listed more than once", file.path));
+        }
+    }
+    Ok(())
+}
+
+/// `overrides` replace files from `base` with the same path
+pub fn merge_skeletons(base: Vec<SkeletonFile>, overrides: Vec<SkeletonFile>) -> Vec<SkeletonFile> {
+    let key = |f: &SkeletonFile| f.path.trim_start_matches('/').to_string();
+    let replaced: HashSet<String> = overrides.iter().map(key).collect();
+    base.into_iter()
+        .filter(|f| !replaced.contains(&key(f)))
+        .chain(overrides)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str) -> SkeletonFile {
+        SkeletonFile { path: path.to_string(), content: content.to_string() }
+    }
+
+    #[test]
+    fn test_validate_skeleton() {
+        assert!(validate_skeleton(&[file("eula.txt", "eula=true"), file("/config/server.properties", "")]).is_ok());
+
+        assert!(validate_skeleton(&[file("../escape.txt", "")]).is_err());
+        assert!(validate_skeleton(&[file("config/../../escape.txt", "")]).is_err());
+        assert!(validate_skeleton(&[file("", "")]).is_err());
+        assert!(validate_skeleton(&[file("/", "")]).is_err());
+        assert!(validate_skeleton(&[file("eula.txt", "a"), file("/eula.txt", "b")]).is_err());
+    }
+
+    #[test]
+    fn test_merge_skeletons() {
+        let merged = merge_skeletons(
+            vec![file("eula.txt", "eula=false"), file("server.properties", "motd=hi")],
+            vec![file("/eula.txt", "eula=true")],
+        );
+        assert_eq!(merged, vec![file("server.properties", "motd=hi"), file("/eula.txt", "eula=true")]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Conditional and ranged file transfers
+//!
+//! Downloads carry a weak ETag built from size and mtime. A client resuming
+//! a download sends it back in `If-Range`, so the range is only applied while
+//! the file is unchanged. Uploads send it in `If-Match` to refuse overwriting
+//! a file someone else changed since it was read.
+
+use std::time::UNIX_EPOCH;
+
+/// Returned when `If-Match` doesn't match the file's current ETag
+#[derive(Debug)]
+pub struct PreconditionFailed {
+    /// None when the file doesn't exist
+    pub current_etag: Option<String>,
+}
+
+impl std::fmt::Display for PreconditionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.current_etag {
+            Some(etag) => write!(f, "File changed since it was read, current ETag is {}", etag),
+            None => write!(f, "File no longer exists"),
+        }
+    }
+}
+
+impl std::error::Error for PreconditionFailed {}
+
+/// Returned when an upload chunk would leave a gap in the file
+#[derive(Debug)]
+pub struct UnexpectedOffset {
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl std::fmt::Display for UnexpectedOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Chunk starts at byte {} but the file has {} bytes, resume from there", self.offset, self.size)
+    }
+}
+
+impl std::error::Error for UnexpectedOffset {}
+
+/// A file as written by an upload
+#[derive(Debug, Clone)]
+pub struct FileVersion {
+    pub path: std::path::PathBuf,
+    pub size: u64,
+    pub etag: String,
+}
+
+/// Fail with `PreconditionFailed` unless `if_match` matches the current ETag
+/// of `path`. `*` only requires the file to exist.
+pub fn check_if_match(path: &std::path::Path, if_match: Option<&str>) -> Result<(), PreconditionFailed> {
+    let Some(if_match) = if_match else {
+        return Ok(());
+    };
+    let current_etag = std::fs::metadata(path).ok().filter(|m| m.is_file()).map(|m| weak_etag(&m));
+    match &current_etag {
+        Some(etag) if etag_matches(if_match, etag) => Ok(()),
+        _ => Err(PreconditionFailed { current_etag }),
+    }
+}
+
+/// Weak ETag of a file, changes whenever its size or mtime does
+pub fn weak_etag(meta: &std::fs::Metadata) -> String {
+    let mtime = meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", meta.len(), mtime)
+}
+
+/// Whether an `If-Match`/`If-Range` value matches `etag`. Uses weak
+/// comparison, the `W/` prefix is ignored on both sides.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+    header.split(',').any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+/// Inclusive byte range within a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// A requested range that lies outside the file (416)
+#[derive(Debug, PartialEq)]
+pub struct RangeNotSatisfiable;
+
+/// Parse a download `Range` header against a file of `size` bytes.
+///
+/// `Ok(None)` means the header is ignored and the whole file is sent, which
+/// is what we do for other units and multiple ranges. `Err` is a range that
+/// can't be served from this file.
+pub fn parse_range(header: &str, size: u64) -> Result<Option<ByteRange>, RangeNotSatisfiable> {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Ok(None);
+    };
+
+    let range = match (start.trim(), end.trim()) {
+        // Suffix range, the last n bytes
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| RangeNotSatisfiable)?;
+            if suffix == 0 || size == 0 {
+                return Err(RangeNotSatisfiable);
+            }
+            ByteRange { start: size.saturating_sub(suffix), end: size - 1 }
+        }
+        (start, "") => {
+            let start: u64 = start.parse().map_err(|_| RangeNotSatisfiable)?;
+            ByteRange { start, end: size.saturating_sub(1) }
+        }
+        (start, end) => {
+            let start: u64 = start.parse().map_err(|_| RangeNotSatisfiable)?;
+            let end: u64 = end.parse().map_err(|_| RangeNotSatisfiable)?;
+            if end < start {
+                return Err(RangeNotSatisfiable);
+            }
+            ByteRange { start, end: end.min(size.saturating_sub(1)) }
+        }
+    };
+
+    if range.start >= size {
+        return Err(RangeNotSatisfiable);
+    }
+    Ok(Some(range))
+}
+
+/// An upload chunk from `Content-Range: bytes start-end/total`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentRange {
+    pub range: ByteRange,
+    /// None for `*`, the final size isn't known yet
+    pub total: Option<u64>,
+}
+
+pub fn parse_content_range(header: &str) -> Result<ContentRange, String> {
+    let invalid = || format!("Invalid Content-Range '{}', expected 'bytes start-end/total'", header);
+    let spec = header.trim().strip_prefix("bytes ").ok_or_else(invalid)?;
+    let (range, total) = spec.split_once('/').ok_or_else(invalid)?;
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse::<u64>().map_err(|_| invalid())?),
+    };
+    if end < start || total.is_some_and(|total| end >= total) {
+        return Err(invalid());
+    }
+
+    Ok(ContentRange { range: ByteRange { start, end }, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some(ByteRange { start: 0, end: 99 })));
+        assert_eq!(parse_range("bytes=500-", 1000), Ok(Some(ByteRange { start: 500, end: 999 })));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some(ByteRange { start: 900, end: 999 })));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Ok(Some(ByteRange { start: 900, end: 999 })));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(RangeNotSatisfiable));
+        assert_eq!(parse_range("bytes=0-1,5-9", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-9", 1000), Ok(None));
+    }
+
+    #[test]
+    fn test_etag_matches_weakly() {
+        assert!(etag_matches("W/\"a-1\"", "W/\"a-1\""));
+        assert!(etag_matches("\"a-1\"", "W/\"a-1\""));
+        assert!(etag_matches("\"x\", W/\"a-1\"", "W/\"a-1\""));
+        assert!(etag_matches("*", "W/\"a-1\""));
+        assert!(!etag_matches("W/\"a-2\"", "W/\"a-1\""));
+    }
+
+    #[test]
+    fn test_parse_content_range() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000").unwrap(),
+            ContentRange { range: ByteRange { start: 100, end: 199 }, total: Some(1000) }
+        );
+        assert_eq!(parse_content_range("bytes 0-9/*").unwrap().total, None);
+        assert!(parse_content_range("bytes 100-199/150").is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Recycle bin for volume deletes
+//!
+//! With the trash enabled for a volume, deletes move the target into
+//! `<volume>/.trash/<item_id>/data` next to a `meta.json` recording where it
+//! came from. Trashed data stays inside the volume, so it keeps counting
+//! against the quota until it is purged, either by hand or once it is older
+//! than the retention. Deletes inside `.trash` itself are always final.
+//!
+//! Everything here is blocking, async callers go through `spawn_blocking`.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+pub const TRASH_DIR: &str = ".trash";
+
+/// Default retention of trashed items
+pub const DEFAULT_RETENTION_SECS: u64 = 7 * 24 * 3600;
+
+const SETTINGS_FILE: &str = "settings.json";
+const META_FILE: &str = "meta.json";
+const DATA_NAME: &str = "data";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrashSettings {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub id: String,
+    /// Path relative to the volume root, where restore puts it back
+    pub original_path: String,
+    pub is_dir: bool,
+    pub deleted_at: u64,
+    /// Filled in when listing
+    #[serde(default)]
+    pub size_bytes: u64,
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn trash_root(volume_root: &Path) -> PathBuf {
+    volume_root.join(TRASH_DIR)
+}
+
+/// `.trash` sits in the volume, so the tenant may have swapped it for a
+/// symlink. Only a real directory, or none yet, is used.
+fn checked_trash_root(volume_root: &Path) -> Result<PathBuf, Error> {
+    let root = trash_root(volume_root);
+    match std::fs::symlink_metadata(&root) {
+        Ok(meta) if !meta.file_type().is_dir() => {
+            Err(Error::new(ErrorKind::PermissionDenied, "Volume trash is not a directory"))
+        }
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(root),
+    }
+}
+
+/// The trash root, created when missing
+fn create_trash_root(volume_root: &Path) -> Result<PathBuf, Error> {
+    let root = checked_trash_root(volume_root)?;
+    std::fs::create_dir_all(&root)?;
+    // It may have been replaced between the check and the create
+    checked_trash_root(volume_root)
+}
+
+/// Item ids are generated by us, anything else could point outside the trash
+fn item_dir(volume_root: &Path, item_id: &str) -> Result<PathBuf, Error> {
+    if item_id.is_empty() || !item_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(Error::new(ErrorKind::NotFound, "Trash item not found"));
+    }
+    Ok(trash_root(volume_root).join(item_id))
+}
+
+pub fn settings(volume_root: &Path) -> TrashSettings {
+    checked_trash_root(volume_root)
+        .and_then(|root| std::fs::read(root.join(SETTINGS_FILE)))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_settings(volume_root: &Path, settings: &TrashSettings) -> Result<(), Error> {
+    let root = create_trash_root(volume_root)?;
+    // A new file renamed over the old one, an existing settings.json may be a symlink
+    let temp = root.join(format!("{}.{}", SETTINGS_FILE, uuid::Uuid::new_v4().simple()));
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).open(&temp)?;
+    std::io::Write::write_all(&mut file, &serde_json::to_vec_pretty(settings)?)?;
+    check_in_trash(volume_root, &temp)?;
+    std::fs::rename(&temp, root.join(SETTINGS_FILE)).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp);
+    })
+}
+
+/// Whether `path` lies inside the trash directory of the volume
+pub fn is_in_trash(volume_root: &Path, path: &Path) -> bool {
+    path.starts_with(trash_root(volume_root))
+}
+
+/// Delete `path`: moved to the trash when it is enabled, removed otherwise.
+/// Returns the trash item when it was kept.
+pub fn delete(volume_root: &Path, path: &Path) -> Result<Option<TrashItem>, Error> {
+    if path == volume_root || path == trash_root(volume_root) {
+        return Err(Error::new(ErrorKind::PermissionDenied, "Refusing to delete the volume root or its trash"));
+    }
+
+    if settings(volume_root).enabled && !is_in_trash(volume_root, path) {
+        return move_to_trash(volume_root, path).map(Some);
+    }
+
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(None)
+}
+
+/// Move `path` (inside the volume) into the trash
+pub fn move_to_trash(volume_root: &Path, path: &Path) -> Result<TrashItem, Error> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let original_path = path
+        .strip_prefix(volume_root)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "Path is outside the volume"))?
+        .to_string_lossy()
+        .to_string();
+
+    let deleted_at = now();
+    let id = format!("{}-{}", deleted_at, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let dir = create_trash_root(volume_root)?.join(&id);
+    std::fs::create_dir(&dir)?;
+    if let Err(e) = check_in_trash(volume_root, &dir) {
+        let _ = std::fs::remove_dir(&dir);
+        return Err(e);
+    }
+
+    let item = TrashItem {
+        id,
+        original_path,
+        is_dir: metadata.is_dir(),
+        deleted_at,
+        size_bytes: 0,
+    };
+    std::fs::write(dir.join(META_FILE), serde_json::to_vec_pretty(&item)?)?;
+    if let Err(e) = std::fs::rename(path, dir.join(DATA_NAME)) {
+        let _ = std::fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+
+    tracing::info!("Moved {} to trash as {}", item.original_path, item.id);
+    Ok(item)
+}
+
+/// `.trash` sits in the volume, so it or the item may have been replaced by a symlink
+fn check_in_trash(volume_root: &Path, dir: &Path) -> Result<(), Error> {
+    checked_trash_root(volume_root)?;
+    let trash = volume_root.canonicalize()?.join(TRASH_DIR);
+    if !dir.canonicalize()?.starts_with(trash) {
+        return Err(Error::new(ErrorKind::PermissionDenied, "Trash item is outside the volume trash"));
+    }
+    Ok(())
+}
+
+/// `meta.json` can be edited by the tenant like any other file of the
+/// volume, so the recorded path is checked like a path from a request
+fn restore_target(volume_root: &Path, item: &TrashItem) -> Result<PathBuf, Error> {
+    let target = super::security::validate_write_path(volume_root, &item.original_path).map_err(|e| {
+        Error::new(ErrorKind::PermissionDenied, format!("Cannot restore to {}: {}", item.original_path, e))
+    })?;
+    if is_in_trash(volume_root, &target) {
+        return Err(Error::new(ErrorKind::PermissionDenied, "Cannot restore into the trash"));
+    }
+    Ok(target)
+}
+
+fn read_item(dir: &Path) -> Result<TrashItem, Error> {
+    let data = std::fs::read(dir.join(META_FILE))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Trashed items, newest first
+pub fn list(volume_root: &Path) -> Result<Vec<TrashItem>, Error> {
+    let entries = match std::fs::read_dir(checked_trash_root(volume_root)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut items = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        // A half-written item from a crash has no metadata, skip it
+        let Ok(mut item) = read_item(&entry.path()) else {
+            continue;
+        };
+        let data = entry.path().join(DATA_NAME);
+        item.size_bytes = if item.is_dir {
+            super::quota::directory_size(&data)?
+        } else {
+            std::fs::symlink_metadata(&data).map(|m| m.len()).unwrap_or(0)
+        };
+        items.push(item);
+    }
+
+    items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then_with(|| b.id.cmp(&a.id)));
+    Ok(items)
+}
+
+/// Move an item back to its original path, which must be free
+pub fn restore(volume_root: &Path, item_id: &str) -> Result<TrashItem, Error> {
+    let dir = item_dir(volume_root, item_id)?;
+    let item = read_item(&dir).map_err(|_| Error::new(ErrorKind::NotFound, "Trash item not found"))?;
+    check_in_trash(volume_root, &dir)?;
+
+    let target = restore_target(volume_root, &item)?;
+    if std::fs::symlink_metadata(&target).is_ok() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists, move it away before restoring", item.original_path),
+        ));
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // The parents may have been swapped for symlinks since the check
+    let target = restore_target(volume_root, &item)?;
+    std::fs::rename(dir.join(DATA_NAME), &target)?;
+    std::fs::remove_dir_all(&dir)?;
+
+    tracing::info!("Restored {} from trash", item.original_path);
+    Ok(item)
+}
+
+/// Delete an item for good
+pub fn purge(volume_root: &Path, item_id: &str) -> Result<(), Error> {
+    let dir = item_dir(volume_root, item_id)?;
+    if !std::fs::symlink_metadata(&dir).is_ok_and(|meta| meta.is_dir()) {
+        return Err(Error::new(ErrorKind::NotFound, "Trash item not found"));
+    }
+    check_in_trash(volume_root, &dir)?;
+    std::fs::remove_dir_all(dir)
+}
+
+/// Delete items trashed more than `retention_secs` ago, `None` empties the trash
+pub fn purge_older_than(volume_root: &Path, retention_secs: Option<u64>) -> Result<usize, Error> {
+    let cutoff = retention_secs.map(|secs| now().saturating_sub(secs));
+    let mut purged = 0;
+    for item in list(volume_root)? {
+        if cutoff.is_none_or(|cutoff| item.deleted_at < cutoff) {
+            purge(volume_root, &item.id)?;
+            purged += 1;
+        }
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trash_and_restore() {
+        let volume = std::env::temp_dir().join(format!("lightd-trash-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(volume.join("world")).unwrap();
+        std::fs::write(volume.join("world/level.dat"), b"level").unwrap();
+        std::fs::write(volume.join("old.log"), b"log").unwrap();
+
+        // Disabled, deletes are final
+        assert!(delete(&volume, &volume.join("old.log")).unwrap().is_none());
+        assert!(!volume.join("old.log").exists());
+
+        set_settings(&volume, &TrashSettings { enabled: true }).unwrap();
+        let item = delete(&volume, &volume.join("world")).unwrap().unwrap();
+        assert!(!volume.join("world").exists());
+
+        let items = list(&volume).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].original_path, "world");
+        assert_eq!(items[0].size_bytes, 5);
+
+        restore(&volume, &item.id).unwrap();
+        assert_eq!(std::fs::read(volume.join("world/level.dat")).unwrap(), b"level");
+        assert!(list(&volume).unwrap().is_empty());
+
+        delete(&volume, &volume.join("world/level.dat")).unwrap();
+        assert_eq!(purge_older_than(&volume, Some(3600)).unwrap(), 0);
+        assert_eq!(purge_older_than(&volume, None).unwrap(), 1);
+        assert!(restore(&volume, "../world").is_err());
+
+        // A tampered meta.json must not move data out of the volume
+        for original_path in ["../escaped", "/tmp/escaped", ".trash/x"] {
+            std::fs::write(volume.join("bad.txt"), b"bad").unwrap();
+            let item = delete(&volume, &volume.join("bad.txt")).unwrap().unwrap();
+            let meta = volume.join(TRASH_DIR).join(&item.id).join(META_FILE);
+            let tampered = TrashItem { original_path: original_path.to_string(), ..item.clone() };
+            std::fs::write(&meta, serde_json::to_vec(&tampered).unwrap()).unwrap();
+            let err = restore(&volume, &item.id).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+            purge(&volume, &item.id).unwrap();
+        }
+
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
+
+    #[test]
+    fn test_symlinked_trash_is_refused() {
+        let volume = std::env::temp_dir().join(format!("lightd-trash-{}", uuid::Uuid::new_v4()));
+        let outside = std::env::temp_dir().join(format!("lightd-outside-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&volume).unwrap();
+        std::fs::create_dir_all(outside.join("victim")).unwrap();
+        std::fs::write(outside.join("victim/keep.txt"), b"keep").unwrap();
+        std::fs::write(volume.join("file.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink(&outside, volume.join(TRASH_DIR)).unwrap();
+
+        let denied = |e: Error| assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        denied(purge(&volume, "victim").unwrap_err());
+        denied(purge_older_than(&volume, None).unwrap_err());
+        denied(set_settings(&volume, &TrashSettings { enabled: true }).unwrap_err());
+        denied(move_to_trash(&volume, &volume.join("file.txt")).unwrap_err());
+        denied(list(&volume).unwrap_err());
+
+        assert!(outside.join("victim/keep.txt").exists());
+        assert!(volume.join("file.txt").exists());
+        assert!(!outside.join(SETTINGS_FILE).exists());
+
+        std::fs::remove_dir_all(&volume).unwrap();
+        std::fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -52,6 +52,20 @@ impl Volume {
         })
     }
 
+    /// A volume that keeps the id it had elsewhere, e.g. on the node it was imported from
+    pub fn with_id(base_path: &str, id: &str, quota_mb: Option<u64>) -> Result<Self, Box<dyn std::error::Error>> {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        Ok(Self {
+            id: id.to_string(),
+            path: PathBuf::from(base_path).join(id),
+            created_at,
+            quota_mb,
+        })
+    }
+
     pub async fn create(&self) -> Result<(), Box<dyn std::error::Error>> {
         tokio::fs::create_dir_all(&self.path).await?;
         tracing::info!("Created volume: {} at {:?}", self.id, self.path);
@@ -11,13 +11,14 @@ mod cli;
 mod remote;
 mod sftp;
 mod billing;
+mod scheduler;
+mod error;
 
 use axum::routing::get;
 use axum::Router;
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 use daemon::timer::Timer;
 use std::sync::Arc;
-use tower_http::cors::{CorsLayer, Any};
 use axum::middleware;
 
 #[derive(Parser)]
@@ -31,8 +32,33 @@ struct Cli {
     #[arg(long = "servers")]
     servers: Option<i64>,
     
+    /// Only list servers in this state (running, installing or failed)
+    #[arg(long = "state", requires = "servers")]
+    state: Option<String>,
+    
+    /// Only list servers using this image
+    #[arg(long = "image", requires = "servers")]
+    image: Option<String>,
+    
+    /// Only list servers with these labels, e.g. customer:acme,tier
+    #[arg(long = "label", requires = "servers")]
+    label: Option<String>,
+    
     #[arg(long = "token")]
     token: Option<String>,
+    
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create and delete containers on the running daemon
+    Container(cli::container::ContainerArgs),
+    /// Attach to a container's console
+    Console(cli::console::ConsoleArgs),
+    /// Print a shell completion script to stdout
+    Completions(cli::completions::CompletionsArgs),
 }
 
 #[tokio::main]
@@ -42,7 +68,13 @@ async fn main() {
     
     let cli = Cli::parse();
 
-    if cli.dev {
+    if let Some(command) = cli.command {
+        match command {
+            Command::Container(args) => cli::container::handle_container_command(args).await,
+            Command::Console(args) => cli::console::handle_console_command(args).await,
+            Command::Completions(args) => cli::completions::print_completions(args, &mut Cli::command()),
+        }
+    } else if cli.dev {
         // Starts lightd in dev mode
         // Allowing lightd to send trace logs for not important things
         tracing_subscriber::fmt::init();
@@ -51,18 +83,48 @@ async fn main() {
         // Token management commands
         cli::token::handle_token_command(&token_cmd).await;
     } else if let Some(page) = cli.servers {
+        let install_state = match cli.state.as_deref().map(str::parse) {
+            Some(Ok(state)) => Some(state),
+            Some(Err(e)) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        let filter = container::manager::ContainerFilter {
+            install_state,
+            image: cli.image,
+            labels: cli.label.as_deref()
+                .map(container::manager::ContainerFilter::parse_labels)
+                .unwrap_or_default(),
+        };
         // List servers, optionally paginated by 'page'
-        servers::list::list_servers(page).await;
+        servers::list::list_servers(page, filter).await;
     } else if cli.servers.is_some() {
         // List all servers if flag is present without a page (defaults to page 1)
-        servers::list::list_servers(1).await;
+        servers::list::list_servers(1, Default::default()).await;
     } else {
         // Run main application without tracing warnings
-        main_app(timer).await;
+        main_app(timer, false).await;
     }
 }
 
-async fn main_app(timer: Timer) {
+/// `dev` allows any CORS origin unless `server.cors` is set
+async fn main_app(timer: Timer, dev: bool) {
+    // Catch config mistakes before anything starts using the values
+    match config::config::Config::load("config.json") {
+        Ok(config) => {
+            if let Err(e) = config.validate() {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+    
     daemon::start::print_banner_async().await;
     let storage_result = daemon::start::check_storage().await;
     match storage_result {
@@ -86,54 +148,88 @@ async fn main_app(timer: Timer) {
         egress_per_gb: config.monitoring.billing.egress_per_gb,
     };
     
+    // Panel issued JWTs are accepted next to our own tokens when configured
+    let jwt_validator = config.authorization.jwt.as_ref().map(|jwt| {
+        let validator = auth::jwt::JwtValidator::from_config(jwt)
+            .expect("Failed to initialize JWT validation");
+        tracing::info!("JWT authentication enabled ({})", jwt.algorithm);
+        Arc::new(validator)
+    });
+    
     // Initialize token manager
     let tokens_db_path = format!("{}/tokens.db", config.storage.base_path);
-    let token_manager = Arc::new(auth::tokens::TokenManager::new(&tokens_db_path)
-        .expect("Failed to initialize token manager"));
+    let mut token_manager = auth::tokens::TokenManager::new(&tokens_db_path)
+        .expect("Failed to initialize token manager");
+    if let Some(validator) = &jwt_validator {
+        token_manager = token_manager.with_jwt(validator.clone());
+    }
+    let token_manager = Arc::new(token_manager);
     
     // Initialize SFTP credentials manager
     let sftp_creds_db_path = format!("{}/sftp_credentials.db", config.storage.base_path);
     let sftp_credentials_manager = Arc::new(sftp::credentials::CredentialsManager::new(&sftp_creds_db_path)
         .expect("Failed to initialize SFTP credentials manager"));
     
+    // Administrative actions and rejected auth attempts, written by the auth middleware
+    let audit_db_path = format!("{}/audit.db", config.storage.base_path);
+    let audit_log = Arc::new(auth::audit::AuditLog::new(&audit_db_path)
+        .expect("Failed to initialize audit log"));
+    
+    // Create auth config for middleware (also owns the auth rate limiter)
+    let mut auth_config = auth::middleware::AuthConfig::from_config(&config)
+        .with_audit(audit_log.clone());
+    if let Some(validator) = jwt_validator {
+        auth_config = auth_config.with_jwt(validator);
+    }
+    let auth_config = Arc::new(auth_config);
+    
+    // Background tasks stop on this before databases are flushed
+    let shutdown = Arc::new(daemon::shutdown::Shutdown::new());
+    
     // Spawn token cleanup task (runs every 5 minutes)
     let token_manager_cleanup = token_manager.clone();
+    let rate_limiter_cleanup = auth_config.rate_limiter.clone();
+    let audit_cleanup = audit_log.clone();
+    let mut cleanup_shutdown = shutdown.signal();
     tokio::spawn(async move {
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(300)).await;
+            tokio::select! {
+                _ = tokio::time::sleep(tokio::time::Duration::from_secs(300)) => {}
+                _ = cleanup_shutdown.recv() => break,
+            }
             if let Err(e) = token_manager_cleanup.cleanup_expired() {
                 tracing::error!("Failed to cleanup expired tokens: {}", e);
             }
+            rate_limiter_cleanup.cleanup();
+            if let Err(e) = audit_cleanup.prune() {
+                tracing::error!("Failed to prune audit log: {}", e);
+            }
         }
     });
     
     let volume_handler = Arc::new(filesystem::handler::VolumeHandler::new(
         config.storage.volumes_path.clone()
-    ));
+    ).with_backup_path(format!("{}/backups", config.storage.base_path))
+        .with_max_decompression_ratio(config.storage.max_decompression_ratio)
+        .with_trash_retention(config.storage.trash_retention_hours * 3600)
+        .with_default_quota(config.storage.default_volume_quota_mb));
+    volume_handler.clone().start_trash_purge(shutdown.signal());
     
     // Initialize network pool
     let network_db_path = format!("{}/network.db", config.storage.base_path);
     let network_pool = Arc::new(network::pool::NetworkPool::new(&network_db_path)
-        .expect("Failed to initialize network pool"));
+        .expect("Failed to initialize network pool")
+        .with_allocation_strategy(config.network.allocation_strategy)
+        .with_low_port_threshold(config.network.low_port_threshold));
     
     // Initialize default ports (25565-25569) on first startup
     // Todo: Fix these so that users can choose via the config.json for said system.
-    let default_ports = vec![
-        ("0.0.0.0".to_string(), 25565, "tcp".to_string()),
-        ("0.0.0.0".to_string(), 25566, "tcp".to_string()),
-        ("0.0.0.0".to_string(), 25567, "tcp".to_string()),
-        ("0.0.0.0".to_string(), 25568, "tcp".to_string()),
-        ("0.0.0.0".to_string(), 25569, "tcp".to_string()),
-    ];
-    
     // Check if pool is empty and add default ports
     match network_pool.get_all_ports().await {
         Ok(ports) if ports.is_empty() => {
             tracing::info!("Initializing default port pool (25565-25569)");
-            for (ip, port, protocol) in default_ports {
-                if let Err(e) = network_pool.add_port(ip.clone(), port, Some(protocol.clone())).await {
-                    tracing::error!("Failed to add default port {}: {}", port, e);
-                }
+            if let Err(e) = network_pool.add_port_range("0.0.0.0".to_string(), 25565, 25569, "tcp".to_string()).await {
+                tracing::error!("Failed to add default ports: {}", e);
             }
         }
         Ok(ports) => {
@@ -149,6 +245,22 @@ async fn main_app(timer: Timer) {
     let firewall_manager = Arc::new(network::firewall::FirewallManager::new(&firewall_db_path)
         .expect("Failed to initialize firewall manager"));
     
+    // Re-apply stored blocklists, then the ones from config (these replace stored lists of the same name)
+    match firewall_manager.restore_blocklists().await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Restored {} firewall blocklists", count),
+        Err(e) => tracing::error!("Failed to restore firewall blocklists: {}", e),
+    }
+    for list in &config.network.blocklists {
+        let result = match list.load_cidrs() {
+            Ok(cidrs) => firewall_manager.set_blocklist(None, &list.name, cidrs).await,
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to apply blocklist {} from config: {}", list.name, e);
+        }
+    }
+    
     // Initialize container manager
     let containers_db_path = format!("{}/containers.db", config.storage.base_path);
     let container_manager = Arc::new(container::manager::ContainerManager::new(&containers_db_path)
@@ -157,10 +269,24 @@ async fn main_app(timer: Timer) {
     // Initialize remote sync manager if enabled (before billing tracker)
     let remote_sync = if let Some(remote_config) = &config.remote {
         if remote_config.enabled {
-            let sync_manager = Arc::new(remote::client::RemoteSyncManager::new(
+            let mut sync_manager = remote::client::RemoteSyncManager::new(
                 remote_config.url.clone(),
                 remote_config.token.clone(),
-            ));
+            );
+            // The config check already loaded these, this only fails if the files changed since
+            if let Some(tls) = &remote_config.tls {
+                sync_manager = match sync_manager.with_tls(tls) {
+                    Ok(sync_manager) => sync_manager,
+                    Err(e) => {
+                        eprintln!("remote.tls: {}", e);
+                        return;
+                    }
+                };
+            }
+            let sync_manager = Arc::new(sync_manager.with_retry_policy(remote_config.retry.clone())
+                .with_billing_batch_size(remote_config.billing_batch_size)
+                .with_offline_queue(&format!("{}/remote_queue.db", config.storage.base_path))
+                .expect("Failed to initialize remote notification queue"));
             
             // Start health check loop (non-blocking)
             sync_manager.start_health_check();
@@ -174,26 +300,6 @@ async fn main_app(timer: Timer) {
         None
     };
     
-    // Start SFTP server if enabled
-    if let Some(sftp_config) = &config.sftp {
-        if sftp_config.enabled {
-            let sftp_server = Arc::new(sftp::server::SftpServerManager::new(
-                sftp_credentials_manager.clone(),
-                config.storage.volumes_path.clone(),
-                sftp_config.port,
-            ));
-            
-            let sftp_server_clone = sftp_server.clone();
-            tokio::spawn(async move {
-                if let Err(e) = sftp_server_clone.start().await {
-                    tracing::error!("SFTP server error: {}", e);
-                }
-            });
-            
-            tracing::info!("SFTP server enabled on port {}", sftp_config.port);
-        }
-    }
-    
     // Initialize billing tracker with remote sync and container manager
     let billing_tracker = {
         let mut tracker = billing::tracker::BillingTracker::new(
@@ -213,12 +319,58 @@ async fn main_app(timer: Timer) {
         Arc::new(tracker)
     };
     
+    // Start SFTP server if enabled
+    if let Some(sftp_config) = &config.sftp
+        && sftp_config.enabled {
+        let host_key_path = sftp_config.host_key_path.clone()
+            .unwrap_or_else(|| format!("{}/sftp_host_key", config.storage.base_path));
+        let sftp_server = Arc::new(sftp::server::SftpServerManager::new(
+            sftp_credentials_manager.clone(),
+            config.storage.volumes_path.clone(),
+            sftp_config.host.clone(),
+            sftp_config.port,
+        ).with_host_key_path(host_key_path)
+            .with_handle_limits(sftp::protocol::HandleLimits {
+                max_open: sftp_config.max_open_handles,
+                idle_timeout: std::time::Duration::from_secs(sftp_config.handle_idle_timeout_secs),
+            })
+            .with_guard(sftp::guard::SftpGuard::new(
+                sftp_config.max_failed_logins,
+                sftp_config.failed_login_window_secs,
+                sftp_config.ban_secs,
+                sftp_config.max_sessions,
+                sftp_config.max_sessions_per_ip,
+            ))
+            .with_billing_tracker(billing_tracker.clone())
+            .with_volume_locks(volume_handler.volume_locks()));
+        
+        let sftp_shutdown = shutdown.signal();
+        tokio::spawn(async move {
+            if let Err(e) = sftp_server.start(sftp_shutdown).await {
+                tracing::error!("SFTP server error: {}", e);
+                eprintln!("SFTP server failed to start: {}", e);
+            }
+        });
+        
+        tracing::info!("SFTP server enabled on {}:{}", sftp_config.host, sftp_config.port);
+    }
+    
     // Start billing monitoring if enabled
     if config.monitoring.enabled {
-        billing_tracker.clone().start_monitoring();
+        billing_tracker.clone().start_monitoring(shutdown.signal());
         tracing::info!("Billing monitoring started");
     }
     
+    // Reload runtime-safe settings when config.json changes
+    let config_reloader = Arc::new(config::reload::ConfigReloader::new(
+        "config.json",
+        config.clone(),
+        billing_tracker.clone(),
+    ));
+    if let Err(e) = config_reloader.clone().watch() {
+        tracing::warn!("Config hot-reload disabled: {}", e);
+    }
+    
     // Initialize lifecycle manager with event channel
     let (lifecycle_manager, mut lifecycle_rx) = container::lifecycle::LifecycleManager::new(container_manager.clone())
         .expect("Failed to initialize lifecycle manager");
@@ -232,27 +384,56 @@ async fn main_app(timer: Timer) {
     // Initialize container updater with event channel
     let (container_updater, mut update_rx) = container::update::ContainerUpdater::new(container_manager.clone())
         .expect("Failed to initialize container updater");
-    let _container_updater = Arc::new(container_updater);
+    let container_updater = Arc::new(container_updater);
     
     // Initialize network rebinder with event channel
-    let (network_rebinder, mut network_rx) = container::network::NetworkRebinder::new(container_manager.clone())
+    let (network_rebinder, mut network_rx) = container::network::NetworkRebinder::new(container_manager.clone(), network_pool.clone())
         .expect("Failed to initialize network rebinder");
     let network_rebinder = Arc::new(network_rebinder);
     
     // Initialize WebSocket event hub
-    let event_hub = Arc::new(websocket::EventHub::new());
+    let event_hub = Arc::new(websocket::EventHub::new()
+        .with_state_store(container_manager.clone())
+        .with_buffer_size(config.server.websocket.buffer_size));
+    
+    // Lets clients retry container creation without creating twice
+    let idempotency_db_path = format!("{}/idempotency.db", config.storage.base_path);
+    let idempotency_store = Arc::new(container::idempotency::IdempotencyStore::new(
+        &idempotency_db_path,
+        config.server.idempotency_ttl_secs,
+    ).expect("Failed to initialize idempotency store"));
+    
+    // Refuses new containers while the node is being drained or serviced
+    let maintenance = Arc::new(daemon::maintenance::Maintenance::load(
+        format!("{}/maintenance.json", config.storage.base_path),
+        config.server.maintenance,
+    ));
+    
+    // Start/stop history from power events and the console watcher
+    let uptime_db_path = format!("{}/uptime.db", config.storage.base_path);
+    let uptime_tracker = Arc::new(container::uptime::UptimeTracker::new(&uptime_db_path)
+        .expect("Failed to initialize uptime tracker"));
+    
+    // Reusable defaults for creates
+    let template_db_path = format!("{}/templates.db", config.storage.base_path);
+    let template_manager = Arc::new(container::template::TemplateManager::new(&template_db_path)
+        .expect("Failed to initialize template manager"));
     
     // Initialize console streamer
     let console_streamer = Arc::new(websocket::ConsoleStreamer::new(
         container_manager.clone(),
         event_hub.clone(),
-    ).expect("Failed to initialize console streamer"));
+    ).expect("Failed to initialize console streamer")
+        .with_remote_sync(remote_sync.clone())
+        .with_uptime_tracker(uptime_tracker.clone()));
     
     // Initialize stats collector
     let stats_collector = Arc::new(websocket::StatsCollector::new(
         container_manager.clone(),
         event_hub.clone(),
-    ).expect("Failed to initialize stats collector"));
+        shutdown.signal(),
+    ).expect("Failed to initialize stats collector")
+        .with_quota_manager(volume_handler.quota_manager()));
     
     tracing::info!("Checking Docker availability");
     match tokio::time::timeout(
@@ -285,13 +466,60 @@ async fn main_app(timer: Timer) {
         }
     }
     
+    // Orphaned containers and networks from failed installs or crashes
+    let reconciler = Arc::new(container::reconcile::Reconciler::new(container_manager.clone())
+        .expect("Failed to initialize reconciler"));
+    if config.docker.reconcile_on_startup {
+        match reconciler.reconcile(false).await {
+            Ok(report) if report.is_empty() => tracing::info!("Reconcile: no orphaned Docker resources"),
+            Ok(report) => {
+                for c in &report.containers {
+                    tracing::info!("Reconcile: removed container {} ({})", c.name, c.docker_id);
+                }
+                for n in &report.networks {
+                    tracing::info!("Reconcile: removed network {}", n.name);
+                }
+                for e in &report.errors {
+                    tracing::warn!("Reconcile: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Startup reconcile failed: {}", e),
+        }
+    }
+    
+    // Old images from reinstalls and image changes, outside every quota
+    let image_pruner = Arc::new(container::images::ImagePruner::new(container_manager.clone())
+        .expect("Failed to initialize image pruner")
+        .with_rebinder(network_rebinder.clone()));
+    if config.docker.image_prune_interval_hours > 0 {
+        image_pruner.clone().start(config.docker.image_prune_interval_hours * 3600, shutdown.signal());
+    }
+    
+    // Runtime state may be stale after a restart, take it from Docker
+    match reconciler.sync_runtime_states().await {
+        Ok(active) => tracing::info!("Runtime state synced, {} container(s) running", active),
+        Err(e) => tracing::warn!("Failed to sync runtime state: {}", e),
+    }
+    
+    // Installs that were running when the daemon went down have no job anymore
+    match lifecycle_manager.resume_interrupted_installs(config.docker.install_resume_attempts).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Resumed {} interrupted install(s)", count),
+        Err(e) => tracing::warn!("Failed to resume interrupted installs: {}", e),
+    }
+    
+    // Node-wide feed behind GET /events, every listener below publishes into it
+    let event_bus = Arc::new(daemon::events::EventBus::new());
+    
     // Clone event_hub for lifecycle events
     let event_hub_lifecycle = event_hub.clone();
+    let event_bus_lifecycle = event_bus.clone();
     let remote_sync_lifecycle = remote_sync.clone();
     
     // Spawn lifecycle event listener
     tokio::spawn(async move {
         while let Some(event) = lifecycle_rx.recv().await {
+            event_bus_lifecycle.publish(daemon::events::EventSource::Lifecycle, event.container_id(), &event);
             match &event {
                 container::lifecycle::LifecycleEvent::DockerConnected => {
                     // Skip logging
@@ -325,27 +553,63 @@ async fn main_app(timer: Timer) {
                     container::lifecycle::LifecycleEvent::ReinstallStarted(id) => {
                         sync.notify_status(id.clone(), "installing".to_string());
                     }
+                    container::lifecycle::LifecycleEvent::InstallResumed(id, _) => {
+                        sync.notify_status(id.clone(), "installing".to_string());
+                    }
+                    container::lifecycle::LifecycleEvent::InstallQueued(id, _) => {
+                        sync.notify_status(id.clone(), "installing".to_string());
+                    }
                     _ => {}
                 }
             }
             
             // Broadcast relevant events to WebSocket clients
             match &event {
+                // The old container is gone and a fresh one doesn't run yet
+                container::lifecycle::LifecycleEvent::RemovingOldContainer(id) => {
+                    event_hub_lifecycle.set_runtime_state(id, websocket::ContainerRuntimeState::Offline).await;
+                }
                 container::lifecycle::LifecycleEvent::PullingImage(id, _) => {
                     websocket::notify_installing(&event_hub_lifecycle, id).await;
                 }
                 container::lifecycle::LifecycleEvent::CreatingContainer(id) => {
                     websocket::notify_installing(&event_hub_lifecycle, id).await;
                 }
+                // The install starts the container right after it is ready
                 container::lifecycle::LifecycleEvent::Ready(id) => {
+                    event_hub_lifecycle.mark_started(id).await;
                     websocket::notify_installed(&event_hub_lifecycle, id).await;
                 }
                 container::lifecycle::LifecycleEvent::Error(id, msg) => {
+                    event_hub_lifecycle.set_runtime_state(id, websocket::ContainerRuntimeState::Offline).await;
                     event_hub_lifecycle.broadcast_daemon_message(id, &format!("Error: {}", msg)).await;
                 }
                 container::lifecycle::LifecycleEvent::ReinstallStarted(id) => {
                     websocket::notify_installing(&event_hub_lifecycle, id).await;
                 }
+                container::lifecycle::LifecycleEvent::VolumeWiped(id) => {
+                    event_hub_lifecycle.broadcast_daemon_message(id, "Volume wiped for reinstall").await;
+                }
+                container::lifecycle::LifecycleEvent::InstallResumed(id, attempt) => {
+                    websocket::notify_installing(&event_hub_lifecycle, id).await;
+                    event_hub_lifecycle.broadcast_daemon_message(
+                        id,
+                        &format!("Install was interrupted by a daemon restart, running it again (attempt {})", attempt),
+                    ).await;
+                }
+                container::lifecycle::LifecycleEvent::InstallQueued(id, ahead) => {
+                    websocket::notify_installing(&event_hub_lifecycle, id).await;
+                    event_hub_lifecycle.broadcast_daemon_message(
+                        id,
+                        &format!("Installing (queued), {} install(s) ahead", ahead),
+                    ).await;
+                }
+                container::lifecycle::LifecycleEvent::RetryingPull(id, attempt) => {
+                    event_hub_lifecycle.broadcast_daemon_message(
+                        id,
+                        &format!("Image pull failed, retrying pull (attempt {})", attempt),
+                    ).await;
+                }
                 _ => {}
             }
         }
@@ -353,32 +617,72 @@ async fn main_app(timer: Timer) {
     
     // Clone event_hub for power events
     let event_hub_power = event_hub.clone();
+    let firewall_power = firewall_manager.clone();
+    let event_bus_power = event_bus.clone();
+    let lifecycle_power = lifecycle_manager.clone();
+    let uptime_power = uptime_tracker.clone();
     
     // Spawn power event listener
     tokio::spawn(async move {
         while let Some(event) = power_rx.recv().await {
             tracing::info!("Container power event: {:?}", event);
+            event_bus_power.publish(daemon::events::EventSource::Power, Some(event.container_id()), &event);
             
-            // Broadcast power events to WebSocket clients
+            // Broadcast power events to WebSocket clients. The runtime state is
+            // set here as well, the console watcher only runs with a client.
             match &event {
                 container::power::PowerEvent::Starting(id) => {
                     event_hub_power.broadcast_event(id, "starting").await;
                 }
                 container::power::PowerEvent::Started(id) => {
                     // Note: We don't broadcast 'running' here - that comes from pattern matching in logs
+                    event_hub_power.mark_started(id).await;
                     event_hub_power.broadcast_daemon_message(id, "Container started").await;
+                    record_uptime(&lifecycle_power, &uptime_power, id, true).await;
+                    restore_bandwidth(&firewall_power, id).await;
+                    sync_blocklists(&firewall_power, id).await;
+                }
+                container::power::PowerEvent::Stopping(id) => {
+                    event_hub_power.broadcast_event(id, "stopping").await;
+                }
+                container::power::PowerEvent::Stopped(id) => {
+                    event_hub_power.set_runtime_state(id, websocket::ContainerRuntimeState::Offline).await;
+                    event_hub_power.broadcast_event(id, "stopped").await;
+                    record_uptime(&lifecycle_power, &uptime_power, id, false).await;
+                    sync_blocklists(&firewall_power, id).await;
                 }
                 container::power::PowerEvent::Killing(id) => {
                     event_hub_power.broadcast_event(id, "stopping").await;
                 }
                 container::power::PowerEvent::Killed(id) => {
+                    event_hub_power.set_runtime_state(id, websocket::ContainerRuntimeState::Offline).await;
                     event_hub_power.broadcast_daemon_message(id, "Container stopped").await;
+                    record_uptime(&lifecycle_power, &uptime_power, id, false).await;
+                    sync_blocklists(&firewall_power, id).await;
                 }
                 container::power::PowerEvent::Restarting(id) => {
                     event_hub_power.broadcast_event(id, "stopping").await;
                 }
                 container::power::PowerEvent::Restarted(id) => {
+                    event_hub_power.set_runtime_state(id, websocket::ContainerRuntimeState::Starting).await;
                     event_hub_power.broadcast_daemon_message(id, "Container restarted").await;
+                    record_uptime(&lifecycle_power, &uptime_power, id, true).await;
+                    restore_bandwidth(&firewall_power, id).await;
+                    sync_blocklists(&firewall_power, id).await;
+                }
+                container::power::PowerEvent::Signaled(id, signal) => {
+                    event_hub_power.broadcast_daemon_message(id, &format!("Sent {} to container", signal)).await;
+                }
+                container::power::PowerEvent::Paused(id) => {
+                    event_hub_power.set_runtime_state(id, websocket::ContainerRuntimeState::Paused).await;
+                    event_hub_power.broadcast_event(id, "paused").await;
+                }
+                container::power::PowerEvent::Unpaused(id) => {
+                    event_hub_power.set_runtime_state(id, websocket::ContainerRuntimeState::Running).await;
+                    event_hub_power.broadcast_event(id, "running").await;
+                }
+                container::power::PowerEvent::Busy(id) => {
+                    event_hub_power.broadcast_daemon_message(id, "busy").await;
                 }
                 container::power::PowerEvent::Error(id, msg) => {
                     event_hub_power.broadcast_daemon_message(id, &format!("Power error: {}", msg)).await;
@@ -387,52 +691,157 @@ async fn main_app(timer: Timer) {
         }
     });
     
-    // Spawn network rebinding event listener
+    let event_hub_network = event_hub.clone();
+    let event_bus_network = event_bus.clone();
+    let firewall_network = firewall_manager.clone();
+    
+    // Spawn network rebinding and image change event listener
     tokio::spawn(async move {
         while let Some(event) = network_rx.recv().await {
             tracing::info!("Container network event: {:?}", event);
+            event_bus_network.publish(daemon::events::EventSource::Network, Some(event.container_id()), &event);
+            
+            match &event {
+                container::network::NetworkEvent::PullingImage(id, image) => {
+                    event_hub_network.broadcast_daemon_message(id, &format!("Pulling image {}", image)).await;
+                }
+                container::network::NetworkEvent::ImageChanged(id, image) => {
+                    event_hub_network.broadcast_daemon_message(id, &format!("Image changed to {}", image)).await;
+                    // The recreated container has a new network namespace and address
+                    restore_bandwidth(&firewall_network, id).await;
+                    sync_blocklists(&firewall_network, id).await;
+                }
+                container::network::NetworkEvent::RebindingComplete(id) => {
+                    restore_bandwidth(&firewall_network, id).await;
+                    sync_blocklists(&firewall_network, id).await;
+                }
+                container::network::NetworkEvent::Error(id, msg) => {
+                    event_hub_network.broadcast_daemon_message(id, &format!("Recreate failed: {}", msg)).await;
+                }
+                _ => {}
+            }
         }
     });
     
+    // Clone event_hub for update events
+    let event_hub_update = event_hub.clone();
+    let event_bus_update = event_bus.clone();
+    
     // Spawn container update event listener
     tokio::spawn(async move {
         while let Some(event) = update_rx.recv().await {
             tracing::info!("Container update event: {:?}", event);
+            event_bus_update.publish(daemon::events::EventSource::Update, Some(event.container_id()), &event);
+            
+            // Give the panel immediate feedback on resource and volume changes
+            match &event {
+                container::update::UpdateEvent::ResourcesUpdated { container_id } => {
+                    event_hub_update.broadcast_daemon_message(container_id, "Resource limits updated").await;
+                }
+                container::update::UpdateEvent::LimitsUpdated { container_id, memory, cpus } => {
+                    event_hub_update.broadcast_limits_updated(container_id, websocket::LimitsUpdate {
+                        memory_limit_bytes: *memory,
+                        cpu_limit: *cpus,
+                    }).await;
+                }
+                container::update::UpdateEvent::VolumesUpdated { container_id } => {
+                    event_hub_update.broadcast_daemon_message(container_id, "Volume mounts updated").await;
+                }
+                container::update::UpdateEvent::Error { container_id, message } => {
+                    event_hub_update.broadcast_daemon_message(container_id, &format!("Update failed: {}", message)).await;
+                }
+                _ => {}
+            }
         }
     });
     
+    // Scheduled tasks fire through the same managers as the API
+    let schedule_db_path = format!("{}/schedules.db", config.storage.base_path);
+    let schedule_manager = Arc::new(scheduler::schedule::ScheduleManager::new(&schedule_db_path)
+        .expect("Failed to initialize schedule manager"));
+    scheduler::runner::ScheduleRunner::new(
+        schedule_manager.clone(),
+        container_manager.clone(),
+        power_manager.clone(),
+        event_hub.clone(),
+        console_streamer.clone(),
+        volume_handler.clone(),
+    ).start(shutdown.signal());
+    
     // Setup WebSocket state
     let ws_state = websocket::WebSocketState {
         manager: container_manager.clone(),
         power: power_manager.clone(),
         event_hub: event_hub.clone(),
-        console_streamer,
-        stats_collector,
+        console_streamer: console_streamer.clone(),
+        stats_collector: stats_collector.clone(),
         token_manager: token_manager.clone(),
+        rate_limiter: auth_config.rate_limiter.clone(),
+        shutdown: shutdown.signal(),
+        lag_limit: websocket::LagLimit::from_config(&config.server.websocket),
     };
     
+    // Moves containers to other nodes. Its own plain client, the remote sync's CA
+    // and client certificate are for the panel and must not reach other nodes.
+    let transfer_client = Arc::new(remote::client::RemoteClient::new(String::new(), String::new()));
+    let transfer_manager = Arc::new(container::transfer::TransferManager::new(
+        container_manager.clone(),
+        lifecycle_manager.clone(),
+        power_manager.clone(),
+        network_pool.clone(),
+        sftp_credentials_manager.clone(),
+        firewall_manager.clone(),
+        volume_handler.clone(),
+        event_hub.clone(),
+        transfer_client,
+    ));
+    
     // Setup routers
-    let public_routes = router::public::public_router();
-    let auth_routes = router::auth::auth_router(token_manager.clone());
-    let remote_routes = router::remote::remote_router();
-    let firewall_routes = router::firewall::firewall_router(firewall_manager);
-    let billing_routes = router::billing::billing_router(billing_tracker);
+    let public_routes = router::public::public_router(router::public::PublicState {
+        lifecycle: lifecycle_manager.clone(),
+        container_manager: container_manager.clone(),
+        token_manager: token_manager.clone(),
+        network_pool: network_pool.clone(),
+        maintenance: maintenance.clone(),
+        remote_sync: remote_sync.clone(),
+    });
+    // Public, token issuance still goes into the audit log
+    let auth_routes = router::auth::auth_router(token_manager.clone(), auth_config.rate_limiter.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::audit_middleware));
+    let remote_routes = router::remote::remote_router(config_reloader, remote_sync.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let firewall_routes = router::firewall::firewall_router(firewall_manager.clone());
+    let billing_routes = router::billing::billing_router(billing_tracker.clone());
+    let metrics_routes = router::metrics::metrics_router(router::metrics::MetricsState {
+        container_manager: container_manager.clone(),
+        network_pool: network_pool.clone(),
+        firewall_manager: firewall_manager.clone(),
+        billing_tracker,
+        event_hub: event_hub.clone(),
+        require_auth: config.metrics.require_auth,
+    });
+    let metrics_routes = if !config.metrics.enabled {
+        Router::new()
+    } else if config.metrics.require_auth {
+        metrics_routes.layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware))
+    } else {
+        metrics_routes
+    };
     
     // SFTP routes
-    let sftp_host = config.server.host.clone();
+    let sftp_host = config.sftp.as_ref()
+        .map(|s| s.host.clone())
+        .unwrap_or_else(|| config.server.host.clone());
     let sftp_port = config.sftp.as_ref().map(|s| s.port).unwrap_or(2022);
     let sftp_routes = router::sftp::sftp_router(
-        sftp_credentials_manager,
+        sftp_credentials_manager.clone(),
         container_manager.clone(),
         sftp_host,
         sftp_port,
     );
     
-    // Create auth config for middleware
-    let auth_config = Arc::new(auth::middleware::AuthConfig::from_config(&config));
-    
     // Protected routes with auth middleware
-    let filesystem_routes = router::filesystem::volume_router(volume_handler)
+    let filesystem_routes = router::filesystem::volume_router(volume_handler.clone(), template_manager.clone(), &config.server.limits)
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
     let network_routes = router::network::network_router(network_pool.clone())
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
@@ -442,7 +851,24 @@ async fn main_app(timer: Timer) {
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
     let sftp_protected_routes = sftp_routes
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
-    let container_routes = router::container::container_router(container_manager, lifecycle_manager, power_manager, network_rebinder, network_pool)
+    let schedule_routes = router::schedule::schedule_router(schedule_manager.clone(), container_manager.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let uptime_routes = router::uptime::uptime_router(uptime_tracker.clone(), container_manager.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let token_admin_routes = router::auth::token_admin_router(token_manager.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let maintenance_routes = router::maintenance::maintenance_router(reconciler, image_pruner, maintenance.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let container_routes = router::container::container_router(container_manager.clone(), lifecycle_manager, power_manager, network_rebinder, network_pool.clone(), container_updater, sftp_credentials_manager.clone(), idempotency_store.clone(), stats_collector, maintenance, transfer_manager, template_manager.clone(), console_streamer, event_hub.clone(), volume_handler, uptime_tracker.clone(), &config.server.limits)
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let template_routes = router::template::template_router(template_manager.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let audit_routes = router::audit::audit_router(audit_log.clone())
+        .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
+    let events_routes = router::events::events_router(router::events::EventsState {
+        bus: event_bus,
+        shutdown: shutdown.signal(),
+    })
         .layer(middleware::from_fn_with_state(auth_config.clone(), auth::middleware::auth_middleware));
     
     // WebSocket route
@@ -450,40 +876,123 @@ async fn main_app(timer: Timer) {
         .route("/ws/:id", get(websocket::ws_handler))
         .with_state(ws_state);
     
-    // Combine routes with CORS
-    let app = public_routes
+    // Volume and container routes bring their own limits, their file
+    // transfers get the larger transfer ones
+    let api_routes = public_routes
         .merge(auth_routes)
+        .merge(token_admin_routes)
         .merge(remote_routes)
-        .merge(filesystem_routes)
         .merge(network_routes)
         .merge(firewall_protected_routes)
         .merge(billing_protected_routes)
         .merge(sftp_protected_routes)
-        .merge(container_routes)
+        .merge(template_routes)
+        .merge(schedule_routes)
+        .merge(uptime_routes)
+        .merge(maintenance_routes)
+        .merge(events_routes)
+        .merge(audit_routes)
         .merge(ws_routes)
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any) // Todo: Get from config.json origin array
-                .allow_methods(Any)
-                .allow_headers(Any)
-        );
+        .merge(metrics_routes);
+    
+    // Combine routes with CORS
+    let app = router::limits::api(api_routes, &config.server.limits)
+        .merge(filesystem_routes)
+        .merge(container_routes)
+        .layer(router::cors::cors_layer(config.server.cors.as_ref(), dev))
+        // Outermost so every response, including rejected ones, carries the ID
+        .layer(middleware::from_fn(router::request_id::request_id_middleware));
     
     // Start server
     // as son as server starts we set startup time
     let elapsed = timer.stop().await;
     println!("Total startup time: {}ms\n", elapsed);
-    let addr = format!("{}:{}", config.server.host, config.server.port);
-    let listener = tokio::net::TcpListener::bind(&addr).await
+    // Resolves hostnames, and takes IPv6 addresses without brackets
+    let listener = tokio::net::TcpListener::bind((config.server.host.as_str(), config.server.port)).await
         .expect("Failed to bind server");
     
    // println!("Server running on http://{}", addr);
     //println!("WebSocket endpoint: ws://{}/ws/<container_id>", addr);
     
-    axum::serve(listener, app).await
+    // Client addresses are needed for per-IP auth rate limiting
+    let shutdown_trigger = shutdown.clone();
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(async move {
+            daemon::shutdown::wait_for_signal().await;
+            // Stops background tasks and closes WebSockets so open connections can drain
+            tracing::info!("Shutting down: stopping background tasks and closing WebSocket connections");
+            shutdown_trigger.trigger();
+        })
+        .await
         .expect("Server failed");
+    
+    tracing::info!("Server stopped accepting connections, flushing databases");
+    let flushes = [
+        ("containers", container_manager.flush().await),
+        ("tokens", token_manager.flush().await),
+        ("sftp credentials", sftp_credentials_manager.flush().await),
+        ("network", network_pool.flush().await),
+        ("firewall", firewall_manager.flush().await),
+        ("schedules", schedule_manager.flush().await),
+        ("uptime", uptime_tracker.flush().await),
+        ("templates", template_manager.flush().await),
+        ("idempotency keys", idempotency_store.flush().await),
+        ("audit log", audit_log.flush().await),
+    ];
+    for (name, result) in flushes {
+        if let Err(e) = result {
+            tracing::error!("Failed to flush {} database: {}", name, e);
+        }
+    }
+    tracing::info!("Shutdown complete");
+}
+
+/// A started container has a new network namespace, put its egress limit back
+async fn restore_bandwidth(firewall: &network::firewall::FirewallManager, internal_id: &str) {
+    if let Err(e) = firewall.restore_bandwidth_limit(internal_id).await {
+        tracing::warn!("Failed to restore bandwidth limit of {}: {}", internal_id, e);
+    }
+}
+
+/// Record a start or stop in the uptime history, at the time Docker reports
+/// so the console watcher seeing the same run doesn't add a second span
+async fn record_uptime(
+    lifecycle: &container::lifecycle::LifecycleManager,
+    uptime: &container::uptime::UptimeTracker,
+    internal_id: &str,
+    running: bool,
+) {
+    let docker = lifecycle.inspect_docker(&format!("lightd-{}", internal_id)).await.ok().flatten();
+    // Docker reports the zero time for a run that hasn't finished
+    let docker_time = |ts: Option<String>| {
+        ts.and_then(|ts| chrono::DateTime::parse_from_rfc3339(&ts).ok())
+            .map(|dt| dt.timestamp())
+            .filter(|ts| *ts > 0)
+            .map(|ts| ts as u64)
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let result = match docker {
+        Some(docker) if running => uptime.record_start(internal_id, docker_time(docker.started_at).unwrap_or(now)),
+        None if running => uptime.record_start(internal_id, now),
+        docker => uptime.record_stop(internal_id, docker.and_then(|d| docker_time(d.finished_at)).unwrap_or(now)),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record uptime of {}: {}", internal_id, e);
+    }
+}
+
+/// Container blocklists match the container's address, which changes with every start
+async fn sync_blocklists(firewall: &network::firewall::FirewallManager, internal_id: &str) {
+    if let Err(e) = firewall.sync_container_blocklists(internal_id).await {
+        tracing::warn!("Failed to apply blocklists of {}: {}", internal_id, e);
+    }
 }
    
 
 async fn run_system_mode(timer: Timer) {
-    main_app(timer).await
+    main_app(timer, true).await
 }
@@ -0,0 +1,163 @@
+//! Egress bandwidth limits with tc
+//!
+//! The limit is an HTB qdisc on the container's end of its veth pair, found
+//! through the network namespace of the container's main process. Shaping
+//! there limits what the server sends out, which is what billing counts as
+//! egress; the host end of the pair would only shape traffic towards it.
+//!
+//! The namespace, and with it the qdisc, is replaced every time the container
+//! starts or is recreated (port rebind, image change), so the limit is stored
+//! and applied again after each of those.
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+use crate::error::LightdError;
+
+/// Highest accepted limit, 100 Gbit
+pub const MAX_EGRESS_MBIT: u32 = 100_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct BandwidthLimit {
+    /// Sustained egress rate in megabits per second
+    pub egress_mbit: u32,
+    /// Bytes allowed above the rate in a burst, derived from the rate when unset
+    #[serde(default)]
+    pub burst_kb: Option<u32>,
+}
+
+impl BandwidthLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.egress_mbit == 0 || self.egress_mbit > MAX_EGRESS_MBIT {
+            return Err(format!("egress_mbit must be between 1 and {}", MAX_EGRESS_MBIT));
+        }
+        if self.burst_kb == Some(0) {
+            return Err("burst_kb must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Burst in KB, by default what the rate sends in 10ms (at least 32KB)
+    fn burst(&self) -> u32 {
+        self.burst_kb.unwrap_or_else(|| (self.egress_mbit * 1000 / 8 / 100).max(32))
+    }
+
+    /// `tc` arguments after `tc`, the HTB root and one class everything falls into
+    fn tc_commands(&self, interface: &str) -> [Vec<String>; 2] {
+        let rate = format!("{}mbit", self.egress_mbit);
+        let burst = format!("{}kb", self.burst());
+        [
+            ["qdisc", "replace", "dev", interface, "root", "handle", "1:", "htb", "default", "10"]
+                .map(str::to_string)
+                .to_vec(),
+            [
+                "class", "replace", "dev", interface, "parent", "1:", "classid", "1:10", "htb",
+                "rate", &rate, "ceil", &rate, "burst", &burst,
+            ]
+                .map(str::to_string)
+                .to_vec(),
+        ]
+    }
+}
+
+/// PID of the container's main process, None while it isn't running
+fn container_pid(container_id: &str) -> Result<Option<u32>, Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new("docker")
+        .args(["inspect", "-f", "{{.State.Pid}}", &format!("lightd-{}", container_id)])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No such") {
+            return Err(LightdError::NotFound("Container").into());
+        }
+        return Err(format!("Failed to inspect container {}: {}", container_id, stderr.trim()).into());
+    }
+    let pid: u32 = String::from_utf8_lossy(&output.stdout).trim().parse()?;
+    Ok(Some(pid).filter(|pid| *pid != 0))
+}
+
+/// Run a command inside the network namespace of `pid`
+fn in_netns(pid: u32, program: &str, args: &[String]) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let pid = pid.to_string();
+    let output = Command::new("nsenter")
+        .args(["-t", &pid, "-n", program])
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Container-side veth interfaces from `ip -o link show`, e.g. `eth0` from
+/// `25: eth0@if26: <BROADCAST,...>`
+pub fn parse_veth_interfaces(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split(": ").nth(1))
+        .filter_map(|name| name.split_once("@if").map(|(name, _)| name.to_string()))
+        .collect()
+}
+
+fn veth_interfaces(pid: u32) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let output = in_netns(pid, "ip", &["-o".to_string(), "link".to_string(), "show".to_string()])?;
+    Ok(parse_veth_interfaces(&output))
+}
+
+/// Shape the container's egress. Returns false when it isn't running, the
+/// limit is then applied on its next start.
+pub fn apply(container_id: &str, limit: &BandwidthLimit) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(pid) = container_pid(container_id)? else {
+        return Ok(false);
+    };
+    let interfaces = veth_interfaces(pid)?;
+    if interfaces.is_empty() {
+        return Err(format!("No network interface found for container {}", container_id).into());
+    }
+    for interface in &interfaces {
+        for args in limit.tc_commands(interface) {
+            in_netns(pid, "tc", &args)?;
+        }
+    }
+    tracing::info!("Limited egress of {} to {}mbit on {}", container_id, limit.egress_mbit, interfaces.join(", "));
+    Ok(true)
+}
+
+/// Remove the HTB qdisc, a container that isn't running has none
+pub fn clear(container_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Ok(Some(pid)) = container_pid(container_id) else {
+        return Ok(());
+    };
+    for interface in veth_interfaces(pid)? {
+        // Fails when no root qdisc of ours is installed, which is fine
+        let _ = in_netns(pid, "tc", &["qdisc", "del", "dev", &interface, "root"].map(str::to_string));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_commands() {
+        let output = "\
+1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 qdisc noqueue state UNKNOWN mode DEFAULT
+25: eth0@if26: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc noqueue state UP mode DEFAULT
+";
+        assert_eq!(parse_veth_interfaces(output), vec!["eth0"]);
+
+        let limit = BandwidthLimit { egress_mbit: 100, burst_kb: None };
+        let [qdisc, class] = limit.tc_commands("eth0");
+        assert_eq!(qdisc.join(" "), "qdisc replace dev eth0 root handle 1: htb default 10");
+        assert_eq!(class.join(" "), "class replace dev eth0 parent 1: classid 1:10 htb rate 100mbit ceil 100mbit burst 125kb");
+
+        assert!(BandwidthLimit { egress_mbit: 0, burst_kb: None }.validate().is_err());
+    }
+}
@@ -0,0 +1,251 @@
+//! CIDR blocklists applied as DROP rules
+//!
+//! A blocklist is a named set of IPv4 ranges, either global or for one
+//! container. Both are enforced in Docker's `DOCKER-USER` chain, which sees
+//! all forwarded container traffic; rules of a container list also match the
+//! container's addresses (`-d`), so they only exist while it runs and are
+//! applied again on every start. The DROP rule is inserted at the top of the
+//! chain so it runs ahead of any accept rules. With `ipset` installed the
+//! whole list is one `hash:net` set matched by a single rule, otherwise every
+//! range gets its own rule. Every rule carries the list's tag as a comment,
+//! so it can be found again after the container's address changed.
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::net::Ipv4Addr;
+use std::process::{Command, Stdio};
+
+/// Chain the rules of every list go into
+const CHAIN: &str = "DOCKER-USER";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blocklist {
+    pub name: String,
+    /// None applies to every container
+    pub container_id: Option<String>,
+    pub cidrs: Vec<String>,
+    pub updated_at: u64,
+}
+
+impl Blocklist {
+    /// sled key, `blocklist:<container_id or "global">:<name>`
+    pub fn key(container_id: Option<&str>, name: &str) -> String {
+        format!("blocklist:{}:{}", container_id.unwrap_or("global"), name)
+    }
+
+    /// Chain older versions put container rules in, nothing jumps to it
+    fn legacy_chain(&self) -> String {
+        match &self.container_id {
+            Some(id) => format!("LIGHTD-{}", id.to_uppercase()),
+            None => CHAIN.to_string(),
+        }
+    }
+
+    /// ipset names are limited to 31 characters. Also the comment tagging the
+    /// list's rules, it needs no quoting in `iptables -S` output.
+    fn set_name(&self) -> String {
+        let scope = self.container_id.as_deref().unwrap_or("global");
+        format!("lightd-bl-{:08x}", super::firewall::short_hash(&format!("{}:{}", scope, self.name)))
+    }
+}
+
+/// Names end up in sled keys and log lines
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("Blocklist name must be 1-64 characters".to_string());
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Blocklist name '{}' may only contain letters, digits, '-' and '_'", name));
+    }
+    Ok(())
+}
+
+/// Normalize an address or range to `a.b.c.d/len` with the host bits cleared
+pub fn parse_cidr(entry: &str) -> Result<String, String> {
+    let entry = entry.trim();
+    let (addr, len) = match entry.split_once('/') {
+        Some((addr, len)) => (addr, len.parse::<u8>().map_err(|_| format!("Invalid prefix length in '{}'", entry))?),
+        None => (entry, 32),
+    };
+    if addr.contains(':') {
+        return Err(format!("'{}' is IPv6, the firewall only manages IPv4 (iptables)", entry));
+    }
+    let addr: Ipv4Addr = addr.parse().map_err(|_| format!("Invalid address '{}'", entry))?;
+    if len > 32 {
+        return Err(format!("Invalid prefix length in '{}'", entry));
+    }
+
+    let mask = if len == 0 { 0 } else { u32::MAX << (32 - len) };
+    Ok(format!("{}/{}", Ipv4Addr::from(u32::from(addr) & mask), len))
+}
+
+/// Parse an uploaded list, one range per line, `#` starts a comment
+pub fn parse_list(text: &str) -> Result<Vec<String>, String> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(parse_cidr)
+        .collect()
+}
+
+pub fn ipset_available() -> bool {
+    Command::new("ipset")
+        .arg("version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+fn iptables(args: &[&str]) -> std::io::Result<std::process::Output> {
+    Command::new("iptables").args(args).output()
+}
+
+/// Addresses of a running container on its networks, empty while it's stopped
+fn container_ips(container_id: &str) -> Vec<String> {
+    Command::new("docker")
+        .args([
+            "inspect", "-f", "{{range .NetworkSettings.Networks}}{{.IPAddress}} {{end}}",
+            &format!("lightd-{}", container_id),
+        ])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Insert a tagged DROP rule at the top of the chain
+fn insert_drop(matches: &[&str], destination: Option<&str>, tag: &str) -> std::io::Result<std::process::Output> {
+    let mut args = vec!["-I", CHAIN, "1"];
+    args.extend(matches);
+    if let Some(ip) = destination {
+        args.extend(["-d", ip]);
+    }
+    args.extend(["-m", "comment", "--comment", tag, "-j", "DROP"]);
+    iptables(&args)
+}
+
+/// Insert the DROP rules of `list` at the top of `DOCKER-USER`. A container
+/// list whose container isn't running only has its old rules removed.
+pub fn apply(list: &Blocklist) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Rules from an earlier apply (or an earlier daemon run) would be doubled
+    remove(list, &list.cidrs);
+    if list.cidrs.is_empty() {
+        return Ok(());
+    }
+
+    let destinations: Vec<Option<String>> = match &list.container_id {
+        Some(id) => container_ips(id).into_iter().map(Some).collect(),
+        None => vec![None],
+    };
+    if destinations.is_empty() {
+        tracing::debug!("Container of blocklist {} isn't running, the list is applied when it starts", list.name);
+        return Ok(());
+    }
+    let tag = list.set_name();
+
+    if ipset_available() {
+        let set = list.set_name();
+        let mut script = format!("create {} hash:net family inet -exist\nflush {}\n", set, set);
+        for cidr in &list.cidrs {
+            script.push_str(&format!("add {} {} -exist\n", set, cidr));
+        }
+
+        let mut child = Command::new("ipset")
+            .args(["restore", "-exist"])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().ok_or("ipset stdin unavailable")?.write_all(script.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!("Failed to load ipset {}: {}", set, String::from_utf8_lossy(&output.stderr)).into());
+        }
+
+        for destination in &destinations {
+            let output = insert_drop(&["-m", "set", "--match-set", &set, "src"], destination.as_deref(), &tag)?;
+            if !output.status.success() {
+                return Err(format!("Failed to apply blocklist {}: {}", list.name, String::from_utf8_lossy(&output.stderr)).into());
+            }
+        }
+        tracing::info!("Applied blocklist {} ({} ranges) via ipset {}", list.name, list.cidrs.len(), set);
+        return Ok(());
+    }
+
+    for cidr in &list.cidrs {
+        for destination in &destinations {
+            let output = insert_drop(&["-s", cidr], destination.as_deref(), &tag)?;
+            if !output.status.success() {
+                return Err(format!("Failed to block {}: {}", cidr, String::from_utf8_lossy(&output.stderr)).into());
+            }
+        }
+    }
+    tracing::info!("Applied blocklist {} ({} ranges) as individual rules", list.name, list.cidrs.len());
+    Ok(())
+}
+
+/// Rules in `iptables -S` output tagged with `tag`, as arguments deleting them
+fn tagged_rules(listing: &str, tag: &str) -> Vec<Vec<String>> {
+    listing
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|args| args.first() == Some(&"-A") && args.windows(2).any(|w| w == ["--comment", tag]))
+        .map(|args| std::iter::once("-D").chain(args[1..].iter().copied()).map(str::to_string).collect())
+        .collect()
+}
+
+/// Delete the rules `apply` created for `cidrs`, whichever way they were applied.
+/// Missing rules and sets are ignored.
+pub fn remove(list: &Blocklist, cidrs: &[String]) {
+    let set = list.set_name();
+
+    if let Ok(output) = iptables(&["-S", CHAIN]) {
+        for rule in tagged_rules(&String::from_utf8_lossy(&output.stdout), &set) {
+            let _ = Command::new("iptables").args(&rule).output();
+        }
+    }
+
+    // Untagged rules left by older versions, -D removes one copy per call
+    let chain = list.legacy_chain();
+    while iptables(&["-D", &chain, "-m", "set", "--match-set", &set, "src", "-j", "DROP"])
+        .is_ok_and(|o| o.status.success())
+    {}
+    for cidr in cidrs {
+        while iptables(&["-D", &chain, "-s", cidr, "-j", "DROP"]).is_ok_and(|o| o.status.success()) {}
+    }
+
+    let _ = Command::new("ipset").args(["destroy", &set]).output();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cidr() {
+        assert_eq!(parse_cidr("10.1.2.3/8").unwrap(), "10.0.0.0/8");
+        assert_eq!(parse_cidr(" 192.168.1.7 ").unwrap(), "192.168.1.7/32");
+        assert_eq!(parse_cidr("0.0.0.0/0").unwrap(), "0.0.0.0/0");
+        assert!(parse_cidr("10.0.0.0/33").is_err());
+        assert!(parse_cidr("2001:db8::/32").is_err());
+        assert!(parse_cidr("not-an-ip").is_err());
+
+        let list = parse_list("# bad actors\n1.2.3.0/24\n\n5.6.7.8 # single host\n").unwrap();
+        assert_eq!(list, vec!["1.2.3.0/24", "5.6.7.8/32"]);
+    }
+
+    #[test]
+    fn test_tagged_rules() {
+        let listing = "-N DOCKER-USER
+-A DOCKER-USER -s 1.2.3.0/24 -d 172.18.0.2/32 -m comment --comment lightd-bl-0000abcd -j DROP
+-A DOCKER-USER -s 1.2.3.0/24 -d 172.18.0.3/32 -m comment --comment lightd-bl-0000ffff -j DROP
+-A DOCKER-USER -j RETURN
+";
+        assert_eq!(
+            tagged_rules(listing, "lightd-bl-0000abcd"),
+            vec![["-D", "DOCKER-USER", "-s", "1.2.3.0/24", "-d", "172.18.0.2/32", "-m", "comment", "--comment", "lightd-bl-0000abcd", "-j", "DROP"]
+                .map(str::to_string)
+                .to_vec()]
+        );
+    }
+}
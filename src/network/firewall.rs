@@ -8,6 +8,9 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use sled::Db;
 
+use super::bandwidth::{self, BandwidthLimit};
+use super::blocklist::{self, Blocklist};
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum FirewallAction {
@@ -39,10 +42,64 @@ pub struct FirewallRule {
     pub enabled: bool,
 }
 
+/// Allow `requests` packets per `per_seconds`, the rest is dropped.
+///
+/// By default one bucket (`-m limit`) is shared by all traffic, so a single
+/// flooding source also throttles everyone else. With `per_source_ip` every
+/// source address gets its own bucket (`-m hashlimit --hashlimit-mode srcip`)
+/// and only the sources going over the rate are dropped.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RateLimit {
     pub requests: u32,
     pub per_seconds: u32,
+    #[serde(default)]
+    pub per_source_ip: bool,
+}
+
+impl RateLimit {
+    /// Both values must be positive, `per_seconds` divides the rate
+    pub fn validate(&self) -> Result<(), String> {
+        if self.requests == 0 || self.per_seconds == 0 {
+            return Err("rate_limit: requests and per_seconds must be greater than 0".to_string());
+        }
+        Ok(())
+    }
+
+    /// iptables rate like "1000/minute", using the smallest unit that keeps
+    /// the amount whole (iptables only takes integers)
+    fn iptables_rate(&self) -> String {
+        for (unit, secs) in [("second", 1u64), ("minute", 60), ("hour", 3600), ("day", 86400)] {
+            let scaled = self.requests as u64 * secs;
+            if scaled.is_multiple_of(self.per_seconds as u64) && scaled / self.per_seconds as u64 >= 1 {
+                return format!("{}/{}", scaled / self.per_seconds as u64, unit);
+            }
+        }
+        // Round to the nearest whole amount per day
+        let per_day = (self.requests as u64 * 86400 / self.per_seconds as u64).max(1);
+        format!("{}/day", per_day)
+    }
+
+    /// Match arguments for this limit. `name` keys the hashlimit table,
+    /// which needs a unique and short (max 15 characters) name.
+    fn match_args(&self, name: &str) -> Vec<String> {
+        let rate = self.iptables_rate();
+        if !self.per_source_ip {
+            return vec!["-m".to_string(), "limit".to_string(), "--limit".to_string(), rate];
+        }
+
+        vec![
+            "-m".to_string(), "hashlimit".to_string(),
+            "--hashlimit-upto".to_string(), rate,
+            "--hashlimit-mode".to_string(), "srcip".to_string(),
+            "--hashlimit-name".to_string(), format!("lightd-{:08x}", short_hash(name)),
+        ]
+    }
+}
+
+/// FNV-1a, for iptables and ipset names that must stay stable across
+/// restarts and fit their length limits
+pub(crate) fn short_hash(name: &str) -> u32 {
+    name.bytes().fold(0x811c9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x01000193))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,6 +141,19 @@ impl FirewallManager {
             rules,
         })
     }
+
+    /// Number of rules, and how many of them are enabled
+    pub async fn rule_counts(&self) -> (usize, usize) {
+        let rules = self.rules.read().await;
+        let enabled = rules.iter().filter(|r| r.enabled).count();
+        (rules.len(), enabled)
+    }
+    
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
     
     /// Create a custom Docker bridge network for a container
     pub async fn create_container_network(
@@ -94,7 +164,7 @@ impl FirewallManager {
         
         // Check if network already exists
         let check = Command::new("docker")
-            .args(&["network", "inspect", &network_name])
+            .args(["network", "inspect", &network_name])
             .output();
         
         if check.is_ok() && check.unwrap().status.success() {
@@ -104,7 +174,7 @@ impl FirewallManager {
         
         // Create isolated bridge network
         let output = Command::new("docker")
-            .args(&[
+            .args([
                 "network", "create",
                 "--driver", "bridge",
                 "--internal=false",
@@ -130,7 +200,7 @@ impl FirewallManager {
         let network_name = format!("lightd-net-{}", container_id);
         
         let output = Command::new("docker")
-            .args(&["network", "rm", &network_name])
+            .args(["network", "rm", &network_name])
             .output()?;
         
         if !output.status.success() {
@@ -244,8 +314,13 @@ impl FirewallManager {
         container_id: &str,
         protection: DDoSProtection,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(rate) = &protection.rate_limit {
+            rate.validate()?;
+        }
         let network_name = format!("lightd-net-{}", container_id);
         
+        // Start from empty chains so reconfiguring doesn't stack rules
+        self.disable_ddos_protection(container_id).await?;
         if !protection.enabled {
             return Ok(());
         }
@@ -274,6 +349,229 @@ impl FirewallManager {
         Ok(())
     }
     
+    /// Stored DDoS protection of a container, None when it isn't enabled
+    pub async fn get_ddos_protection(
+        &self,
+        container_id: &str,
+    ) -> Result<Option<DDoSProtection>, Box<dyn std::error::Error + Send + Sync>> {
+        let key = format!("ddos:{}", container_id);
+        match self.db.get(key.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Remove the SYN, CONN and RATE chains and the stored config of a container.
+    /// Returns whether protection was active, disabling twice is fine.
+    pub async fn disable_ddos_protection(
+        &self,
+        container_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let network_name = format!("lightd-net-{}", container_id);
+        
+        for kind in ["SYN", "CONN", "RATE"] {
+            let chain = format!("LIGHTD-{}-{}", kind, network_name);
+            // Fails when the chain was never created, which is fine
+            let _ = Command::new("iptables")
+                .args(["-F", &chain])
+                .output();
+            let _ = Command::new("iptables")
+                .args(["-X", &chain])
+                .output();
+        }
+        
+        let key = format!("ddos:{}", container_id);
+        let was_enabled = self.db.remove(key.as_bytes())?.is_some();
+        if was_enabled {
+            tracing::info!("Disabled DDoS protection for container: {}", container_id);
+        }
+        Ok(was_enabled)
+    }
+    
+    /// Store and apply an egress limit. Returns whether it is active now, a
+    /// stopped container gets it on its next start.
+    pub async fn set_bandwidth_limit(
+        &self,
+        container_id: &str,
+        limit: BandwidthLimit,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        limit.validate()?;
+        let id = container_id.to_string();
+        let applied = tokio::task::spawn_blocking(move || bandwidth::apply(&id, &limit)).await??;
+
+        let key = format!("bandwidth:{}", container_id);
+        self.db.insert(key.as_bytes(), serde_json::to_vec(&limit)?)?;
+        Ok(applied)
+    }
+
+    /// Stored egress limit, None means unlimited
+    pub async fn get_bandwidth_limit(
+        &self,
+        container_id: &str,
+    ) -> Result<Option<BandwidthLimit>, Box<dyn std::error::Error + Send + Sync>> {
+        let key = format!("bandwidth:{}", container_id);
+        match self.db.get(key.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the egress limit. Returns whether one was set.
+    pub async fn clear_bandwidth_limit(
+        &self,
+        container_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let id = container_id.to_string();
+        tokio::task::spawn_blocking(move || bandwidth::clear(&id)).await??;
+
+        let key = format!("bandwidth:{}", container_id);
+        let was_set = self.db.remove(key.as_bytes())?.is_some();
+        if was_set {
+            tracing::info!("Removed bandwidth limit of container: {}", container_id);
+        }
+        Ok(was_set)
+    }
+
+    /// Apply the stored egress limit again, the container got a new network
+    /// namespace when it started
+    pub async fn restore_bandwidth_limit(
+        &self,
+        container_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(limit) = self.get_bandwidth_limit(container_id).await? else {
+            return Ok(());
+        };
+        let id = container_id.to_string();
+        tokio::task::spawn_blocking(move || bandwidth::apply(&id, &limit)).await??;
+        Ok(())
+    }
+
+    /// Blocklists of one scope, `None` lists the global ones
+    pub async fn list_blocklists(
+        &self,
+        container_id: Option<&str>,
+    ) -> Result<Vec<Blocklist>, Box<dyn std::error::Error + Send + Sync>> {
+        let prefix = Blocklist::key(container_id, "");
+        let mut lists = Vec::new();
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = item?;
+            lists.push(serde_json::from_slice(&value)?);
+        }
+        Ok(lists)
+    }
+    
+    pub async fn get_blocklist(
+        &self,
+        container_id: Option<&str>,
+        name: &str,
+    ) -> Result<Option<Blocklist>, Box<dyn std::error::Error + Send + Sync>> {
+        match self.db.get(Blocklist::key(container_id, name).as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    
+    /// Create or replace a blocklist and apply it. Entries must already be
+    /// normalized with `blocklist::parse_cidr`.
+    pub async fn set_blocklist(
+        &self,
+        container_id: Option<&str>,
+        name: &str,
+        mut cidrs: Vec<String>,
+    ) -> Result<Blocklist, Box<dyn std::error::Error + Send + Sync>> {
+        blocklist::validate_name(name)?;
+        cidrs.sort();
+        cidrs.dedup();
+        
+        if let Some(old) = self.get_blocklist(container_id, name).await? {
+            blocklist::remove(&old, &old.cidrs);
+        }
+        
+        let list = Blocklist {
+            name: name.to_string(),
+            container_id: container_id.map(str::to_string),
+            cidrs,
+            updated_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        };
+        blocklist::apply(&list)?;
+        
+        let key = Blocklist::key(container_id, name);
+        self.db.insert(key.as_bytes(), serde_json::to_vec(&list)?)?;
+        Ok(list)
+    }
+    
+    /// Add entries to a blocklist, creating it if needed
+    pub async fn add_blocklist_entries(
+        &self,
+        container_id: Option<&str>,
+        name: &str,
+        cidrs: Vec<String>,
+    ) -> Result<Blocklist, Box<dyn std::error::Error + Send + Sync>> {
+        let mut merged = self.get_blocklist(container_id, name).await?
+            .map(|list| list.cidrs)
+            .unwrap_or_default();
+        merged.extend(cidrs);
+        self.set_blocklist(container_id, name, merged).await
+    }
+    
+    /// Remove entries from a blocklist, None when the list doesn't exist
+    pub async fn remove_blocklist_entries(
+        &self,
+        container_id: Option<&str>,
+        name: &str,
+        cidrs: &[String],
+    ) -> Result<Option<Blocklist>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(list) = self.get_blocklist(container_id, name).await? else {
+            return Ok(None);
+        };
+        let remaining = list.cidrs.into_iter().filter(|c| !cidrs.contains(c)).collect();
+        Ok(Some(self.set_blocklist(container_id, name, remaining).await?))
+    }
+    
+    /// Remove a blocklist and its rules, returns whether it existed
+    pub async fn delete_blocklist(
+        &self,
+        container_id: Option<&str>,
+        name: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(list) = self.get_blocklist(container_id, name).await? else {
+            return Ok(false);
+        };
+        blocklist::remove(&list, &list.cidrs);
+        self.db.remove(Blocklist::key(container_id, name).as_bytes())?;
+        tracing::info!("Removed blocklist {}", name);
+        Ok(true)
+    }
+    
+    /// Re-apply every stored blocklist, iptables rules don't survive a host reboot
+    pub async fn restore_blocklists(&self) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let mut restored = 0;
+        for item in self.db.scan_prefix(b"blocklist:") {
+            let (_, value) = item?;
+            let list: Blocklist = serde_json::from_slice(&value)?;
+            match blocklist::apply(&list) {
+                Ok(()) => restored += 1,
+                Err(e) => tracing::warn!("Failed to restore blocklist {}: {}", list.name, e),
+            }
+        }
+        Ok(restored)
+    }
+    
+    /// Re-apply a container's blocklists, its rules match the address it has
+    /// while running. Called on start (new address) and stop (rules removed).
+    pub async fn sync_container_blocklists(
+        &self,
+        container_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for list in self.list_blocklists(Some(container_id)).await? {
+            tokio::task::spawn_blocking(move || blocklist::apply(&list)).await??;
+        }
+        Ok(())
+    }
+    
     /// Apply iptables rule
     async fn apply_iptables_rule(
         &self,
@@ -286,7 +584,7 @@ impl FirewallManager {
         // Ensure chain exists
         if add {
             let _ = Command::new("iptables")
-                .args(&["-N", &chain])
+                .args(["-N", &chain])
                 .output();
         }
         
@@ -318,10 +616,7 @@ impl FirewallManager {
         
         // Rate limiting
         if let Some(ref rate) = rule.rate_limit {
-            args.push("-m".to_string());
-            args.push("limit".to_string());
-            args.push("--limit".to_string());
-            args.push(format!("{}/{}", rate.requests, rate.per_seconds));
+            args.extend(rate.match_args(&rule.id));
         }
         
         // Action
@@ -350,12 +645,12 @@ impl FirewallManager {
         
         // Create chain
         let _ = Command::new("iptables")
-            .args(&["-N", &chain])
+            .args(["-N", &chain])
             .output();
         
         // Limit SYN packets
         let output = Command::new("iptables")
-            .args(&[
+            .args([
                 "-A", &chain,
                 "-p", "tcp",
                 "--syn",
@@ -373,7 +668,7 @@ impl FirewallManager {
         
         // Drop excessive SYN packets
         Command::new("iptables")
-            .args(&["-A", &chain, "-p", "tcp", "--syn", "-j", "DROP"])
+            .args(["-A", &chain, "-p", "tcp", "--syn", "-j", "DROP"])
             .output()?;
         
         tracing::info!("Applied SYN flood protection for {}", network_name);
@@ -390,11 +685,11 @@ impl FirewallManager {
         
         // Create chain
         let _ = Command::new("iptables")
-            .args(&["-N", &chain])
+            .args(["-N", &chain])
             .output();
         
         let output = Command::new("iptables")
-            .args(&[
+            .args([
                 "-A", &chain,
                 "-p", "tcp",
                 "-m", "connlimit",
@@ -423,16 +718,15 @@ impl FirewallManager {
         
         // Create chain
         let _ = Command::new("iptables")
-            .args(&["-N", &chain])
+            .args(["-N", &chain])
             .output();
         
+        let mut args = vec!["-A".to_string(), chain.clone()];
+        args.extend(rate.match_args(&chain));
+        args.extend(["-j".to_string(), "ACCEPT".to_string()]);
+        
         let output = Command::new("iptables")
-            .args(&[
-                "-A", &chain,
-                "-m", "limit",
-                "--limit", &format!("{}/{}", rate.requests, rate.per_seconds),
-                "-j", "ACCEPT",
-            ])
+            .args(&args)
             .output()?;
         
         if !output.status.success() {
@@ -442,10 +736,14 @@ impl FirewallManager {
         
         // Drop packets exceeding rate
         Command::new("iptables")
-            .args(&["-A", &chain, "-j", "DROP"])
+            .args(["-A", &chain, "-j", "DROP"])
             .output()?;
         
-        tracing::info!("Applied rate limit for {}", network_name);
+        tracing::info!(
+            "Applied {} rate limit for {}",
+            if rate.per_source_ip { "per source IP" } else { "global" },
+            network_name
+        );
         Ok(())
     }
     
@@ -457,23 +755,19 @@ impl FirewallManager {
         }
         
         // Validate ports are in valid range
-        if let Some(port) = rule.source_port {
-            if port == 0 {
-                return Err("Invalid source port".into());
-            }
+        if let Some(port) = rule.source_port
+            && port == 0 {
+            return Err("Invalid source port".into());
         }
         
-        if let Some(port) = rule.dest_port {
-            if port == 0 {
-                return Err("Invalid destination port".into());
-            }
+        if let Some(port) = rule.dest_port
+            && port == 0 {
+            return Err("Invalid destination port".into());
         }
         
         // Validate rate limit
         if let Some(ref rate) = rule.rate_limit {
-            if rate.requests == 0 || rate.per_seconds == 0 {
-                return Err("Invalid rate limit values".into());
-            }
+            rate.validate()?;
         }
         
         Ok(())
@@ -490,9 +784,14 @@ impl FirewallManager {
             self.remove_rule(&rule.id).await?;
         }
         
-        // Remove DDoS config
-        let key = format!("ddos:{}", container_id);
-        self.db.remove(key.as_bytes())?;
+        // Remove DDoS chains and config
+        self.disable_ddos_protection(container_id).await?;
+        
+        self.clear_bandwidth_limit(container_id).await?;
+        
+        for list in self.list_blocklists(Some(container_id)).await? {
+            self.delete_blocklist(Some(container_id), &list.name).await?;
+        }
         
         // Remove network
         self.remove_container_network(container_id).await?;
@@ -500,10 +799,10 @@ impl FirewallManager {
         // Remove iptables chains
         let chain = format!("LIGHTD-{}", container_id.to_uppercase());
         let _ = Command::new("iptables")
-            .args(&["-F", &chain])
+            .args(["-F", &chain])
             .output();
         let _ = Command::new("iptables")
-            .args(&["-X", &chain])
+            .args(["-X", &chain])
             .output();
         
         tracing::info!("Cleaned up firewall rules for container: {}", container_id);
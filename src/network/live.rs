@@ -0,0 +1,192 @@
+//! Live view of the iptables chains lightd manages
+//!
+//! The stored rules and DDoS config say what should be installed, this reads
+//! back what the kernel actually has, with the packet and byte counters of
+//! every rule. Chains are listed with `-n -v -x` so addresses aren't resolved
+//! and counters are exact rather than rounded to K/M/G.
+
+use serde::Serialize;
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    fn command(self) -> &'static str {
+        match self {
+            Family::Ipv4 => "iptables",
+            Family::Ipv6 => "ip6tables",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LiveRule {
+    pub packets: u64,
+    pub bytes: u64,
+    /// Empty for rules that only count
+    pub target: String,
+    pub protocol: String,
+    pub in_interface: String,
+    pub out_interface: String,
+    pub source: String,
+    pub destination: String,
+    /// Match details iptables prints after the addresses, e.g. `tcp dpt:25565`
+    pub options: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LiveChain {
+    pub family: Family,
+    pub name: String,
+    /// Rules jumping to this chain, 0 means nothing sends traffic through it
+    pub references: Option<u64>,
+    /// Only built-in chains have a policy
+    pub policy: Option<String>,
+    pub rules: Vec<LiveRule>,
+}
+
+/// Chains lightd creates for a container: its rule chain and the DDoS chains
+pub fn container_chains(container_id: &str) -> Vec<String> {
+    let network_name = format!("lightd-net-{}", container_id);
+    let mut chains = vec![format!("LIGHTD-{}", container_id.to_uppercase())];
+    chains.extend(["SYN", "CONN", "RATE"].iter().map(|kind| format!("LIGHTD-{}-{}", kind, network_name)));
+    chains
+}
+
+/// Whether the family's tool is installed and the kernel has its filter
+/// table, hosts without IPv6 often lack both
+pub fn available(family: Family) -> bool {
+    Command::new(family.command())
+        .args(["-L", "INPUT", "-n"])
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+/// List one chain, `Ok(None)` when it doesn't exist in this family
+pub fn list_chain(family: Family, chain: &str) -> Result<Option<LiveChain>, Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new(family.command())
+        .args(["-L", chain, "-n", "-v", "-x"])
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No chain") || stderr.contains("does not exist") {
+            return Ok(None);
+        }
+        return Err(format!("{} -L {} failed: {}", family.command(), chain, stderr.trim()).into());
+    }
+    Ok(parse_chain(family, &String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the output of `iptables -L <chain> -n -v -x`
+pub fn parse_chain(family: Family, output: &str) -> Option<LiveChain> {
+    let mut lines = output.lines();
+    let header = lines.next()?.strip_prefix("Chain ")?;
+    let (name, details) = header.split_once(' ').unwrap_or((header, ""));
+    let details = details.trim().trim_start_matches('(').trim_end_matches(')');
+
+    let references = details
+        .strip_suffix(" references")
+        .or_else(|| details.strip_suffix(" reference"))
+        .and_then(|n| n.parse().ok());
+    let policy = details
+        .strip_prefix("policy ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .map(str::to_string);
+
+    // Skip the column header
+    let rules = lines.skip(1).filter_map(parse_rule).collect();
+
+    Some(LiveChain {
+        family,
+        name: name.to_string(),
+        references,
+        policy,
+        rules,
+    })
+}
+
+fn parse_rule(line: &str) -> Option<LiveRule> {
+    let mut fields = line.split_whitespace().peekable();
+    let packets = fields.next()?.parse().ok()?;
+    let bytes = fields.next()?.parse().ok()?;
+
+    // The target column is blank for counting rules, the protocol comes first then
+    let mut target = fields.next()?.to_string();
+    let protocol = if is_protocol(&target) && fields.peek().is_some_and(|next| is_opt(next) || *next == "*") {
+        std::mem::take(&mut target)
+    } else {
+        fields.next()?.to_string()
+    };
+    // ip6tables leaves the opt column empty
+    if fields.peek().is_some_and(|next| is_opt(next)) {
+        fields.next();
+    }
+
+    let in_interface = fields.next()?.to_string();
+    let out_interface = fields.next()?.to_string();
+    let source = fields.next()?.to_string();
+    let destination = fields.next()?.to_string();
+    let options = fields.collect::<Vec<_>>().join(" ");
+
+    Some(LiveRule {
+        packets,
+        bytes,
+        target,
+        protocol,
+        in_interface,
+        out_interface,
+        source,
+        destination,
+        options,
+    })
+}
+
+fn is_opt(field: &str) -> bool {
+    matches!(field, "--" | "-f" | "!f")
+}
+
+fn is_protocol(field: &str) -> bool {
+    matches!(field, "all" | "tcp" | "udp" | "icmp" | "ipv6-icmp") || field.parse::<u8>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chain() {
+        let output = "\
+Chain LIGHTD-SYN-lightd-net-abc (1 references)
+    pkts      bytes target     prot opt in     out     source               destination
+    1204    72240 ACCEPT     6    --  *      *       0.0.0.0/0            0.0.0.0/0            tcp flags:0x17/0x02 limit: avg 10/sec burst 20
+      37     2220 DROP       tcp  --  *      *       0.0.0.0/0            0.0.0.0/0            tcp flags:0x17/0x02
+       5      300            all  --  *      *       10.0.0.0/8           0.0.0.0/0
+";
+        let chain = parse_chain(Family::Ipv4, output).unwrap();
+        assert_eq!(chain.name, "LIGHTD-SYN-lightd-net-abc");
+        assert_eq!(chain.references, Some(1));
+        assert_eq!(chain.rules.len(), 3);
+        assert_eq!(chain.rules[0].packets, 1204);
+        assert_eq!(chain.rules[0].bytes, 72240);
+        assert_eq!(chain.rules[0].target, "ACCEPT");
+        assert_eq!(chain.rules[0].options, "tcp flags:0x17/0x02 limit: avg 10/sec burst 20");
+        assert_eq!(chain.rules[1].target, "DROP");
+        assert_eq!(chain.rules[2].target, "");
+        assert_eq!(chain.rules[2].source, "10.0.0.0/8");
+
+        let output = "\
+Chain INPUT (policy ACCEPT 0 packets, 0 bytes)
+    pkts      bytes target     prot opt in     out     source               destination
+       3      180 DROP       tcp      *      *       ::/0                 ::/0                 tcp dpt:25565
+";
+        let chain = parse_chain(Family::Ipv6, output).unwrap();
+        assert_eq!(chain.policy.as_deref(), Some("ACCEPT"));
+        assert_eq!(chain.rules[0].source, "::/0");
+        assert_eq!(chain.rules[0].options, "tcp dpt:25565");
+    }
+}
@@ -1,2 +1,5 @@
 pub mod pool;
 pub mod firewall;
+pub mod blocklist;
+pub mod live;
+pub mod bandwidth;
@@ -1,6 +1,8 @@
+use crate::container::state::PortBinding;
 use serde::{Deserialize, Serialize};
 use sled::Db;
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,14 +15,249 @@ pub struct NetworkPort {
     pub created_at: u64,
 }
 
+/// How free ports are picked from the pool
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationStrategy {
+    /// Any free port, scatters allocations
+    #[default]
+    Random,
+    /// Lowest free port first, for predictable port maps
+    Sequential,
+}
+
+/// Check that an address can be bound on this node (wildcard or one of its own IPs)
+pub fn validate_bind_ip(ip: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let addr: std::net::IpAddr = ip
+        .parse()
+        .map_err(|_| format!("Invalid bind IP '{}'", ip))?;
+
+    if addr.is_unspecified() {
+        return Ok(());
+    }
+
+    // Binding only succeeds for addresses assigned to a local interface
+    std::net::UdpSocket::bind((addr, 0))
+        .map(|_| ())
+        .map_err(|_| format!("Bind IP {} is not an address of this node", ip).into())
+}
+
+/// Returned when a host port is held by a process lightd doesn't know about
+#[derive(Debug)]
+pub struct HostPortInUse {
+    pub ip: String,
+    pub port: u16,
+    pub protocol: String,
+}
+
+impl std::fmt::Display for HostPortInUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Port {}:{}/{} is already in use on the host", self.ip, self.port, self.protocol)
+    }
+}
+
+impl std::error::Error for HostPortInUse {}
+
+/// Check that nothing on the host holds `port` by binding it for a moment.
+/// Only "address in use" counts, a permission error on a low port doesn't
+/// say anything about the port.
+pub fn check_host_port_free(ip: &str, port: u16, protocol: &str) -> Result<(), HostPortInUse> {
+    let Ok(addr) = ip.parse::<std::net::IpAddr>() else {
+        return Ok(());
+    };
+    let bound = if protocol.eq_ignore_ascii_case("udp") {
+        std::net::UdpSocket::bind((addr, port)).map(drop)
+    } else {
+        std::net::TcpListener::bind((addr, port)).map(drop)
+    };
+    match bound {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => Err(HostPortInUse {
+            ip: ip.to_string(),
+            port,
+            protocol: protocol.to_lowercase(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Result of a range import
+#[derive(Debug, Clone, Serialize)]
+pub struct PortRangeSummary {
+    pub added: usize,
+    pub skipped: usize,
+}
+
+/// Entry counts of one protocol
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProtocolStats {
+    pub total: usize,
+    pub in_use: usize,
+    pub available: usize,
+}
+
+impl ProtocolStats {
+    fn count(&mut self, port: &NetworkPort) {
+        self.total += 1;
+        if port.in_use {
+            self.in_use += 1;
+        } else {
+            self.available += 1;
+        }
+    }
+}
+
+/// Pool utilization, overall and per protocol
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PoolStats {
+    pub total: usize,
+    pub in_use: usize,
+    pub available: usize,
+    pub tcp: ProtocolStats,
+    pub udp: ProtocolStats,
+}
+
+/// Returned when a range removal hits ports that are allocated to containers
+#[derive(Debug)]
+pub struct PortsInUse {
+    pub ports: Vec<NetworkPort>,
+}
+
+impl std::fmt::Display for PortsInUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} port(s) in the range are in use, nothing was removed", self.ports.len())
+    }
+}
+
+impl std::error::Error for PortsInUse {}
+
+/// Parse a protocol filter, "both" means tcp and udp
+fn parse_protocols(protocol: &str) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    match protocol.to_lowercase().as_str() {
+        "tcp" => Ok(vec!["tcp".to_string()]),
+        "udp" => Ok(vec!["udp".to_string()]),
+        "both" => Ok(vec!["tcp".to_string(), "udp".to_string()]),
+        other => Err(format!("Invalid protocol '{}', must be 'tcp', 'udp' or 'both'", other).into()),
+    }
+}
+
+/// Pool entries claimed for a container that is still being set up.
+/// Dropping it returns them to the pool unless `commit` was called, so every
+/// early return of a create frees what it allocated.
+pub struct PortReservation {
+    pool: Arc<NetworkPool>,
+    ids: Vec<String>,
+}
+
+impl PortReservation {
+    pub fn new(pool: Arc<NetworkPool>) -> Self {
+        Self { pool, ids: Vec::new() }
+    }
+
+    /// Track an entry that was just marked in use
+    pub fn add(&mut self, id: String) {
+        self.ids.push(id);
+    }
+
+    /// Keep the ports, they now belong to the container
+    pub fn commit(mut self) {
+        self.ids.clear();
+    }
+}
+
+impl Drop for PortReservation {
+    fn drop(&mut self) {
+        if self.ids.is_empty() {
+            return;
+        }
+        match self.pool.release_ids(&self.ids) {
+            Ok(released) => tracing::info!("Returned {} reserved port(s) to the pool", released),
+            Err(e) => tracing::error!("Failed to return reserved ports {:?} to the pool: {}", self.ids, e),
+        }
+    }
+}
+
 pub struct NetworkPool {
     db: Arc<Db>,
+    /// Serializes allocations that have to claim several entries at once
+    allocation_lock: Mutex<()>,
+    /// Strategy used when a caller doesn't pick one
+    default_strategy: AllocationStrategy,
+    /// Warn when fewer free ports than this are left for a protocol
+    low_port_threshold: usize,
 }
 
 impl NetworkPool {
     pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let db = sled::open(db_path)?;
-        Ok(Self { db: Arc::new(db) })
+        Ok(Self {
+            db: Arc::new(db),
+            allocation_lock: Mutex::new(()),
+            default_strategy: AllocationStrategy::default(),
+            low_port_threshold: 10,
+        })
+    }
+
+    /// Set the default allocation strategy
+    pub fn with_allocation_strategy(mut self, strategy: AllocationStrategy) -> Self {
+        self.default_strategy = strategy;
+        self
+    }
+
+    pub fn allocation_strategy(&self) -> AllocationStrategy {
+        self.default_strategy
+    }
+
+    /// Free ports per protocol below which allocations log a warning, 0 disables it
+    pub fn with_low_port_threshold(mut self, threshold: usize) -> Self {
+        self.low_port_threshold = threshold;
+        self
+    }
+
+    pub async fn stats(&self) -> Result<PoolStats, Box<dyn std::error::Error + Send + Sync>> {
+        let mut stats = PoolStats::default();
+
+        for port in self.get_all_ports().await? {
+            match port.protocol.as_str() {
+                "tcp" => stats.tcp.count(&port),
+                "udp" => stats.udp.count(&port),
+                _ => {}
+            }
+        }
+
+        stats.total = stats.tcp.total + stats.udp.total;
+        stats.in_use = stats.tcp.in_use + stats.udp.in_use;
+        stats.available = stats.tcp.available + stats.udp.available;
+        Ok(stats)
+    }
+
+    /// Log a warning for each protocol running low on free ports
+    pub async fn warn_if_low(&self) {
+        if self.low_port_threshold == 0 {
+            return;
+        }
+        let stats = match self.stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                tracing::warn!("Failed to read port pool stats: {}", e);
+                return;
+            }
+        };
+
+        for (protocol, counts) in [("tcp", &stats.tcp), ("udp", &stats.udp)] {
+            // Pools without this protocol at all aren't running low
+            if counts.total > 0 && counts.available < self.low_port_threshold {
+                tracing::warn!(
+                    "Port pool is running low: {} of {} {} ports free (threshold {}), add ports before allocations fail",
+                    counts.available, counts.total, protocol, self.low_port_threshold
+                );
+            }
+        }
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
     }
 
     pub async fn add_port(&self, ip: String, port: u16, protocol: Option<String>) -> Result<NetworkPort, Box<dyn std::error::Error + Send + Sync>> {
@@ -29,7 +266,10 @@ impl NetworkPool {
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
 
-        let protocol = protocol.unwrap_or_else(|| "tcp".to_string());
+        let protocol = match protocol {
+            Some(protocol) => crate::container::state::normalize_protocol(&protocol)?,
+            None => "tcp".to_string(),
+        };
 
         let network_port = NetworkPort {
             id: id.clone(),
@@ -44,7 +284,7 @@ impl NetworkPool {
         self.db.insert(id.as_bytes(), serialized)?;
 
         // Try to open port with iptables if available
-        self.open_iptables_port(&ip, port, &protocol).await;
+        Self::open_iptables_port(&ip, port, &protocol).await;
 
         tracing::info!("Added network port {}:{}/{} with ID {}", ip, port, protocol, id);
         Ok(network_port)
@@ -72,18 +312,98 @@ impl NetworkPool {
     }
 
     pub async fn get_random_available(&self) -> Result<Option<NetworkPort>, Box<dyn std::error::Error + Send + Sync>> {
-        let ports = self.get_all_ports().await?;
-        let available: Vec<NetworkPort> = ports.into_iter().filter(|p| !p.in_use).collect();
-        
-        if available.is_empty() {
-            Ok(None)
-        } else {
-            use rand::seq::SliceRandom;
-            let mut rng = rand::thread_rng();
-            Ok(available.choose(&mut rng).cloned())
+        self.get_available(AllocationStrategy::Random, None, None).await
+    }
+
+    /// Pick a free port using the given strategy (does not mark it in use).
+    /// With `bind_ip`, only entries for that address or the wildcard address qualify,
+    /// with `protocol` only entries opened for that protocol.
+    pub async fn get_available(&self, strategy: AllocationStrategy, bind_ip: Option<&str>, protocol: Option<&str>) -> Result<Option<NetworkPort>, Box<dyn std::error::Error + Send + Sync>> {
+        let available = self.get_available_ports().await?
+            .into_iter()
+            .filter(|p| Self::matches_bind_ip(p, bind_ip))
+            .filter(|p| protocol.is_none_or(|protocol| p.protocol == protocol))
+            .collect();
+        Ok(Self::pick(available, strategy, |p| p.port))
+    }
+
+    /// Pick a free port like `get_available` and mark it in use under the
+    /// allocation lock, so two creates can never claim the same entry.
+    pub async fn allocate(&self, strategy: AllocationStrategy, bind_ip: Option<&str>, protocol: Option<&str>) -> Result<Option<NetworkPort>, Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.allocation_lock.lock().await;
+
+        let Some(mut port) = self.get_available(strategy, bind_ip, protocol).await? else {
+            return Ok(None);
+        };
+        port.in_use = true;
+        self.db.insert(port.id.as_bytes(), serde_json::to_vec(&port)?)?;
+
+        tracing::info!("Allocated port {}:{}/{}", port.ip, port.port, port.protocol);
+        Ok(Some(port))
+    }
+
+    fn matches_bind_ip(port: &NetworkPort, bind_ip: Option<&str>) -> bool {
+        match bind_ip {
+            Some(ip) => port.ip == ip || port.ip == "0.0.0.0",
+            None => true,
+        }
+    }
+
+    fn pick<T: Clone>(candidates: Vec<T>, strategy: AllocationStrategy, port_of: impl Fn(&T) -> u16) -> Option<T> {
+        match strategy {
+            AllocationStrategy::Random => {
+                use rand::seq::SliceRandom;
+                let mut rng = rand::thread_rng();
+                candidates.choose(&mut rng).cloned()
+            }
+            AllocationStrategy::Sequential => {
+                candidates.into_iter().min_by_key(port_of)
+            }
         }
     }
 
+    /// Claim a port number that is free on both TCP and UDP (same IP).
+    /// Both entries are marked in use in one batch. Returns (tcp, udp).
+    pub async fn allocate_paired(&self, strategy: AllocationStrategy, bind_ip: Option<&str>) -> Result<Option<(NetworkPort, NetworkPort)>, Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.allocation_lock.lock().await;
+
+        let available: Vec<NetworkPort> = self.get_available_ports().await?
+            .into_iter()
+            .filter(|p| Self::matches_bind_ip(p, bind_ip))
+            .collect();
+        let udp: std::collections::HashMap<(String, u16), NetworkPort> = available
+            .iter()
+            .filter(|p| p.protocol == "udp")
+            .map(|p| ((p.ip.clone(), p.port), p.clone()))
+            .collect();
+
+        let pairs: Vec<(NetworkPort, NetworkPort)> = available
+            .into_iter()
+            .filter(|p| p.protocol == "tcp")
+            .filter_map(|tcp| {
+                udp.get(&(tcp.ip.clone(), tcp.port))
+                    .cloned()
+                    .map(|udp| (tcp, udp))
+            })
+            .collect();
+
+        let (mut tcp, mut udp) = match Self::pick(pairs, strategy, |(tcp, _)| tcp.port) {
+            Some(pair) => pair,
+            None => return Ok(None),
+        };
+
+        tcp.in_use = true;
+        udp.in_use = true;
+
+        let mut batch = sled::Batch::default();
+        batch.insert(tcp.id.as_bytes(), serde_json::to_vec(&tcp)?);
+        batch.insert(udp.id.as_bytes(), serde_json::to_vec(&udp)?);
+        self.db.apply_batch(batch)?;
+
+        tracing::info!("Allocated paired port {}:{} (tcp+udp)", tcp.ip, tcp.port);
+        Ok(Some((tcp, udp)))
+    }
+
     pub async fn mark_in_use(&self, id: &str, in_use: bool) -> Result<NetworkPort, Box<dyn std::error::Error + Send + Sync>> {
         if let Some(mut port) = self.get_port(id).await? {
             port.in_use = in_use;
@@ -101,7 +421,7 @@ impl NetworkPool {
             self.db.remove(id.as_bytes())?;
             
             // Try to close port with iptables if available
-            self.close_iptables_port(&port.ip, port.port, &port.protocol).await;
+            Self::close_iptables_port(&port.ip, port.port, &port.protocol).await;
             
             tracing::info!("Deleted network port {}:{}/{}", port.ip, port.port, port.protocol);
             Ok(port)
@@ -135,18 +455,210 @@ impl NetworkPool {
         Ok(added)
     }
 
+    /// Add every port in `start..=end` in one sled batch, skipping ports already in the pool.
+    /// `protocol` may be "tcp", "udp" or "both".
+    pub async fn add_port_range(
+        &self,
+        ip: String,
+        start: u16,
+        end: u16,
+        protocol: String,
+    ) -> Result<PortRangeSummary, Box<dyn std::error::Error + Send + Sync>> {
+        if start == 0 || start > end {
+            return Err("Invalid port range".into());
+        }
+
+        let protocols = parse_protocols(&protocol)?;
+
+        let existing: std::collections::HashSet<(String, u16, String)> = self
+            .get_all_ports()
+            .await?
+            .into_iter()
+            .map(|p| (p.ip, p.port, p.protocol))
+            .collect();
+
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let mut batch = sled::Batch::default();
+        let mut added = Vec::new();
+        let mut skipped = 0;
+
+        for port in start..=end {
+            for protocol in &protocols {
+                if existing.contains(&(ip.clone(), port, protocol.clone())) {
+                    skipped += 1;
+                    continue;
+                }
+
+                let network_port = NetworkPort {
+                    id: Uuid::new_v4().to_string(),
+                    ip: ip.clone(),
+                    port,
+                    protocol: protocol.clone(),
+                    in_use: false,
+                    created_at,
+                };
+
+                batch.insert(network_port.id.as_bytes(), serde_json::to_vec(&network_port)?);
+                added.push((port, protocol.clone()));
+            }
+        }
+
+        // All or nothing
+        self.db.apply_batch(batch)?;
+
+        tracing::info!(
+            "Added port range {}:{}-{}/{} ({} added, {} skipped)",
+            ip, start, end, protocol, added.len(), skipped
+        );
+
+        let summary = PortRangeSummary {
+            added: added.len(),
+            skipped,
+        };
+
+        // Opening thousands of iptables rules takes a while, don't block the caller
+        tokio::spawn(async move {
+            for (port, protocol) in added {
+                Self::open_iptables_port(&ip, port, &protocol).await;
+            }
+        });
+
+        Ok(summary)
+    }
+
+    /// Remove every pool entry in `start..=end` in one sled batch, on all IPs.
+    /// Nothing is removed if any matching entry is in use, the error lists them.
+    pub async fn remove_port_range(
+        &self,
+        start: u16,
+        end: u16,
+        protocol: &str,
+    ) -> Result<Vec<NetworkPort>, Box<dyn std::error::Error + Send + Sync>> {
+        if start == 0 || start > end {
+            return Err("Invalid port range".into());
+        }
+        let protocols = parse_protocols(protocol)?;
+
+        // Allocations must not claim a port between the check and the batch
+        let _lock = self.allocation_lock.lock().await;
+
+        let (in_use, removable): (Vec<NetworkPort>, Vec<NetworkPort>) = self
+            .get_all_ports()
+            .await?
+            .into_iter()
+            .filter(|p| (start..=end).contains(&p.port) && protocols.contains(&p.protocol))
+            .partition(|p| p.in_use);
+
+        if !in_use.is_empty() {
+            return Err(Box::new(PortsInUse { ports: in_use }));
+        }
+
+        let mut batch = sled::Batch::default();
+        for port in &removable {
+            batch.remove(port.id.as_bytes());
+        }
+        self.db.apply_batch(batch)?;
+
+        tracing::info!(
+            "Removed port range {}-{}/{} ({} entries)",
+            start, end, protocol, removable.len()
+        );
+
+        let closing = removable.clone();
+        tokio::spawn(async move {
+            for port in closing {
+                Self::close_iptables_port(&port.ip, port.port, &port.protocol).await;
+            }
+        });
+
+        Ok(removable)
+    }
+
     pub async fn get_available_ports(&self) -> Result<Vec<NetworkPort>, Box<dyn std::error::Error + Send + Sync>> {
         let ports = self.get_all_ports().await?;
         Ok(ports.into_iter().filter(|p| !p.in_use).collect())
     }
 
-    pub async fn return_port_to_pool(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.mark_in_use(id, false).await?;
-        tracing::info!("Returned port {} to pool", id);
-        Ok(())
+    /// Return the entries a container's `bindings` were allocated from, in one batch.
+    ///
+    /// Bindings carry the id of their entry, so only that entry is freed and the
+    /// same port number on another IP or protocol stays with its owner. Bindings
+    /// stored before the id was recorded free one in-use entry with the same port,
+    /// protocol and IP (or the wildcard address).
+    pub async fn release_ports(&self, bindings: &[PortBinding]) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        if bindings.is_empty() {
+            return Ok(0);
+        }
+
+        let _lock = self.allocation_lock.lock().await;
+
+        let mut ids: Vec<String> = bindings.iter().filter_map(|b| b.pool_id.clone()).collect();
+        let legacy: Vec<&PortBinding> = bindings.iter().filter(|b| b.pool_id.is_none()).collect();
+        if !legacy.is_empty() {
+            let mut in_use: Vec<NetworkPort> = self.get_all_ports().await?
+                .into_iter()
+                .filter(|p| p.in_use && !ids.contains(&p.id))
+                .collect();
+            for binding in legacy {
+                let found = in_use.iter().position(|p| {
+                    p.port == binding.host_port
+                        && p.protocol.eq_ignore_ascii_case(&binding.protocol)
+                        && (p.ip == binding.host_ip || p.ip == "0.0.0.0")
+                });
+                if let Some(index) = found {
+                    ids.push(in_use.remove(index).id);
+                }
+            }
+        }
+
+        let released = self.release_ids(&ids)?;
+        tracing::info!("Released {} pool entries for {} binding(s)", released, bindings.len());
+        Ok(released)
     }
 
-    async fn open_iptables_port(&self, ip: &str, port: u16, protocol: &str) {
+    /// Mark the given entries available again in one batch. Synchronous so
+    /// `PortReservation` can call it on drop.
+    pub fn release_ids(&self, ids: &[String]) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let mut batch = sled::Batch::default();
+        let mut released = 0;
+
+        for id in ids {
+            let Some(data) = self.db.get(id.as_bytes())? else {
+                continue;
+            };
+            let mut port: NetworkPort = serde_json::from_slice(&data)?;
+            if port.in_use {
+                port.in_use = false;
+                batch.insert(id.as_bytes(), serde_json::to_vec(&port)?);
+                released += 1;
+            }
+        }
+
+        self.db.apply_batch(batch)?;
+        Ok(released)
+    }
+
+    /// Build INPUT rule args; the wildcard address means "any destination", so no `-d`
+    fn iptables_port_args(action: &str, ip: &str, port: u16, protocol: &str) -> Vec<String> {
+        let mut args = vec![
+            action.to_string(), "INPUT".to_string(),
+            "-p".to_string(), protocol.to_string(),
+        ];
+        if ip != "0.0.0.0" {
+            args.push("-d".to_string());
+            args.push(ip.to_string());
+        }
+        args.extend([
+            "--dport".to_string(), port.to_string(),
+            "-j".to_string(), "ACCEPT".to_string(),
+        ]);
+        args
+    }
+
+    async fn open_iptables_port(ip: &str, port: u16, protocol: &str) {
         #[cfg(unix)]
         {
             let result = tokio::process::Command::new("which")
@@ -156,13 +668,7 @@ impl NetworkPool {
 
             if result.is_ok() && result.unwrap().status.success() {
                 let cmd_result = tokio::process::Command::new("iptables")
-                    .args(&[
-                        "-A", "INPUT",
-                        "-p", protocol,
-                        "-d", ip,
-                        "--dport", &port.to_string(),
-                        "-j", "ACCEPT"
-                    ])
+                    .args(Self::iptables_port_args("-A", ip, port, protocol))
                     .output()
                     .await;
 
@@ -178,7 +684,7 @@ impl NetworkPool {
         }
     }
 
-    async fn close_iptables_port(&self, ip: &str, port: u16, protocol: &str) {
+    async fn close_iptables_port(ip: &str, port: u16, protocol: &str) {
         #[cfg(unix)]
         {
             let result = tokio::process::Command::new("which")
@@ -188,13 +694,7 @@ impl NetworkPool {
 
             if result.is_ok() && result.unwrap().status.success() {
                 let cmd_result = tokio::process::Command::new("iptables")
-                    .args(&[
-                        "-D", "INPUT",
-                        "-p", protocol,
-                        "-d", ip,
-                        "--dport", &port.to_string(),
-                        "-j", "ACCEPT"
-                    ])
+                    .args(Self::iptables_port_args("-D", ip, port, protocol))
                     .output()
                     .await;
 
@@ -210,3 +710,90 @@ impl NetworkPool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pool with free tcp entries, written directly so no iptables rules are touched
+    fn test_pool(ports: std::ops::RangeInclusive<u16>) -> (Arc<NetworkPool>, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("lightd-pool-{}", Uuid::new_v4()));
+        let pool = NetworkPool::new(path.to_str().unwrap()).unwrap();
+        for port in ports {
+            let entry = NetworkPort {
+                id: Uuid::new_v4().to_string(),
+                ip: "0.0.0.0".to_string(),
+                port,
+                protocol: "tcp".to_string(),
+                in_use: false,
+                created_at: 0,
+            };
+            pool.db.insert(entry.id.as_bytes(), serde_json::to_vec(&entry).unwrap()).unwrap();
+        }
+        (Arc::new(pool), path)
+    }
+
+    async fn reserve_one(pool: &Arc<NetworkPool>, reservation: &mut PortReservation) {
+        let port = pool.allocate(AllocationStrategy::Sequential, None, Some("tcp")).await.unwrap().unwrap();
+        reservation.add(port.id);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_reservation_returns_ports() {
+        let (pool, path) = test_pool(30000..=30003);
+        let before = pool.stats().await.unwrap();
+
+        {
+            // Dropped without commit, as on any early return after assigning ports
+            let mut reservation = PortReservation::new(pool.clone());
+            reserve_one(&pool, &mut reservation).await;
+            reserve_one(&pool, &mut reservation).await;
+            assert_eq!(pool.stats().await.unwrap().in_use, 2);
+        }
+
+        let after = pool.stats().await.unwrap();
+        assert_eq!(after.in_use, before.in_use);
+        assert_eq!(after.available, before.available);
+
+        let mut reservation = PortReservation::new(pool.clone());
+        reserve_one(&pool, &mut reservation).await;
+        reservation.commit();
+        assert_eq!(pool.stats().await.unwrap().in_use, 1);
+
+        drop(pool);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[tokio::test]
+    async fn test_release_frees_only_the_bound_entry() {
+        let (pool, path) = test_pool(30000..=30000);
+        let tcp = pool.allocate(AllocationStrategy::Sequential, None, Some("tcp")).await.unwrap().unwrap();
+        // Same port number on another protocol, owned by a different container
+        let udp = NetworkPort { id: Uuid::new_v4().to_string(), protocol: "udp".to_string(), in_use: true, ..tcp.clone() };
+        pool.db.insert(udp.id.as_bytes(), serde_json::to_vec(&udp).unwrap()).unwrap();
+
+        let binding = PortBinding {
+            container_port: 25565,
+            host_port: 30000,
+            protocol: "tcp".to_string(),
+            host_ip: "0.0.0.0".to_string(),
+            pool_id: Some(tcp.id.clone()),
+        };
+        assert_eq!(pool.release_ports(&[binding]).await.unwrap(), 1);
+        assert!(!pool.get_port(&tcp.id).await.unwrap().unwrap().in_use);
+        assert!(pool.get_port(&udp.id).await.unwrap().unwrap().in_use);
+
+        drop(pool);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_check_host_port_free() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(check_host_port_free("127.0.0.1", port, "tcp").is_err());
+
+        drop(listener);
+        assert!(check_host_port_free("127.0.0.1", port, "tcp").is_ok());
+    }
+}
@@ -2,11 +2,24 @@
 //! 
 //! Sends updates about container status, errors, and configuration to remote.
 
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::Notify;
 
-#[derive(Debug, Clone, Serialize)]
+use super::queue::NotificationQueue;
+use crate::config::config::{RemoteRetryConfig, RemoteTlsConfig};
+
+/// Header carrying the sha256 of a container export sent between nodes
+pub const EXPORT_SHA256_HEADER: &str = "x-export-sha256";
+
+/// Upper bound for streaming an export to another node, volumes can be large
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(12 * 3600);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "event")]
 pub enum RemoteEvent {
     #[serde(rename = "update")]
@@ -30,6 +43,35 @@ pub enum RemoteEvent {
         estimated_cost: f64,
         timestamp: u64,
     },
+    #[serde(rename = "billing_batch")]
+    BillingBatch {
+        entries: Vec<BillingReport>,
+    },
+}
+
+/// One container's usage inside a billing batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BillingReport {
+    pub server: String,
+    pub memory_gb: f64,
+    pub cpu_vcpus: f64,
+    pub storage_gb: f64,
+    pub egress_gb: f64,
+    pub duration_hours: f64,
+    pub estimated_cost: f64,
+    pub timestamp: u64,
+}
+
+impl RemoteEvent {
+    /// Short label for logs
+    fn describe(&self) -> &'static str {
+        match self {
+            RemoteEvent::Update { status: Some(_), .. } => "status update",
+            RemoteEvent::Update { .. } => "error update",
+            RemoteEvent::Billing { .. } => "billing update",
+            RemoteEvent::BillingBatch { .. } => "billing batch",
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,6 +80,100 @@ pub struct HealthResponse {
     pub endpoint: String,
 }
 
+/// Failed remote call, tagged with whether it is worth retrying
+#[derive(Debug)]
+pub struct RemoteError {
+    message: String,
+    transient: bool,
+}
+
+impl RemoteError {
+    fn from_status(status: reqwest::StatusCode) -> Self {
+        Self {
+            message: format!("Remote returned status: {}", status),
+            // 5xx and 429 usually clear up on their own, 4xx won't
+            transient: status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.transient
+    }
+}
+
+impl From<reqwest::Error> for RemoteError {
+    fn from(e: reqwest::Error) -> Self {
+        Self {
+            transient: e.is_timeout() || e.is_connect() || e.is_request(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// Certificates from `remote.tls`, loaded and parsed
+pub struct RemoteTls {
+    roots: Vec<reqwest::Certificate>,
+    identity: Option<reqwest::Identity>,
+}
+
+/// Every certificate block of a PEM bundle, `Certificate::from_pem` takes one
+fn pem_certificates(pem: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    pem.split_inclusive(END)
+        .filter_map(|chunk| chunk.find("-----BEGIN CERTIFICATE-----").map(|start| &chunk[start..]))
+        .filter(|block| block.ends_with(END))
+        .map(str::to_string)
+        .collect()
+}
+
+impl RemoteTls {
+    /// Read and parse the configured files, naming the one that failed
+    pub fn load(config: &RemoteTlsConfig) -> Result<Self, String> {
+        let read = |field: &str, path: &str| {
+            std::fs::read_to_string(path).map_err(|e| format!("{} '{}' can't be read: {}", field, path, e))
+        };
+
+        let mut roots = Vec::new();
+        if let Some(path) = &config.ca_cert {
+            let blocks = pem_certificates(&read("ca_cert", path)?);
+            if blocks.is_empty() {
+                return Err(format!("ca_cert '{}' has no PEM certificate", path));
+            }
+            for block in blocks {
+                roots.push(reqwest::Certificate::from_pem(block.as_bytes())
+                    .map_err(|e| format!("ca_cert '{}' is not a valid certificate: {}", path, e))?);
+            }
+        }
+
+        let identity = match (&config.client_cert, &config.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = read("client_cert", cert_path)?;
+                let key = read("client_key", key_path)?;
+                if key.contains("BEGIN RSA PRIVATE KEY") || key.contains("BEGIN EC PRIVATE KEY") {
+                    return Err(format!(
+                        "client_key '{}' must be PKCS#8 (BEGIN PRIVATE KEY), convert it with `openssl pkcs8 -topk8 -nocrypt`",
+                        key_path
+                    ));
+                }
+                Some(reqwest::Identity::from_pkcs8_pem(cert.as_bytes(), key.as_bytes())
+                    .map_err(|e| format!("client_cert '{}' and client_key '{}' don't form a usable identity: {}", cert_path, key_path, e))?)
+            }
+            (None, None) => None,
+            _ => return Err("client_cert and client_key must be set together".to_string()),
+        };
+
+        Ok(Self { roots, identity })
+    }
+}
+
 pub struct RemoteClient {
     url: String,
     token: String,
@@ -57,6 +193,26 @@ impl RemoteClient {
             client,
         }
     }
+
+    /// Client that trusts only the configured CA (if any) and presents the
+    /// client certificate (if any)
+    pub fn with_tls(url: String, token: String, tls: RemoteTls) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(10));
+        if !tls.roots.is_empty() {
+            builder = builder.tls_built_in_root_certs(false);
+            for root in tls.roots {
+                builder = builder.add_root_certificate(root);
+            }
+        }
+        if let Some(identity) = tls.identity {
+            builder = builder.identity(identity);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("Failed to build the remote TLS client: {}", e))?;
+
+        Ok(Self { url, token, client })
+    }
     
     /// Check if remote is healthy and active
     pub async fn check_health(&self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
@@ -77,73 +233,10 @@ impl RemoteClient {
         Ok(health.status == 200 && health.endpoint == "active")
     }
     
-    /// Send status update to remote
-    pub async fn send_status_update(
-        &self,
-        internal_id: &str,
-        status: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let event = RemoteEvent::Update {
-            server: internal_id.to_string(),
-            status: Some(status.to_string()),
-            error: None,
-            data: None,
-        };
-        
-        self.send_event(event).await
-    }
-    
-    /// Send error update to remote
-    pub async fn send_error_update(
-        &self,
-        internal_id: &str,
-        error: &str,
-        data: Option<String>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let event = RemoteEvent::Update {
-            server: internal_id.to_string(),
-            status: None,
-            error: Some(error.to_string()),
-            data,
-        };
-        
-        self.send_event(event).await
-    }
-    
-    /// Send billing usage update to remote
-    pub async fn send_billing_update(
-        &self,
-        internal_id: &str,
-        memory_gb: f64,
-        cpu_vcpus: f64,
-        storage_gb: f64,
-        egress_gb: f64,
-        duration_hours: f64,
-        estimated_cost: f64,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let event = RemoteEvent::Billing {
-            server: internal_id.to_string(),
-            memory_gb,
-            cpu_vcpus,
-            storage_gb,
-            egress_gb,
-            duration_hours,
-            estimated_cost,
-            timestamp,
-        };
-        
-        self.send_event(event).await
-    }
-    
     /// Send generic event to remote
-    async fn send_event(
+    pub async fn send_event(
         &self,
-        event: RemoteEvent,
+        event: &RemoteEvent,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let update_url = format!("{}/update", self.url);
         
@@ -151,12 +244,13 @@ impl RemoteClient {
             .post(&update_url)
             .header("Authorization", format!("Bearer {}", self.token))
             .header("Content-Type", "application/json")
-            .json(&event)
+            .json(event)
             .send()
-            .await?;
+            .await
+            .map_err(RemoteError::from)?;
         
         if !response.status().is_success() {
-            return Err(format!("Remote returned status: {}", response.status()).into());
+            return Err(RemoteError::from_status(response.status()).into());
         }
         
         tracing::debug!("Sent event to remote: {:?}", event);
@@ -204,35 +298,235 @@ impl RemoteClient {
         
         Ok(())
     }
+
+    /// Stream a container export to the import endpoint of another lightd
+    /// node and return its response. Uses the shared HTTP client, with the
+    /// short default timeout lifted for this request.
+    pub async fn send_export(
+        &self,
+        node_url: &str,
+        node_token: &str,
+        body: reqwest::Body,
+        size: u64,
+        sha256: &str,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error + Send + Sync>> {
+        let import_url = format!("{}/containers/import", node_url.trim_end_matches('/'));
+
+        let response = self.client
+            .post(&import_url)
+            .header("Authorization", format!("Bearer {}", node_token))
+            .header("Accept", "Application/vnd.pkglatv1+json")
+            .header("Content-Type", "application/x-tar")
+            .header("Content-Length", size)
+            .header(EXPORT_SHA256_HEADER, sha256)
+            .timeout(TRANSFER_TIMEOUT)
+            .body(body)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body: serde_json::Value = response.json().await.unwrap_or(serde_json::Value::Null);
+        if !status.is_success() {
+            let error = body.get("error").and_then(|e| e.as_str()).unwrap_or("no error message");
+            return Err(format!("Target node returned {}: {}", status, error).into());
+        }
+        Ok(body)
+    }
+}
+
+/// Delay before retry number `attempt` (1-based): exponential, capped, with jitter
+fn backoff_delay(policy: &RemoteRetryConfig, attempt: u32) -> Duration {
+    let exp = policy
+        .initial_delay_ms
+        .saturating_mul(1u64 << attempt.saturating_sub(1).min(20));
+    let capped = exp.min(policy.max_delay_ms);
+    // Random point in the upper half so bursts of failures don't retry in lockstep
+    let jittered = rand::thread_rng().gen_range(capped / 2..=capped);
+    Duration::from_millis(jittered)
+}
+
+/// Final outcome of sending one notification
+enum Delivery {
+    Sent,
+    /// Remote refused it for a non-transient reason, retrying won't help
+    Rejected,
+    /// Still failing with transient errors after all attempts
+    Deferred,
+}
+
+/// Run `send` until it succeeds, fails permanently, or runs out of attempts
+async fn send_with_retry<F, Fut>(policy: &RemoteRetryConfig, what: &str, mut send: F) -> Delivery
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), Box<dyn std::error::Error + Send + Sync>>>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        let e = match send().await {
+            Ok(()) => return Delivery::Sent,
+            Err(e) => e,
+        };
+
+        let transient = e
+            .downcast_ref::<RemoteError>()
+            .map(|r| r.is_transient())
+            .unwrap_or(false);
+
+        if !transient {
+            tracing::error!("Remote rejected {}, dropping it: {}", what, e);
+            return Delivery::Rejected;
+        }
+        if attempt >= max_attempts {
+            tracing::warn!("Remote {} still failing after {} attempt(s): {}", what, attempt, e);
+            return Delivery::Deferred;
+        }
+
+        let delay = backoff_delay(policy, attempt);
+        tracing::warn!(
+            "Remote {} failed (attempt {}/{}), retrying in {}ms: {}",
+            what, attempt, max_attempts, delay.as_millis(), e
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+pub const DEFAULT_BILLING_BATCH_SIZE: usize = 100;
+
+/// Failed contacts in a row before the remote counts as unhealthy, so one
+/// dropped request doesn't flip readiness
+const UNHEALTHY_AFTER_FAILURES: u32 = 3;
+
+/// How the last contacts with the remote went, health checks and deliveries alike
+#[derive(Default)]
+struct SyncHealth {
+    /// Unix time of the last successful contact, 0 before the first one
+    last_ok_at: AtomicU64,
+    consecutive_failures: AtomicU32,
+    last_error: Mutex<Option<String>>,
+}
+
+impl SyncHealth {
+    fn record_ok(&self) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.last_ok_at.store(now, Ordering::SeqCst);
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        *self.last_error.lock().unwrap() = None;
+    }
+
+    fn record_failure(&self, error: String) {
+        self.consecutive_failures.fetch_add(1, Ordering::SeqCst);
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+}
+
+/// Whether status and billing updates are reaching the remote
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteStatus {
+    pub healthy: bool,
+    /// Unix time of the last successful health check or delivery
+    pub last_ok_at: Option<u64>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    /// Notifications waiting in the offline queue
+    pub queued: usize,
 }
 
 /// Remote sync manager that handles background syncing
 pub struct RemoteSyncManager {
     client: Arc<RemoteClient>,
+    retry: Arc<RemoteRetryConfig>,
+    /// Durable backlog; when set, notifications are queued and sent by the drain task
+    queue: Option<Arc<NotificationQueue>>,
+    /// Wakes the drain task
+    drain_signal: Arc<Notify>,
+    /// Last known remote health, draining pauses while false
+    online: Arc<AtomicBool>,
+    health: Arc<SyncHealth>,
+    billing_batch_size: usize,
 }
 
 impl RemoteSyncManager {
     pub fn new(url: String, token: String) -> Self {
         Self {
             client: Arc::new(RemoteClient::new(url, token)),
+            retry: Arc::new(RemoteRetryConfig::default()),
+            queue: None,
+            drain_signal: Arc::new(Notify::new()),
+            online: Arc::new(AtomicBool::new(true)),
+            health: Arc::new(SyncHealth::default()),
+            billing_batch_size: DEFAULT_BILLING_BATCH_SIZE,
+        }
+    }
+
+    /// Talk to the remote with a private CA and/or a client certificate
+    pub fn with_tls(mut self, tls: &RemoteTlsConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let loaded = RemoteTls::load(tls)?;
+        self.client = Arc::new(RemoteClient::with_tls(self.client.url.clone(), self.client.token.clone(), loaded)?);
+        Ok(self)
+    }
+
+    /// Set the retry policy used for notifications
+    pub fn with_retry_policy(mut self, retry: RemoteRetryConfig) -> Self {
+        self.retry = Arc::new(retry);
+        self
+    }
+
+    /// Set the maximum number of reports per billing batch request
+    pub fn with_billing_batch_size(mut self, size: usize) -> Self {
+        self.billing_batch_size = size.max(1);
+        self
+    }
+
+    /// Persist notifications in a sled queue so they survive remote outages and restarts
+    pub fn with_offline_queue(mut self, db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let queue = NotificationQueue::new(db_path)?;
+        if !queue.is_empty() {
+            tracing::info!("Found {} queued remote notification(s) from a previous run", queue.len());
         }
+        self.queue = Some(Arc::new(queue));
+        Ok(self)
     }
     
-    /// Start health check loop
+    /// Start health check loop (and the queue drain task, if the queue is enabled)
     pub fn start_health_check(&self) {
+        if let Some(queue) = &self.queue {
+            self.start_drain_task(queue.clone());
+        }
+
         let client = self.client.clone();
+        let queue = self.queue.clone();
+        let drain_signal = self.drain_signal.clone();
+        let online = self.online.clone();
+        let health = self.health.clone();
         
         tokio::spawn(async move {
             loop {
                 match client.check_health().await {
                     Ok(true) => {
                         tracing::debug!("Remote health check: OK");
+                        health.record_ok();
+                        if !online.swap(true, Ordering::SeqCst) {
+                            tracing::info!("Remote is reachable again");
+                        }
+                        if queue.as_ref().is_some_and(|q| !q.is_empty()) {
+                            drain_signal.notify_one();
+                        }
                     }
                     Ok(false) => {
                         tracing::warn!("Remote health check: Failed");
+                        health.record_failure("Health check reported the remote inactive".to_string());
+                        online.store(false, Ordering::SeqCst);
                     }
                     Err(e) => {
                         tracing::error!("Remote health check error: {}", e);
+                        health.record_failure(format!("Health check failed: {}", e));
+                        online.store(false, Ordering::SeqCst);
                     }
                 }
                 
@@ -241,30 +535,123 @@ impl RemoteSyncManager {
             }
         });
     }
-    
-    /// Send status update (non-blocking)
-    pub fn notify_status(&self, internal_id: String, status: String) {
+
+    fn start_drain_task(&self, queue: Arc<NotificationQueue>) {
         let client = self.client.clone();
-        
+        let retry = self.retry.clone();
+        let drain_signal = self.drain_signal.clone();
+        let online = self.online.clone();
+        let health = self.health.clone();
+
+        // Resume whatever was left in the queue before a restart
+        drain_signal.notify_one();
+
         tokio::spawn(async move {
-            if let Err(e) = client.send_status_update(&internal_id, &status).await {
-                tracing::error!("Failed to send status update to remote: {}", e);
+            loop {
+                drain_signal.notified().await;
+                Self::drain(&client, &retry, &queue, &online, &health).await;
             }
         });
     }
-    
-    /// Send error update (non-blocking)
-    pub fn notify_error(&self, internal_id: String, error: String, data: Option<String>) {
+
+    /// Send queued notifications oldest first. Stops at the first one the remote
+    /// can't take, so later updates for a container never overtake earlier ones.
+    async fn drain(
+        client: &RemoteClient,
+        retry: &RemoteRetryConfig,
+        queue: &NotificationQueue,
+        online: &AtomicBool,
+        health: &SyncHealth,
+    ) {
+        while online.load(Ordering::SeqCst) {
+            let (id, event) = match queue.front() {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!("Failed to read remote notification queue: {}", e);
+                    break;
+                }
+            };
+
+            let delivery = send_with_retry(retry, event.describe(), || client.send_event(&event)).await;
+            if let Delivery::Sent = delivery {
+                health.record_ok();
+            }
+            match delivery {
+                Delivery::Sent | Delivery::Rejected => {
+                    if let Err(e) = queue.remove(id) {
+                        tracing::error!("Failed to remove delivered remote notification: {}", e);
+                        break;
+                    }
+                }
+                Delivery::Deferred => {
+                    health.record_failure(format!("Could not deliver {}", event.describe()));
+                    tracing::warn!(
+                        "Remote unreachable, holding {} notification(s) until it recovers",
+                        queue.len()
+                    );
+                    online.store(false, Ordering::SeqCst);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Queue a notification, or send it directly when there is no queue
+    fn dispatch(&self, event: RemoteEvent) {
+        if let Some(queue) = &self.queue {
+            match queue.push(&event) {
+                Ok(()) => {
+                    if self.online.load(Ordering::SeqCst) {
+                        self.drain_signal.notify_one();
+                    }
+                    return;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to queue remote notification, sending directly: {}", e);
+                }
+            }
+        }
+
         let client = self.client.clone();
+        let retry = self.retry.clone();
+        let health = self.health.clone();
         
         tokio::spawn(async move {
-            if let Err(e) = client.send_error_update(&internal_id, &error, data).await {
-                tracing::error!("Failed to send error update to remote: {}", e);
+            let what = event.describe();
+            match send_with_retry(&retry, what, || client.send_event(&event)).await {
+                Delivery::Sent => health.record_ok(),
+                Delivery::Rejected => {}
+                Delivery::Deferred => {
+                    health.record_failure(format!("Could not deliver {}", what));
+                    tracing::error!("Abandoning remote {}", what);
+                }
             }
         });
     }
     
+    /// Send status update (non-blocking)
+    pub fn notify_status(&self, internal_id: String, status: String) {
+        self.dispatch(RemoteEvent::Update {
+            server: internal_id,
+            status: Some(status),
+            error: None,
+            data: None,
+        });
+    }
+    
+    /// Send error update (non-blocking)
+    pub fn notify_error(&self, internal_id: String, error: String, data: Option<String>) {
+        self.dispatch(RemoteEvent::Update {
+            server: internal_id,
+            status: None,
+            error: Some(error),
+            data,
+        });
+    }
+    
     /// Send billing usage update (non-blocking)
+    #[allow(unused, clippy::too_many_arguments)]
     pub fn notify_billing(
         &self,
         internal_id: String,
@@ -275,26 +662,108 @@ impl RemoteSyncManager {
         duration_hours: f64,
         estimated_cost: f64,
     ) {
-        let client = self.client.clone();
-        
-        tokio::spawn(async move {
-            if let Err(e) = client.send_billing_update(
-                &internal_id,
-                memory_gb,
-                cpu_vcpus,
-                storage_gb,
-                egress_gb,
-                duration_hours,
-                estimated_cost,
-            ).await {
-                tracing::error!("Remote: {}", e);
+        let timestamp = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_secs(),
+            Err(e) => {
+                tracing::error!("Not sending billing update of {}, system clock is before 1970: {}", internal_id, e);
+                return;
             }
+        };
+
+        self.dispatch(RemoteEvent::Billing {
+            server: internal_id,
+            memory_gb,
+            cpu_vcpus,
+            storage_gb,
+            egress_gb,
+            duration_hours,
+            estimated_cost,
+            timestamp,
         });
     }
     
-    #[allow(unused)]
-    /// Get client for direct access
-    pub fn client(&self) -> Arc<RemoteClient> {
-        self.client.clone()
+    /// Send usage for many containers in as few requests as possible (non-blocking).
+    /// Reports are split into chunks of at most `billing_batch_size`.
+    pub fn notify_billing_batch(&self, reports: Vec<BillingReport>) {
+        for chunk in reports.chunks(self.billing_batch_size) {
+            self.dispatch(RemoteEvent::BillingBatch {
+                entries: chunk.to_vec(),
+            });
+        }
+    }
+    
+    /// Health of the connection to the remote, for `/remote/status` and readiness
+    pub fn status(&self) -> RemoteStatus {
+        let consecutive_failures = self.health.consecutive_failures.load(Ordering::SeqCst);
+        RemoteStatus {
+            healthy: consecutive_failures < UNHEALTHY_AFTER_FAILURES,
+            last_ok_at: Some(self.health.last_ok_at.load(Ordering::SeqCst)).filter(|t| *t > 0),
+            consecutive_failures,
+            last_error: self.health.last_error.lock().unwrap().clone(),
+            queued: self.queue.as_ref().map_or(0, |q| q.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RemoteRetryConfig {
+            max_attempts: 10,
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+        };
+
+        let first = backoff_delay(&policy, 1).as_millis();
+        assert!((50..=100).contains(&first));
+
+        let third = backoff_delay(&policy, 3).as_millis();
+        assert!((200..=400).contains(&third));
+
+        let capped = backoff_delay(&policy, 30).as_millis();
+        assert!((500..=1000).contains(&capped));
+    }
+
+    #[test]
+    fn test_tls_config_errors_name_the_file() {
+        let bundle = "# corp CA\n-----BEGIN CERTIFICATE-----\nAAA\n-----END CERTIFICATE-----\n\n-----BEGIN CERTIFICATE-----\nBBB\n-----END CERTIFICATE-----\n";
+        let blocks = pem_certificates(bundle);
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks[1].starts_with("-----BEGIN CERTIFICATE-----\nBBB"));
+
+        let missing = RemoteTlsConfig {
+            ca_cert: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        let err = RemoteTls::load(&missing).err().unwrap();
+        assert!(err.starts_with("ca_cert '/nonexistent/ca.pem' can't be read"));
+
+        let half = RemoteTlsConfig {
+            client_cert: Some("/etc/lightd/client.pem".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(RemoteTls::load(&half).err().unwrap(), "client_cert and client_key must be set together");
+    }
+
+    #[test]
+    fn test_status_turns_unhealthy_after_repeated_failures() {
+        let sync = RemoteSyncManager::new("http://127.0.0.1:1".to_string(), String::new());
+        assert!(sync.status().healthy);
+        assert_eq!(sync.status().last_ok_at, None);
+
+        for _ in 0..UNHEALTHY_AFTER_FAILURES {
+            sync.health.record_failure("connection refused".to_string());
+        }
+        let status = sync.status();
+        assert!(!status.healthy);
+        assert_eq!(status.consecutive_failures, UNHEALTHY_AFTER_FAILURES);
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+
+        sync.health.record_ok();
+        let status = sync.status();
+        assert!(status.healthy && status.last_ok_at.is_some() && status.last_error.is_none());
     }
 }
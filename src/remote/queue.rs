@@ -0,0 +1,98 @@
+//! Durable queue for remote notifications
+//!
+//! Notifications are written here before being sent so an unreachable remote
+//! (or a daemon restart) doesn't lose them. Keys are sled-generated ids stored
+//! big-endian, so iteration order is insertion order.
+
+use sled::Db;
+
+use super::client::RemoteEvent;
+
+pub struct NotificationQueue {
+    db: Db,
+}
+
+impl NotificationQueue {
+    pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db })
+    }
+
+    /// Append a notification to the end of the queue
+    pub fn push(&self, event: &RemoteEvent) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let id = self.db.generate_id()?;
+        let serialized = serde_json::to_vec(event)?;
+        self.db.insert(id.to_be_bytes(), serialized)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Oldest queued notification, if any
+    pub fn front(&self) -> Result<Option<(u64, RemoteEvent)>, Box<dyn std::error::Error + Send + Sync>> {
+        while let Some((key, value)) = self.db.first()? {
+            let id = u64::from_be_bytes(key.as_ref().try_into()?);
+            match serde_json::from_slice(&value) {
+                Ok(event) => return Ok(Some((id, event))),
+                Err(e) => {
+                    // An unreadable entry would block the queue forever
+                    tracing::error!("Dropping corrupted remote notification {}: {}", id, e);
+                    self.db.remove(key)?;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove a notification once it has been delivered or rejected
+    pub fn remove(&self, id: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.remove(id.to_be_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(server: &str, status: &str) -> RemoteEvent {
+        RemoteEvent::Update {
+            server: server.to_string(),
+            status: Some(status.to_string()),
+            error: None,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_queue_is_fifo_and_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("lightd-queue-{}", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        {
+            let queue = NotificationQueue::new(path).unwrap();
+            queue.push(&status("srv", "installing")).unwrap();
+            queue.push(&status("srv", "ready")).unwrap();
+        }
+
+        let queue = NotificationQueue::new(path).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        let (id, event) = queue.front().unwrap().unwrap();
+        assert!(matches!(event, RemoteEvent::Update { status: Some(ref s), .. } if s == "installing"));
+        queue.remove(id).unwrap();
+
+        let (_, event) = queue.front().unwrap().unwrap();
+        assert!(matches!(event, RemoteEvent::Update { status: Some(ref s), .. } if s == "ready"));
+
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,67 @@
+//! Audit log routes
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::auth::audit::{AuditEntry, AuditFilter, AuditLog};
+
+/// Default and maximum entries per request
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    /// Internal ID, only entries for requests under `/containers/<id>`
+    container: Option<String>,
+    /// Unix seconds, inclusive
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct AuditResponse {
+    entries: Vec<AuditEntry>,
+}
+
+pub fn audit_router(audit: Arc<AuditLog>) -> Router {
+    Router::new()
+        .route("/audit", get(list_audit))
+        .with_state(audit)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse { error: message.into() })).into_response()
+}
+
+/// Newest entries first
+async fn list_audit(
+    State(audit): State<Arc<AuditLog>>,
+    Query(query): Query<AuditQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return error(StatusCode::BAD_REQUEST, format!("limit must be between 1 and {}", MAX_LIMIT));
+    }
+
+    let filter = AuditFilter {
+        container: query.container,
+        since: query.since,
+        limit,
+    };
+    match audit.query(&filter) {
+        Ok(entries) => (StatusCode::OK, Json(AuditResponse { entries })).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
@@ -1,20 +1,23 @@
 //! Authentication routes for token generation
 
 use axum::{
-    extract::State,
+    extract::{ConnectInfo, Path, State},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+use crate::auth::rate_limit::RateLimiter;
 use crate::auth::tokens::TokenManager;
 
 #[derive(Clone)]
 pub struct AuthState {
     pub token_manager: Arc<TokenManager>,
+    pub rate_limiter: Arc<RateLimiter>,
 }
 
 #[derive(Deserialize)]
@@ -31,33 +34,59 @@ struct GenerateTokenResponse {
     token: String,
 }
 
+#[derive(Deserialize)]
+struct RefreshTokenRequest {
+    /// Current, non-expired token
+    token: String,
+    /// Optional TTL for the new token, defaults to the original TTL
+    ttl: Option<String>,
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
 }
 
-pub fn auth_router(token_manager: Arc<TokenManager>) -> Router {
-    let state = AuthState { token_manager };
+/// Token as listed to admins, without the secret
+#[derive(Serialize)]
+struct TokenInfo {
+    id: String,
+    created_at: u64,
+    expires_at: u64,
+    remove_on_use: bool,
+}
+
+pub fn auth_router(token_manager: Arc<TokenManager>, rate_limiter: Arc<RateLimiter>) -> Router {
+    let state = AuthState { token_manager, rate_limiter };
     
     Router::new()
         .route("/auth/tokens", post(generate_token))
+        .route("/auth/token/refresh", post(refresh_token))
         .with_state(state)
 }
 
+/// Listing and revoking tokens, needs the API token
+pub fn token_admin_router(token_manager: Arc<TokenManager>) -> Router {
+    Router::new()
+        .route("/auth/tokens", get(list_tokens))
+        .route("/auth/tokens/:id", delete(revoke_token))
+        .with_state(token_manager)
+}
+
 /// Parse TTL string (e.g., "15m", "1h", "30s")
 fn parse_ttl(ttl: &str) -> Result<u64, String> {
     let ttl = ttl.trim();
     
-    if ttl.ends_with('m') {
-        let minutes: u64 = ttl[..ttl.len()-1].parse()
+    if let Some(minutes) = ttl.strip_suffix('m') {
+        let minutes: u64 = minutes.parse()
             .map_err(|_| "Invalid TTL format")?;
         Ok(minutes * 60)
-    } else if ttl.ends_with('h') {
-        let hours: u64 = ttl[..ttl.len()-1].parse()
+    } else if let Some(hours) = ttl.strip_suffix('h') {
+        let hours: u64 = hours.parse()
             .map_err(|_| "Invalid TTL format")?;
         Ok(hours * 3600)
-    } else if ttl.ends_with('s') {
-        ttl[..ttl.len()-1].parse()
+    } else if let Some(seconds) = ttl.strip_suffix('s') {
+        seconds.parse()
             .map_err(|_| "Invalid TTL format".to_string())
     } else {
         // Assume seconds if no suffix
@@ -95,3 +124,101 @@ async fn generate_token(
         }
     }
 }
+
+async fn refresh_token(
+    State(state): State<AuthState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(payload): Json<RefreshTokenRequest>,
+) -> Response {
+    if state.rate_limiter.is_limited(addr.ip()) {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ErrorResponse {
+                error: "Too many failed authentication attempts, try again later".to_string(),
+            }),
+        ).into_response();
+    }
+    
+    let ttl_seconds = match payload.ttl.as_deref().map(parse_ttl) {
+        Some(Ok(s)) => Some(s),
+        Some(Err(e)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse { error: e }),
+            ).into_response();
+        }
+        None => None,
+    };
+    
+    match state.token_manager.refresh_token(&payload.token, ttl_seconds) {
+        Ok(Some(token)) => {
+            (StatusCode::OK, Json(GenerateTokenResponse { token })).into_response()
+        }
+        Ok(None) => {
+            state.rate_limiter.record_failure(addr.ip());
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse {
+                    error: "Invalid or expired token".to_string(),
+                }),
+            ).into_response()
+        }
+        Err(e) => {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response()
+        }
+    }
+}
+
+async fn list_tokens(State(token_manager): State<Arc<TokenManager>>) -> Response {
+    match token_manager.list_tokens() {
+        Ok(tokens) => {
+            let tokens: Vec<TokenInfo> = tokens.into_iter()
+                .map(|t| TokenInfo {
+                    id: t.id,
+                    created_at: t.created_at,
+                    expires_at: t.expires_at,
+                    remove_on_use: t.remove_on_use,
+                })
+                .collect();
+            (StatusCode::OK, Json(tokens)).into_response()
+        }
+        Err(e) => {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response()
+        }
+    }
+}
+
+async fn revoke_token(
+    State(token_manager): State<Arc<TokenManager>>,
+    Path(id): Path<String>,
+) -> Response {
+    match token_manager.revoke_token(&id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Token not found".to_string(),
+                }),
+            ).into_response()
+        }
+        Err(e) => {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response()
+        }
+    }
+}
@@ -1,19 +1,40 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Json, Response},
     routing::{get, post, delete},
     Router,
 };
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
 
-use crate::container::lifecycle::LifecycleManager;
-use crate::container::manager::ContainerManager;
-use crate::container::power::{PowerManager, PowerAction};
-use crate::container::network::NetworkRebinder;
-use crate::container::state::{InstallState, PortBinding};
-use crate::container::update::{ContainerUpdater, ResourceLimits};
+use crate::container::export;
+use crate::container::idempotency::{IdempotencyClaim, IdempotencyStore, StoredResponse};
+use crate::container::lifecycle::{DockerInspect, LifecycleManager};
+use crate::container::logs;
+use crate::container::manager::{ContainerFilter, ContainerManager};
+use crate::container::power::{normalize_signal, PowerManager, PowerAction, PowerBusy, Readiness};
+use crate::container::images::InstallRunning;
+use crate::container::network::{ContainerRunning, NetworkRebinder};
+use crate::container::state::{normalize_protocol, parse_run_as, validate_env_key, validate_label_key, validate_pids_limit, validate_start_patterns, ContainerState, InstallState, LogRotation, PortBinding, Ulimits};
+use crate::container::template::{ContainerTemplate, CreateLimits, PortRequest, TemplateManager};
+use crate::container::transfer::{validate_target_url, TransferManager};
+use crate::container::update::{split_mounts, ContainerUpdater, MountSpec, ResourceLimits};
+use crate::container::uptime::UptimeTracker;
+use crate::config::config::HttpLimitsConfig;
+use crate::daemon::maintenance::Maintenance;
+use crate::error;
+use crate::filesystem::handler::VolumeHandler;
+use crate::network::pool::{check_host_port_free, validate_bind_ip, AllocationStrategy, HostPortInUse, PortReservation};
+use crate::remote::client::EXPORT_SHA256_HEADER;
+use crate::router::limits;
+use crate::sftp::credentials::CredentialsManager;
+use crate::websocket::{ConsoleStreamer, ContainerRuntimeState, EventHub, StatsCollector};
 use std::collections::HashMap;
 
 #[derive(Clone)]
@@ -23,6 +44,19 @@ pub struct ContainerAppState {
     pub power: Arc<PowerManager>,
     pub network: Arc<NetworkRebinder>,
     pub pool: Arc<crate::network::pool::NetworkPool>,
+    pub updater: Arc<ContainerUpdater>,
+    pub credentials: Arc<CredentialsManager>,
+    pub idempotency: Arc<IdempotencyStore>,
+    pub stats: Arc<StatsCollector>,
+    pub maintenance: Arc<Maintenance>,
+    pub transfers: Arc<TransferManager>,
+    pub templates: Arc<TemplateManager>,
+    /// Watches the console for start patterns while a start waits for readiness
+    pub console: Arc<ConsoleStreamer>,
+    pub event_hub: Arc<EventHub>,
+    /// Imports register their volume here, exports read its quota
+    pub volumes: Arc<VolumeHandler>,
+    pub uptime: Arc<UptimeTracker>,
 }
 
 // === Request DTOs ===
@@ -31,30 +65,112 @@ pub struct ContainerAppState {
 struct CreateContainerRequest {
     internal_id: String,
     volume_id: String,
-    startup_command: String,
-    image: String,
+    /// Template the unset fields below come from
+    template_id: Option<String>,
+    /// Required without a template
+    startup_command: Option<String>,
+    /// Required without a template
+    image: Option<String>,
     install_script: Option<String>,
     /// Pattern to detect when server is fully started (string or regex)
     start_pattern: Option<String>,
     /// Port requests - user specifies container_port, we assign host_port from pool
     ports: Option<Vec<PortRequest>>,
+    /// Host address to publish ports on (must belong to this node), defaults to the pool entry's IP
+    bind_ip: Option<String>,
+    /// Override the node's port allocation strategy for this container
+    allocation_strategy: Option<AllocationStrategy>,
+    /// Command written to stdin for a graceful stop (e.g. "stop")
+    stop_command: Option<String>,
+    /// Grace period in seconds before SIGKILL
+    stop_timeout: Option<u64>,
+    /// Metadata for grouping and search, e.g. {"customer": "acme"}
+    #[serde(default)]
+    labels: HashMap<String, String>,
+    /// SFTP login, defaults to the internal_id
+    sftp_username: Option<String>,
+    /// SFTP password, generated when not given
+    sftp_password: Option<String>,
+    /// Applied from the first start, no need for a later update
+    limits: Option<CreateLimits>,
+    /// Docker log rotation, defaults to `docker.log_rotation` from the config
+    log_rotation: Option<LogRotation>,
+    /// `uid` or `uid:gid` the server runs as, defaults to `docker.container_user`
+    user: Option<String>,
+    /// Max processes and threads, -1 for unlimited. Defaults to `docker.pids_limit`
+    pids_limit: Option<i64>,
+    /// `nofile`/`nproc` ulimits, unset ones come from `docker.ulimits`
+    ulimits: Option<Ulimits>,
+    /// Custom bind mounts, container path -> host path or `{"source", "read_only"}`
+    #[serde(default)]
+    mount: HashMap<String, MountSpec>,
+    /// Environment variables, merged over the template's
+    #[serde(default)]
+    env: HashMap<String, String>,
+}
+
+impl CreateContainerRequest {
+    /// Fill what the request left unset from the template. Ports are taken
+    /// as a whole, env and limits per key.
+    fn apply_template(&mut self, template: ContainerTemplate) {
+        self.image = self.image.take().or(Some(template.image));
+        self.startup_command = self.startup_command.take().or(Some(template.startup_command));
+        self.install_script = self.install_script.take().or(template.install_script);
+        self.start_pattern = self.start_pattern.take().or(template.start_pattern);
+        self.stop_command = self.stop_command.take().or(template.stop_command);
+        self.stop_timeout = self.stop_timeout.or(template.stop_timeout);
+        for (key, value) in template.env {
+            self.env.entry(key).or_insert(value);
+        }
+        if self.ports.is_none() && !template.ports.is_empty() {
+            self.ports = Some(template.ports);
+        }
+        self.limits = match (self.limits.take(), template.limits) {
+            (Some(own), Some(defaults)) => Some(own.or(&defaults)),
+            (own, defaults) => own.or(defaults),
+        };
+    }
 }
 
+/// Default and maximum page sizes for GET /containers
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 500;
+
 #[derive(Deserialize)]
-struct PortRequest {
-    pub container_port: u16,
-    #[serde(default = "default_protocol")]
-    pub protocol: String,
+struct ListContainersQuery {
+    page: Option<usize>,
+    per_page: Option<usize>,
+    /// running (installed), installing or failed
+    state: Option<String>,
+    image: Option<String>,
+    /// Comma separated key:value or key entries, all must match
+    label: Option<String>,
 }
 
-fn default_protocol() -> String {
-    "tcp".to_string()
+#[derive(Deserialize)]
+struct LogsQuery {
+    /// Unix seconds, from the start of the logs when unset
+    since: Option<i64>,
+    /// Unix seconds, up to now when unset
+    until: Option<i64>,
+    /// Only the last N lines of the range
+    tail: Option<usize>,
+    /// From a previous page's `next`
+    skip: Option<usize>,
+    /// Lines per page, at most `logs::MAX_LINES`
+    limit: Option<usize>,
 }
 
 #[derive(Deserialize)]
 struct ReinstallContainerRequest {
     image: String,
     install_script: Option<String>,
+    /// Delete all volume data before installing
+    #[serde(default)]
+    wipe_volume: bool,
+    /// Must be true alongside `wipe_volume`
+    #[serde(default)]
+    confirm_wipe: bool,
 }
 
 #[derive(Deserialize)]
@@ -67,19 +183,73 @@ struct UpdateStartupRequest {
     startup_command: String,
 }
 
+#[derive(Deserialize)]
+struct SignalRequest {
+    /// Signal name or number, e.g. "SIGHUP", "HUP" or "1"
+    signal: String,
+}
+
+#[derive(Deserialize)]
+struct UpdateStopConfigRequest {
+    /// Command written to stdin before stopping, null to skip straight to SIGTERM
+    stop_command: Option<String>,
+    /// Grace period in seconds before SIGKILL
+    stop_timeout: Option<u64>,
+}
+
 #[derive(Deserialize)]
 struct UpdateStartPatternRequest {
-    /// Pattern to detect when server is fully started (string or regex)
-    /// Set to null to disable pattern matching
+    /// Regexes that mark the server as started, replaces the whole list.
+    /// An empty list disables pattern matching
+    start_patterns: Option<Vec<String>>,
+    /// Single pattern, older clients; null disables pattern matching
     start_pattern: Option<String>,
 }
 
+#[derive(Serialize)]
+struct StartPatternResponse {
+    start_patterns: Vec<String>,
+}
+
+/// Default lines a start pattern test reads back
+const DEFAULT_PATTERN_TEST_LINES: usize = 500;
+
+#[derive(Deserialize)]
+struct TestStartPatternRequest {
+    /// Candidate regex, not saved
+    pattern: String,
+    /// Most recent lines to check, at most `logs::MAX_LINES`
+    lines: Option<usize>,
+}
+
+/// Default and maximum seconds `POST /containers/:id/start?wait=true` holds the response
+const DEFAULT_START_WAIT_SECS: u64 = 60;
+const MAX_START_WAIT_SECS: u64 = 600;
+
+#[derive(Deserialize)]
+struct StartQuery {
+    /// Respond once the server is ready instead of right after Docker starts it
+    #[serde(default)]
+    wait: bool,
+    /// Seconds to wait for readiness, at most `MAX_START_WAIT_SECS`
+    timeout: Option<u64>,
+}
+
 // === Response DTOs ===
 
 #[derive(Serialize)]
 struct CreateContainerResponse {
     internal_id: String,
     message: String,
+    /// Only returned here and on rotate, the password is stored hashed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sftp: Option<SftpLogin>,
+}
+
+#[derive(Serialize)]
+struct SftpLogin {
+    username: String,
+    password: String,
 }
 
 #[derive(Serialize)]
@@ -90,6 +260,15 @@ struct ContainerStatusResponse {
     container_id: Option<String>,
     is_healthy: bool,
     corruption_issue: Option<String>,
+    /// Why the last install failed
+    install_error: Option<String>,
+    /// Last known runtime state, null before it was ever recorded
+    runtime_state: Option<String>,
+    /// Crashes, exits with an error that weren't requested
+    restart_count: u64,
+    last_exit_code: Option<i64>,
+    last_exit_at: Option<u64>,
+    last_error: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -113,6 +292,18 @@ struct ValidateResponse {
     docker_synced: bool,
 }
 
+#[derive(Serialize)]
+struct InspectResponse {
+    internal_id: String,
+    /// What lightd has stored
+    db: ContainerState,
+    /// What Docker reports, null when the Docker container is missing
+    docker: Option<DockerInspect>,
+    docker_synced: bool,
+    /// Disagreements between the two views
+    mismatches: Vec<String>,
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
@@ -123,130 +314,430 @@ struct SuccessResponse {
     message: String,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn container_router(
     manager: Arc<ContainerManager>,
     lifecycle: Arc<LifecycleManager>,
     power: Arc<PowerManager>,
     network: Arc<NetworkRebinder>,
     pool: Arc<crate::network::pool::NetworkPool>,
+    updater: Arc<ContainerUpdater>,
+    credentials: Arc<CredentialsManager>,
+    idempotency: Arc<IdempotencyStore>,
+    stats: Arc<StatsCollector>,
+    maintenance: Arc<Maintenance>,
+    transfers: Arc<TransferManager>,
+    templates: Arc<TemplateManager>,
+    console: Arc<ConsoleStreamer>,
+    event_hub: Arc<EventHub>,
+    volumes: Arc<VolumeHandler>,
+    uptime: Arc<UptimeTracker>,
+    limits: &HttpLimitsConfig,
 ) -> Router {
-    let state = ContainerAppState { manager, lifecycle, power, network, pool };
+    let state = ContainerAppState { manager, lifecycle, power, network, pool, updater, credentials, idempotency, stats, maintenance, transfers, templates, console, event_hub, volumes, uptime };
 
-    Router::new()
+    // Volume archives, they get the transfer limits
+    let archives = Router::new()
+        .route("/containers/:id/export", get(export_container))
+        .route("/containers/import", post(import_container))
+        .with_state(state.clone());
+
+    // `?wait=true` holds the response until the server is ready
+    let start = Router::new()
+        .route("/containers/:id/start", post(start_container))
+        .with_state(state.clone());
+
+    let api = Router::new()
         // Container CRUD
         .route("/containers", post(create_container))
         .route("/containers", get(list_containers))
         .route("/containers/:id", get(get_container))
         .route("/containers/:id", delete(delete_container))
+        .route("/containers/:id/labels", post(update_labels))
+        // Node migration
+        .route("/containers/:id/transfer", post(transfer_container))
+        .route("/containers/:id/transfer/:job_id", get(get_transfer))
         // Container lifecycle
         .route("/containers/:id/reinstall", post(reinstall_container))
         .route("/containers/:id/repair", post(repair_container))
         .route("/containers/:id/validate", get(validate_container))
         .route("/containers/:id/status", get(get_container_status))
+        .route("/containers/:id/stats", get(get_container_stats))
+        .route("/containers/:id/inspect", get(inspect_container))
+        .route("/containers/:id/logs", get(get_container_logs))
         // Update operations
         .route("/containers/:id/startup", post(update_startup_command))
-        .route("/containers/:id/start-pattern", post(update_start_pattern))
+        .route("/containers/:id/start-pattern", get(get_start_pattern).post(update_start_pattern))
+        .route("/containers/:id/start-pattern/test", post(test_start_pattern))
+        .route("/containers/:id/stop-config", post(update_stop_config))
         .route("/containers/:id/resources", post(update_resources))
         .route("/containers/:id/resources", get(get_resources))
         .route("/containers/:id/volumes", post(update_volumes))
         // Power actions
-        .route("/containers/:id/start", post(start_container))
+        .route("/containers/:id/stop", post(stop_container))
         .route("/containers/:id/kill", post(kill_container))
         .route("/containers/:id/restart", post(restart_container))
+        .route("/containers/:id/signal", post(signal_container))
+        .route("/containers/:id/pause", post(pause_container))
+        .route("/containers/:id/resume", post(resume_container))
+        .route("/power/bulk", post(bulk_power))
         // Network operations
         .route("/containers/:id/rebind-network", post(rebind_network))
-        .with_state(state)
+        .route("/containers/:id/image", post(change_image))
+        .with_state(state);
+
+    limits::api(api, limits)
+        .merge(limits::waiting(start, limits, MAX_START_WAIT_SECS))
+        .merge(limits::transfers(archives, limits))
 }
 
 // Container Crud handlers
 
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Create, or with an `Idempotency-Key` header replay the first create that used the key
 #[axum::debug_handler]
 async fn create_container(
     State(state): State<ContainerAppState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateContainerRequest>,
 ) -> Response {
+    let key = match headers.get(IDEMPOTENCY_KEY).map(|v| v.to_str()) {
+        None => return create_container_once(state, payload).await,
+        Some(Ok(key)) if !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN => key.to_string(),
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: format!("Idempotency-Key must be 1-{} visible ASCII characters", MAX_IDEMPOTENCY_KEY_LEN),
+                }),
+            ).into_response();
+        }
+    };
+
+    match state.idempotency.claim(&key, &payload.internal_id) {
+        Ok(IdempotencyClaim::New) => {}
+        Ok(IdempotencyClaim::Replay(stored)) => {
+            tracing::info!("Replaying create of {} for Idempotency-Key {}", payload.internal_id, key);
+            let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+            return (status, [("idempotent-replayed", "true")], Json(stored.body)).into_response();
+        }
+        Ok(IdempotencyClaim::InProgress) => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    error: "A create with this Idempotency-Key is still in progress".to_string(),
+                }),
+            ).into_response();
+        }
+        Ok(IdempotencyClaim::Mismatch(internal_id)) => {
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ErrorResponse {
+                    error: format!("Idempotency-Key was already used to create container {}", internal_id),
+                }),
+            ).into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response();
+        }
+    }
+
+    // Failed or dropped creates don't hold on to the key, the client can retry them
+    let claim = state.idempotency.guard(&key);
+    let response = create_container_once(state.clone(), payload).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response();
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16(),
+        body: serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null),
+    };
+    if let Err(e) = claim.complete(stored) {
+        tracing::error!("Failed to store response for Idempotency-Key {}: {}", key, e);
+    }
+
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// 503 for requests that put new work on the node while it is in maintenance
+fn refuse_in_maintenance(state: &ContainerAppState) -> Option<Response> {
+    if !state.maintenance.is_enabled() {
+        return None;
+    }
+    Some((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(ErrorResponse { error: state.maintenance.message() }),
+    ).into_response())
+}
+
+async fn create_container_once(
+    state: ContainerAppState,
+    mut payload: CreateContainerRequest,
+) -> Response {
+    if let Some(response) = refuse_in_maintenance(&state) {
+        return response;
+    }
+
+    if let Some(template_id) = payload.template_id.clone() {
+        match state.templates.get(&template_id) {
+            Ok(Some(template)) => payload.apply_template(template),
+            Ok(None) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse {
+                        error: format!("Template '{}' not found", template_id),
+                    }),
+                ).into_response();
+            }
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: e.to_string(),
+                    }),
+                ).into_response();
+            }
+        }
+    }
+    let (Some(image), Some(startup_command)) = (payload.image.take(), payload.startup_command.take()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "image and startup_command are required without a template_id".to_string(),
+            }),
+        ).into_response();
+    };
+
+    if let Err(e) = payload.env.keys().try_for_each(|k| validate_env_key(k)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+
+    if let Err(e) = payload.labels.keys().try_for_each(|k| validate_label_key(k)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+
+    if let Some(ref username) = payload.sftp_username
+        && let Ok(Some(_)) = state.credentials.find_by_username(username) {
+        return (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: format!("SFTP username '{}' is already in use", username),
+            }),
+        ).into_response();
+    }
+
+    if let Some(ref bind_ip) = payload.bind_ip
+        && let Err(e) = validate_bind_ip(bind_ip) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response();
+    }
+    
+    for request in payload.ports.iter_mut().flatten() {
+        match normalize_protocol(&request.protocol) {
+            Ok(protocol) => request.protocol = protocol,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse { error: e }),
+                ).into_response();
+            }
+        }
+    }
+    
+    if let Some(Err(e)) = payload.log_rotation.as_ref().map(LogRotation::validate) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("log_rotation: {}", e),
+            }),
+        ).into_response();
+    }
+    
+    if let Some(Err(e)) = payload.user.as_deref().map(parse_run_as) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("user: {}", e),
+            }),
+        ).into_response();
+    }
+    
+    if let Some(Err(e)) = payload.pids_limit.map(validate_pids_limit) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+    
+    if let Some(Err(e)) = payload.ulimits.as_ref().map(Ulimits::validate) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("ulimits: {}", e),
+            }),
+        ).into_response();
+    }
+    
+    let (mounts, read_only_mounts) = match split_mounts(std::mem::take(&mut payload.mount), state.lifecycle.mount_policy()) {
+        Ok(split) => split,
+        Err(e) => return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("mount: {}", e),
+            }),
+        ).into_response(),
+    };
+    
+    if let Some(ref limits) = payload.limits {
+        let limits = ResourceLimits {
+            memory: limits.memory,
+            cpus: limits.cpu,
+            ..Default::default()
+        };
+        if let Err(e) = state.updater.validate_limits(&limits).await {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response();
+        }
+    }
+    
+    let volume_id = payload.volume_id.clone();
+    
     // Create container state
     match state
         .manager
         .create_container(
             payload.internal_id.clone(),
             payload.volume_id,
-            payload.startup_command,
+            startup_command,
         )
         .await
     {
         Ok(_) => {
-            // Update start_pattern if provided
-            if let Some(pattern) = payload.start_pattern {
-                if let Ok(Some(mut container)) = state.manager.get_container(&payload.internal_id).await {
-                    container.start_pattern = Some(pattern);
-                    let _ = state.manager.update_container(container).await;
+            // Record the image and start_pattern if provided
+            if let Ok(Some(mut container)) = state.manager.get_container(&payload.internal_id).await {
+                container.image = Some(image.clone());
+                container.start_pattern = payload.start_pattern;
+                container.labels = payload.labels;
+                container.log_rotation = payload.log_rotation;
+                container.user = payload.user;
+                container.pids_limit = payload.pids_limit;
+                container.ulimits = payload.ulimits;
+                container.env = payload.env;
+                container.mount = mounts;
+                container.read_only_mounts = read_only_mounts;
+                if let Some(limits) = payload.limits {
+                    container.limits.memory = limits.memory;
+                    container.limits.cpu = limits.cpu;
                 }
+                let _ = state.manager.update_container(container).await;
             }
             
+            // Update stop sequence if provided
+            if (payload.stop_command.is_some() || payload.stop_timeout.is_some())
+                && let Err(e) = state.manager.update_stop_config(
+                    &payload.internal_id,
+                    payload.stop_command,
+                    payload.stop_timeout,
+                ).await {
+                    tracing::error!("Failed to set stop config for {}: {}", payload.internal_id, e);
+                }
+            
             // Assign ports from pool if requested
+            let mut reservation = None;
             if let Some(port_requests) = payload.ports {
-                let mut assigned_ports = Vec::new();
-                
-                for request in port_requests {
-                    // Get random available port from pool
-                    match state.pool.get_random_available().await {
-                        Ok(Some(network_port)) => {
-                            // Mark port as in use
-                            if let Err(e) = state.pool.mark_in_use(&network_port.id, true).await {
-                                tracing::error!("Failed to mark port {} as in use: {}", network_port.id, e);
-                                continue;
-                            }
-                            
-                            // Create port binding
-                            let binding = PortBinding {
-                                container_port: request.container_port,
-                                host_port: network_port.port,
-                                protocol: request.protocol,
-                            };
-                            
-                            assigned_ports.push(binding);
-                            tracing::info!("Assigned port {} -> {} for container {}", 
-                                request.container_port, network_port.port, payload.internal_id);
-                        }
-                        Ok(None) => {
-                            tracing::error!("No available ports in pool for container {}", payload.internal_id);
-                            return (
-                                StatusCode::SERVICE_UNAVAILABLE,
-                                Json(ErrorResponse {
-                                    error: "No available ports in pool".to_string(),
-                                }),
-                            ).into_response();
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to get port from pool: {}", e);
-                            return (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                Json(ErrorResponse {
-                                    error: format!("Failed to assign ports: {}", e),
-                                }),
-                            ).into_response();
-                        }
+                let strategy = payload.allocation_strategy
+                    .unwrap_or_else(|| state.pool.allocation_strategy());
+                let assigned_ports = match assign_ports(
+                    &state,
+                    &payload.internal_id,
+                    port_requests,
+                    strategy,
+                    payload.bind_ip.as_deref(),
+                ).await {
+                    Ok((ports, ports_reservation)) => {
+                        reservation = Some(ports_reservation);
+                        ports
                     }
-                }
+                    Err(response) => return response,
+                };
                 
                 // Update container with assigned ports
                 if let Ok(Some(mut container)) = state.manager.get_container(&payload.internal_id).await {
                     container.ports = assigned_ports;
                     let _ = state.manager.update_container(container).await;
                 }
+                state.pool.warn_if_low().await;
             }
             
+            // SFTP access to the new volume, bcrypt runs off the runtime
+            let generated = {
+                let credentials = state.credentials.clone();
+                let internal_id = payload.internal_id.clone();
+                let volume_id = volume_id.clone();
+                let (username, password) = (payload.sftp_username, payload.sftp_password);
+                tokio::task::spawn_blocking(move || {
+                    credentials.generate_credentials(&internal_id, &volume_id, username, password)
+                }).await
+            };
+            let sftp = match generated.map_err(Into::into).and_then(|r| r) {
+                Ok((username, password)) => Some(SftpLogin { username, password }),
+                Err(e) => {
+                    tracing::error!("Failed to create SFTP credentials for {}: {}", payload.internal_id, e);
+                    None
+                }
+            };
+            
             // Start async installation
             if let Err(e) = state
                 .lifecycle
                 .install_container(
                     payload.internal_id.clone(),
-                    payload.image,
+                    image,
                     payload.install_script,
                 )
                 .await
             {
+                // The reservation hands the ports back, the record must not keep them
+                clear_ports(&state, &payload.internal_id).await;
+                // Nothing was installed, the login must not outlive the attempt
+                if sftp.is_some()
+                    && let Err(e) = state.credentials.delete_credentials(&payload.internal_id) {
+                    tracing::error!("Failed to delete SFTP credentials of {}: {}", payload.internal_id, e);
+                }
                 return (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(ErrorResponse {
@@ -254,10 +745,14 @@ async fn create_container(
                     }),
                 ).into_response();
             }
+            if let Some(reservation) = reservation {
+                reservation.commit();
+            }
 
             (StatusCode::OK, Json(CreateContainerResponse {
                 internal_id: payload.internal_id,
                 message: "Container installation started".to_string(),
+                sftp,
             })).into_response()
         }
         Err(e) => (
@@ -269,11 +764,186 @@ async fn create_container(
     }
 }
 
+/// Forget the port bindings of a container whose reservation was rolled back
+async fn clear_ports(state: &ContainerAppState, internal_id: &str) {
+    if let Ok(Some(mut container)) = state.manager.get_container(internal_id).await {
+        container.ports.clear();
+        let _ = state.manager.update_container(container).await;
+    }
+}
+
+/// Claim a host port from the pool for each request, in order. The ports go
+/// back to the pool when the returned reservation is dropped without `commit`.
+async fn assign_ports(
+    state: &ContainerAppState,
+    internal_id: &str,
+    port_requests: Vec<PortRequest>,
+    strategy: AllocationStrategy,
+    bind_ip: Option<&str>,
+) -> Result<(Vec<PortBinding>, PortReservation), Response> {
+    let mut assigned_ports = Vec::new();
+    let mut reservation = PortReservation::new(state.pool.clone());
+    
+    for request in port_requests {
+        if request.both_protocols {
+            match state.pool.allocate_paired(strategy, bind_ip).await {
+                Ok(Some((tcp, udp))) => {
+                    let host_port = tcp.port;
+                    for network_port in [tcp, udp] {
+                        reservation.add(network_port.id.clone());
+                        assigned_ports.push(PortBinding {
+                            container_port: request.container_port,
+                            host_port: network_port.port,
+                            protocol: network_port.protocol,
+                            host_ip: bind_ip.map(str::to_string).unwrap_or(network_port.ip),
+                            pool_id: Some(network_port.id),
+                        });
+                    }
+                    tracing::info!("Assigned port {} -> {} (tcp+udp) for container {}",
+                        request.container_port, host_port, internal_id);
+                }
+                Ok(None) => {
+                    tracing::error!("No port free on both TCP and UDP for container {}", internal_id);
+                    return Err((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(ErrorResponse {
+                            error: "No port available on both TCP and UDP".to_string(),
+                        }),
+                    ).into_response());
+                }
+                Err(e) => {
+                    tracing::error!("Failed to allocate paired port: {}", e);
+                    return Err((
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(ErrorResponse {
+                            error: format!("Failed to assign ports: {}", e),
+                        }),
+                    ).into_response());
+                }
+            }
+            continue;
+        }
+        
+        // Claim a free entry opened for the same protocol
+        match state.pool.allocate(strategy, bind_ip, Some(&request.protocol)).await {
+            Ok(Some(network_port)) => {
+                reservation.add(network_port.id.clone());
+                
+                // Create port binding
+                let binding = PortBinding {
+                    container_port: request.container_port,
+                    host_port: network_port.port,
+                    protocol: request.protocol,
+                    host_ip: bind_ip.map(str::to_string).unwrap_or(network_port.ip),
+                    pool_id: Some(network_port.id.clone()),
+                };
+                
+                assigned_ports.push(binding);
+                tracing::info!("Assigned port {} -> {} for container {}", 
+                    request.container_port, network_port.port, internal_id);
+            }
+            Ok(None) => {
+                tracing::error!("No available {} ports in pool for container {}", request.protocol, internal_id);
+                return Err((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(ErrorResponse {
+                        error: format!("No available {} ports in pool", request.protocol),
+                    }),
+                ).into_response());
+            }
+            Err(e) => {
+                tracing::error!("Failed to allocate port from pool: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: format!("Failed to assign ports: {}", e),
+                    }),
+                ).into_response());
+            }
+        }
+    }
+    
+    // The pool only knows about lightd, another process on the host may hold the port
+    for binding in &assigned_ports {
+        if let Err(e) = check_host_port_free(&binding.host_ip, binding.host_port, &binding.protocol) {
+            tracing::warn!("{} for container {}", e, internal_id);
+            return Err((
+                StatusCode::CONFLICT,
+                Json(ErrorResponse {
+                    error: format!("{}, remove it from the pool or free it", e),
+                }),
+            ).into_response());
+        }
+    }
+    
+    Ok((assigned_ports, reservation))
+}
+
 async fn list_containers(
     State(state): State<ContainerAppState>,
+    Query(query): Query<ListContainersQuery>,
+) -> Response {
+    let install_state = match query.state.as_deref().map(str::parse::<InstallState>) {
+        Some(Ok(s)) => Some(s),
+        Some(Err(e)) => return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response(),
+        None => None,
+    };
+
+    let filter = ContainerFilter {
+        install_state,
+        image: query.image.filter(|i| !i.is_empty()),
+        labels: query.label.as_deref().map(ContainerFilter::parse_labels).unwrap_or_default(),
+    };
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+
+    match state.manager.list_containers_page(&filter, query.page.unwrap_or(1), per_page).await {
+        Ok(page) => (StatusCode::OK, Json(page)).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct UpdateLabelsRequest {
+    /// Labels to set, a null value removes the label
+    labels: HashMap<String, Option<String>>,
+}
+
+#[derive(Serialize)]
+struct LabelsResponse {
+    labels: HashMap<String, String>,
+}
+
+async fn update_labels(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateLabelsRequest>,
 ) -> Response {
-    match state.manager.list_containers().await {
-        Ok(containers) => (StatusCode::OK, Json(containers)).into_response(),
+    if let Err(e) = payload.labels.keys().try_for_each(|k| validate_label_key(k)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+
+    if let Ok(None) = state.manager.get_container(&id).await {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response();
+    }
+
+    match state.manager.update_labels(&id, payload.labels).await {
+        Ok(labels) => (StatusCode::OK, Json(LabelsResponse { labels })).into_response(),
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
@@ -311,27 +981,23 @@ async fn delete_container(
     // Get container to check for ports before deletion
     if let Ok(Some(container)) = state.manager.get_container(&id).await {
         // Return ports to pool
-        for port_binding in &container.ports {
-            // Find the port in the pool by host_port and mark as available
-            if let Ok(all_ports) = state.pool.get_all_ports().await {
-                for network_port in all_ports {
-                    if network_port.port == port_binding.host_port && network_port.in_use {
-                        if let Err(e) = state.pool.mark_in_use(&network_port.id, false).await {
-                            tracing::error!("Failed to return port {} to pool: {}", network_port.port, e);
-                        } else {
-                            tracing::info!("Returned port {} to pool", network_port.port);
-                        }
-                        break;
-                    }
-                }
-            }
+        if let Err(e) = state.pool.release_ports(&container.ports).await {
+            tracing::error!("Failed to return ports of {} to pool: {}", id, e);
         }
     }
     
     match state.manager.delete_container(&id).await {
-        Ok(container) => (StatusCode::OK, Json(container)).into_response(),
+        Ok(container) => {
+            if let Err(e) = state.credentials.delete_credentials(&id) {
+                tracing::error!("Failed to delete SFTP credentials of {}: {}", id, e);
+            }
+            if let Err(e) = state.uptime.delete(&id) {
+                tracing::error!("Failed to delete uptime history of {}: {}", id, e);
+            }
+            (StatusCode::OK, Json(container)).into_response()
+        }
         Err(e) => (
-            StatusCode::NOT_FOUND,
+            error::status_code(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -341,16 +1007,425 @@ async fn delete_container(
 
 
 
-/// Reinstall a container - removes old Docker container and creates new one
-#[axum::debug_handler]
-async fn reinstall_container(
+/// Stream a tar of the container state and volume for importing on another node
+async fn export_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
-    Json(payload): Json<ReinstallContainerRequest>,
 ) -> Response {
-    // Check if container exists
-    match state.manager.get_container(&id).await {
-        Ok(Some(container)) => {
+    let container = match state.manager.get_container(&id).await {
+        Ok(Some(container)) => container,
+        Ok(None) => return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    };
+
+    let exports_dir = state.lifecycle.base_path().join("exports");
+    let archive = exports_dir.join(format!("{}-{}.tar", id, uuid::Uuid::new_v4()));
+    let volume = state.lifecycle.volume_path(&container.volume_id);
+    let quota_mb = state.volumes.get_volume(&container.volume_id).await.and_then(|v| v.quota_mb);
+
+    let written = {
+        let archive = archive.clone();
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&exports_dir).map_err(|e| e.to_string())?;
+            export::write_export(&container, &volume, quota_mb, &archive)
+        }).await
+    };
+    if let Err(e) = written.map_err(|e| e.to_string()).and_then(|r| r) {
+        let _ = tokio::fs::remove_file(&archive).await;
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: format!("Failed to export container: {}", e),
+            }),
+        ).into_response();
+    }
+
+    let file = match tokio::fs::File::open(&archive).await {
+        Ok(file) => file,
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    };
+    // The open handle keeps the data readable, nothing is left behind on disk
+    let _ = tokio::fs::remove_file(&archive).await;
+
+    tracing::info!("Exporting container {}", id);
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/x-tar".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}.tar\"", id)),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    ).into_response()
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    internal_id: String,
+    ports: Vec<PortBinding>,
+    message: String,
+}
+
+/// Recreate a container from an export archive sent as the raw request body
+async fn import_container(
+    State(state): State<ContainerAppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    if let Some(response) = refuse_in_maintenance(&state) {
+        return response;
+    }
+
+    let imports_dir = state.lifecycle.base_path().join("imports");
+    let archive = imports_dir.join(format!("{}.tar", uuid::Uuid::new_v4()));
+
+    let expected_sha256 = headers
+        .get(EXPORT_SHA256_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase());
+
+    let response = import_archive(&state, &imports_dir, &archive, body, expected_sha256).await;
+    let _ = tokio::fs::remove_file(&archive).await;
+    response
+}
+
+async fn import_archive(
+    state: &ContainerAppState,
+    imports_dir: &std::path::Path,
+    archive: &std::path::Path,
+    body: Body,
+    expected_sha256: Option<String>,
+) -> Response {
+    let error = |status: StatusCode, error: String| {
+        (status, Json(ErrorResponse { error })).into_response()
+    };
+
+    // Volumes can be large, spool the upload to disk instead of buffering it
+    let spooled = async {
+        tokio::fs::create_dir_all(imports_dir).await?;
+        let mut file = tokio::fs::File::create(archive).await?;
+        let mut hasher = sha2::Sha256::new();
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(std::io::Error::other)?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok::<_, std::io::Error>(hasher.finalize())
+    }.await;
+    let digest = match spooled {
+        Ok(digest) => digest.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+        Err(e) => return error(StatusCode::BAD_REQUEST, format!("Failed to receive archive: {}", e)),
+    };
+    // Sent by transfers from another node, a mismatch means the upload was cut short
+    if let Some(expected) = expected_sha256
+        && expected != digest {
+        tracing::warn!("Rejected import with sha256 {}, sender announced {}", digest, expected);
+        return error(
+            StatusCode::BAD_REQUEST,
+            "Archive checksum mismatch, the transfer was incomplete or corrupted".to_string(),
+        );
+    }
+
+    let manifest = {
+        let archive = archive.to_path_buf();
+        match tokio::task::spawn_blocking(move || export::read_manifest(&archive)).await {
+            Ok(Ok(manifest)) => manifest,
+            Ok(Err(e)) => return error(StatusCode::BAD_REQUEST, e),
+            Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        }
+    };
+    let quota_mb = manifest.volume_quota_mb;
+    let mut exported = manifest.state;
+    let internal_id = exported.internal_id.clone();
+    let Some(image) = exported.image.clone() else {
+        return error(StatusCode::BAD_REQUEST, "Export has no image recorded, the container can't be installed".to_string());
+    };
+    // The manifest is only as trustworthy as its sender, it gets the checks of a create
+    if let Err(e) = validate_import(state, &mut exported).await {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+
+    match state.manager.get_container(&internal_id).await {
+        Ok(Some(_)) => return error(
+            StatusCode::CONFLICT,
+            format!("Container {} already exists on this node", internal_id),
+        ),
+        Ok(None) => {}
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+
+    let volume = match state.volumes.create_volume_with_id(&exported.volume_id, quota_mb).await {
+        Ok(volume) => volume,
+        Err(e) => return error(error::status_code(&*e), e.to_string()),
+    };
+
+    let volume_id = exported.volume_id.clone();
+    match import_unpacked(state, archive, volume.path, exported, image).await {
+        Ok(response) => response,
+        Err(response) => {
+            // Nothing of a failed import stays behind, the sender still has the container
+            if let Ok(Some(_)) = state.manager.get_container(&internal_id).await
+                && let Err(e) = state.manager.delete_container(&internal_id).await {
+                tracing::error!("Failed to remove the record of failed import {}: {}", internal_id, e);
+            }
+            if let Err(e) = state.volumes.delete_volume(&volume_id).await.map_err(|e| e.to_string()) {
+                tracing::error!("Failed to remove the volume of failed import {}: {}", internal_id, e);
+            }
+            response
+        }
+    }
+}
+
+/// Apply the checks `create_container_once` runs on its request to an
+/// imported container. Mounts are split and checked against this node's policy.
+async fn validate_import(state: &ContainerAppState, exported: &mut ContainerState) -> Result<(), String> {
+    exported.env.keys().try_for_each(|k| validate_env_key(k))?;
+    exported.labels.keys().try_for_each(|k| validate_label_key(k))?;
+    if let Some(rotation) = &exported.log_rotation {
+        rotation.validate().map_err(|e| format!("log_rotation: {}", e))?;
+    }
+    if let Some(user) = exported.user.as_deref() {
+        parse_run_as(user).map_err(|e| format!("user: {}", e))?;
+    }
+    if let Some(pids_limit) = exported.pids_limit {
+        validate_pids_limit(pids_limit)?;
+    }
+    if let Some(ulimits) = &exported.ulimits {
+        ulimits.validate().map_err(|e| format!("ulimits: {}", e))?;
+    }
+    validate_start_patterns(&exported.start_patterns)?;
+
+    let mounts = std::mem::take(&mut exported.mount)
+        .into_iter()
+        .map(|(target, source)| {
+            let read_only = exported.read_only_mounts.contains(&target);
+            (target, MountSpec::Options { source, read_only: Some(read_only) })
+        })
+        .collect();
+    let (mount, read_only_mounts) = split_mounts(mounts, state.lifecycle.mount_policy())
+        .map_err(|e| format!("mount: {}", e))?;
+    exported.mount = mount;
+    exported.read_only_mounts = read_only_mounts;
+
+    let limits = ResourceLimits {
+        memory: exported.limits.memory,
+        cpus: exported.limits.cpu,
+        ..Default::default()
+    };
+    state.updater.validate_limits(&limits).await.map_err(|e| e.to_string())
+}
+
+/// Unpack the volume and install the imported container. An error response
+/// means the caller has to remove the record and the volume again.
+async fn import_unpacked(
+    state: &ContainerAppState,
+    archive: &std::path::Path,
+    volume: std::path::PathBuf,
+    exported: ContainerState,
+    image: String,
+) -> Result<Response, Response> {
+    let error = |status: StatusCode, error: String| {
+        (status, Json(ErrorResponse { error })).into_response()
+    };
+    let internal_id = exported.internal_id.clone();
+
+    let unpacked = {
+        let archive = archive.to_path_buf();
+        tokio::task::spawn_blocking(move || export::unpack_volume(&archive, &volume)).await
+    };
+    match unpacked {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(error(StatusCode::BAD_REQUEST, format!("Failed to unpack volume: {}", e))),
+        Err(e) => return Err(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+
+    if let Err(e) = state.manager.create_container(
+        internal_id.clone(),
+        exported.volume_id.clone(),
+        exported.startup_command.clone(),
+    ).await {
+        return Err(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+
+    // Ports from the old node mean nothing here, request the same container ports again.
+    // A tcp and udp binding that shared a host port are kept paired.
+    let mut port_requests: Vec<PortRequest> = Vec::new();
+    for binding in &exported.ports {
+        let paired = exported.ports.iter().any(|other| {
+            other.host_port == binding.host_port && other.protocol != binding.protocol
+        });
+        if paired {
+            if binding.protocol == "tcp" {
+                port_requests.push(PortRequest {
+                    container_port: binding.container_port,
+                    protocol: binding.protocol.clone(),
+                    both_protocols: true,
+                });
+            }
+            continue;
+        }
+        port_requests.push(PortRequest {
+            container_port: binding.container_port,
+            protocol: binding.protocol.clone(),
+            both_protocols: false,
+        });
+    }
+    let strategy = state.pool.allocation_strategy();
+    let (ports, reservation) = assign_ports(state, &internal_id, port_requests, strategy, None).await?;
+
+    match state.manager.get_container(&internal_id).await {
+        Ok(Some(mut container)) => {
+            container.limits = exported.limits;
+            container.ports = ports.clone();
+            container.start_pattern = exported.start_pattern;
+            container.start_patterns = exported.start_patterns;
+            container.stop_command = exported.stop_command;
+            container.stop_timeout = exported.stop_timeout;
+            container.image = Some(image.clone());
+            container.labels = exported.labels;
+            container.log_rotation = exported.log_rotation;
+            container.user = exported.user;
+            container.pids_limit = exported.pids_limit;
+            container.ulimits = exported.ulimits;
+            container.env = exported.env;
+            container.mount = exported.mount;
+            container.read_only_mounts = exported.read_only_mounts;
+            if let Err(e) = state.manager.update_container(container).await {
+                return Err(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+            }
+        }
+        Ok(None) => return Err(error(StatusCode::INTERNAL_SERVER_ERROR, "Imported container disappeared".to_string())),
+        Err(e) => return Err(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+
+    // The files came with the archive, so no install script runs
+    if let Err(e) = state.lifecycle.install_container(internal_id.clone(), image, None).await {
+        return Err(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+    reservation.commit();
+
+    tracing::info!("Imported container {} with {} port(s)", internal_id, ports.len());
+    Ok((StatusCode::CREATED, Json(ImportResponse {
+        internal_id,
+        ports,
+        message: "Container imported, installation started".to_string(),
+    })).into_response())
+}
+
+#[derive(Deserialize)]
+struct TransferRequest {
+    /// Base URL of the target lightd node
+    target_url: String,
+    /// API token of the target node
+    token: String,
+}
+
+/// Move the container to another node, polled through the returned job
+async fn transfer_container(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<TransferRequest>,
+) -> Response {
+    let target_url = match validate_target_url(&payload.target_url) {
+        Ok(url) => url,
+        Err(e) => return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response(),
+    };
+
+    let container = match state.manager.get_container(&id).await {
+        Ok(Some(container)) => container,
+        Ok(None) => return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    };
+
+    if container.is_installing {
+        return (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "Cannot transfer while container is installing".to_string(),
+            }),
+        ).into_response();
+    }
+    if let Some(job) = state.transfers.active(&id) {
+        return (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: format!("Container is already being transferred (job {})", job.id),
+            }),
+        ).into_response();
+    }
+
+    tracing::info!("Transferring container {} to {}", id, target_url);
+    let job = state.transfers.start(container, target_url, payload.token);
+    (StatusCode::ACCEPTED, Json(job)).into_response()
+}
+
+async fn get_transfer(
+    State(state): State<ContainerAppState>,
+    Path((id, job_id)): Path<(String, String)>,
+) -> Response {
+    match state.transfers.get(&id, &job_id) {
+        Some(job) => (StatusCode::OK, Json(job)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Transfer not found".to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Reinstall a container - removes old Docker container and creates new one
+#[axum::debug_handler]
+async fn reinstall_container(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<ReinstallContainerRequest>,
+) -> Response {
+    if let Some(response) = refuse_in_maintenance(&state) {
+        return response;
+    }
+
+    if payload.wipe_volume && !payload.confirm_wipe {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "wipe_volume deletes all volume data, set confirm_wipe to true to proceed".to_string(),
+            }),
+        ).into_response();
+    }
+
+    // Check if container exists
+    match state.manager.get_container(&id).await {
+        Ok(Some(container)) => {
             // Check if already installing
             if container.is_installing {
                 return (
@@ -366,16 +1441,21 @@ async fn reinstall_container(
                 id.clone(),
                 payload.image,
                 payload.install_script,
+                payload.wipe_volume,
             ).await {
                 Ok(_) => (
                     StatusCode::OK,
                     Json(ReinstallResponse {
                         internal_id: id,
-                        message: "Container reinstall started".to_string(),
+                        message: if payload.wipe_volume {
+                            "Container reinstall started, volume will be wiped".to_string()
+                        } else {
+                            "Container reinstall started".to_string()
+                        },
                     }),
                 ).into_response(),
                 Err(e) => (
-                    StatusCode::INTERNAL_SERVER_ERROR,
+                    error::status_code(&*e),
                     Json(ErrorResponse {
                         error: e.to_string(),
                     }),
@@ -397,174 +1477,721 @@ async fn reinstall_container(
     }
 }
 
-/// Repair a corrupted container - detects issues and reinstalls if needed
+/// Repair a corrupted container - detects issues and reinstalls if needed
+#[axum::debug_handler]
+async fn repair_container(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<RepairContainerRequest>,
+) -> Response {
+    match state.lifecycle.repair_if_corrupted(id.clone(), payload.image).await {
+        Ok(repaired) => {
+            let message = if repaired {
+                "Container corruption detected and repair started".to_string()
+            } else {
+                "Container is healthy, no repair needed".to_string()
+            };
+
+            (StatusCode::OK, Json(RepairResponse {
+                internal_id: id,
+                repaired,
+                message,
+            })).into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Validate container state and check for corruption
+async fn validate_container(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+) -> Response {
+    // First validate DB state
+    let (is_valid, issue) = match state.manager.validate_container(&id).await {
+        Ok(result) => result,
+        Err(e) => {
+            return (
+                error::status_code(&*e),
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response();
+        }
+    };
+
+    // Then check Docker sync
+    let docker_synced = state.lifecycle.verify_container_sync(&id).await.unwrap_or_default();
+
+    (StatusCode::OK, Json(ValidateResponse {
+        internal_id: id,
+        is_valid: is_valid && docker_synced,
+        issue,
+        docker_synced,
+    })).into_response()
+}
+
+/// Where the stored state and Docker's state disagree
+fn inspect_mismatches(db: &ContainerState, docker: Option<&DockerInspect>, docker_synced: bool) -> Vec<String> {
+    let mut mismatches = Vec::new();
+
+    if !docker_synced {
+        match &db.container_id {
+            Some(id) => mismatches.push(format!("Docker container {} no longer exists", id)),
+            None => mismatches.push("Marked ready but has no Docker container".to_string()),
+        }
+    }
+    if db.is_installing != (db.install_state == InstallState::Installing) {
+        mismatches.push(format!(
+            "is_installing is {} but install_state is {:?}",
+            db.is_installing, db.install_state
+        ));
+    }
+
+    if let Some(docker) = docker {
+        for port in &db.ports {
+            let key = port.docker_key();
+            let published = docker.port_bindings.iter().any(|b| {
+                b.container_port == key && b.host_port.as_deref() == Some(port.host_port.to_string().as_str())
+            });
+            if !published {
+                mismatches.push(format!("Port {} is not published on host port {}", key, port.host_port));
+            }
+        }
+        for destination in db.mount.keys() {
+            if !docker.mounts.iter().any(|m| m.destination.as_deref() == Some(destination.as_str())) {
+                mismatches.push(format!("Mount {} is missing in Docker", destination));
+            }
+        }
+    }
+
+    mismatches
+}
+
+/// Stored state and `docker inspect` side by side
+async fn inspect_container(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+) -> Response {
+    let container = match state.manager.get_container(&id).await {
+        Ok(Some(container)) => container,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            })).into_response();
+        }
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: e.to_string(),
+            })).into_response();
+        }
+    };
+
+    let docker_synced = match state.lifecycle.verify_container_sync(&id).await {
+        Ok(synced) => synced,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: e.to_string(),
+            })).into_response();
+        }
+    };
+
+    let docker = match &container.container_id {
+        Some(container_id) => match state.lifecycle.inspect_docker(container_id).await {
+            Ok(docker) => docker,
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                    error: e.to_string(),
+                })).into_response();
+            }
+        },
+        None => None,
+    };
+
+    let mismatches = inspect_mismatches(&container, docker.as_ref(), docker_synced);
+
+    (StatusCode::OK, Json(InspectResponse {
+        internal_id: id,
+        db: container,
+        docker,
+        docker_synced,
+        mismatches,
+    })).into_response()
+}
+
+/// Past output between two times, read from Docker's logs
+async fn get_container_logs(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Query(query): Query<LogsQuery>,
+) -> Response {
+    if query.since.is_some_and(|t| t < 0) || query.until.is_some_and(|t| t < 0) {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "since and until must be unix timestamps".to_string(),
+        })).into_response();
+    }
+    if let (Some(since), Some(until)) = (query.since, query.until)
+        && since > until {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "since must not be after until".to_string(),
+        })).into_response();
+    }
+    if query.tail.is_some() && query.skip.is_some() {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: "tail cannot be combined with skip".to_string(),
+        })).into_response();
+    }
+
+    let container_id = match state.manager.get_container(&id).await {
+        Ok(Some(container)) => container.container_id,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            })).into_response();
+        }
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: e.to_string(),
+            })).into_response();
+        }
+    };
+    let Some(container_id) = container_id else {
+        return (StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Container has no Docker container yet".to_string(),
+        })).into_response();
+    };
+
+    let range = logs::LogRange {
+        since: query.since,
+        until: query.until,
+        tail: query.tail,
+        skip: query.skip.unwrap_or(0),
+        limit: query.limit.unwrap_or(logs::MAX_LINES),
+    };
+    match state.lifecycle.read_logs(&container_id, &range).await {
+        Ok(page) => (StatusCode::OK, Json(page)).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: e.to_string(),
+        })).into_response(),
+    }
+}
+
+/// Current resource usage, one reading for clients that don't keep a WebSocket open
+async fn get_container_stats(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.stats.snapshot(&id).await {
+        Ok(Some(stats)) => (StatusCode::OK, Json(stats)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Get detailed container status
+async fn get_container_status(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.manager.get_container(&id).await {
+        Ok(Some(container)) => {
+            // Validate for corruption
+            let (is_healthy, corruption_issue) = match state.manager.validate_container(&id).await {
+                Ok((valid, issue)) => (valid, issue),
+                Err(_) => (false, Some("Validation error".to_string())),
+            };
+
+            let install_state_str = match container.install_state {
+                InstallState::Ready => "ready",
+                InstallState::Installing => "installing",
+                InstallState::Failed => "failed",
+            };
+
+            let runtime_state = state.manager.runtime_state(&id);
+
+            (StatusCode::OK, Json(ContainerStatusResponse {
+                internal_id: container.internal_id,
+                install_state: install_state_str.to_string(),
+                is_installing: container.is_installing,
+                container_id: container.container_id,
+                is_healthy,
+                corruption_issue,
+                install_error: container.install_error,
+                runtime_state,
+                restart_count: container.restart_count,
+                last_exit_code: container.last_exit_code,
+                last_exit_at: container.last_exit_at,
+                last_error: container.last_error,
+            })).into_response()
+        }
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+// === Update Handlers ===
+
+/// Update container startup command
+async fn update_startup_command(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateStartupRequest>,
+) -> Response {
+    match state.manager.update_startup_command(&id, payload.startup_command).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(SuccessResponse {
+                message: "Startup command updated".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => (
+            error::status_code(&*e),
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Current start patterns of a container
+async fn get_start_pattern(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.manager.get_container(&id).await {
+        Ok(Some(container)) => (
+            StatusCode::OK,
+            Json(StartPatternResponse {
+                start_patterns: container.start_pattern_list(),
+            }),
+        ).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Replace the start patterns of a container, each must compile as a regex
+async fn update_start_pattern(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateStartPatternRequest>,
+) -> Response {
+    let patterns = payload.start_patterns
+        .unwrap_or_else(|| payload.start_pattern.into_iter().collect());
+    if let Err(e) = validate_start_patterns(&patterns) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+
+    match state.manager.update_start_patterns(&id, patterns.clone()).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(StartPatternResponse {
+                start_patterns: patterns,
+            }),
+        ).into_response(),
+        Err(e) => (
+            error::status_code(&*e),
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+/// Run a candidate start pattern over the latest output, without saving it
+async fn test_start_pattern(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<TestStartPatternRequest>,
+) -> Response {
+    if let Err(e) = validate_start_patterns(std::slice::from_ref(&payload.pattern)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse { error: e }),
+        ).into_response();
+    }
+    let lines = payload.lines.unwrap_or(DEFAULT_PATTERN_TEST_LINES);
+    if lines == 0 || lines > logs::MAX_LINES {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("lines must be between 1 and {}", logs::MAX_LINES),
+        })).into_response();
+    }
+    let pattern = regex::Regex::new(&payload.pattern).expect("checked by validate_start_patterns");
+
+    let container_id = match state.manager.get_container(&id).await {
+        Ok(Some(container)) => container.container_id,
+        Ok(None) => {
+            return (StatusCode::NOT_FOUND, Json(ErrorResponse {
+                error: "Container not found".to_string(),
+            })).into_response();
+        }
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+                error: e.to_string(),
+            })).into_response();
+        }
+    };
+    let Some(container_id) = container_id else {
+        return (StatusCode::CONFLICT, Json(ErrorResponse {
+            error: "Container has no Docker container yet".to_string(),
+        })).into_response();
+    };
+
+    // Docker's log, so output from before a daemon restart counts too
+    let range = logs::LogRange {
+        tail: Some(lines),
+        limit: lines,
+        ..Default::default()
+    };
+    match state.lifecycle.read_logs(&container_id, &range).await {
+        Ok(page) => (StatusCode::OK, Json(logs::test_start_pattern(&pattern, page.lines))).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: e.to_string(),
+        })).into_response(),
+    }
+}
+
+/// Update container graceful stop sequence
+async fn update_stop_config(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<UpdateStopConfigRequest>,
+) -> Response {
+    match state.manager.update_stop_config(&id, payload.stop_command, payload.stop_timeout).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(SuccessResponse {
+                message: "Stop config updated".to_string(),
+            }),
+        ).into_response(),
+        Err(e) => (
+            error::status_code(&*e),
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        ).into_response(),
+    }
+}
+
+// === Power Action Handlers ===
+
+/// Map a power action error to a status code (409 when another action is running)
+fn power_error_status(e: &(dyn std::error::Error + Send + Sync + 'static)) -> StatusCode {
+    if e.downcast_ref::<PowerBusy>().is_some() {
+        StatusCode::CONFLICT
+    } else {
+        error::status_code(e)
+    }
+}
+
+/// Default and maximum number of bulk power actions running at once
+const DEFAULT_BULK_CONCURRENCY: usize = 5;
+const MAX_BULK_CONCURRENCY: usize = 50;
+
+#[derive(Deserialize)]
+struct BulkPowerRequest {
+    /// start, stop, kill or restart
+    action: String,
+    /// Containers to act on, or use `filter`
+    internal_ids: Option<Vec<String>>,
+    filter: Option<BulkPowerFilter>,
+    /// Actions running at once
+    concurrency: Option<usize>,
+}
+
+/// Same fields as the GET /containers query, `{}` matches every container
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BulkPowerFilter {
+    state: Option<String>,
+    image: Option<String>,
+    label: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BulkPowerResult {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct BulkPowerResponse {
+    action: String,
+    succeeded: usize,
+    failed: usize,
+    results: std::collections::BTreeMap<String, BulkPowerResult>,
+}
+
+/// Run one power action on many containers, a few at a time, and wait for all of them
+async fn bulk_power(
+    State(state): State<ContainerAppState>,
+    Json(payload): Json<BulkPowerRequest>,
+) -> Response {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response();
+
+    let action = match payload.action.to_lowercase().as_str() {
+        "start" => PowerAction::Start,
+        "stop" => PowerAction::Stop,
+        "kill" => PowerAction::Kill,
+        "restart" => PowerAction::Restart,
+        "pause" => PowerAction::Pause,
+        "resume" => PowerAction::Unpause,
+        other => return bad_request(format!("Unknown action '{}', expected start, stop, kill, restart, pause or resume", other)),
+    };
+
+    let internal_ids = match (payload.internal_ids, payload.filter) {
+        (Some(ids), None) => ids,
+        (None, Some(filter)) => {
+            let install_state = match filter.state.as_deref().map(str::parse::<InstallState>) {
+                Some(Ok(s)) => Some(s),
+                Some(Err(e)) => return bad_request(e),
+                None => None,
+            };
+            let filter = ContainerFilter {
+                install_state,
+                image: filter.image.filter(|i| !i.is_empty()),
+                labels: filter.label.as_deref().map(ContainerFilter::parse_labels).unwrap_or_default(),
+            };
+            match state.manager.list_containers().await {
+                Ok(containers) => containers
+                    .into_iter()
+                    .filter(|c| filter.matches(c))
+                    .map(|c| c.internal_id)
+                    .collect(),
+                Err(e) => return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse { error: e.to_string() }),
+                ).into_response(),
+            }
+        }
+        _ => return bad_request("Give either internal_ids or filter".to_string()),
+    };
+
+    let concurrency = payload.concurrency.unwrap_or(DEFAULT_BULK_CONCURRENCY).clamp(1, MAX_BULK_CONCURRENCY);
+    tracing::info!("Bulk {:?} of {} containers, {} at a time", action, internal_ids.len(), concurrency);
+
+    let power = state.power.clone();
+    let results: std::collections::BTreeMap<String, BulkPowerResult> = futures::stream::iter(internal_ids)
+        .map(|internal_id| {
+            let power = power.clone();
+            let action = action.clone();
+            async move {
+                let result = match power.run_action(internal_id.clone(), action).await {
+                    Ok(()) => BulkPowerResult { success: true, error: None },
+                    Err(e) => BulkPowerResult { success: false, error: Some(e.to_string()) },
+                };
+                (internal_id, result)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+
+    let succeeded = results.values().filter(|r| r.success).count();
+    (StatusCode::OK, Json(BulkPowerResponse {
+        action: payload.action.to_lowercase(),
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    })).into_response()
+}
+
+#[derive(Serialize)]
+struct StartReadyResponse {
+    internal_id: String,
+    #[serde(flatten)]
+    readiness: Readiness,
+}
+
 #[axum::debug_handler]
-async fn repair_container(
+async fn start_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
-    Json(payload): Json<RepairContainerRequest>,
+    Query(query): Query<StartQuery>,
 ) -> Response {
-    match state.lifecycle.repair_if_corrupted(id.clone(), payload.image).await {
-        Ok(repaired) => {
-            let message = if repaired {
-                "Container corruption detected and repair started".to_string()
-            } else {
-                "Container is healthy, no repair needed".to_string()
-            };
+    if query.wait {
+        return start_and_wait(state, id, query.timeout.unwrap_or(DEFAULT_START_WAIT_SECS)).await;
+    }
 
-            (StatusCode::OK, Json(RepairResponse {
-                internal_id: id,
-                repaired,
-                message,
-            })).into_response()
-        }
+    match state.power.execute_action(id.clone(), PowerAction::Start).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(SuccessResponse {
+                message: format!("Container {} start initiated", id),
+            }),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
-        ).into_response(),
+        )
+            .into_response(),
     }
 }
 
-/// Validate container state and check for corruption
-async fn validate_container(
-    State(state): State<ContainerAppState>,
-    Path(id): Path<String>,
-) -> Response {
-    // First validate DB state
-    let (is_valid, issue) = match state.manager.validate_container(&id).await {
-        Ok(result) => result,
-        Err(e) => {
-            return (
-                StatusCode::NOT_FOUND,
-                Json(ErrorResponse {
-                    error: e.to_string(),
-                }),
-            ).into_response();
-        }
-    };
+/// Start, then hold the response until the start pattern matched (200) or the
+/// server exited or `timeout` passed (503), with the final state either way
+async fn start_and_wait(state: ContainerAppState, id: String, timeout: u64) -> Response {
+    if timeout == 0 || timeout > MAX_START_WAIT_SECS {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse {
+            error: format!("timeout must be between 1 and {} seconds", MAX_START_WAIT_SECS),
+        })).into_response();
+    }
 
-    // Then check Docker sync
-    let docker_synced = match state.lifecycle.verify_container_sync(&id).await {
-        Ok(synced) => synced,
-        Err(_) => false,
-    };
+    // Only the console watcher moves the state on, so make sure one runs, and
+    // start from `starting` rather than whatever the last run left behind
+    let previous = state.manager.runtime_state(&id);
+    if let Err(e) = state.console.start_streaming(id.clone()).await {
+        tracing::warn!("Failed to start console streaming for {}: {}", id, e);
+    }
+    state.event_hub.set_runtime_state(&id, ContainerRuntimeState::Starting).await;
 
-    (StatusCode::OK, Json(ValidateResponse {
-        internal_id: id,
-        is_valid: is_valid && docker_synced,
-        issue,
-        docker_synced,
-    })).into_response()
+    // Unlike the spawned start, run_action reports a failed Docker start right here
+    if let Err(e) = state.power.run_action(id.clone(), PowerAction::Start).await {
+        let previous = previous.and_then(|s| s.parse().ok()).unwrap_or(ContainerRuntimeState::Offline);
+        state.event_hub.set_runtime_state(&id, previous).await;
+        return (power_error_status(&*e), Json(ErrorResponse {
+            error: e.to_string(),
+        })).into_response();
+    }
+
+    match state.power.wait_until_ready(&id, std::time::Duration::from_secs(timeout)).await {
+        Ok(readiness) => {
+            let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+            (status, Json(StartReadyResponse { internal_id: id, readiness })).into_response()
+        }
+        Err(e) => (error::status_code(&*e), Json(ErrorResponse {
+            error: e.to_string(),
+        })).into_response(),
+    }
 }
 
-/// Get detailed container status
-async fn get_container_status(
+#[axum::debug_handler]
+async fn stop_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
 ) -> Response {
-    match state.manager.get_container(&id).await {
-        Ok(Some(container)) => {
-            // Validate for corruption
-            let (is_healthy, corruption_issue) = match state.manager.validate_container(&id).await {
-                Ok((valid, issue)) => (valid, issue),
-                Err(_) => (false, Some("Validation error".to_string())),
-            };
-
-            let install_state_str = match container.install_state {
-                InstallState::Ready => "ready",
-                InstallState::Installing => "installing",
-                InstallState::Failed => "failed",
-            };
-
-            (StatusCode::OK, Json(ContainerStatusResponse {
-                internal_id: container.internal_id,
-                install_state: install_state_str.to_string(),
-                is_installing: container.is_installing,
-                container_id: container.container_id,
-                is_healthy,
-                corruption_issue,
-            })).into_response()
-        }
-        Ok(None) => (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Container not found".to_string(),
+    match state.power.execute_action(id.clone(), PowerAction::Stop).await {
+        Ok(_) => (
+            StatusCode::OK,
+            Json(SuccessResponse {
+                message: format!("Container {} stop initiated", id),
             }),
-        ).into_response(),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
-        ).into_response(),
+        )
+            .into_response(),
     }
 }
 
-// === Update Handlers ===
-
-/// Update container startup command
-async fn update_startup_command(
+#[axum::debug_handler]
+async fn kill_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
-    Json(payload): Json<UpdateStartupRequest>,
 ) -> Response {
-    match state.manager.update_startup_command(&id, payload.startup_command).await {
+    match state.power.execute_action(id.clone(), PowerAction::Kill).await {
         Ok(_) => (
             StatusCode::OK,
             Json(SuccessResponse {
-                message: "Startup command updated".to_string(),
+                message: format!("Container {} kill initiated", id),
             }),
-        ).into_response(),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::NOT_FOUND,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
-        ).into_response(),
+        )
+            .into_response(),
     }
 }
 
-/// Update container start pattern
-async fn update_start_pattern(
+#[axum::debug_handler]
+async fn restart_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
-    Json(payload): Json<UpdateStartPatternRequest>,
 ) -> Response {
-    match state.manager.update_start_pattern(&id, payload.start_pattern).await {
+    match state.power.execute_action(id.clone(), PowerAction::Restart).await {
         Ok(_) => (
             StatusCode::OK,
             Json(SuccessResponse {
-                message: "Start pattern updated".to_string(),
+                message: format!("Container {} restart initiated", id),
             }),
-        ).into_response(),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::NOT_FOUND,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
-        ).into_response(),
+        )
+            .into_response(),
     }
 }
 
-// === Power Action Handlers ===
-
+/// Freeze the container without stopping it
 #[axum::debug_handler]
-async fn start_container(
+async fn pause_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
 ) -> Response {
-    match state.power.execute_action(id.clone(), PowerAction::Start).await {
+    match state.power.execute_action(id.clone(), PowerAction::Pause).await {
         Ok(_) => (
             StatusCode::OK,
             Json(SuccessResponse {
-                message: format!("Container {} start initiated", id),
+                message: format!("Container {} pause initiated", id),
             }),
         )
             .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -574,20 +2201,20 @@ async fn start_container(
 }
 
 #[axum::debug_handler]
-async fn kill_container(
+async fn resume_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
 ) -> Response {
-    match state.power.execute_action(id.clone(), PowerAction::Kill).await {
+    match state.power.execute_action(id.clone(), PowerAction::Unpause).await {
         Ok(_) => (
             StatusCode::OK,
             Json(SuccessResponse {
-                message: format!("Container {} kill initiated", id),
+                message: format!("Container {} resume initiated", id),
             }),
         )
             .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -597,20 +2224,34 @@ async fn kill_container(
 }
 
 #[axum::debug_handler]
-async fn restart_container(
+async fn signal_container(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
+    Json(payload): Json<SignalRequest>,
 ) -> Response {
-    match state.power.execute_action(id.clone(), PowerAction::Restart).await {
+    let signal = match normalize_signal(&payload.signal) {
+        Some(signal) => signal,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: format!("Signal not allowed: {}", payload.signal),
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    match state.power.execute_action(id.clone(), PowerAction::Signal(signal.clone())).await {
         Ok(_) => (
             StatusCode::OK,
             Json(SuccessResponse {
-                message: format!("Container {} restart initiated", id),
+                message: format!("Sending {} to container {}", signal, id),
             }),
         )
             .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            power_error_status(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -619,7 +2260,6 @@ async fn restart_container(
     }
 }
 
-
 // === Network Rebinding Handler ===
 
 #[derive(Deserialize)]
@@ -642,8 +2282,79 @@ async fn rebind_network(
             }),
         )
             .into_response(),
+        Err(e) if e.downcast_ref::<HostPortInUse>().is_some() => (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
         Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            error::status_code(&*e),
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct ChangeImageRequest {
+    image: String,
+}
+
+/// Recreate the container on another image, keeping its volume and settings
+async fn change_image(
+    State(state): State<ContainerAppState>,
+    Path(id): Path<String>,
+    Json(payload): Json<ChangeImageRequest>,
+) -> Response {
+    if payload.image.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "image cannot be empty".to_string(),
+            }),
+        ).into_response();
+    }
+    match state.manager.get_container(&id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Container not found".to_string(),
+                }),
+            ).into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response();
+        }
+    }
+
+    match state.network.change_image(id.clone(), payload.image.clone()).await {
+        Ok(_) => (
+            StatusCode::ACCEPTED,
+            Json(SuccessResponse {
+                message: format!("Changing image of container {} to {}", id, payload.image.trim()),
+            }),
+        )
+            .into_response(),
+        Err(e) if e.downcast_ref::<ContainerRunning>().is_some() || e.downcast_ref::<InstallRunning>().is_some() => (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            error::status_code(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -672,11 +2383,14 @@ struct UpdateResourcesRequest {
     cpuset_cpus: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     blkio_weight: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpus: Option<f64>, // CPU cores, e.g. 2.0
 }
 
 #[derive(Deserialize)]
 struct UpdateVolumesRequest {
-    volumes: HashMap<String, String>,
+    /// Container path -> host path or `{"source", "read_only"}`
+    volumes: HashMap<String, MountSpec>,
 }
 
 #[derive(Serialize)]
@@ -689,6 +2403,7 @@ struct ResourcesResponse {
     cpu_quota: Option<i64>,
     cpuset_cpus: Option<String>,
     blkio_weight: Option<u16>,
+    cpus: Option<f64>,
 }
 
 /// Update container resource limits (live, no restart)
@@ -697,20 +2412,6 @@ async fn update_resources(
     Path(id): Path<String>,
     Json(payload): Json<UpdateResourcesRequest>,
 ) -> Response {
-    // Create updater
-    let updater = match ContainerUpdater::new(state.manager.clone()) {
-        Ok((updater, _rx)) => updater,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to create updater: {}", e),
-                }),
-            )
-                .into_response();
-        }
-    };
-
     let limits = ResourceLimits {
         memory: payload.memory,
         memory_swap: payload.memory_swap,
@@ -720,9 +2421,10 @@ async fn update_resources(
         cpu_quota: payload.cpu_quota,
         cpuset_cpus: payload.cpuset_cpus,
         blkio_weight: payload.blkio_weight,
+        cpus: payload.cpus,
     };
 
-    match updater.update_resources(id.clone(), limits).await {
+    match state.updater.update_resources(id.clone(), limits).await {
         Ok(()) => (
             StatusCode::ACCEPTED,
             Json(SuccessResponse {
@@ -745,20 +2447,7 @@ async fn get_resources(
     State(state): State<ContainerAppState>,
     Path(id): Path<String>,
 ) -> Response {
-    let updater = match ContainerUpdater::new(state.manager.clone()) {
-        Ok((updater, _rx)) => updater,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to create updater: {}", e),
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match updater.get_current_resources(&id).await {
+    match state.updater.get_current_resources(&id).await {
         Ok(limits) => (
             StatusCode::OK,
             Json(ResourcesResponse {
@@ -770,11 +2459,12 @@ async fn get_resources(
                 cpu_quota: limits.cpu_quota,
                 cpuset_cpus: limits.cpuset_cpus,
                 blkio_weight: limits.blkio_weight,
+                cpus: limits.cpus,
             }),
         )
             .into_response(),
         Err(e) => (
-            StatusCode::NOT_FOUND,
+            error::status_code(&*e),
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
@@ -789,20 +2479,7 @@ async fn update_volumes(
     Path(id): Path<String>,
     Json(payload): Json<UpdateVolumesRequest>,
 ) -> Response {
-    let updater = match ContainerUpdater::new(state.manager.clone()) {
-        Ok((updater, _rx)) => updater,
-        Err(e) => {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to create updater: {}", e),
-                }),
-            )
-                .into_response();
-        }
-    };
-
-    match updater.update_volumes(id.clone(), payload.volumes).await {
+    match state.updater.update_volumes(id.clone(), payload.volumes, state.lifecycle.mount_policy()).await {
         Ok(()) => (
             StatusCode::ACCEPTED,
             Json(SuccessResponse {
@@ -0,0 +1,50 @@
+//! CORS layer built from `server.cors`
+//!
+//! Without a `server.cors` section any origin is allowed in `--dev` and no
+//! cross-origin browser requests are allowed otherwise, so a production node
+//! only answers the panel once its origin is configured. Values were checked
+//! by `Config::validate` at startup.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+use crate::config::config::CorsConfig;
+
+pub fn cors_layer(config: Option<&CorsConfig>, dev: bool) -> CorsLayer {
+    let layer = CorsLayer::new().expose_headers([super::request_id::X_REQUEST_ID.clone()]);
+
+    let Some(config) = config else {
+        if dev {
+            return layer.allow_origin(Any).allow_methods(Any).allow_headers(Any);
+        }
+        tracing::warn!("server.cors is not set, browsers can't call the API from other origins");
+        return layer;
+    };
+
+    let wildcard = |values: &[String]| values.iter().any(|v| v == "*");
+
+    let origins = if wildcard(&config.allowed_origins) {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(config.allowed_origins.iter().filter_map(|o| HeaderValue::from_str(o).ok()))
+    };
+    let methods = if wildcard(&config.allowed_methods) {
+        AllowMethods::any()
+    } else {
+        AllowMethods::list(
+            config.allowed_methods.iter().filter_map(|m| Method::from_bytes(m.to_uppercase().as_bytes()).ok()),
+        )
+    };
+    let headers = if wildcard(&config.allowed_headers) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(config.allowed_headers.iter().filter_map(|h| HeaderName::from_bytes(h.as_bytes()).ok()))
+    };
+
+    tracing::info!("CORS allows origins: {}", config.allowed_origins.join(", "));
+    layer
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.allow_credentials)
+}
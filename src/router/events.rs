@@ -0,0 +1,70 @@
+//! Node-wide event stream over Server-Sent Events
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::get,
+    Router,
+};
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::daemon::events::EventBus;
+use crate::daemon::shutdown::ShutdownSignal;
+
+#[derive(Clone)]
+pub struct EventsState {
+    pub bus: Arc<EventBus>,
+    /// Ends open streams so graceful shutdown doesn't wait on them
+    pub shutdown: ShutdownSignal,
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Only events for this container, node events like `docker_connected` are left out
+    container_id: Option<String>,
+}
+
+pub fn events_router(state: EventsState) -> Router {
+    Router::new()
+        .route("/events", get(stream_events))
+        .with_state(state)
+}
+
+/// Everything published after the client connected, nothing is replayed
+async fn stream_events(
+    State(state): State<EventsState>,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.bus.subscribe();
+    let stream = stream::unfold((rx, state.shutdown, query.container_id), |(mut rx, mut shutdown, filter)| async move {
+        loop {
+            let received = tokio::select! {
+                _ = shutdown.recv() => return None,
+                received = rx.recv() => received,
+            };
+            let event = match received {
+                Ok(event) => event,
+                // The client missed events, tell it so it can refetch state
+                Err(RecvError::Lagged(skipped)) => {
+                    let event = Event::default().event("lagged").data(skipped.to_string());
+                    return Some((Ok(event), (rx, shutdown, filter)));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if filter.as_ref().is_some_and(|id| event.container_id.as_ref() != Some(id)) {
+                continue;
+            }
+            let sse = Event::default()
+                .id(event.id.to_string())
+                .json_data(&event)
+                .unwrap_or_else(|_| Event::default().comment("unserializable event"));
+            return Some((Ok(sse), (rx, shutdown, filter)));
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
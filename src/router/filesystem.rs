@@ -1,19 +1,34 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post, delete},
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post, put, delete},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
 
 use crate::filesystem::handler::VolumeHandler;
 use crate::filesystem::fileinfo::FileObject;
+use crate::filesystem::backup::BackupInfo;
+use crate::filesystem::handler::ArchiveTooLarge;
+use crate::filesystem::hash::{self, HashAlgorithm};
+use crate::filesystem::trash::{TrashItem, TrashSettings};
+use crate::filesystem::transfer::{self, FileVersion, PreconditionFailed, UnexpectedOffset};
+use crate::config::config::HttpLimitsConfig;
+use crate::container::template::TemplateManager;
+use crate::error::{self, LightdError};
+use crate::filesystem::skeleton::{merge_skeletons, validate_skeleton, SkeletonFile};
+use crate::router::limits;
 
 #[derive(Clone)]
 pub struct AppState {
     pub volume_handler: Arc<VolumeHandler>,
+    /// Stored skeletons for `template_id` on create
+    pub templates: Arc<TemplateManager>,
 }
 
 #[derive(Serialize)]
@@ -49,6 +64,68 @@ struct WriteFileRequest {
 struct WriteFileResponse {
     success: bool,
     path: String,
+    size: u64,
+    etag: String,
+}
+
+impl From<FileVersion> for WriteFileResponse {
+    fn from(version: FileVersion) -> Self {
+        Self {
+            success: true,
+            path: version.path.to_string_lossy().to_string(),
+            size: version.size,
+            etag: version.etag,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FilePathQuery {
+    path: String,
+}
+
+#[derive(Serialize)]
+struct DeletePathResponse {
+    success: bool,
+    /// Set when the volume has the trash enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trashed: Option<TrashItem>,
+}
+
+#[derive(Serialize)]
+struct TrashResponse {
+    enabled: bool,
+    retention_secs: u64,
+    items: Vec<TrashItem>,
+}
+
+#[derive(Serialize)]
+struct PurgeResponse {
+    purged: usize,
+}
+
+#[derive(Deserialize)]
+struct UsageQuery {
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct HashQuery {
+    path: String,
+    #[serde(default = "default_hash_algorithm")]
+    algo: String,
+}
+
+fn default_hash_algorithm() -> String {
+    "sha256".to_string()
+}
+
+#[derive(Serialize)]
+struct HashResponse {
+    path: String,
+    algorithm: HashAlgorithm,
+    size_bytes: u64,
+    digest: String,
 }
 
 #[derive(Deserialize)]
@@ -101,9 +178,14 @@ struct CompressResponse {
     path: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 struct CreateVolumeRequest {
     size: Option<u64>, // Size in MB
+    /// Default files written into the new volume
+    #[serde(default)]
+    files: Vec<SkeletonFile>,
+    /// Container template whose `files` are written first, `files` override them by path
+    template_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -119,51 +201,110 @@ struct ResizeVolumeRequest {
     size: u64, // New size in MB
 }
 
-pub fn volume_router(volume_handler: Arc<VolumeHandler>) -> Router {
-    let state = AppState { volume_handler };
+#[derive(Deserialize)]
+struct CreateBackupRequest {
+    name: Option<String>,
+}
 
-    Router::new()
+#[derive(Serialize)]
+struct BackupsResponse {
+    backups: Vec<BackupInfo>,
+}
+
+/// Map backup errors to a status, VolumeHandler/BackupStore return a `LightdError` for the expected ones
+fn backup_error(e: Box<dyn std::error::Error + Send + Sync>) -> (StatusCode, Json<ErrorResponse>) {
+    (error::status_code(&*e), Json(ErrorResponse { error: e.to_string() }))
+}
+
+pub fn volume_router(volume_handler: Arc<VolumeHandler>, templates: Arc<TemplateManager>, limits: &HttpLimitsConfig) -> Router {
+    let state = AppState { volume_handler, templates };
+
+    // Whole files move through these, they get the transfer limits
+    let transfers = Router::new()
+        .route("/volumes/:id/download", get(download_file))
+        .route("/volumes/:id/upload", put(upload_file))
+        .route("/volumes/:id/copy", post(copy_file_or_folder))
+        .route("/volumes/:id/decompress", post(decompress_archive))
+        .route("/volumes/:id/compress", post(compress_files))
+        .with_state(state.clone());
+
+    let api = Router::new()
         .route("/volumes", post(create_volume))
         .route("/volumes", get(list_volumes))
         .route("/volumes/:id", delete(delete_volume))
         .route("/volumes/:id/files", get(list_files))
         .route("/volumes/:id/files/detailed", get(list_files_detailed))
         .route("/volumes/:id/write", post(write_file))
+        .route("/volumes/:id/files", delete(delete_path))
+        .route("/volumes/:id/trash", get(list_trash).delete(empty_trash))
+        .route("/volumes/:id/trash/settings", put(set_trash_settings))
+        .route("/volumes/:id/trash/:item_id", delete(purge_trash_item))
+        .route("/volumes/:id/trash/:item_id/restore", post(restore_trash_item))
+        .route("/volumes/:id/usage", get(directory_usage))
+        .route("/volumes/:id/hash", get(hash_file))
+        .route("/volumes/:id/hash/:job_id", get(get_hash_job))
         .route("/volumes/:id/create-folder", post(create_folder))
-        .route("/volumes/:id/copy", post(copy_file_or_folder))
-        .route("/volumes/:id/decompress", post(decompress_archive))
-        .route("/volumes/:id/compress", post(compress_files))
         .route("/volumes/:id/quota", get(get_volume_quota))
         .route("/volumes/:id/resize", post(resize_volume))
-        .with_state(state)
+        .route("/volumes/:id/backups", post(create_backup))
+        .route("/volumes/:id/backups", get(list_backups))
+        .route("/volumes/:id/backups/:backup_id", delete(delete_backup))
+        .route("/volumes/:id/backups/:backup_id/restore", post(restore_backup))
+        .with_state(state);
+
+    limits::api(api, limits).merge(limits::transfers(transfers, limits))
 }
 
 async fn create_volume(
     State(state): State<AppState>,
     body: Option<Json<CreateVolumeRequest>>,
 ) -> Result<Json<VolumeResponse>, (StatusCode, Json<ErrorResponse>)> {
-    let size_mb = body.and_then(|b| b.size);
-    
-    let result = if size_mb.is_some() {
-        state.volume_handler.create_volume_with_quota(size_mb).await
-    } else {
-        state.volume_handler.create_volume().await
+    let request = body.map(|Json(request)| request).unwrap_or_default();
+    let files = match request.template_id {
+        Some(template_id) => match state.templates.get(&template_id) {
+            Ok(Some(template)) => merge_skeletons(template.files, request.files),
+            Ok(None) => return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse { error: format!("Template '{}' not found", template_id) }),
+            )),
+            Err(e) => return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error: e.to_string() }),
+            )),
+        },
+        None => request.files,
     };
+    // Before the volume exists, so a bad path doesn't leave one behind
+    if let Err(e) = validate_skeleton(&files) {
+        return Err((StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })));
+    }
     
-    match result {
-        Ok(volume) => Ok(Json(VolumeResponse {
-            id: volume.id,
-            path: volume.path.to_string_lossy().to_string(),
-            created_at: volume.created_at,
-            quota_mb: volume.quota_mb,
-        })),
-        Err(e) => Err((
+    let volume = match state.volume_handler.create_volume_with_quota(request.size).await {
+        Ok(volume) => volume,
+        Err(e) => return Err((
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
                 error: e.to_string(),
             }),
         )),
+    };
+    
+    // The error isn't Send, it can't be held across the cleanup
+    let seeded = state.volume_handler.seed_files(&volume.id, &files).await.map_err(write_error);
+    if let Err(response) = seeded {
+        // A partly seeded volume isn't what was asked for
+        if let Err(delete_err) = state.volume_handler.delete_volume(&volume.id).await {
+            tracing::error!("Failed to remove volume {} after seeding failed: {}", volume.id, delete_err);
+        }
+        return Err(response);
     }
+    
+    Ok(Json(VolumeResponse {
+        id: volume.id,
+        path: volume.path.to_string_lossy().to_string(),
+        created_at: volume.created_at,
+        quota_mb: volume.quota_mb,
+    }))
 }
 
 async fn list_volumes(
@@ -213,24 +354,288 @@ async fn list_files(
     }
 }
 
+/// 507 when the volume's quota has no room for the write, 500 otherwise
+fn write_error(e: Box<dyn std::error::Error>) -> (StatusCode, Json<ErrorResponse>) {
+    let status = if let Some(e) = e.downcast_ref::<LightdError>() {
+        e.status_code()
+    } else if e.downcast_ref::<ArchiveTooLarge>().is_some() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else if e.downcast_ref::<PreconditionFailed>().is_some() {
+        StatusCode::PRECONDITION_FAILED
+    } else if e.downcast_ref::<UnexpectedOffset>().is_some() {
+        StatusCode::RANGE_NOT_SATISFIABLE
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    (status, Json(ErrorResponse { error: e.to_string() }))
+}
+
+/// Errors of reading a file, anything but a `LightdError` is a bad path
+fn read_error(e: Box<dyn std::error::Error>) -> Response {
+    let error = e.to_string();
+    let status = match e.downcast_ref::<LightdError>() {
+        Some(e) => e.status_code(),
+        None => StatusCode::BAD_REQUEST,
+    };
+    (status, Json(ErrorResponse { error })).into_response()
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Write a text file, `If-Match` makes it fail with 412 if the file changed
 async fn write_file(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    headers: HeaderMap,
     Json(payload): Json<WriteFileRequest>,
-) -> Result<Json<WriteFileResponse>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<([(header::HeaderName, String); 1], Json<WriteFileResponse>), (StatusCode, Json<ErrorResponse>)> {
     let content = payload.content.unwrap_or_default();
+    let if_match = header_str(&headers, header::IF_MATCH);
     
-    match state.volume_handler.write_file(&id, &payload.filename, &content).await {
-        Ok(path) => Ok(Json(WriteFileResponse {
-            success: true,
-            path: path.to_string_lossy().to_string(),
-        })),
-        Err(e) => Err((
+    match state.volume_handler.write_file(&id, &payload.filename, &content, if_match).await {
+        Ok(version) => Ok(([(header::ETAG, version.etag.clone())], Json(version.into()))),
+        Err(e) => Err(write_error(e)),
+    }
+}
+
+/// Download a file. A single `Range` is served as 206, `If-Range` only
+/// applies it while the file still has the given ETag.
+async fn download_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FilePathQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let (mut file, meta) = match state.volume_handler.open_file(&id, &query.path).await {
+        Ok(opened) => opened,
+        Err(e) => return read_error(e),
+    };
+    let size = meta.len();
+    let etag = transfer::weak_etag(&meta);
+
+    // A stale If-Range means the file changed, the client gets all of it again
+    let range_applies = header_str(&headers, header::IF_RANGE)
+        .is_none_or(|if_range| transfer::etag_matches(if_range, &etag));
+    let range = match header_str(&headers, header::RANGE) {
+        Some(value) if range_applies => match transfer::parse_range(value, size) {
+            Ok(range) => range,
+            Err(_) => return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+            ).into_response(),
+        },
+        _ => None,
+    };
+
+    let name = std::path::Path::new(&query.path)
+        .file_name()
+        .map(|n| n.to_string_lossy().replace('"', ""))
+        .unwrap_or_else(|| "download".to_string());
+    let common = [
+        (header::ETAG, etag),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+        (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+    ];
+
+    let Some(range) = range else {
+        return (
+            StatusCode::OK,
+            common,
+            [(header::CONTENT_LENGTH, size.to_string())],
+            Body::from_stream(ReaderStream::new(file)),
+        ).into_response();
+    };
+
+    if let Err(e) = file.seek(std::io::SeekFrom::Start(range.start)).await {
+        return (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )),
+            Json(ErrorResponse { error: e.to_string() }),
+        ).into_response();
+    }
+    let length = range.end - range.start + 1;
+    (
+        StatusCode::PARTIAL_CONTENT,
+        common,
+        [
+            (header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end, size)),
+            (header::CONTENT_LENGTH, length.to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(file.take(length))),
+    ).into_response()
+}
+
+/// Upload a raw body to a file. `Content-Range` writes one chunk of a
+/// resumable upload, `If-Match` fails with 412 if the file changed.
+async fn upload_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FilePathQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let range = match header_str(&headers, header::CONTENT_RANGE).map(transfer::parse_content_range) {
+        Some(Ok(range)) => Some(range),
+        Some(Err(error)) => return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response(),
+        None => None,
+    };
+    let if_match = header_str(&headers, header::IF_MATCH);
+    let size_hint = header_str(&headers, header::CONTENT_LENGTH).and_then(|v| v.parse().ok());
+
+    match state.volume_handler
+        .upload_file(&id, &query.path, body.into_data_stream(), range, if_match, size_hint)
+        .await
+    {
+        Ok(version) => (
+            StatusCode::OK,
+            [(header::ETAG, version.etag.clone())],
+            Json(WriteFileResponse::from(version)),
+        ).into_response(),
+        Err(e) => write_error(e).into_response(),
+    }
+}
+
+/// Trash errors come as io::Error, their kind picks the status
+fn trash_error(e: Box<dyn std::error::Error>) -> Response {
+    let status = match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+        Some(std::io::ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+        Some(std::io::ErrorKind::AlreadyExists) => StatusCode::CONFLICT,
+        Some(std::io::ErrorKind::PermissionDenied) => StatusCode::FORBIDDEN,
+        _ => error::status_code(&*e),
+    };
+    (status, Json(ErrorResponse { error: e.to_string() })).into_response()
+}
+
+/// Delete a file or folder, into the trash if the volume has it enabled
+async fn delete_path(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<FilePathQuery>,
+) -> Response {
+    match state.volume_handler.delete_path(&id, &query.path).await {
+        Ok(trashed) => Json(DeletePathResponse { success: true, trashed }).into_response(),
+        Err(e) if e.downcast_ref::<std::io::Error>().is_some() => trash_error(e),
+        Err(e) => read_error(e),
+    }
+}
+
+async fn list_trash(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    let settings = match state.volume_handler.trash_settings(&id).await {
+        Ok(settings) => settings,
+        Err(e) => return trash_error(e),
+    };
+    match state.volume_handler.list_trash(&id).await {
+        Ok(items) => Json(TrashResponse {
+            enabled: settings.enabled,
+            retention_secs: state.volume_handler.trash_retention_secs(),
+            items,
+        }).into_response(),
+        Err(e) => trash_error(e),
+    }
+}
+
+async fn set_trash_settings(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(settings): Json<TrashSettings>,
+) -> Response {
+    match state.volume_handler.set_trash_settings(&id, settings.clone()).await {
+        Ok(()) => Json(settings).into_response(),
+        Err(e) => trash_error(e),
+    }
+}
+
+async fn restore_trash_item(
+    State(state): State<AppState>,
+    Path((id, item_id)): Path<(String, String)>,
+) -> Response {
+    match state.volume_handler.restore_trash(&id, &item_id).await {
+        Ok(item) => Json(item).into_response(),
+        Err(e) => trash_error(e),
+    }
+}
+
+async fn purge_trash_item(
+    State(state): State<AppState>,
+    Path((id, item_id)): Path<(String, String)>,
+) -> Response {
+    match state.volume_handler.purge_trash(&id, Some(&item_id)).await {
+        Ok(purged) => Json(PurgeResponse { purged }).into_response(),
+        Err(e) => trash_error(e),
+    }
+}
+
+async fn empty_trash(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.volume_handler.purge_trash(&id, None).await {
+        Ok(purged) => Json(PurgeResponse { purged }).into_response(),
+        Err(e) => trash_error(e),
+    }
+}
+
+/// Size of every child of a directory, for a disk usage breakdown
+async fn directory_usage(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<UsageQuery>,
+) -> Response {
+    match state.volume_handler.directory_sizes(&id, query.path.as_deref()).await {
+        Ok(usage) => Json(usage).into_response(),
+        Err(e) => read_error(e),
+    }
+}
+
+/// Checksum of a file. Large files are hashed in the background, the
+/// response is then a 202 with the job to poll.
+async fn hash_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<HashQuery>,
+) -> Response {
+    let algorithm: HashAlgorithm = match query.algo.parse() {
+        Ok(algorithm) => algorithm,
+        Err(error) => return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response(),
+    };
+    let size_bytes = match state.volume_handler.open_file(&id, &query.path).await {
+        Ok((_, meta)) => meta.len(),
+        Err(e) => return read_error(e),
+    };
+
+    if size_bytes > hash::INLINE_LIMIT_BYTES {
+        return match state.volume_handler.start_hash_job(&id, &query.path, algorithm).await {
+            Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+            Err(e) => read_error(e),
+        };
+    }
+
+    match state.volume_handler.hash_file(&id, &query.path, algorithm).await {
+        Ok(digest) => Json(HashResponse {
+            path: query.path,
+            algorithm,
+            size_bytes,
+            digest,
+        }).into_response(),
+        Err(e) => read_error(e),
+    }
+}
+
+async fn get_hash_job(
+    State(state): State<AppState>,
+    Path((id, job_id)): Path<(String, String)>,
+) -> Response {
+    match state.volume_handler.hash_job(&id, &job_id) {
+        Some(job) => Json(job).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse { error: "Hash job not found".to_string() }),
+        ).into_response(),
     }
 }
 
@@ -244,12 +649,7 @@ async fn create_folder(
             success: true,
             path: path.to_string_lossy().to_string(),
         })),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )),
+        Err(e) => Err(write_error(e)),
     }
 }
 
@@ -263,12 +663,7 @@ async fn copy_file_or_folder(
             success: true,
             path: path.to_string_lossy().to_string(),
         })),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )),
+        Err(e) => Err(write_error(e)),
     }
 }
 
@@ -282,12 +677,7 @@ async fn decompress_archive(
             success: true,
             path: path.to_string_lossy().to_string(),
         })),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: e.to_string(),
-            }),
-        )),
+        Err(e) => Err(write_error(e)),
     }
 }
 
@@ -368,3 +758,54 @@ async fn list_files_detailed(
         )),
     }
 }
+
+async fn create_backup(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Option<Json<CreateBackupRequest>>,
+) -> Result<(StatusCode, Json<BackupInfo>), (StatusCode, Json<ErrorResponse>)> {
+    let name = body.and_then(|b| b.0.name).filter(|n| !n.trim().is_empty());
+    if name.as_ref().is_some_and(|n| n.len() > 128) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Backup name must be at most 128 characters".to_string(),
+            }),
+        ));
+    }
+
+    match state.volume_handler.create_backup(&id, name).await {
+        Ok(backup) => Ok((StatusCode::ACCEPTED, Json(backup))),
+        Err(e) => Err(backup_error(e)),
+    }
+}
+
+async fn list_backups(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<BackupsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match state.volume_handler.list_backups(&id).await {
+        Ok(backups) => Ok(Json(BackupsResponse { backups })),
+        Err(e) => Err(backup_error(e)),
+    }
+}
+
+async fn restore_backup(
+    State(state): State<AppState>,
+    Path((id, backup_id)): Path<(String, String)>,
+) -> Result<(StatusCode, Json<BackupInfo>), (StatusCode, Json<ErrorResponse>)> {
+    match state.volume_handler.restore_backup(&id, &backup_id).await {
+        Ok(backup) => Ok((StatusCode::ACCEPTED, Json(backup))),
+        Err(e) => Err(backup_error(e)),
+    }
+}
+
+async fn delete_backup(
+    State(state): State<AppState>,
+    Path((id, backup_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
+    match state.volume_handler.delete_backup(&id, &backup_id).await {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(backup_error(e)),
+    }
+}
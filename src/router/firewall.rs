@@ -1,7 +1,7 @@
 //! Firewall API routes
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::{delete, get, post, put},
@@ -11,6 +11,10 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::error;
+use crate::network::bandwidth::BandwidthLimit;
+use crate::network::blocklist::{self, Blocklist};
+use crate::network::live::{self, Family, LiveChain};
 use crate::network::firewall::{
     DDoSProtection, FirewallAction, FirewallManager, FirewallRule, Protocol, RateLimit,
 };
@@ -65,6 +69,65 @@ struct RulesListResponse {
     rules: Vec<FirewallRule>,
 }
 
+/// Blocklist scope, no container_id means global
+#[derive(Deserialize)]
+struct BlocklistScope {
+    container_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BlocklistEntriesRequest {
+    #[serde(default)]
+    cidrs: Vec<String>,
+    /// Uploaded list, one address or range per line, `#` comments
+    #[serde(default)]
+    list: Option<String>,
+}
+
+impl BlocklistEntriesRequest {
+    fn parse(&self) -> Result<Vec<String>, String> {
+        let mut cidrs = self.cidrs.iter()
+            .map(|c| blocklist::parse_cidr(c))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(list) = &self.list {
+            cidrs.extend(blocklist::parse_list(list)?);
+        }
+        Ok(cidrs)
+    }
+}
+
+#[derive(Serialize)]
+struct BlocklistsResponse {
+    blocklists: Vec<Blocklist>,
+}
+
+#[derive(Serialize)]
+struct MissingChain {
+    family: Family,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct LiveChainsResponse {
+    container_id: String,
+    chains: Vec<LiveChain>,
+    /// Chains lightd would create that aren't installed
+    missing: Vec<MissingChain>,
+    /// Families whose tool or filter table isn't there, nothing could be read for them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    unavailable: Vec<Family>,
+}
+
+#[derive(Serialize)]
+struct BandwidthResponse {
+    container_id: String,
+    /// None means unlimited
+    limit: Option<BandwidthLimit>,
+    /// Whether the limit is in place right now, false until a stopped container starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    applied: Option<bool>,
+}
+
 #[derive(Serialize)]
 struct NetworkResponse {
     network_name: String,
@@ -80,7 +143,21 @@ pub fn firewall_router(manager: Arc<FirewallManager>) -> Router {
         .route("/firewall/rules/:rule_id", delete(delete_rule))
         .route("/firewall/rules/:rule_id/toggle", put(toggle_rule))
         .route("/firewall/rules/container/:container_id", get(get_container_rules))
-        .route("/firewall/ddos/:container_id", post(enable_ddos_protection))
+        .route(
+            "/firewall/ddos/:container_id",
+            post(enable_ddos_protection).get(get_ddos_protection).delete(disable_ddos_protection),
+        )
+        .route(
+            "/firewall/bandwidth/:container_id",
+            put(set_bandwidth_limit).get(get_bandwidth_limit).delete(clear_bandwidth_limit),
+        )
+        .route("/firewall/live/:container_id", get(get_live_chains))
+        .route("/firewall/blocklists", get(list_blocklists))
+        .route("/firewall/blocklists/:name", put(set_blocklist).delete(delete_blocklist))
+        .route(
+            "/firewall/blocklists/:name/entries",
+            post(add_blocklist_entries).delete(remove_blocklist_entries),
+        )
         .route("/firewall/cleanup/:container_id", delete(cleanup_container))
         .with_state(state)
 }
@@ -221,6 +298,9 @@ async fn enable_ddos_protection(
     Path(container_id): Path<String>,
     Json(req): Json<DDoSProtectionRequest>,
 ) -> Response {
+    if let Some(Err(e)) = req.rate_limit.as_ref().map(|rate| rate.validate()) {
+        return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e })).into_response();
+    }
     let protection = DDoSProtection {
         enabled: req.enabled,
         syn_flood_protection: req.syn_flood_protection,
@@ -250,6 +330,308 @@ async fn enable_ddos_protection(
     }
 }
 
+/// Current DDoS protection of a container
+async fn get_ddos_protection(
+    State(state): State<FirewallState>,
+    Path(container_id): Path<String>,
+) -> Response {
+    match state.manager.get_ddos_protection(&container_id).await {
+        Ok(Some(protection)) => (StatusCode::OK, Json(protection)).into_response(),
+        // Same shape as an enabled config so clients can read `enabled` either way
+        Ok(None) => (
+            StatusCode::OK,
+            Json(DDoSProtection {
+                enabled: false,
+                syn_flood_protection: false,
+                connection_limit: None,
+                rate_limit: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Set the container's egress limit
+async fn set_bandwidth_limit(
+    State(state): State<FirewallState>,
+    Path(container_id): Path<String>,
+    Json(limit): Json<BandwidthLimit>,
+) -> Response {
+    if let Err(e) = limit.validate() {
+        return bad_request(e);
+    }
+
+    match state.manager.set_bandwidth_limit(&container_id, limit).await {
+        Ok(applied) => (
+            StatusCode::OK,
+            Json(BandwidthResponse {
+                container_id,
+                limit: Some(limit),
+                applied: Some(applied),
+            }),
+        )
+            .into_response(),
+        // 404 for a container Docker doesn't know
+        Err(e) => (
+            error::status_code(&*e),
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Current egress limit of a container
+async fn get_bandwidth_limit(
+    State(state): State<FirewallState>,
+    Path(container_id): Path<String>,
+) -> Response {
+    match state.manager.get_bandwidth_limit(&container_id).await {
+        Ok(limit) => (
+            StatusCode::OK,
+            Json(BandwidthResponse {
+                container_id,
+                limit,
+                applied: None,
+            }),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Remove the egress limit, the container is unlimited again
+async fn clear_bandwidth_limit(
+    State(state): State<FirewallState>,
+    Path(container_id): Path<String>,
+) -> Response {
+    match state.manager.clear_bandwidth_limit(&container_id).await {
+        Ok(was_set) => {
+            let message = if was_set {
+                "Bandwidth limit removed"
+            } else {
+                "No bandwidth limit was set"
+            };
+            (
+                StatusCode::OK,
+                Json(SuccessResponse {
+                    message: message.to_string(),
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => internal_error(e),
+    }
+}
+
+/// The container's chains as iptables and ip6tables have them right now
+async fn get_live_chains(Path(container_id): Path<String>) -> Response {
+    let id = container_id.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let mut chains = Vec::new();
+        let mut missing = Vec::new();
+        let mut unavailable = Vec::new();
+        for family in [Family::Ipv4, Family::Ipv6] {
+            if !live::available(family) {
+                unavailable.push(family);
+                continue;
+            }
+            for name in live::container_chains(&id) {
+                match live::list_chain(family, &name)? {
+                    Some(chain) => chains.push(chain),
+                    None if family == Family::Ipv4 => missing.push(MissingChain { family, name }),
+                    // lightd only installs IPv4 rules, IPv6 chains show up only when someone added them
+                    None => {}
+                }
+            }
+        }
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>((chains, missing, unavailable))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((chains, missing, unavailable))) => (
+            StatusCode::OK,
+            Json(LiveChainsResponse {
+                container_id,
+                chains,
+                missing,
+                unavailable,
+            }),
+        )
+            .into_response(),
+        Ok(Err(e)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+/// Turn DDoS protection off, also succeeds when it wasn't on
+async fn disable_ddos_protection(
+    State(state): State<FirewallState>,
+    Path(container_id): Path<String>,
+) -> Response {
+    match state.manager.disable_ddos_protection(&container_id).await {
+        Ok(was_enabled) => {
+            let message = if was_enabled {
+                "DDoS protection disabled"
+            } else {
+                "DDoS protection was not enabled"
+            };
+            (
+                StatusCode::OK,
+                Json(SuccessResponse {
+                    message: message.to_string(),
+                }),
+            )
+                .into_response()
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+fn bad_request(error: String) -> Response {
+    (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response()
+}
+
+fn internal_error(e: Box<dyn std::error::Error + Send + Sync>) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Blocklists of one container, or the global ones
+async fn list_blocklists(
+    State(state): State<FirewallState>,
+    Query(scope): Query<BlocklistScope>,
+) -> Response {
+    match state.manager.list_blocklists(scope.container_id.as_deref()).await {
+        Ok(blocklists) => (StatusCode::OK, Json(BlocklistsResponse { blocklists })).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Create or replace a blocklist
+async fn set_blocklist(
+    State(state): State<FirewallState>,
+    Path(name): Path<String>,
+    Query(scope): Query<BlocklistScope>,
+    Json(req): Json<BlocklistEntriesRequest>,
+) -> Response {
+    if let Err(e) = blocklist::validate_name(&name) {
+        return bad_request(e);
+    }
+    let cidrs = match req.parse() {
+        Ok(cidrs) => cidrs,
+        Err(e) => return bad_request(e),
+    };
+
+    match state.manager.set_blocklist(scope.container_id.as_deref(), &name, cidrs).await {
+        Ok(list) => (StatusCode::OK, Json(list)).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Add entries, creating the blocklist if needed
+async fn add_blocklist_entries(
+    State(state): State<FirewallState>,
+    Path(name): Path<String>,
+    Query(scope): Query<BlocklistScope>,
+    Json(req): Json<BlocklistEntriesRequest>,
+) -> Response {
+    if let Err(e) = blocklist::validate_name(&name) {
+        return bad_request(e);
+    }
+    let cidrs = match req.parse() {
+        Ok(cidrs) => cidrs,
+        Err(e) => return bad_request(e),
+    };
+
+    match state.manager.add_blocklist_entries(scope.container_id.as_deref(), &name, cidrs).await {
+        Ok(list) => (StatusCode::OK, Json(list)).into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Remove entries from a blocklist
+async fn remove_blocklist_entries(
+    State(state): State<FirewallState>,
+    Path(name): Path<String>,
+    Query(scope): Query<BlocklistScope>,
+    Json(req): Json<BlocklistEntriesRequest>,
+) -> Response {
+    let cidrs = match req.parse() {
+        Ok(cidrs) => cidrs,
+        Err(e) => return bad_request(e),
+    };
+
+    match state.manager.remove_blocklist_entries(scope.container_id.as_deref(), &name, &cidrs).await {
+        Ok(Some(list)) => (StatusCode::OK, Json(list)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("Blocklist {} not found", name),
+            }),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+/// Remove a blocklist and its DROP rules
+async fn delete_blocklist(
+    State(state): State<FirewallState>,
+    Path(name): Path<String>,
+    Query(scope): Query<BlocklistScope>,
+) -> Response {
+    match state.manager.delete_blocklist(scope.container_id.as_deref(), &name).await {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(SuccessResponse {
+                message: "Blocklist removed".to_string(),
+            }),
+        )
+            .into_response(),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("Blocklist {} not found", name),
+            }),
+        )
+            .into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
 /// Clean up all firewall rules for a container
 async fn cleanup_container(
     State(state): State<FirewallState>,
@@ -0,0 +1,70 @@
+//! Request timeouts and body size caps built from `server.limits`
+//!
+//! Every route gets `request_timeout_secs` and `max_body_bytes`, except the
+//! ones that move or process whole files: uploads, downloads, import/export
+//! and archive operations. Those routers are built separately and get the
+//! `transfer_*` values instead, so main merges them after the API limits.
+//! Routes that may hold the response on purpose, like a start waiting for
+//! readiness, get the longest wait on top of `request_timeout_secs`.
+//!
+//! The timeout covers the handler up to the response headers. A download
+//! streams its body after that, so only a slow start counts against it.
+
+use axum::{
+    extract::DefaultBodyLimit,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Router,
+};
+use serde::Serialize;
+use std::time::Duration;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+
+use crate::config::config::HttpLimitsConfig;
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Limits for regular API routes
+pub fn api(router: Router, limits: &HttpLimitsConfig) -> Router {
+    with_limits(router, limits.request_timeout_secs, limits.max_body_bytes)
+}
+
+/// Limits for uploads, downloads, import/export and archive operations
+pub fn transfers(router: Router, limits: &HttpLimitsConfig) -> Router {
+    with_limits(router, limits.transfer_timeout_secs, limits.transfer_max_body_bytes)
+}
+
+/// Limits for routes that may hold the response for up to `wait_secs`
+pub fn waiting(router: Router, limits: &HttpLimitsConfig, wait_secs: u64) -> Router {
+    with_limits(router, limits.request_timeout_secs + wait_secs, limits.max_body_bytes)
+}
+
+fn with_limits(router: Router, timeout_secs: u64, max_body_bytes: usize) -> Router {
+    router
+        // The layer below does the limiting, axum's own 2 MB cap would undercut it
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(TimeoutLayer::new(Duration::from_secs(timeout_secs)))
+        .layer(axum::middleware::map_response(move |response: Response| async move {
+            json_error(response, timeout_secs, max_body_bytes)
+        }))
+}
+
+/// The layers answer with an empty or plain text body, clients expect `{"error"}`
+fn json_error(response: Response, timeout_secs: u64, max_body_bytes: usize) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    let error = match response.status() {
+        _ if is_json => return response,
+        StatusCode::REQUEST_TIMEOUT => format!("Request did not finish within {} seconds", timeout_secs),
+        StatusCode::PAYLOAD_TOO_LARGE => format!("Request body is larger than {} bytes", max_body_bytes),
+        _ => return response,
+    };
+    (response.status(), Json(ErrorResponse { error })).into_response()
+}
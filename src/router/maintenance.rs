@@ -0,0 +1,107 @@
+//! Node maintenance routes
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::container::images::{ImagePruner, InstallRunning};
+use crate::container::reconcile::Reconciler;
+use crate::daemon::maintenance::Maintenance;
+
+#[derive(Clone)]
+pub struct MaintenanceState {
+    reconciler: Arc<Reconciler>,
+    image_pruner: Arc<ImagePruner>,
+    maintenance: Arc<Maintenance>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct ReconcileQuery {
+    /// Only report what would be removed
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct SetModeRequest {
+    enabled: bool,
+    reason: Option<String>,
+}
+
+pub fn maintenance_router(
+    reconciler: Arc<Reconciler>,
+    image_pruner: Arc<ImagePruner>,
+    maintenance: Arc<Maintenance>,
+) -> Router {
+    Router::new()
+        .route("/maintenance/reconcile", post(reconcile))
+        .route("/maintenance/prune-images", post(prune_images))
+        .route("/maintenance/mode", get(get_mode).put(set_mode))
+        .with_state(MaintenanceState { reconciler, image_pruner, maintenance })
+}
+
+/// Remove (or with `?dry_run=true` just list) orphaned Docker resources
+async fn reconcile(
+    State(state): State<MaintenanceState>,
+    Query(query): Query<ReconcileQuery>,
+) -> Response {
+    match state.reconciler.reconcile(query.dry_run).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e.to_string() }),
+        )
+            .into_response(),
+    }
+}
+
+/// Remove (or with `?dry_run=true` just list) images no container uses
+async fn prune_images(
+    State(state): State<MaintenanceState>,
+    Query(query): Query<ReconcileQuery>,
+) -> Response {
+    match state.image_pruner.prune(query.dry_run).await {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) if e.is::<InstallRunning>() => (
+            StatusCode::CONFLICT,
+            Json(ErrorResponse { error: e.to_string() }),
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: e.to_string() }),
+        )
+            .into_response(),
+    }
+}
+
+async fn get_mode(State(state): State<MaintenanceState>) -> Response {
+    (StatusCode::OK, Json(state.maintenance.status())).into_response()
+}
+
+/// Turn maintenance mode on or off
+async fn set_mode(
+    State(state): State<MaintenanceState>,
+    Json(payload): Json<SetModeRequest>,
+) -> Response {
+    let reason = payload.reason.filter(|r| !r.trim().is_empty());
+    match state.maintenance.set(payload.enabled, reason) {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: format!("Failed to save maintenance mode: {}", e) }),
+        )
+            .into_response(),
+    }
+}
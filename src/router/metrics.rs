@@ -0,0 +1,133 @@
+//! Prometheus metrics endpoint
+//!
+//! Renders the text exposition format by hand from the existing managers, the
+//! numbers are cheap to compute on every scrape.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::billing::tracker::BillingTracker;
+use crate::container::manager::ContainerManager;
+use crate::container::state::InstallState;
+use crate::network::firewall::FirewallManager;
+use crate::network::pool::NetworkPool;
+use crate::websocket::EventHub;
+
+#[derive(Clone)]
+pub struct MetricsState {
+    pub container_manager: Arc<ContainerManager>,
+    pub network_pool: Arc<NetworkPool>,
+    pub firewall_manager: Arc<FirewallManager>,
+    pub billing_tracker: Arc<BillingTracker>,
+    pub event_hub: Arc<EventHub>,
+    /// When false the route is unauthenticated, so only loopback clients are served
+    pub require_auth: bool,
+}
+
+pub fn metrics_router(state: MetricsState) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+/// Builds Prometheus text output, one metric family at a time
+#[derive(Default)]
+struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    fn gauge(&mut self, name: &str, help: &str, samples: &[(&str, f64)]) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} gauge", name);
+        for (labels, value) in samples {
+            if labels.is_empty() {
+                let _ = writeln!(self.out, "{} {}", name, value);
+            } else {
+                let _ = writeln!(self.out, "{}{{{}}} {}", name, labels, value);
+            }
+        }
+    }
+}
+
+async fn metrics(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<MetricsState>,
+) -> Response {
+    if !state.require_auth && !addr.ip().is_loopback() {
+        return (StatusCode::FORBIDDEN, "metrics are only served to local clients\n").into_response();
+    }
+
+    let mut w = MetricsWriter::default();
+
+    match state.container_manager.list_containers().await {
+        Ok(containers) => {
+            let count = |wanted: InstallState| {
+                containers.iter().filter(|c| c.install_state == wanted).count() as f64
+            };
+            w.gauge("lightd_containers", "Containers managed by this node by install state", &[
+                ("state=\"ready\"", count(InstallState::Ready)),
+                ("state=\"installing\"", count(InstallState::Installing)),
+                ("state=\"failed\"", count(InstallState::Failed)),
+            ]);
+        }
+        Err(e) => tracing::warn!("Metrics: failed to list containers: {}", e),
+    }
+
+    match state.network_pool.get_all_ports().await {
+        Ok(ports) => {
+            let in_use = ports.iter().filter(|p| p.in_use).count();
+            w.gauge("lightd_port_pool_ports", "Ports in the allocation pool", &[
+                ("state=\"in_use\"", in_use as f64),
+                ("state=\"free\"", (ports.len() - in_use) as f64),
+            ]);
+        }
+        Err(e) => tracing::warn!("Metrics: failed to read port pool: {}", e),
+    }
+
+    let (rules, enabled_rules) = state.firewall_manager.rule_counts().await;
+    w.gauge("lightd_firewall_rules", "Firewall rules stored on this node", &[
+        ("state=\"enabled\"", enabled_rules as f64),
+        ("state=\"disabled\"", (rules - enabled_rules) as f64),
+    ]);
+
+    let tracked = state.billing_tracker.get_tracked_containers().await;
+    let mut hourly_cost = 0.0;
+    let mut egress_gb = 0.0;
+    for container_id in &tracked {
+        if let Ok(snapshot) = state.billing_tracker.get_usage_snapshot(container_id, 1.0).await {
+            hourly_cost += state.billing_tracker.calculate_cost(&snapshot).await;
+            egress_gb += snapshot.egress_gb;
+        }
+    }
+    w.gauge("lightd_billing_tracked_containers", "Containers with billing usage data", &[
+        ("", tracked.len() as f64),
+    ]);
+    w.gauge("lightd_billing_estimated_cost_last_hour", "Estimated cost of all containers over the last hour", &[
+        ("", hourly_cost),
+    ]);
+    w.gauge("lightd_billing_egress_gb_last_hour", "Egress of all containers over the last hour in GB", &[
+        ("", egress_gb),
+    ]);
+
+    w.gauge("lightd_websocket_channels", "Containers with an active event channel", &[
+        ("", state.event_hub.channel_count() as f64),
+    ]);
+    w.gauge("lightd_websocket_connections", "Connected WebSocket clients", &[
+        ("", state.event_hub.subscriber_count() as f64),
+    ]);
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        w.out,
+    ).into_response()
+}
@@ -7,3 +7,13 @@ pub mod remote;
 pub mod sftp;
 pub mod firewall;
 pub mod billing;
+pub mod metrics;
+pub mod schedule;
+pub mod maintenance;
+pub mod request_id;
+pub mod uptime;
+pub mod cors;
+pub mod template;
+pub mod limits;
+pub mod events;
+pub mod audit;
@@ -1,14 +1,14 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json, Response},
     routing::{get, post, delete},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::network::pool::{NetworkPool, NetworkPort};
+use crate::network::pool::{NetworkPool, NetworkPort, PoolStats, PortRangeSummary, PortsInUse};
 
 #[derive(Clone)]
 pub struct NetworkState {
@@ -45,6 +45,25 @@ struct BulkAddPort {
     protocol: String,
 }
 
+#[derive(Deserialize)]
+struct AddPortRangeRequest {
+    ip: String,
+    start: u16,
+    end: u16,
+    /// "tcp", "udp" or "both"
+    #[serde(default = "default_protocol")]
+    protocol: String,
+}
+
+#[derive(Deserialize)]
+struct RemovePortRangeQuery {
+    start: u16,
+    end: u16,
+    /// "tcp", "udp" or "both"
+    #[serde(default = "default_protocol")]
+    protocol: String,
+}
+
 fn default_protocol() -> String {
     "tcp".to_string()
 }
@@ -54,6 +73,19 @@ struct ErrorResponse {
     error: String,
 }
 
+#[derive(Serialize)]
+struct RemovePortRangeResponse {
+    removed: Vec<NetworkPort>,
+    count: usize,
+}
+
+/// 409 body listing the allocated ports that blocked a range removal
+#[derive(Serialize)]
+struct PortsInUseResponse {
+    error: String,
+    blocked: Vec<NetworkPort>,
+}
+
 #[derive(Serialize)]
 struct BulkDeleteResponse {
     deleted: Vec<String>,
@@ -72,7 +104,9 @@ pub fn network_router(pool: Arc<NetworkPool>) -> Router {
         .route("/network/ports", post(add_port))
         .route("/network/ports", get(get_all_ports))
         .route("/network/ports/bulk", post(bulk_add))
+        .route("/network/ports/range", post(add_port_range).delete(remove_port_range))
         .route("/network/ports/available", get(get_available_ports))
+        .route("/network/ports/stats", get(get_pool_stats))
         .route("/network/ports/random", get(get_random_port))
         .route("/network/ports/:id", get(get_port))
         .route("/network/ports/:id", delete(delete_port))
@@ -236,6 +270,64 @@ async fn bulk_add(
     }
 }
 
+#[axum::debug_handler]
+async fn add_port_range(
+    State(state): State<NetworkState>,
+    Json(payload): Json<AddPortRangeRequest>,
+) -> Result<Json<PortRangeSummary>, (StatusCode, Json<ErrorResponse>)> {
+    state.pool.add_port_range(payload.ip, payload.start, payload.end, payload.protocol).await
+        .map(Json)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            )
+        })
+}
+
+async fn remove_port_range(
+    State(state): State<NetworkState>,
+    Query(query): Query<RemovePortRangeQuery>,
+) -> Response {
+    match state.pool.remove_port_range(query.start, query.end, &query.protocol).await {
+        Ok(removed) => {
+            let count = removed.len();
+            (StatusCode::OK, Json(RemovePortRangeResponse { removed, count })).into_response()
+        }
+        Err(e) => match e.downcast::<PortsInUse>() {
+            Ok(in_use) => (
+                StatusCode::CONFLICT,
+                Json(PortsInUseResponse {
+                    error: in_use.to_string(),
+                    blocked: in_use.ports,
+                }),
+            ).into_response(),
+            Err(e) => (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: e.to_string(),
+                }),
+            ).into_response(),
+        },
+    }
+}
+
+async fn get_pool_stats(
+    State(state): State<NetworkState>,
+) -> Result<Json<PoolStats>, (StatusCode, Json<ErrorResponse>)> {
+    match state.pool.stats().await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: e.to_string(),
+            }),
+        )),
+    }
+}
+
 async fn get_available_ports(
     State(state): State<NetworkState>,
 ) -> Result<Json<Vec<NetworkPort>>, (StatusCode, Json<ErrorResponse>)> {
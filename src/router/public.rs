@@ -1,12 +1,34 @@
 //! Public routes that don't require authentication
 
 use axum::{
+    extract::State,
     http::StatusCode,
     response::{IntoResponse, Response},
     routing::get,
     Json, Router,
 };
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::auth::tokens::TokenManager;
+use crate::container::lifecycle::LifecycleManager;
+use crate::container::manager::ContainerManager;
+use crate::daemon::maintenance::Maintenance;
+use crate::network::pool::NetworkPool;
+use crate::remote::client::RemoteSyncManager;
+
+/// Subsystems the readiness probe checks
+#[derive(Clone)]
+pub struct PublicState {
+    pub lifecycle: Arc<LifecycleManager>,
+    pub container_manager: Arc<ContainerManager>,
+    pub token_manager: Arc<TokenManager>,
+    pub network_pool: Arc<NetworkPool>,
+    pub maintenance: Arc<Maintenance>,
+    /// Checked only when remote sync is enabled
+    pub remote_sync: Option<Arc<RemoteSyncManager>>,
+}
 
 #[derive(Serialize)]
 struct PingResponse {
@@ -14,9 +36,34 @@ struct PingResponse {
     version: String,
 }
 
-pub fn public_router() -> Router {
+#[derive(Serialize)]
+struct HealthResponse {
+    status: String,
+    version: String,
+    /// New containers are refused while this is set
+    maintenance: bool,
+}
+
+#[derive(Serialize)]
+struct ReadyResponse {
+    status: String,
+    /// Set while the node refuses new containers, it stays ready for the
+    /// ones it already has
+    maintenance: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance_reason: Option<String>,
+    /// Subsystem -> "ok" or the error it reported
+    checks: BTreeMap<&'static str, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failing: Vec<&'static str>,
+}
+
+pub fn public_router(state: PublicState) -> Router {
     Router::new()
         .route("/api/v1/public/ping", get(ping))
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .with_state(state)
 }
 
 async fn ping() -> Response {
@@ -25,3 +72,75 @@ async fn ping() -> Response {
         version: env!("CARGO_PKG_VERSION").to_string(),
     })).into_response()
 }
+
+/// Liveness: the process is up and serving requests
+async fn health(State(state): State<PublicState>) -> Response {
+    (StatusCode::OK, Json(HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        maintenance: state.maintenance.is_enabled(),
+    })).into_response()
+}
+
+/// Readiness: Docker, databases and the port pool are all usable
+async fn ready(State(state): State<PublicState>) -> Response {
+    let mut results: Vec<(&'static str, Result<(), String>)> = Vec::new();
+
+    results.push(("docker", state.lifecycle.check_docker().await.map_err(|e| e.to_string())));
+
+    let database = state.container_manager.check_db()
+        .and_then(|_| state.token_manager.check_db())
+        .map_err(|e| e.to_string());
+    results.push(("database", database));
+
+    let network_pool = state.network_pool.get_all_ports().await
+        .map(|_| ())
+        .map_err(|e| e.to_string());
+    results.push(("network_pool", network_pool));
+
+    if let Some(remote_sync) = &state.remote_sync {
+        let status = remote_sync.status();
+        let remote = if status.healthy {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} failed contact(s) in a row: {}",
+                status.consecutive_failures,
+                status.last_error.unwrap_or_default()
+            ))
+        };
+        results.push(("remote", remote));
+    }
+
+    let mut checks = BTreeMap::new();
+    let mut failing = Vec::new();
+    for (name, result) in results {
+        match result {
+            Ok(()) => {
+                checks.insert(name, "ok".to_string());
+            }
+            Err(e) => {
+                tracing::warn!("Readiness check failed for {}: {}", name, e);
+                checks.insert(name, e);
+                failing.push(name);
+            }
+        }
+    }
+
+    let maintenance = state.maintenance.status();
+    let (status, label) = if !failing.is_empty() {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    } else if maintenance.enabled {
+        (StatusCode::OK, "maintenance")
+    } else {
+        (StatusCode::OK, "ready")
+    };
+
+    (status, Json(ReadyResponse {
+        status: label.to_string(),
+        maintenance: maintenance.enabled,
+        maintenance_reason: maintenance.reason,
+        checks,
+        failing,
+    })).into_response()
+}
@@ -13,10 +13,14 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::config::config::Config;
+use crate::config::reload::ConfigReloader;
+use crate::remote::client::{RemoteStatus, RemoteSyncManager};
 
 #[derive(Clone)]
 pub struct RemoteState {
-    // Add any state needed
+    reloader: Arc<ConfigReloader>,
+    /// None when remote sync is disabled
+    remote_sync: Option<Arc<RemoteSyncManager>>,
 }
 
 #[derive(Serialize)]
@@ -25,19 +29,57 @@ struct ErrorResponse {
 }
 
 #[derive(Serialize)]
-struct SuccessResponse {
+struct ReloadResponse {
     message: String,
+    applied: Vec<String>,
+    restart_required: Vec<String>,
 }
 
-pub fn remote_router() -> Router {
-    let state = RemoteState {};
+#[derive(Serialize)]
+struct RemoteStatusResponse {
+    enabled: bool,
+    #[serde(flatten)]
+    status: Option<RemoteStatus>,
+}
+
+pub fn remote_router(reloader: Arc<ConfigReloader>, remote_sync: Option<Arc<RemoteSyncManager>>) -> Router {
+    let state = RemoteState { reloader, remote_sync };
     
     Router::new()
         .route("/remote/config", get(get_config))
         .route("/remote/config/reload", post(reload_config))
+        .route("/remote/status", get(get_status))
         .with_state(state)
 }
 
+/// Whether updates are reaching the remote
+async fn get_status(
+    State(state): State<RemoteState>,
+) -> Response {
+    (StatusCode::OK, Json(RemoteStatusResponse {
+        enabled: state.remote_sync.is_some(),
+        status: state.remote_sync.as_ref().map(|sync| sync.status()),
+    })).into_response()
+}
+
+/// Secrets and private key paths left out of the config response
+const REDACTED: &[&str] = &[
+    "/authorization/token",
+    "/authorization/jwt/secret",
+    "/remote/token",
+    "/remote/tls/client_key",
+    "/sftp/host_key_path",
+];
+
+/// Replace every set value of `REDACTED` with a placeholder
+fn redact(config: &mut serde_json::Value) {
+    for pointer in REDACTED {
+        if let Some(value) = config.pointer_mut(pointer).filter(|v| !v.is_null()) {
+            *value = serde_json::Value::String("[redacted]".to_string());
+        }
+    }
+}
+
 /// Get current configuration
 async fn get_config(
     State(_state): State<RemoteState>,
@@ -45,7 +87,10 @@ async fn get_config(
     match Config::load("config.json") {
         Ok(config) => {
             match serde_json::to_value(&config) {
-                Ok(json) => (StatusCode::OK, Json(json)).into_response(),
+                Ok(mut json) => {
+                    redact(&mut json);
+                    (StatusCode::OK, Json(json)).into_response()
+                }
                 Err(e) => (
                     StatusCode::INTERNAL_SERVER_ERROR,
                     Json(ErrorResponse {
@@ -63,16 +108,16 @@ async fn get_config(
     }
 }
 
-/// Reload configuration from file
+/// Reload configuration from file and apply runtime-safe settings
 async fn reload_config(
-    State(_state): State<RemoteState>,
+    State(state): State<RemoteState>,
 ) -> Response {
-    match Config::load("config.json") {
-        Ok(_config) => {
-            // TODO: Actually reload the config in the running application
-            // This would require passing config through Arc<RwLock<Config>>
-            (StatusCode::OK, Json(SuccessResponse {
+    match state.reloader.reload().await {
+        Ok(summary) => {
+            (StatusCode::OK, Json(ReloadResponse {
                 message: "Configuration reloaded successfully".to_string(),
+                applied: summary.applied,
+                restart_required: summary.restart_required,
             })).into_response()
         }
         Err(e) => (
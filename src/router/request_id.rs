@@ -0,0 +1,67 @@
+//! Request correlation IDs
+//!
+//! Every request runs inside a `request` span carrying its ID, so log lines
+//! from the handler, and from background jobs it starts, can be tied together.
+//! A valid `X-Request-Id` sent by the client is reused, otherwise one is
+//! generated. The ID is echoed back in the response header.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// ID of the current request, available to handlers as an extension
+#[derive(Debug, Clone)]
+#[allow(unused)]
+pub struct RequestId(pub String);
+
+/// Accept client IDs that are short and safe to put in logs and headers
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+pub async fn request_id_middleware(mut req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid_request_id(v))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+    );
+
+    let mut response = next.run(req).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_validation() {
+        assert!(is_valid_request_id("3f2b9c1e-7d4a-4a8e-9b1f-0c2d3e4f5a6b"));
+        assert!(is_valid_request_id("panel:req_42"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+    }
+}
@@ -0,0 +1,173 @@
+//! Container schedule routes
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::container::manager::ContainerManager;
+use crate::scheduler::schedule::{parse_cron, Schedule, ScheduleAction, ScheduleManager};
+
+#[derive(Clone)]
+pub struct ScheduleState {
+    schedules: Arc<ScheduleManager>,
+    containers: Arc<ContainerManager>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct CreateScheduleRequest {
+    name: String,
+    cron: String,
+    action: ScheduleAction,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct UpdateScheduleRequest {
+    name: Option<String>,
+    cron: Option<String>,
+    action: Option<ScheduleAction>,
+    enabled: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct SchedulesResponse {
+    schedules: Vec<Schedule>,
+}
+
+pub fn schedule_router(schedules: Arc<ScheduleManager>, containers: Arc<ContainerManager>) -> Router {
+    let state = ScheduleState { schedules, containers };
+
+    Router::new()
+        .route("/containers/:id/schedules", get(list_schedules).post(create_schedule))
+        .route(
+            "/containers/:id/schedules/:schedule_id",
+            get(get_schedule).put(update_schedule).delete(delete_schedule),
+        )
+        .with_state(state)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse { error: message.into() })).into_response()
+}
+
+/// 400 response when the cron expression or the action is invalid
+fn validate(cron: &str, action: &ScheduleAction) -> Option<Response> {
+    parse_cron(cron)
+        .and_then(|_| action.validate())
+        .err()
+        .map(|e| error(StatusCode::BAD_REQUEST, e))
+}
+
+/// 404 response when the container doesn't exist
+async fn missing_container(state: &ScheduleState, internal_id: &str) -> Option<Response> {
+    match state.containers.get_container(internal_id).await {
+        Ok(Some(_)) => None,
+        Ok(None) => Some(error(StatusCode::NOT_FOUND, "Container not found")),
+        Err(e) => Some(error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn list_schedules(
+    State(state): State<ScheduleState>,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = missing_container(&state, &id).await {
+        return response;
+    }
+
+    match state.schedules.list(&id) {
+        Ok(schedules) => (StatusCode::OK, Json(SchedulesResponse { schedules })).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn create_schedule(
+    State(state): State<ScheduleState>,
+    Path(id): Path<String>,
+    Json(payload): Json<CreateScheduleRequest>,
+) -> Response {
+    if let Some(response) = missing_container(&state, &id).await {
+        return response;
+    }
+
+    if let Some(response) = validate(&payload.cron, &payload.action) {
+        return response;
+    }
+
+    match state.schedules.create(&id, payload.name, payload.cron, payload.action, payload.enabled) {
+        Ok(schedule) => (StatusCode::CREATED, Json(schedule)).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn get_schedule(
+    State(state): State<ScheduleState>,
+    Path((id, schedule_id)): Path<(String, String)>,
+) -> Response {
+    match state.schedules.get(&id, &schedule_id) {
+        Ok(Some(schedule)) => (StatusCode::OK, Json(schedule)).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Schedule not found"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn update_schedule(
+    State(state): State<ScheduleState>,
+    Path((id, schedule_id)): Path<(String, String)>,
+    Json(payload): Json<UpdateScheduleRequest>,
+) -> Response {
+    let mut schedule = match state.schedules.get(&id, &schedule_id) {
+        Ok(Some(schedule)) => schedule,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Schedule not found"),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+
+    if let Some(name) = payload.name {
+        schedule.name = name;
+    }
+    if let Some(cron) = payload.cron {
+        schedule.cron = cron;
+    }
+    if let Some(action) = payload.action {
+        schedule.action = action;
+    }
+    if let Some(enabled) = payload.enabled {
+        schedule.enabled = enabled;
+    }
+
+    if let Some(response) = validate(&schedule.cron, &schedule.action) {
+        return response;
+    }
+
+    match state.schedules.update(schedule) {
+        Ok(schedule) => (StatusCode::OK, Json(schedule)).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn delete_schedule(
+    State(state): State<ScheduleState>,
+    Path((id, schedule_id)): Path<(String, String)>,
+) -> Response {
+    match state.schedules.delete(&id, &schedule_id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Schedule not found"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::container::manager::ContainerManager;
+use crate::error;
 use crate::sftp::credentials::CredentialsManager;
 
 #[derive(Clone)]
@@ -69,6 +70,7 @@ pub fn sftp_router(
     Router::new()
         .route("/containers/:id/sftp/credentials", post(generate_credentials))
         .route("/containers/:id/sftp/info", get(get_sftp_info))
+        .route("/containers/:id/sftp/rotate", post(rotate_password))
         .with_state(state)
 }
 
@@ -99,13 +101,37 @@ async fn generate_credentials(
         }
     };
     
-    // Generate credentials
-    match state.credentials_manager.generate_credentials(
-        &container_id,
-        &container.volume_id,
-        payload.username,
-        payload.password,
-    ) {
+    if let Some(ref username) = payload.username {
+        match state.credentials_manager.find_by_username(username) {
+            Ok(Some(owner)) if owner.container_id != container_id => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(ErrorResponse {
+                        error: format!("SFTP username '{}' is already in use", username),
+                    }),
+                ).into_response();
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: format!("Failed to check username: {}", e),
+                    }),
+                ).into_response();
+            }
+        }
+    }
+    
+    // Generate credentials, bcrypt runs off the runtime
+    let generated = {
+        let credentials = state.credentials_manager.clone();
+        let container_id = container_id.clone();
+        tokio::task::spawn_blocking(move || {
+            credentials.generate_credentials(&container_id, &container.volume_id, payload.username, payload.password)
+        }).await
+    };
+    match generated.map_err(Into::into).and_then(|r| r) {
         Ok((username, password)) => {
             tracing::info!("Generated SFTP credentials for container: {}", container_id);
             
@@ -114,12 +140,13 @@ async fn generate_credentials(
                 password,
                 host: state.sftp_host.clone(),
                 port: state.sftp_port,
-                volume_path: format!("/home/container"),
+                volume_path: "/home/container".to_string(),
             })).into_response()
         }
         Err(e) => {
+            // A username claimed since the check above is a conflict
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
+                error::status_code(&*e),
                 Json(ErrorResponse {
                     error: format!("Failed to generate credentials: {}", e),
                 }),
@@ -128,6 +155,57 @@ async fn generate_credentials(
     }
 }
 
+/// Replace the SFTP password of a container, the username stays the same
+async fn rotate_password(
+    State(state): State<SftpState>,
+    Path(container_id): Path<String>,
+) -> Response {
+    let container = match state.container_manager.get_container(&container_id).await {
+        Ok(Some(c)) => c,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "Container not found".to_string(),
+                }),
+            ).into_response();
+        }
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("Failed to get container: {}", e),
+                }),
+            ).into_response();
+        }
+    };
+    
+    let rotated = {
+        let credentials = state.credentials_manager.clone();
+        let container_id = container_id.clone();
+        tokio::task::spawn_blocking(move || credentials.rotate_password(&container_id, &container.volume_id)).await
+    };
+    match rotated.map_err(Into::into).and_then(|r| r) {
+        Ok((username, password)) => {
+            (StatusCode::OK, Json(CredentialsResponse {
+                username,
+                password,
+                host: state.sftp_host.clone(),
+                port: state.sftp_port,
+                volume_path: "/home/container".to_string(),
+            })).into_response()
+        }
+        Err(e) => {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("Failed to rotate password: {}", e),
+                }),
+            ).into_response()
+        }
+    }
+}
+
 /// Get SFTP connection info for a container
 async fn get_sftp_info(
     State(state): State<SftpState>,
@@ -140,7 +218,7 @@ async fn get_sftp_info(
                 username: creds.username,
                 host: state.sftp_host.clone(),
                 port: state.sftp_port,
-                volume_path: format!("/home/container"),
+                volume_path: "/home/container".to_string(),
                 created_at: creds.created_at,
                 updated_at: creds.updated_at,
             })).into_response()
@@ -0,0 +1,143 @@
+//! Container template routes
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::container::template::{ContainerTemplate, CreateLimits, PortRequest, TemplateManager};
+use crate::filesystem::skeleton::SkeletonFile;
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct TemplateRequest {
+    /// Chosen by the caller, generated when not given. Ignored on PUT
+    id: Option<String>,
+    name: String,
+    description: Option<String>,
+    image: String,
+    startup_command: String,
+    install_script: Option<String>,
+    start_pattern: Option<String>,
+    stop_command: Option<String>,
+    stop_timeout: Option<u64>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    #[serde(default)]
+    ports: Vec<PortRequest>,
+    limits: Option<CreateLimits>,
+    #[serde(default)]
+    files: Vec<SkeletonFile>,
+}
+
+impl TemplateRequest {
+    fn into_template(self, id: String) -> ContainerTemplate {
+        ContainerTemplate {
+            id,
+            name: self.name,
+            description: self.description,
+            image: self.image,
+            startup_command: self.startup_command,
+            install_script: self.install_script,
+            start_pattern: self.start_pattern,
+            stop_command: self.stop_command,
+            stop_timeout: self.stop_timeout,
+            env: self.env,
+            ports: self.ports,
+            limits: self.limits,
+            files: self.files,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TemplatesResponse {
+    templates: Vec<ContainerTemplate>,
+}
+
+pub fn template_router(templates: Arc<TemplateManager>) -> Router {
+    Router::new()
+        .route("/templates", get(list_templates).post(create_template))
+        .route("/templates/:id", get(get_template).put(replace_template).delete(delete_template))
+        .with_state(templates)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse { error: message.into() })).into_response()
+}
+
+async fn list_templates(State(templates): State<Arc<TemplateManager>>) -> Response {
+    match templates.list() {
+        Ok(templates) => (StatusCode::OK, Json(TemplatesResponse { templates })).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn create_template(
+    State(templates): State<Arc<TemplateManager>>,
+    Json(payload): Json<TemplateRequest>,
+) -> Response {
+    let id = payload.id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let mut template = payload.into_template(id);
+    if let Err(e) = template.validate() {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+
+    match templates.create(template) {
+        Ok(Some(template)) => (StatusCode::CREATED, Json(template)).into_response(),
+        Ok(None) => error(StatusCode::CONFLICT, "A template with this id already exists"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn get_template(
+    State(templates): State<Arc<TemplateManager>>,
+    Path(id): Path<String>,
+) -> Response {
+    match templates.get(&id) {
+        Ok(Some(template)) => (StatusCode::OK, Json(template)).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Template not found"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+/// Replace the whole template, containers created from it keep their values
+async fn replace_template(
+    State(templates): State<Arc<TemplateManager>>,
+    Path(id): Path<String>,
+    Json(payload): Json<TemplateRequest>,
+) -> Response {
+    let mut template = payload.into_template(id);
+    if let Err(e) = template.validate() {
+        return error(StatusCode::BAD_REQUEST, e);
+    }
+
+    match templates.replace(template) {
+        Ok(Some(template)) => (StatusCode::OK, Json(template)).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "Template not found"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+async fn delete_template(
+    State(templates): State<Arc<TemplateManager>>,
+    Path(id): Path<String>,
+) -> Response {
+    match templates.delete(&id) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => error(StatusCode::NOT_FOUND, "Template not found"),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
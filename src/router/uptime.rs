@@ -0,0 +1,70 @@
+//! Container uptime routes
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::container::manager::ContainerManager;
+use crate::container::uptime::{UptimeTracker, RETENTION_SECS};
+
+/// 30 days
+const DEFAULT_WINDOW_SECS: u64 = 30 * 24 * 3600;
+
+#[derive(Clone)]
+pub struct UptimeState {
+    uptime: Arc<UptimeTracker>,
+    containers: Arc<ContainerManager>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct UptimeQuery {
+    window_secs: Option<u64>,
+}
+
+pub fn uptime_router(uptime: Arc<UptimeTracker>, containers: Arc<ContainerManager>) -> Router {
+    let state = UptimeState { uptime, containers };
+
+    Router::new()
+        .route("/containers/:id/uptime", get(get_uptime))
+        .with_state(state)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(ErrorResponse { error: message.into() })).into_response()
+}
+
+async fn get_uptime(
+    State(state): State<UptimeState>,
+    Path(id): Path<String>,
+    Query(query): Query<UptimeQuery>,
+) -> Response {
+    let window_secs = query.window_secs.unwrap_or(DEFAULT_WINDOW_SECS);
+    if window_secs == 0 || window_secs > RETENTION_SECS {
+        return error(
+            StatusCode::BAD_REQUEST,
+            format!("window_secs must be between 1 and {}", RETENTION_SECS),
+        );
+    }
+
+    let container = match state.containers.get_container(&id).await {
+        Ok(Some(container)) => container,
+        Ok(None) => return error(StatusCode::NOT_FOUND, "Container not found"),
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    };
+
+    match state.uptime.report(&id, window_secs, container.created_at) {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
@@ -0,0 +1,161 @@
+//! Background task firing due schedules
+//!
+//! Checks every `TICK_SECS` and runs actions through the same managers the
+//! HTTP and WebSocket handlers use, so a scheduled restart behaves exactly
+//! like one sent from the panel.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::schedule::{Schedule, ScheduleAction, ScheduleManager};
+use crate::container::manager::ContainerManager;
+use crate::container::power::{PowerAction, PowerManager};
+use crate::daemon::shutdown::ShutdownSignal;
+use crate::error::LightdError;
+use crate::filesystem::handler::VolumeHandler;
+use crate::websocket::{ConsoleStreamer, EventHub};
+
+const TICK_SECS: u64 = 15;
+/// Runs missed by more than this (e.g. while the daemon was down) are skipped
+const MAX_LATENESS_SECS: u64 = 300;
+
+pub struct ScheduleRunner {
+    schedules: Arc<ScheduleManager>,
+    containers: Arc<ContainerManager>,
+    power: Arc<PowerManager>,
+    event_hub: Arc<EventHub>,
+    console_streamer: Arc<ConsoleStreamer>,
+    volume_handler: Arc<VolumeHandler>,
+}
+
+impl ScheduleRunner {
+    pub fn new(
+        schedules: Arc<ScheduleManager>,
+        containers: Arc<ContainerManager>,
+        power: Arc<PowerManager>,
+        event_hub: Arc<EventHub>,
+        console_streamer: Arc<ConsoleStreamer>,
+        volume_handler: Arc<VolumeHandler>,
+    ) -> Self {
+        Self {
+            schedules,
+            containers,
+            power,
+            event_hub,
+            console_streamer,
+            volume_handler,
+        }
+    }
+
+    pub fn start(self, mut shutdown: ShutdownSignal) {
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(TICK_SECS)) => {}
+                    _ = shutdown.recv() => break,
+                }
+
+                if let Err(e) = self.tick().await {
+                    tracing::error!("Scheduler tick failed: {}", e);
+                }
+            }
+            tracing::info!("Scheduler stopped");
+        });
+    }
+
+    async fn tick(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        for schedule in self.schedules.list_all()? {
+            if !schedule.enabled {
+                continue;
+            }
+            let Some(next_run) = schedule.next_run else {
+                continue;
+            };
+            if next_run > now {
+                continue;
+            }
+
+            // The container is gone, its schedules go with it
+            match self.containers.get_container(&schedule.internal_id).await {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    if let Err(e) = self.schedules.delete(&schedule.internal_id, &schedule.id) {
+                        tracing::error!("Failed to delete schedule {}: {}", schedule.id, e);
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to load container {} for schedule {}: {}", schedule.internal_id, schedule.id, e);
+                    continue;
+                }
+            }
+
+            if now - next_run > MAX_LATENESS_SECS {
+                tracing::warn!(
+                    "Skipping missed run of schedule '{}' for {} (due {}s ago)",
+                    schedule.name, schedule.internal_id, now - next_run
+                );
+            } else {
+                self.fire(&schedule).await;
+            }
+
+            if let Err(e) = self.schedules.mark_run(&schedule, now) {
+                tracing::error!("Failed to record run of schedule {}: {}", schedule.id, e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn fire(&self, schedule: &Schedule) {
+        let internal_id = &schedule.internal_id;
+        tracing::info!("Running schedule '{}' for {}: {}", schedule.name, internal_id, schedule.action.describe());
+
+        let result = match &schedule.action {
+            ScheduleAction::Power { action } => {
+                let action = match action.as_str() {
+                    "start" => PowerAction::Start,
+                    "stop" => PowerAction::Stop,
+                    "kill" => PowerAction::Kill,
+                    _ => PowerAction::Restart,
+                };
+                self.power.execute_action(internal_id.clone(), action).await
+            }
+            ScheduleAction::Command { command } => self.send_command(internal_id, command).await,
+            ScheduleAction::Backup { name } => self.backup(internal_id, name.clone()).await,
+        };
+
+        let message = match result {
+            Ok(()) => format!("Scheduled task '{}' ran: {}", schedule.name, schedule.action.describe()),
+            Err(e) => {
+                tracing::error!("Schedule '{}' for {} failed: {}", schedule.name, internal_id, e);
+                format!("Scheduled task '{}' failed: {}", schedule.name, e)
+            }
+        };
+        self.event_hub.broadcast_daemon_message(internal_id, &message).await;
+    }
+
+    async fn send_command(&self, internal_id: &str, command: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // stdin is only attached while a streamer runs for the container
+        if self.event_hub.get_channel(internal_id).is_none() {
+            self.console_streamer.start_streaming(internal_id.to_string()).await?;
+        }
+
+        let channel = self.event_hub.get_channel(internal_id)
+            .ok_or("Console channel not available")?;
+        channel.send_command(command.to_string())
+            .map_err(|e| format!("Failed to queue command: {}", e))?;
+        Ok(())
+    }
+
+    async fn backup(&self, internal_id: &str, name: Option<String>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let container = self.containers.get_container(internal_id).await?
+            .ok_or(LightdError::NotFound("Container"))?;
+        self.volume_handler.create_backup(&container.volume_id, name).await?;
+        Ok(())
+    }
+}
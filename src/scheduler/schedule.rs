@@ -0,0 +1,285 @@
+//! Per-container schedules stored in sled
+//!
+//! Keys are `<internal_id>/<schedule_id>` so one container's schedules can be
+//! read with a prefix scan. Cron expressions are evaluated in UTC.
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// What a schedule does when it fires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleAction {
+    /// start, stop, restart or kill
+    Power { action: String },
+    /// Line written to the server console
+    Command { command: String },
+    /// Backup of the container's volume
+    Backup { name: Option<String> },
+}
+
+impl ScheduleAction {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ScheduleAction::Power { action } => match action.as_str() {
+                "start" | "stop" | "restart" | "kill" => Ok(()),
+                _ => Err(format!("Unknown power action '{}', expected start, stop, restart or kill", action)),
+            },
+            ScheduleAction::Command { command } if command.trim().is_empty() => {
+                Err("Command cannot be empty".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ScheduleAction::Power { action } => format!("power {}", action),
+            ScheduleAction::Command { command } => format!("command '{}'", command),
+            ScheduleAction::Backup { .. } => "backup".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub internal_id: String,
+    pub name: String,
+    /// Cron expression, 5 fields (minute precision) or 6-7 fields with seconds
+    pub cron: String,
+    pub action: ScheduleAction,
+    pub enabled: bool,
+    pub last_run: Option<u64>,
+    pub next_run: Option<u64>,
+    pub created_at: u64,
+}
+
+/// Parse a cron expression, standard 5-field expressions run at second 0
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule, String> {
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    let expr = match fields.len() {
+        5 => format!("0 {} {}", fields[..4].join(" "), day_of_week(fields[4])),
+        6 | 7 => expr.trim().to_string(),
+        _ => return Err(format!("Invalid cron expression '{}': expected 5 to 7 fields", expr)),
+    };
+
+    cron::Schedule::from_str(&expr).map_err(|e| format!("Invalid cron expression '{}': {}", expr, e))
+}
+
+/// Translate a standard day-of-week field (0-7, Sunday is 0 and 7) to the
+/// cron crate's numbering (1-7, Sunday is 1). Numeric items are expanded to
+/// plain lists so ranges running into Sunday stay correct; names and `*` are
+/// the same in both and pass through.
+fn day_of_week(field: &str) -> String {
+    field
+        .split(',')
+        .map(|item| day_of_week_item(item).unwrap_or_else(|| item.to_string()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn day_of_week_item(item: &str) -> Option<String> {
+    let (range, step) = match item.split_once('/') {
+        Some((range, step)) => (range, step.parse::<u8>().ok().filter(|s| *s > 0)?),
+        None => (item, 1),
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (start.parse::<u8>().ok()?, end.parse::<u8>().ok()?),
+        // `n/step` runs to the end of the week
+        None if item.contains('/') => (range.parse::<u8>().ok()?, 6),
+        None => {
+            let day = range.parse::<u8>().ok()?;
+            (day, day)
+        }
+    };
+    if start > end || end > 7 {
+        return None;
+    }
+
+    let mut days: Vec<u8> = (start..=end).step_by(step as usize).map(|day| day % 7 + 1).collect();
+    days.sort_unstable();
+    days.dedup();
+    Some(days.iter().map(|day| day.to_string()).collect::<Vec<_>>().join(","))
+}
+
+/// Next time the expression fires strictly after `after` (Unix seconds)
+pub fn next_run_after(expr: &str, after: u64) -> Option<u64> {
+    let schedule = parse_cron(expr).ok()?;
+    let after = Utc.timestamp_opt(after as i64, 0).single()?;
+    schedule.after(&after).next().map(|t| t.timestamp() as u64)
+}
+
+pub struct ScheduleManager {
+    db: Arc<Db>,
+}
+
+impl ScheduleManager {
+    pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let db = sled::open(db_path)?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    fn key(internal_id: &str, schedule_id: &str) -> String {
+        format!("{}/{}", internal_id, schedule_id)
+    }
+
+    fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    pub fn create(
+        &self,
+        internal_id: &str,
+        name: String,
+        cron: String,
+        action: ScheduleAction,
+        enabled: bool,
+    ) -> Result<Schedule, Box<dyn std::error::Error + Send + Sync>> {
+        parse_cron(&cron)?;
+        action.validate()?;
+
+        let now = Self::now();
+        let schedule = Schedule {
+            id: uuid::Uuid::new_v4().to_string(),
+            internal_id: internal_id.to_string(),
+            name,
+            next_run: next_run_after(&cron, now),
+            cron,
+            action,
+            enabled,
+            last_run: None,
+            created_at: now,
+        };
+
+        self.save(&schedule)?;
+        tracing::info!("Created schedule {} for container {}", schedule.id, internal_id);
+        Ok(schedule)
+    }
+
+    /// Validate and store a changed schedule, recomputing its next run
+    pub fn update(&self, mut schedule: Schedule) -> Result<Schedule, Box<dyn std::error::Error + Send + Sync>> {
+        parse_cron(&schedule.cron)?;
+        schedule.action.validate()?;
+
+        schedule.next_run = next_run_after(&schedule.cron, Self::now());
+        self.save(&schedule)?;
+        Ok(schedule)
+    }
+
+    fn save(&self, schedule: &Schedule) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let serialized = serde_json::to_vec(schedule)?;
+        self.db.insert(Self::key(&schedule.internal_id, &schedule.id).as_bytes(), serialized)?;
+        Ok(())
+    }
+
+    pub fn get(&self, internal_id: &str, schedule_id: &str) -> Result<Option<Schedule>, Box<dyn std::error::Error + Send + Sync>> {
+        match self.db.get(Self::key(internal_id, schedule_id).as_bytes())? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list(&self, internal_id: &str) -> Result<Vec<Schedule>, Box<dyn std::error::Error + Send + Sync>> {
+        let prefix = format!("{}/", internal_id);
+        Self::collect(self.db.scan_prefix(prefix.as_bytes()))
+    }
+
+    pub fn list_all(&self) -> Result<Vec<Schedule>, Box<dyn std::error::Error + Send + Sync>> {
+        Self::collect(self.db.iter())
+    }
+
+    /// Decode stored schedules, a corrupt record is logged and skipped so it
+    /// can't hide the others
+    fn collect(iter: sled::Iter) -> Result<Vec<Schedule>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut schedules = Vec::new();
+
+        for item in iter {
+            let (key, value) = item?;
+            match serde_json::from_slice(&value) {
+                Ok(schedule) => schedules.push(schedule),
+                Err(e) => tracing::warn!(
+                    "Skipping unreadable schedule {}: {}",
+                    String::from_utf8_lossy(&key), e
+                ),
+            }
+        }
+
+        Ok(schedules)
+    }
+
+    pub fn delete(&self, internal_id: &str, schedule_id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let removed = self.db.remove(Self::key(internal_id, schedule_id).as_bytes())?.is_some();
+        if removed {
+            tracing::info!("Deleted schedule {} of container {}", schedule_id, internal_id);
+        }
+        Ok(removed)
+    }
+
+    /// Record that a schedule fired (or was skipped) at `at`
+    ///
+    /// Applied to the stored record with compare-and-swap, so a schedule deleted
+    /// or edited while its action ran is neither brought back nor overwritten
+    /// with the stale copy.
+    pub fn mark_run(&self, schedule: &Schedule, at: u64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key = Self::key(&schedule.internal_id, &schedule.id);
+
+        loop {
+            let Some(current) = self.db.get(key.as_bytes())? else {
+                return Ok(());
+            };
+            let mut stored: Schedule = serde_json::from_slice(&current)?;
+            stored.last_run = Some(at);
+            stored.next_run = next_run_after(&stored.cron, at);
+
+            let updated = serde_json::to_vec(&stored)?;
+            if self.db.compare_and_swap(key.as_bytes(), Some(current), Some(updated))?.is_ok() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cron_accepts_five_fields() {
+        assert!(parse_cron("0 4 * * *").is_ok());
+        assert!(parse_cron("30 0 4 * * *").is_ok());
+        assert!(parse_cron("every night").is_err());
+    }
+
+    #[test]
+    fn test_next_run_after_daily() {
+        // 2024-01-01 00:00:00 UTC, next 04:00 run is four hours later
+        let midnight = 1704067200;
+        assert_eq!(next_run_after("0 4 * * *", midnight), Some(midnight + 4 * 3600));
+    }
+
+    #[test]
+    fn test_day_of_week_zero_is_sunday() {
+        // 2024-01-01 was a Monday, the next Sunday 04:00 is six days later
+        let monday = 1704067200;
+        let sunday = monday + 6 * 86400 + 4 * 3600;
+        assert_eq!(next_run_after("0 4 * * 0", monday), Some(sunday));
+        assert_eq!(next_run_after("0 4 * * 7", monday), Some(sunday));
+        assert_eq!(next_run_after("0 4 * * 1", monday), Some(monday + 4 * 3600));
+        assert_eq!(next_run_after("0 4 * * 5-7", monday), Some(monday + 4 * 86400 + 4 * 3600));
+        assert_eq!(day_of_week("1-5"), "2,3,4,5,6");
+        assert_eq!(day_of_week("MON-FRI"), "MON-FRI");
+    }
+}
@@ -3,13 +3,13 @@
 //! CLI command to list all containers/servers with pagination.
 
 use crate::config::config::Config;
-use crate::container::manager::ContainerManager;
+use crate::container::manager::{ContainerFilter, ContainerManager};
 use crate::container::state::InstallState;
 
 const PAGE_SIZE: usize = 5;
 
 #[allow(unused_variables)]
-pub async fn list_servers(page: i64) {
+pub async fn list_servers(page: i64, filter: ContainerFilter) {
     // Load config
     let config = match Config::load("config.json") {
         Ok(cfg) => cfg,
@@ -29,17 +29,29 @@ pub async fn list_servers(page: i64) {
         }
     };
     
-    // Get all containers
-    let containers = match manager.list_containers().await {
-        Ok(c) => c,
+    // Out of range pages show the last one, so count first
+    let page = if page < 1 { 1 } else { page as usize };
+    let listing = match manager.list_containers_page(&filter, page, PAGE_SIZE).await {
+        Ok(l) if l.containers.is_empty() && l.total > 0 => {
+            match manager.list_containers_page(&filter, l.total_pages, PAGE_SIZE).await {
+                Ok(last) => last,
+                Err(e) => {
+                    eprintln!("Failed to list containers: {}", e);
+                    return;
+                }
+            }
+        }
+        Ok(l) => l,
         Err(e) => {
             eprintln!("Failed to list containers: {}", e);
             return;
         }
     };
     
-    let total_count = containers.len();
-    let total_pages = (total_count + PAGE_SIZE - 1) / PAGE_SIZE;
+    let total_count = listing.total;
+    let total_pages = listing.total_pages;
+    let page = listing.page;
+    let containers = listing.containers;
     
     if total_count == 0 {
         println!("╔═══════════════════════════════════════════════════════════════════╗");
@@ -48,12 +60,8 @@ pub async fn list_servers(page: i64) {
         return;
     }
     
-    // Calculate page bounds
-    let page = if page < 1 { 1 } else { page as usize };
-    let page = if page > total_pages { total_pages } else { page };
-    
     let start_idx = (page - 1) * PAGE_SIZE;
-    let end_idx = std::cmp::min(start_idx + PAGE_SIZE, total_count);
+    let end_idx = start_idx + containers.len();
     
     // Print header
     println!();
@@ -65,7 +73,7 @@ pub async fn list_servers(page: i64) {
     println!("╠═══════════════════════════════════════════════════════════════════════════════════════╣");
     
     // Print each server
-    for (i, container) in containers.iter().skip(start_idx).take(PAGE_SIZE).enumerate() {
+    for (i, container) in containers.iter().enumerate() {
         let idx = start_idx + i + 1;
         
         // Determine status
@@ -2,9 +2,10 @@
 //! 
 //! Manages per-container SFTP credentials with password hashing
 
+use crate::error::LightdError;
 use serde::{Deserialize, Serialize};
 use sled::Db;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SftpCredentials {
@@ -18,15 +19,23 @@ pub struct SftpCredentials {
 
 pub struct CredentialsManager {
     db: Arc<Db>,
+    /// Held from the username check to the insert, two containers can't claim the same name
+    claim: Mutex<()>,
 }
 
 impl CredentialsManager {
     pub fn new(db_path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let db = sled::open(db_path)?;
-        Ok(Self { db: Arc::new(db) })
+        Ok(Self { db: Arc::new(db), claim: Mutex::new(()) })
+    }
+
+    /// Flush pending writes to disk
+    pub async fn flush(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.db.flush_async().await?;
+        Ok(())
     }
     
-    /// Generate new SFTP credentials for a container
+    /// Generate new SFTP credentials for a container (blocking, bcrypt)
     pub fn generate_credentials(
         &self,
         container_id: &str,
@@ -42,21 +51,19 @@ impl CredentialsManager {
         let username = custom_username.unwrap_or_else(|| container_id.to_string());
         
         // Generate password (default: random or custom)
-        let password = custom_password.unwrap_or_else(|| {
-            use rand::Rng;
-            let charset: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!@#$%^&*";
-            let mut rng = rand::thread_rng();
-            (0..24)
-                .map(|_| {
-                    let idx = rng.gen_range(0..charset.len());
-                    charset[idx] as char
-                })
-                .collect()
-        });
+        let password = custom_password.unwrap_or_else(generate_password);
         
         // Hash password
         let password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST)?;
         
+        let _claim = self.claim.lock().unwrap();
+        
+        // Logins are looked up by username, so it must not belong to another container
+        if let Some(owner) = self.find_by_username(&username)?
+            && owner.container_id != container_id {
+            return Err(LightdError::Conflict(format!("SFTP username '{}' is already in use", username)).into());
+        }
+        
         let credentials = SftpCredentials {
             container_id: container_id.to_string(),
             username: username.clone(),
@@ -75,6 +82,45 @@ impl CredentialsManager {
         Ok((username, password))
     }
     
+    /// Replace the password of a container's credentials, keeping the username.
+    /// Containers without credentials get new ones with the default username (blocking, bcrypt).
+    pub fn rotate_password(
+        &self,
+        container_id: &str,
+        volume_id: &str,
+    ) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(mut credentials) = self.get_credentials(container_id)? else {
+            return self.generate_credentials(container_id, volume_id, None, None);
+        };
+        
+        let password = generate_password();
+        credentials.password_hash = bcrypt::hash(&password, bcrypt::DEFAULT_COST)?;
+        credentials.volume_id = volume_id.to_string();
+        credentials.updated_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        
+        self.db.insert(container_id.as_bytes(), serde_json::to_vec(&credentials)?)?;
+        tracing::info!("Rotated SFTP password for container: {}", container_id);
+        
+        Ok((credentials.username, password))
+    }
+    
+    /// Credentials using `username`, whichever container they belong to
+    pub fn find_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<SftpCredentials>, Box<dyn std::error::Error + Send + Sync>> {
+        for item in self.db.iter() {
+            let (_, value) = item?;
+            if let Ok(creds) = serde_json::from_slice::<SftpCredentials>(&value)
+                && creds.username == username {
+                return Ok(Some(creds));
+            }
+        }
+        Ok(None)
+    }
+    
     /// Get credentials for a container
     pub fn get_credentials(
         &self,
@@ -98,14 +144,13 @@ impl CredentialsManager {
         // Search for credentials by username
         for item in self.db.iter() {
             let (_, value) = item?;
-            if let Ok(creds) = serde_json::from_slice::<SftpCredentials>(&value) {
-                if creds.username == username {
-                    // Verify password
-                    if bcrypt::verify(password, &creds.password_hash)? {
-                        return Ok(Some(creds));
-                    } else {
-                        return Ok(None);
-                    }
+            if let Ok(creds) = serde_json::from_slice::<SftpCredentials>(&value)
+                && creds.username == username {
+                // Verify password
+                if bcrypt::verify(password, &creds.password_hash)? {
+                    return Ok(Some(creds));
+                } else {
+                    return Ok(None);
                 }
             }
         }
@@ -123,3 +168,16 @@ impl CredentialsManager {
         Ok(())
     }
 }
+
+/// Random 24 character password
+fn generate_password() -> String {
+    use rand::Rng;
+    let charset: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!@#$%^&*";
+    let mut rng = rand::thread_rng();
+    (0..24)
+        .map(|_| {
+            let idx = rng.gen_range(0..charset.len());
+            charset[idx] as char
+        })
+        .collect()
+}
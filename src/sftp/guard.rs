@@ -0,0 +1,175 @@
+//! Brute force protection for the SFTP server
+//!
+//! Failed logins are counted per IP with the same token bucket the HTTP auth
+//! layer uses. An IP that runs out of attempts is banned for a while, its
+//! connections are dropped before the SSH handshake. A semaphore caps the
+//! number of concurrent sessions, and a per-IP count keeps a single client
+//! (which may never try to log in) from taking all of them.
+
+use dashmap::DashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::auth::rate_limit::RateLimiter;
+
+pub struct SftpGuard {
+    failures: RateLimiter,
+    /// Banned IPs and when the ban ends
+    bans: DashMap<IpAddr, Instant>,
+    ban_duration: Duration,
+    sessions: Arc<Semaphore>,
+    max_sessions: usize,
+    /// Open sessions per client IP
+    sessions_per_ip: Arc<DashMap<IpAddr, usize>>,
+    max_sessions_per_ip: usize,
+}
+
+/// A session slot, released when dropped
+pub struct SessionPermit {
+    _permit: OwnedSemaphorePermit,
+    ip: IpAddr,
+    sessions_per_ip: Arc<DashMap<IpAddr, usize>>,
+}
+
+impl Drop for SessionPermit {
+    fn drop(&mut self) {
+        self.sessions_per_ip.remove_if_mut(&self.ip, |_, count| {
+            *count -= 1;
+            *count == 0
+        });
+    }
+}
+
+/// Why a connection didn't get a session slot
+#[derive(Debug, PartialEq)]
+pub enum SessionLimit {
+    Server(usize),
+    PerIp(usize),
+}
+
+impl SftpGuard {
+    pub fn new(
+        max_failed_logins: u32,
+        window_secs: u64,
+        ban_secs: u64,
+        max_sessions: usize,
+        max_sessions_per_ip: usize,
+    ) -> Self {
+        Self {
+            failures: RateLimiter::new(max_failed_logins, window_secs),
+            bans: DashMap::new(),
+            ban_duration: Duration::from_secs(ban_secs),
+            sessions: Arc::new(Semaphore::new(max_sessions)),
+            max_sessions,
+            sessions_per_ip: Arc::new(DashMap::new()),
+            max_sessions_per_ip,
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        match self.bans.get(&ip) {
+            Some(until) => Instant::now() < *until,
+            None => false,
+        }
+    }
+
+    /// Count a failed login, banning the IP once it runs out of attempts
+    pub fn record_failure(&self, ip: IpAddr) {
+        self.failures.record_failure(ip);
+        if self.failures.is_limited(ip) && !self.is_banned(ip) {
+            self.bans.insert(ip, Instant::now() + self.ban_duration);
+            tracing::warn!(
+                "Banned {} from SFTP for {}s after repeated failed logins",
+                ip, self.ban_duration.as_secs()
+            );
+        }
+    }
+
+    /// Slot for a new session from `ip`, refused when the IP or the server is
+    /// at its limit. The slot is released when the permit is dropped.
+    pub fn try_acquire_session(&self, ip: IpAddr) -> Result<SessionPermit, SessionLimit> {
+        // The entry stays locked until the count is updated, so parallel
+        // connections from one IP can't overshoot
+        let mut count = self.sessions_per_ip.entry(ip).or_insert(0);
+        if *count >= self.max_sessions_per_ip {
+            return Err(SessionLimit::PerIp(self.max_sessions_per_ip));
+        }
+        let Ok(permit) = self.sessions.clone().try_acquire_owned() else {
+            drop(count);
+            self.sessions_per_ip.remove_if(&ip, |_, count| *count == 0);
+            return Err(SessionLimit::Server(self.max_sessions));
+        };
+        *count += 1;
+
+        Ok(SessionPermit {
+            _permit: permit,
+            ip,
+            sessions_per_ip: self.sessions_per_ip.clone(),
+        })
+    }
+
+    /// Forget expired bans and refilled buckets
+    pub fn cleanup(&self) {
+        let now = Instant::now();
+        self.bans.retain(|ip, until| {
+            let active = now < *until;
+            if !active {
+                tracing::info!("SFTP ban for {} expired", ip);
+            }
+            active
+        });
+        self.failures.cleanup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_banned_after_max_failures() {
+        let guard = SftpGuard::new(3, 60, 600, 10, 10);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        for _ in 0..2 {
+            guard.record_failure(ip);
+            assert!(!guard.is_banned(ip));
+        }
+        guard.record_failure(ip);
+
+        assert!(guard.is_banned(ip));
+        assert!(!guard.is_banned(other));
+    }
+
+    #[test]
+    fn test_session_cap() {
+        let guard = SftpGuard::new(3, 60, 600, 2, 10);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let first = guard.try_acquire_session(ip);
+        let _second = guard.try_acquire_session(ip);
+        assert!(first.is_ok());
+        assert_eq!(guard.try_acquire_session(ip).err(), Some(SessionLimit::Server(2)));
+
+        drop(first);
+        assert!(guard.try_acquire_session(ip).is_ok());
+    }
+
+    #[test]
+    fn test_session_cap_per_ip() {
+        let guard = SftpGuard::new(3, 60, 600, 10, 2);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = guard.try_acquire_session(ip).unwrap();
+        let _second = guard.try_acquire_session(ip).unwrap();
+        assert_eq!(guard.try_acquire_session(ip).err(), Some(SessionLimit::PerIp(2)));
+        assert!(guard.try_acquire_session(other).is_ok());
+
+        drop(first);
+        assert!(guard.try_acquire_session(ip).is_ok());
+    }
+}
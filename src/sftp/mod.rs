@@ -2,3 +2,4 @@ pub mod server;
 pub mod credentials;
 pub mod session;
 pub mod protocol;
+pub mod guard;
@@ -5,10 +5,15 @@
 use russh_sftp::protocol::{FileAttributes, OpenFlags};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, RwLock};
+
+use crate::filesystem::owner;
+use crate::filesystem::trash;
 
 /// SFTP file handle
 pub struct SftpHandle {
@@ -17,6 +22,26 @@ pub struct SftpHandle {
     pub is_dir: bool,
     pub dir_entries: Option<Vec<tokio::fs::DirEntry>>,
     pub dir_index: usize,
+    /// Last request that used the handle, for the idle sweep
+    pub last_used: Instant,
+}
+
+/// Per-session bounds on open handles
+#[derive(Debug, Clone, Copy)]
+pub struct HandleLimits {
+    /// OPEN/OPENDIR beyond this fail until a handle is closed
+    pub max_open: usize,
+    /// Handles untouched for this long are closed by the sweep
+    pub idle_timeout: Duration,
+}
+
+impl Default for HandleLimits {
+    fn default() -> Self {
+        Self {
+            max_open: 256,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
 }
 
 /// SFTP protocol handler
@@ -24,6 +49,13 @@ pub struct SftpProtocol {
     pub volume_path: PathBuf,
     pub handles: Arc<Mutex<HashMap<String, SftpHandle>>>,
     pub handle_counter: Arc<Mutex<u32>>,
+    pub limits: HandleLimits,
+    /// Bytes sent to the client by READ, not yet taken for billing
+    pub bytes_read: AtomicU64,
+    /// Bytes received from the client by WRITE
+    pub bytes_written: AtomicU64,
+    /// The volume's lock, held shared by each mutating request
+    pub volume_lock: Option<Arc<RwLock<()>>>,
 }
 
 impl SftpProtocol {
@@ -31,14 +63,45 @@ impl SftpProtocol {
         // Canonicalize volume root so path containment checks are reliable
         let volume_path = volume_path
             .canonicalize()
-            .unwrap_or_else(|_| volume_path);
+            .unwrap_or(volume_path);
         Self {
             volume_path,
             handles: Arc::new(Mutex::new(HashMap::new())),
             handle_counter: Arc::new(Mutex::new(0)),
+            limits: HandleLimits::default(),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            volume_lock: None,
+        }
+    }
+    
+    pub fn with_limits(mut self, limits: HandleLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+    
+    /// Wait for the volume's compress/decompress/copy before changing files
+    pub fn with_volume_lock(mut self, lock: Arc<RwLock<()>>) -> Self {
+        self.volume_lock = Some(lock);
+        self
+    }
+    
+    /// Shared hold on the volume for one request, see `filesystem::lock`
+    async fn hold_volume(&self) -> Option<OwnedRwLockReadGuard<()>> {
+        match &self.volume_lock {
+            Some(lock) => Some(lock.clone().read_owned().await),
+            None => None,
         }
     }
     
+    /// Bytes read and written since the last call, resetting both counters
+    pub fn take_transferred(&self) -> (u64, u64) {
+        (
+            self.bytes_read.swap(0, Ordering::Relaxed),
+            self.bytes_written.swap(0, Ordering::Relaxed),
+        )
+    }
+    
     /// Volume id (last path component) for normalizing client paths like /volume_id/...
     fn volume_id(&self) -> Option<&std::ffi::OsStr> {
         self.volume_path.file_name()
@@ -50,14 +113,12 @@ impl SftpProtocol {
         if path.is_empty() {
             return std::borrow::Cow::Borrowed(".");
         }
-        if let Some(vid) = self.volume_id() {
-            if let Some(vid_str) = vid.to_str() {
-                if path == vid_str || path.starts_with(&format!("{}/", vid_str)) {
-                    let rest = path.strip_prefix(vid_str).unwrap_or(path).trim_start_matches('/');
-                    return std::borrow::Cow::Borrowed(if rest.is_empty() { "." } else { rest });
-                }
+        if let Some(vid) = self.volume_id()
+            && let Some(vid_str) = vid.to_str()
+                && (path == vid_str || path.starts_with(&format!("{}/", vid_str))) {
+                let rest = path.strip_prefix(vid_str).unwrap_or(path).trim_start_matches('/');
+                return std::borrow::Cow::Borrowed(if rest.is_empty() { "." } else { rest });
             }
-        }
         std::borrow::Cow::Borrowed(path)
     }
     
@@ -68,6 +129,37 @@ impl SftpProtocol {
         format!("handle_{}", *counter)
     }
     
+    /// Fail early when the session already has the maximum number of handles open
+    async fn check_handle_limit(&self) -> Result<(), String> {
+        if self.handles.lock().await.len() >= self.limits.max_open {
+            return Err(format!("Too many open handles (limit {})", self.limits.max_open));
+        }
+        Ok(())
+    }
+    
+    /// Store a handle, re-checking the limit under the lock
+    async fn insert_handle(&self, handle: SftpHandle) -> Result<String, String> {
+        let handle_id = self.next_handle().await;
+        let mut handles = self.handles.lock().await;
+        if handles.len() >= self.limits.max_open {
+            return Err(format!("Too many open handles (limit {})", self.limits.max_open));
+        }
+        handles.insert(handle_id.clone(), handle);
+        Ok(handle_id)
+    }
+    
+    /// Close handles untouched for longer than the idle timeout, returns how many
+    pub async fn sweep_idle_handles(&self) -> usize {
+        let mut handles = self.handles.lock().await;
+        let before = handles.len();
+        handles.retain(|_, h| h.last_used.elapsed() < self.limits.idle_timeout);
+        let closed = before - handles.len();
+        if closed > 0 {
+            tracing::info!("Closed {} idle SFTP handle(s) in {}", closed, self.volume_path.display());
+        }
+        closed
+    }
+    
     /// Resolve and validate path within chroot
     fn resolve_path(&self, requested_path: &str) -> Result<PathBuf, String> {
         let normalized = self.normalize_requested_path(requested_path);
@@ -127,10 +219,16 @@ impl SftpProtocol {
         flags: OpenFlags,
     ) -> Result<String, String> {
         let resolved_path = self.resolve_path(path)?;
+        self.check_handle_limit().await?;
+        let _volume = if flags.intersects(OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE) {
+            self.hold_volume().await
+        } else {
+            None
+        };
         
         tracing::debug!("SFTP OPEN: {:?} with flags {:?}", resolved_path, flags);
         
-        match fs::metadata(&resolved_path).await {
+        let existed = match fs::metadata(&resolved_path).await {
             Ok(meta) => {
                 if meta.is_dir() {
                     return Err(format!(
@@ -138,6 +236,7 @@ impl SftpProtocol {
                         resolved_path.display()
                     ));
                 }
+                true
             }
             Err(e) if !flags.contains(OpenFlags::CREATE) => {
                 return Err(format!(
@@ -146,14 +245,13 @@ impl SftpProtocol {
                     e
                 ));
             }
-            _ => {}
-        }
+            Err(_) => false,
+        };
         
-        if flags.contains(OpenFlags::CREATE) {
-            if let Some(parent) = resolved_path.parent() {
-                fs::create_dir_all(parent).await
-                    .map_err(|e| format!("Failed to create parent directory: {}", e))?;
-            }
+        if flags.contains(OpenFlags::CREATE)
+            && let Some(parent) = resolved_path.parent() {
+            owner::create_dirs(&self.volume_path, parent)
+                .map_err(|e| format!("Failed to create parent directory: {}", e))?;
         }
         
         // Open file based on flags
@@ -175,19 +273,21 @@ impl SftpProtocol {
                 .map_err(|e| format!("Failed to open file: {} ({})", resolved_path.display(), e))?
         };
         
+        // A new file belongs to the server's user like the rest of the volume
+        if !existed {
+            owner::adopt(&self.volume_path, &resolved_path)
+                .map_err(|e| format!("Failed to set owner of {}: {}", resolved_path.display(), e))?;
+        }
+        
         // Create handle
-        let handle_id = self.next_handle().await;
-        let handle = SftpHandle {
+        self.insert_handle(SftpHandle {
             path: resolved_path,
             file: Some(file),
             is_dir: false,
             dir_entries: None,
             dir_index: 0,
-        };
-        
-        self.handles.lock().await.insert(handle_id.clone(), handle);
-        
-        Ok(handle_id)
+            last_used: Instant::now(),
+        }).await
     }
     
     /// Handle SFTP READ request
@@ -200,6 +300,7 @@ impl SftpProtocol {
         let mut handles = self.handles.lock().await;
         let handle_data = handles.get_mut(handle)
             .ok_or_else(|| "Invalid handle".to_string())?;
+        handle_data.last_used = Instant::now();
         
         let file = handle_data.file.as_mut()
             .ok_or_else(|| "Handle is not a file".to_string())?;
@@ -214,6 +315,7 @@ impl SftpProtocol {
             .map_err(|e| format!("Read failed: {}", e))?;
         
         buffer.truncate(bytes_read);
+        self.bytes_read.fetch_add(bytes_read as u64, Ordering::Relaxed);
         Ok(buffer)
     }
     
@@ -224,9 +326,11 @@ impl SftpProtocol {
         offset: u64,
         data: &[u8],
     ) -> Result<(), String> {
+        let _volume = self.hold_volume().await;
         let mut handles = self.handles.lock().await;
         let handle_data = handles.get_mut(handle)
             .ok_or_else(|| "Invalid handle".to_string())?;
+        handle_data.last_used = Instant::now();
         
         let file = handle_data.file.as_mut()
             .ok_or_else(|| "Handle is not a file".to_string())?;
@@ -242,6 +346,7 @@ impl SftpProtocol {
         file.flush().await
             .map_err(|e| format!("Flush failed: {}", e))?;
         
+        self.bytes_written.fetch_add(data.len() as u64, Ordering::Relaxed);
         Ok(())
     }
     
@@ -256,6 +361,7 @@ impl SftpProtocol {
     /// Handle SFTP OPENDIR request
     pub async fn handle_opendir(&self, path: &str) -> Result<String, String> {
         let resolved_path = self.resolve_path(path)?;
+        self.check_handle_limit().await?;
         
         tracing::debug!("SFTP OPENDIR: {:?}", resolved_path);
         
@@ -270,18 +376,14 @@ impl SftpProtocol {
         }
         
         // Create handle
-        let handle_id = self.next_handle().await;
-        let handle = SftpHandle {
+        self.insert_handle(SftpHandle {
             path: resolved_path,
             file: None,
             is_dir: true,
             dir_entries: Some(entries),
             dir_index: 0,
-        };
-        
-        self.handles.lock().await.insert(handle_id.clone(), handle);
-        
-        Ok(handle_id)
+            last_used: Instant::now(),
+        }).await
     }
     
     /// Handle SFTP READDIR request
@@ -289,6 +391,7 @@ impl SftpProtocol {
         let mut handles = self.handles.lock().await;
         let handle_data = handles.get_mut(handle)
             .ok_or_else(|| "Invalid handle".to_string())?;
+        handle_data.last_used = Instant::now();
         
         if !handle_data.is_dir {
             return Err("Handle is not a directory".to_string());
@@ -343,14 +446,41 @@ impl SftpProtocol {
     /// Handle SFTP MKDIR request
     pub async fn handle_mkdir(&self, path: &str) -> Result<(), String> {
         let resolved_path = self.resolve_path(path)?;
+        let _volume = self.hold_volume().await;
         
-        fs::create_dir_all(&resolved_path).await
+        owner::create_dirs(&self.volume_path, &resolved_path)
             .map_err(|e| format!("Failed to create directory: {}", e))
     }
     
+    /// Move `path` to the volume's trash if it has one enabled, returns
+    /// false when the caller should delete it for real
+    async fn try_trash(&self, path: &Path) -> Result<bool, std::io::Error> {
+        let volume = self.volume_path.clone();
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            if path == volume || !trash::settings(&volume).enabled || trash::is_in_trash(&volume, &path) {
+                return Ok(false);
+            }
+            trash::move_to_trash(&volume, &path).map(|_| true)
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+    
     /// Handle SFTP RMDIR request
     pub async fn handle_rmdir(&self, path: &str) -> Result<(), String> {
         let resolved_path = self.resolve_path(path)?;
+        let _volume = self.hold_volume().await;
+        
+        // RMDIR only takes empty directories, with or without the trash
+        let mut entries = fs::read_dir(&resolved_path).await
+            .map_err(|e| format!("Failed to remove directory: {}", e))?;
+        if entries.next_entry().await.map_err(|e| format!("Failed to remove directory: {}", e))?.is_some() {
+            return Err("Failed to remove directory: Directory not empty".to_string());
+        }
+        if self.try_trash(&resolved_path).await.map_err(|e| format!("Failed to remove directory: {}", e))? {
+            return Ok(());
+        }
         
         fs::remove_dir(&resolved_path).await
             .map_err(|e| format!("Failed to remove directory: {}", e))
@@ -359,6 +489,11 @@ impl SftpProtocol {
     /// Handle SFTP REMOVE request
     pub async fn handle_remove(&self, path: &str) -> Result<(), String> {
         let resolved_path = self.resolve_path(path)?;
+        let _volume = self.hold_volume().await;
+        
+        if self.try_trash(&resolved_path).await.map_err(|e| format!("Failed to remove file: {}", e))? {
+            return Ok(());
+        }
         
         fs::remove_file(&resolved_path).await
             .map_err(|e| format!("Failed to remove file: {}", e))
@@ -400,8 +535,104 @@ impl SftpProtocol {
     pub async fn handle_rename(&self, oldpath: &str, newpath: &str) -> Result<(), String> {
         let old_resolved = self.resolve_path(oldpath)?;
         let new_resolved = self.resolve_path(newpath)?;
+        let _volume = self.hold_volume().await;
         
         fs::rename(&old_resolved, &new_resolved).await
             .map_err(|e| format!("Failed to rename: {}", e))
     }
+    
+    /// Handle posix-rename@openssh.com, atomically replaces an existing destination
+    pub async fn handle_posix_rename(&self, oldpath: &str, newpath: &str) -> Result<(), String> {
+        let old_resolved = self.resolve_path(oldpath)?;
+        let new_resolved = self.resolve_path(newpath)?;
+        let _volume = self.hold_volume().await;
+        
+        fs::rename(&old_resolved, &new_resolved).await
+            .map_err(|e| format!("Failed to rename: {}", e))
+    }
+    
+    /// Handle hardlink@openssh.com, both paths must be inside the volume
+    pub async fn handle_hardlink(&self, oldpath: &str, newpath: &str) -> Result<(), String> {
+        let old_resolved = self.resolve_path(oldpath)?;
+        let new_resolved = self.resolve_path(newpath)?;
+        let _volume = self.hold_volume().await;
+        
+        fs::hard_link(&old_resolved, &new_resolved).await
+            .map_err(|e| format!("Failed to create hard link: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_volume() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lightd-sftp-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("server.properties"), b"motd=hi").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_open_beyond_cap_fails() {
+        let volume = temp_volume();
+        let protocol = SftpProtocol::new(volume.clone()).with_limits(HandleLimits {
+            max_open: 2,
+            idle_timeout: Duration::from_secs(300),
+        });
+
+        assert!(protocol.handle_open("server.properties", OpenFlags::READ).await.is_ok());
+        assert!(protocol.handle_opendir("/").await.is_ok());
+        assert!(protocol.handle_open("server.properties", OpenFlags::READ).await.is_err());
+        assert!(protocol.handle_opendir("/").await.is_err());
+        assert_eq!(protocol.handles.lock().await.len(), 2);
+
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_posix_rename_replaces_and_hardlink_stays_in_volume() {
+        let volume = temp_volume();
+        std::fs::write(volume.join("server.properties.tmp"), b"motd=new").unwrap();
+        let protocol = SftpProtocol::new(volume.clone());
+
+        protocol.handle_posix_rename("server.properties.tmp", "server.properties").await.unwrap();
+        assert_eq!(std::fs::read(volume.join("server.properties")).unwrap(), b"motd=new");
+
+        protocol.handle_hardlink("server.properties", "link.properties").await.unwrap();
+        assert_eq!(std::fs::read(volume.join("link.properties")).unwrap(), b"motd=new");
+        assert!(protocol.handle_hardlink("server.properties", "../escape").await.is_err());
+
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sweep_closes_idle_handles() {
+        let volume = temp_volume();
+        let protocol = SftpProtocol::new(volume.clone()).with_limits(HandleLimits {
+            max_open: 2,
+            idle_timeout: Duration::ZERO,
+        });
+
+        protocol.handle_open("server.properties", OpenFlags::READ).await.unwrap();
+        assert_eq!(protocol.sweep_idle_handles().await, 1);
+        assert!(protocol.handles.lock().await.is_empty());
+
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_transferred_bytes_are_taken_once() {
+        let volume = temp_volume();
+        let protocol = SftpProtocol::new(volume.clone());
+
+        let handle = protocol.handle_open("server.properties", OpenFlags::READ | OpenFlags::WRITE).await.unwrap();
+        assert_eq!(protocol.handle_read(&handle, 0, 1024).await.unwrap(), b"motd=hi");
+        protocol.handle_write(&handle, 7, b"!!").await.unwrap();
+
+        assert_eq!(protocol.take_transferred(), (7, 2));
+        assert_eq!(protocol.take_transferred(), (0, 0));
+
+        std::fs::remove_dir_all(&volume).unwrap();
+    }
 }
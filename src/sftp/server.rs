@@ -1,40 +1,120 @@
 //! SFTP server implementation
 //! 
-//! Runs SFTP server as part of lightd daemon with per-container isolation
+//! Runs SFTP server as part of lightd daemon with per-container isolation.
+//! The host key is generated on first start and kept on disk, so clients
+//! don't see a changed fingerprint after every restart.
 
 use russh::server::{Config as SshConfig, run_stream};
 use russh_keys::key::KeyPair;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::net::TcpListener;
 
 use super::credentials::CredentialsManager;
+use super::guard::{SessionLimit, SftpGuard};
+use super::protocol::HandleLimits;
 use super::session::SftpSession;
+use crate::billing::tracker::BillingTracker;
+use crate::daemon::shutdown::ShutdownSignal;
+use crate::filesystem::lock::VolumeLocks;
 
 pub struct SftpServerManager {
     credentials_manager: Arc<CredentialsManager>,
     base_volumes_path: String,
+    host: String,
     port: u16,
+    host_key_path: Option<PathBuf>,
+    handle_limits: HandleLimits,
+    guard: Arc<SftpGuard>,
+    billing: Option<Arc<BillingTracker>>,
+    volume_locks: Option<Arc<VolumeLocks>>,
 }
 
 impl SftpServerManager {
     pub fn new(
         credentials_manager: Arc<CredentialsManager>,
         base_volumes_path: String,
+        host: String,
         port: u16,
     ) -> Self {
         Self {
             credentials_manager,
             base_volumes_path,
+            host,
             port,
+            host_key_path: None,
+            handle_limits: HandleLimits::default(),
+            guard: Arc::new(SftpGuard::new(5, 300, 900, 100, 10)),
+            billing: None,
+            volume_locks: None,
         }
     }
     
-    /// Start SFTP server
-    pub async fn start(self: Arc<Self>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Generate host key
+    /// Bill SFTP downloads as egress of the container owning the volume
+    pub fn with_billing_tracker(mut self, billing: Arc<BillingTracker>) -> Self {
+        self.billing = Some(billing);
+        self
+    }
+    
+    /// Mutating requests wait while the volume is being compressed, decompressed or copied
+    pub fn with_volume_locks(mut self, locks: Arc<VolumeLocks>) -> Self {
+        self.volume_locks = Some(locks);
+        self
+    }
+    
+    /// Failed login bans and the concurrent session cap
+    pub fn with_guard(mut self, guard: SftpGuard) -> Self {
+        self.guard = Arc::new(guard);
+        self
+    }
+    
+    /// Per-session cap on open handles and idle timeout
+    pub fn with_handle_limits(mut self, limits: HandleLimits) -> Self {
+        self.handle_limits = limits;
+        self
+    }
+    
+    /// Load the host key from `path`, creating it there on first start
+    pub fn with_host_key_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.host_key_path = Some(path.into());
+        self
+    }
+    
+    fn load_or_generate_host_key(path: &Path) -> Result<KeyPair, Box<dyn std::error::Error + Send + Sync>> {
+        if path.exists() {
+            return Ok(russh_keys::load_secret_key(path, None)?);
+        }
+        
         let key_pair = KeyPair::generate_ed25519()
             .ok_or("Failed to generate host key")?;
         
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::File::create(path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        russh_keys::encode_pkcs8_pem(&key_pair, &mut file)?;
+        
+        tracing::info!("Generated SFTP host key at {}", path.display());
+        Ok(key_pair)
+    }
+    
+    /// Start SFTP server, runs until shutdown
+    pub async fn start(self: Arc<Self>, mut shutdown: ShutdownSignal) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key_pair = match &self.host_key_path {
+            Some(path) => Self::load_or_generate_host_key(path)?,
+            // Ephemeral key, clients will see a new fingerprint on every start
+            None => KeyPair::generate_ed25519().ok_or("Failed to generate host key")?,
+        };
+        if let Ok(public_key) = key_pair.clone_public_key() {
+            tracing::info!("SFTP host key fingerprint: SHA256:{}", public_key.fingerprint());
+        }
+        
         let config = SshConfig {
             inactivity_timeout: Some(std::time::Duration::from_secs(300)), // 5 minutes
             auth_rejection_time: std::time::Duration::from_secs(3),
@@ -47,14 +127,30 @@ impl SftpServerManager {
         
         let config = Arc::new(config);
         
-        let addr = format!("0.0.0.0:{}", self.port);
-        let listener = TcpListener::bind(&addr).await?;
+        // IPv6 addresses can't go through "host:port" formatting, hostnames are resolved
+        let bound = match self.host.parse::<IpAddr>() {
+            Ok(ip) => TcpListener::bind(SocketAddr::new(ip, self.port)).await,
+            Err(_) => TcpListener::bind((self.host.as_str(), self.port)).await,
+        };
+        let listener = bound
+            .map_err(|e| format!("Failed to bind SFTP listener on {} port {}: {}", self.host, self.port, e))?;
+        let addr = listener.local_addr()?;
         
         tracing::info!("SFTP server listening on {}", addr);
-        println!("SFTP server running on port {}", self.port);
+        println!("SFTP server running on {}", addr);
+        
+        let mut cleanup = tokio::time::interval(std::time::Duration::from_secs(60));
         
         loop {
-            let (stream, peer_addr) = match listener.accept().await {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = cleanup.tick() => {
+                    self.guard.cleanup();
+                    continue;
+                }
+                _ = shutdown.recv() => break,
+            };
+            let (stream, peer_addr) = match accepted {
                 Ok(s) => s,
                 Err(e) => {
                     tracing::error!("Failed to accept SFTP connection: {}", e);
@@ -62,6 +158,24 @@ impl SftpServerManager {
                 }
             };
             
+            // Banned clients are dropped before the handshake
+            if self.guard.is_banned(peer_addr.ip()) {
+                tracing::debug!("Dropping SFTP connection from banned {}", peer_addr);
+                continue;
+            }
+            
+            let permit = match self.guard.try_acquire_session(peer_addr.ip()) {
+                Ok(permit) => permit,
+                Err(SessionLimit::Server(max)) => {
+                    tracing::warn!("SFTP session limit ({}) reached, dropping connection from {}", max, peer_addr);
+                    continue;
+                }
+                Err(SessionLimit::PerIp(max)) => {
+                    tracing::warn!("{} already has {} SFTP sessions, dropping connection", peer_addr.ip(), max);
+                    continue;
+                }
+            };
+            
             // Set TCP keepalive
             if let Err(e) = stream.set_nodelay(true) {
                 tracing::warn!("Failed to set TCP_NODELAY: {}", e);
@@ -69,19 +183,32 @@ impl SftpServerManager {
             
             tracing::info!("SFTP connection from: {}", peer_addr);
             
-            let session = SftpSession::new(
+            let mut session = SftpSession::new(
                 self.credentials_manager.clone(),
                 self.base_volumes_path.clone(),
-            );
+            )
+            .with_handle_limits(self.handle_limits)
+            .with_guard(self.guard.clone(), peer_addr.ip());
+            if let Some(billing) = &self.billing {
+                session = session.with_billing(billing.clone());
+            }
+            if let Some(locks) = &self.volume_locks {
+                session = session.with_volume_locks(locks.clone());
+            }
             
             let config = config.clone();
             
             tokio::spawn(async move {
+                // Held until the session ends
+                let _permit = permit;
                 if let Err(e) = run_stream(config, stream, session).await {
                     tracing::error!("SFTP session error from {}: {}", peer_addr, e);
                 }
                 tracing::info!("SFTP session ended for {}", peer_addr);
             });
         }
+        
+        tracing::info!("SFTP server stopped");
+        Ok(())
     }
 }
@@ -8,11 +8,21 @@ use russh::server::{Auth, Handler, Msg, Session};
 use russh::{Channel, ChannelId, CryptoVec};
 use russh_keys::key::PublicKey;
 use russh_sftp::protocol::{FileAttributes, OpenFlags, StatusCode};
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use super::credentials::CredentialsManager;
-use super::protocol::SftpProtocol;
+use super::guard::SftpGuard;
+use super::protocol::{HandleLimits, SftpProtocol};
+use crate::billing::tracker::BillingTracker;
+use crate::filesystem::lock::VolumeLocks;
+
+/// Extended requests handled in `data`, sent in the VERSION reply
+const SUPPORTED_EXTENSIONS: [(&str, &str); 2] = [
+    ("posix-rename@openssh.com", "1"),
+    ("hardlink@openssh.com", "1"),
+];
 
 pub struct SftpSession {
     pub username: Option<String>,
@@ -20,6 +30,14 @@ pub struct SftpSession {
     pub credentials_manager: Arc<CredentialsManager>,
     pub base_volumes_path: String,
     pub sftp_protocol: Option<Arc<SftpProtocol>>,
+    pub handle_limits: HandleLimits,
+    /// Failed login tracking, with the IP of the connecting client
+    pub guard: Option<(Arc<SftpGuard>, IpAddr)>,
+    /// Downloads are billed as egress of the logged in volume
+    pub billing: Option<Arc<BillingTracker>>,
+    /// Per-volume locks shared with the HTTP file routes
+    pub volume_locks: Option<Arc<VolumeLocks>>,
+    pub volume_id: Option<String>,
 }
 
 impl SftpSession {
@@ -30,20 +48,69 @@ impl SftpSession {
             credentials_manager,
             base_volumes_path,
             sftp_protocol: None,
+            handle_limits: HandleLimits::default(),
+            guard: None,
+            billing: None,
+            volume_locks: None,
+            volume_id: None,
         }
     }
     
-    /// Parse SFTP packet
-    fn parse_sftp_packet<'a>(&self, data: &'a [u8]) -> Option<(u8, u32, &'a [u8])> {
-        if data.len() < 5 {
-            return None;
+    pub fn with_handle_limits(mut self, limits: HandleLimits) -> Self {
+        self.handle_limits = limits;
+        self
+    }
+    
+    pub fn with_guard(mut self, guard: Arc<SftpGuard>, peer_ip: IpAddr) -> Self {
+        self.guard = Some((guard, peer_ip));
+        self
+    }
+    
+    pub fn with_billing(mut self, billing: Arc<BillingTracker>) -> Self {
+        self.billing = Some(billing);
+        self
+    }
+    
+    pub fn with_volume_locks(mut self, locks: Arc<VolumeLocks>) -> Self {
+        self.volume_locks = Some(locks);
+        self
+    }
+    
+    /// The client got banned earlier in this connection
+    fn is_banned(&self) -> bool {
+        matches!(&self.guard, Some((guard, ip)) if guard.is_banned(*ip))
+    }
+    
+    fn record_failure(&self) {
+        if let Some((guard, ip)) = &self.guard {
+            guard.record_failure(*ip);
         }
+    }
+    
+    /// Hand bytes read since the last flush to the billing tracker
+    fn flush_egress(protocol: &SftpProtocol, billing: &BillingTracker, volume_id: &str) {
+        let (bytes_read, _) = protocol.take_transferred();
+        billing.record_sftp_egress(volume_id, bytes_read);
+    }
+    
+    /// Close idle handles and flush billed egress until the session's
+    /// protocol handler is dropped
+    fn spawn_handle_sweeper(protocol: &Arc<SftpProtocol>, egress: Option<(Arc<BillingTracker>, String)>) {
+        let weak = Arc::downgrade(protocol);
+        let interval = (protocol.limits.idle_timeout / 2).max(std::time::Duration::from_secs(1));
         
-        let packet_type = data[0];
-        let request_id = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
-        let payload = &data[5..];
-        
-        Some((packet_type, request_id, payload))
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(protocol) = weak.upgrade() else {
+                    break;
+                };
+                protocol.sweep_idle_handles().await;
+                if let Some((billing, volume_id)) = &egress {
+                    Self::flush_egress(&protocol, billing, volume_id);
+                }
+            }
+        });
     }
     
     /// Send SFTP status response
@@ -67,7 +134,7 @@ impl SftpSession {
         packet.put_u32(response.len() as u32);
         packet.put_slice(&response);
         
-        let _ = session.data(channel, CryptoVec::from_slice(&packet));
+        session.data(channel, CryptoVec::from_slice(&packet));
     }
     
     /// Send SFTP handle response
@@ -88,7 +155,7 @@ impl SftpSession {
         packet.put_u32(response.len() as u32);
         packet.put_slice(&response);
         
-        let _ = session.data(channel, CryptoVec::from_slice(&packet));
+        session.data(channel, CryptoVec::from_slice(&packet));
     }
     
     /// Send SFTP data response
@@ -109,7 +176,16 @@ impl SftpSession {
         packet.put_u32(response.len() as u32);
         packet.put_slice(&response);
         
-        let _ = session.data(channel, CryptoVec::from_slice(&packet));
+        session.data(channel, CryptoVec::from_slice(&packet));
+    }
+    
+    /// Read an SFTP string (u32 length + bytes) at `offset`, returns it and the offset after it
+    fn read_string(data: &[u8], offset: usize) -> Option<(String, usize)> {
+        let len_bytes = data.get(offset..offset + 4)?;
+        let len = u32::from_be_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]) as usize;
+        let end = (offset + 4).checked_add(len)?;
+        let bytes = data.get(offset + 4..end)?;
+        Some((String::from_utf8_lossy(bytes).to_string(), end))
     }
     
     /// Format Unix-style longname from attributes (e.g. "drwxr-xr-x" or "-rw-r--r--")
@@ -179,7 +255,7 @@ impl SftpSession {
         packet.put_u32(response.len() as u32);
         packet.put_slice(&response);
         
-        let _ = session.data(channel, CryptoVec::from_slice(&packet));
+        session.data(channel, CryptoVec::from_slice(&packet));
     }
     
     /// Send SFTP attrs response
@@ -218,7 +294,18 @@ impl SftpSession {
         packet.put_u32(response.len() as u32);
         packet.put_slice(&response);
         
-        let _ = session.data(channel, CryptoVec::from_slice(&packet));
+        session.data(channel, CryptoVec::from_slice(&packet));
+    }
+}
+
+impl Drop for SftpSession {
+    /// Bill what was downloaded since the sweeper's last flush
+    fn drop(&mut self) {
+        if let (Some(protocol), Some(billing), Some(volume_id)) =
+            (&self.sftp_protocol, &self.billing, &self.volume_id)
+        {
+            Self::flush_egress(protocol, billing, volume_id);
+        }
     }
 }
 
@@ -233,6 +320,13 @@ impl Handler for SftpSession {
     ) -> Result<Auth, Self::Error> {
         tracing::info!("SFTP auth attempt for user: {}", user);
         
+        if self.is_banned() {
+            tracing::warn!("SFTP auth for user {} rejected, client is banned", user);
+            return Ok(Auth::Reject {
+                proceed_with_methods: None,
+            });
+        }
+        
         // Verify credentials
         match self.credentials_manager.verify_credentials(user, password) {
             Ok(Some(creds)) => {
@@ -244,12 +338,21 @@ impl Handler for SftpSession {
                 self.username = Some(user.to_string());
                 
                 // Initialize SFTP protocol handler
-                self.sftp_protocol = Some(Arc::new(SftpProtocol::new(volume_path)));
+                let mut protocol = SftpProtocol::new(volume_path).with_limits(self.handle_limits);
+                if let Some(locks) = &self.volume_locks {
+                    protocol = protocol.with_volume_lock(locks.lock(&creds.volume_id));
+                }
+                let protocol = Arc::new(protocol);
+                self.volume_id = Some(creds.volume_id.clone());
+                let egress = self.billing.clone().map(|b| (b, creds.volume_id.clone()));
+                Self::spawn_handle_sweeper(&protocol, egress);
+                self.sftp_protocol = Some(protocol);
                 
                 Ok(Auth::Accept)
             }
             Ok(None) => {
                 tracing::warn!("SFTP auth failed for user: {}", user);
+                self.record_failure();
                 Ok(Auth::Reject {
                     proceed_with_methods: None,
                 })
@@ -268,7 +371,8 @@ impl Handler for SftpSession {
         _user: &str,
         _public_key: &PublicKey,
     ) -> Result<Auth, Self::Error> {
-        // Public key auth not implemented yet
+        // Public key auth not implemented yet. Clients offer keys before
+        // falling back to passwords, so this doesn't count as a failed login.
         Ok(Auth::Reject {
             proceed_with_methods: None,
         })
@@ -302,6 +406,14 @@ impl Handler for SftpSession {
             response.put_u8(2); // SSH_FXP_VERSION
             response.put_u32(3); // SFTP protocol version 3
             
+            // Advertise supported extensions as (name, version) pairs
+            for (name, version) in SUPPORTED_EXTENSIONS {
+                response.put_u32(name.len() as u32);
+                response.put_slice(name.as_bytes());
+                response.put_u32(version.len() as u32);
+                response.put_slice(version.as_bytes());
+            }
+            
             let mut packet = BytesMut::new();
             packet.put_u32(response.len() as u32);
             packet.put_slice(&response);
@@ -856,6 +968,55 @@ impl Handler for SftpSession {
                     }
                 }
             }
+            200 => {
+                // SSH_FXP_EXTENDED: request_id, extension name, extension specific data
+                if packet_data.len() >= 5 {
+                    let request_id = u32::from_be_bytes([
+                        packet_data[1], packet_data[2], packet_data[3], packet_data[4],
+                    ]);
+                    
+                    let Some((extension, offset)) = Self::read_string(packet_data, 5) else {
+                        self.send_status(session, channel, request_id, StatusCode::BadMessage, "Malformed extended request").await;
+                        return Ok(());
+                    };
+                    
+                    match extension.as_str() {
+                        "posix-rename@openssh.com" | "hardlink@openssh.com" => {
+                            let paths = Self::read_string(packet_data, offset)
+                                .and_then(|(old, next)| Self::read_string(packet_data, next).map(|(new, _)| (old, new)));
+                            let Some((oldpath, newpath)) = paths else {
+                                self.send_status(session, channel, request_id, StatusCode::BadMessage, "Malformed extended request").await;
+                                return Ok(());
+                            };
+                            
+                            let result = if extension == "posix-rename@openssh.com" {
+                                protocol.handle_posix_rename(&oldpath, &newpath).await
+                            } else {
+                                protocol.handle_hardlink(&oldpath, &newpath).await
+                            };
+                            
+                            match result {
+                                Ok(_) => {
+                                    self.send_status(session, channel, request_id, StatusCode::Ok, "OK").await;
+                                }
+                                Err(e) => {
+                                    self.send_status(session, channel, request_id, StatusCode::Failure, &e).await;
+                                }
+                            }
+                        }
+                        _ => {
+                            tracing::debug!("Unsupported SFTP extension: {}", extension);
+                            self.send_status(
+                                session,
+                                channel,
+                                request_id,
+                                StatusCode::OpUnsupported,
+                                &format!("Extension {} not supported", extension),
+                            ).await;
+                        }
+                    }
+                }
+            }
             _ => {
                 // For now, send "operation not supported" for all other operations
                 if packet_data.len() >= 5 {
@@ -4,6 +4,7 @@
 
 use bollard::container::{AttachContainerOptions, LogsOptions, LogOutput};
 use bollard::Docker;
+use dashmap::DashMap;
 use futures::StreamExt;
 use std::sync::Arc;
 use std::time::Duration;
@@ -13,6 +14,9 @@ use tracing::{debug};
 
 use super::event_hub::{EventHub, ContainerRuntimeState};
 use crate::container::manager::ContainerManager;
+use crate::container::uptime::UptimeTracker;
+use crate::error::LightdError;
+use crate::remote::client::RemoteSyncManager;
 
 /// Check if a container is running
 async fn is_container_running(docker: &Docker, container_id: &str) -> bool {
@@ -26,6 +30,30 @@ async fn is_container_running(docker: &Docker, container_id: &str) -> bool {
     }
 }
 
+/// How the container last exited
+struct ExitDetails {
+    exit_code: i64,
+    oom_killed: bool,
+    /// Set when the exit was an error: OOM kill, Docker error or non-zero code
+    error: Option<String>,
+}
+
+async fn exit_details(docker: &Docker, container_id: &str) -> Option<ExitDetails> {
+    let state = docker.inspect_container(container_id, None).await.ok()?.state?;
+    let oom_killed = state.oom_killed == Some(true);
+    let exit_code = state.exit_code.unwrap_or(if oom_killed { 137 } else { 0 });
+    let error = if oom_killed {
+        Some("Container killed: out of memory".to_string())
+    } else if let Some(error) = state.error.filter(|e| !e.is_empty()) {
+        Some(error)
+    } else if exit_code != 0 {
+        Some(format!("Exited with code {}", exit_code))
+    } else {
+        None
+    };
+    Some(ExitDetails { exit_code, oom_killed, error })
+}
+
 /// Get container start timestamp
 async fn get_container_started_at(docker: &Docker, container_id: &str) -> Option<i64> {
     match docker.inspect_container(container_id, None).await {
@@ -43,11 +71,22 @@ async fn get_container_started_at(docker: &Docker, container_id: &str) -> Option
     }
 }
 
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 /// Console streamer that manages stdin/stdout for a container
 pub struct ConsoleStreamer {
     docker: Arc<Docker>,
     manager: Arc<ContainerManager>,
     event_hub: Arc<EventHub>,
+    remote_sync: Option<Arc<RemoteSyncManager>>,
+    uptime: Option<Arc<UptimeTracker>>,
+    /// Internal ID -> Docker ID of the container a streamer watches, one streamer each
+    active: DashMap<String, String>,
 }
 
 #[allow(unused_mut)]
@@ -56,26 +95,50 @@ impl ConsoleStreamer {
         manager: Arc<ContainerManager>,
         event_hub: Arc<EventHub>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Arc::new(Docker::connect_with_local_defaults()?);
+        let docker = Arc::new(crate::container::docker::client()?);
         
         Ok(Self {
             docker,
             manager,
             event_hub,
+            remote_sync: None,
+            uptime: None,
+            active: DashMap::new(),
         })
     }
     
-    /// Start streaming for a container (called when WebSocket connects)
+    /// Report crashes such as OOM kills to the remote panel
+    pub fn with_remote_sync(mut self, remote_sync: Option<Arc<RemoteSyncManager>>) -> Self {
+        self.remote_sync = remote_sync;
+        self
+    }
+    
+    /// Persist the starts and stops the watcher observes
+    pub fn with_uptime_tracker(mut self, uptime: Arc<UptimeTracker>) -> Self {
+        self.uptime = Some(uptime);
+        self
+    }
+    
+    /// Start streaming for a container (called when WebSocket connects, or
+    /// a start waits for readiness). A no-op while a streamer already
+    /// watches the same Docker container.
     pub async fn start_streaming(&self, internal_id: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Get container state
         let state = self.manager.get_container(&internal_id).await?
-            .ok_or("Container not found")?;
+            .ok_or(LightdError::NotFound("Container"))?;
         
+        let start_patterns = state.start_pattern_list();
         let container_id = state.container_id.ok_or("Container not ready")?;
-        let start_pattern = state.start_pattern.clone();
+        
+        if self.active.insert(internal_id.clone(), container_id.clone()).as_ref() == Some(&container_id) {
+            return Ok(());
+        }
         
         let docker = self.docker.clone();
+        let manager = self.manager.clone();
         let event_hub = self.event_hub.clone();
+        let remote_sync = self.remote_sync.clone();
+        let uptime = self.uptime.clone();
         let internal_id_clone = internal_id.clone();
         
         // Get or create the channel
@@ -85,11 +148,14 @@ impl ConsoleStreamer {
         tokio::spawn(async move {
             Self::stream_logs_attached(
                 docker,
+                manager,
                 container_id,
                 internal_id_clone,
                 event_hub,
+                remote_sync,
+                uptime,
                 command_rx,
-                start_pattern,
+                start_patterns,
             ).await;
         });
         
@@ -97,13 +163,17 @@ impl ConsoleStreamer {
     }
     
     /// Stream logs in attached mode - uses docker attach for stdin + docker logs for output
+    #[allow(clippy::too_many_arguments)]
     async fn stream_logs_attached(
         docker: Arc<Docker>,
+        manager: Arc<ContainerManager>,
         container_id: String,
         internal_id: String,
         event_hub: Arc<EventHub>,
+        remote_sync: Option<Arc<RemoteSyncManager>>,
+        uptime: Option<Arc<UptimeTracker>>,
         mut input_rx: mpsc::UnboundedReceiver<String>,
-        start_pattern: Option<String>,
+        start_patterns: Vec<String>,
     ) {
         let mut last_line: Option<String> = None;
         let mut duplicate_count: u32 = 0;
@@ -111,10 +181,10 @@ impl ConsoleStreamer {
 
         tracing::info!("Starting log streamer for container {}", internal_id);
         
-        // Compile regex if pattern provided
-        let pattern_regex = start_pattern.as_ref().and_then(|p| {
-            regex::Regex::new(p).ok()
-        });
+        // Compile the start patterns, older records may hold one that doesn't compile
+        let pattern_regexes: Vec<regex::Regex> = start_patterns.iter()
+            .filter_map(|p| regex::Regex::new(p).ok())
+            .collect();
 
         // Spawn a task for stdin handling (attach for input only)
         let docker_input = docker.clone();
@@ -181,13 +251,41 @@ impl ConsoleStreamer {
                     // Container just stopped
                     tracing::info!("Container {} stopped", internal_id);
                     event_hub.broadcast_event(&internal_id, "exit").await;
-                    event_hub.broadcast_daemon_message(&internal_id, "Container stopped").await;
+                    
+                    let details = exit_details(&docker, &container_id).await;
+                    if let Some(ref details) = details
+                        && let Err(e) = manager.record_exit(&internal_id, details.exit_code, details.error.clone()).await {
+                        tracing::warn!("Failed to record exit of {}: {}", internal_id, e);
+                    }
+                    
+                    if let Some(exit_code) = details.filter(|d| d.oom_killed).map(|d| d.exit_code) {
+                        tracing::warn!("Container {} was killed by the OOM killer (exit code {})", internal_id, exit_code);
+                        event_hub.broadcast_oom_killed(&internal_id, exit_code).await;
+                        event_hub.broadcast_daemon_message(&internal_id, "Container killed: out of memory").await;
+                        
+                        if let Some(ref sync) = remote_sync {
+                            sync.notify_error(
+                                internal_id.clone(),
+                                "oom_killed".to_string(),
+                                Some(format!("Container killed: out of memory (exit code {})", exit_code)),
+                            );
+                        }
+                    } else {
+                        event_hub.broadcast_daemon_message(&internal_id, "Container stopped").await;
+                    }
+                    
+                    if let Some(ref uptime) = uptime
+                        && let Err(e) = uptime.record_stop(&internal_id, unix_now()) {
+                        tracing::warn!("Failed to record stop of {}: {}", internal_id, e);
+                    }
                     
                     // Update state
                     if let Some(channel) = event_hub.get_channel(&internal_id) {
                         channel.set_state(ContainerRuntimeState::Offline).await;
                     }
                     was_running = false;
+                    // The next start has to match a pattern again
+                    pattern_matched = false;
                 }
                 
                 debug!("Container {} not running, waiting...", internal_id);
@@ -201,6 +299,16 @@ impl ConsoleStreamer {
                 tracing::info!("Container {} is now running", internal_id);
                 was_running = true;
                 
+                if let Some(ref uptime) = uptime {
+                    // Docker's start time, so re-attaching to the same run isn't a new start
+                    let started_at = get_container_started_at(&docker, &container_id).await
+                        .map(|ts| ts.max(0) as u64)
+                        .unwrap_or_else(unix_now);
+                    if let Err(e) = uptime.record_start(&internal_id, started_at) {
+                        tracing::warn!("Failed to record start of {}: {}", internal_id, e);
+                    }
+                }
+                
                 // Update state to starting (will become running when pattern matches)
                 if let Some(channel) = event_hub.get_channel(&internal_id) {
                     channel.set_state(ContainerRuntimeState::Starting).await;
@@ -250,29 +358,25 @@ impl ConsoleStreamer {
                                 debug!("Container {} log #{}: {}", internal_id, log_count, line);
                                 
                                 // Check for start pattern match
-                                if !pattern_matched {
-                                    if let Some(ref regex) = pattern_regex {
-                                        if regex.is_match(line) {
-                                            pattern_matched = true;
-                                            tracing::info!("Server marked as running, start up pattern matched. for {}: {}", internal_id, line);
-                                            
-                                            // Transition to running state
-                                            if let Some(channel) = event_hub.get_channel(&internal_id) {
-                                                channel.set_state(ContainerRuntimeState::Running).await;
-                                            }
-                                            event_hub.broadcast_event(&internal_id, "running").await;
-                                            event_hub.broadcast_daemon_message(&internal_id, "Server started").await;
-                                        }
+                                if !pattern_matched
+                                    && let Some(regex) = pattern_regexes.iter().find(|regex| regex.is_match(line)) {
+                                    pattern_matched = true;
+                                    tracing::info!("Server marked as running, start up pattern {} matched. for {}: {}", regex.as_str(), internal_id, line);
+                                    
+                                    // Transition to running state
+                                    if let Some(channel) = event_hub.get_channel(&internal_id) {
+                                        channel.set_state(ContainerRuntimeState::Running).await;
                                     }
+                                    event_hub.broadcast_event(&internal_id, "running").await;
+                                    event_hub.broadcast_daemon_message(&internal_id, "Server started").await;
                                 }
                                 
                                 // Check for duplicates
-                                if let Some(ref last) = last_line {
-                                    if last == line {
-                                        duplicate_count += 1;
-                                        event_hub.broadcast_console_duplicate(&internal_id, duplicate_count).await;
-                                        continue;
-                                    }
+                                if let Some(ref last) = last_line
+                                    && last == line {
+                                    duplicate_count += 1;
+                                    event_hub.broadcast_console_duplicate(&internal_id, duplicate_count).await;
+                                    continue;
                                 }
 
                                 last_line = Some(line.to_string());
@@ -3,11 +3,28 @@
 //! This module provides a centralized event broadcasting system for container
 //! WebSocket connections, similar to Pterodactyl Wings also the name is very funny.
 
+use crate::container::manager::ContainerManager;
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, mpsc, RwLock};
 
+/// Events buffered per subscriber unless `server.websocket.buffer_size` says otherwise
+const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// Lines kept in each container's log buffer
+const LOG_BUFFER_LINES: usize = 1000;
+
+/// Longest pattern accepted by `search_logs`
+const MAX_SEARCH_PATTERN_LEN: usize = 256;
+
+/// Cap on the compiled size of a search regex. The regex crate already runs
+/// in linear time, this keeps huge repetitions from eating memory instead.
+const SEARCH_REGEX_SIZE_LIMIT: usize = 256 * 1024;
+
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
 /// Events that can be sent TO the WebSocket clients
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "event", content = "args")]
@@ -35,6 +52,22 @@ pub enum OutboundEvent {
     /// Response to logs request
     #[serde(rename = "logs")]
     Logs(Vec<String>),
+    
+    /// Response to a log search, one JSON `{"line", "text"}` object per match
+    #[serde(rename = "search_results")]
+    SearchResults(Vec<String>),
+    
+    /// Container was killed by the kernel OOM killer, args hold the exit code
+    #[serde(rename = "oom_killed")]
+    OomKilled(Vec<String>),
+
+    /// Progress of a transfer to another node, args hold the stage and percentage
+    #[serde(rename = "transfer progress")]
+    TransferProgress(Vec<String>),
+
+    /// Memory/CPU caps changed, args hold one JSON `LimitsUpdate`
+    #[serde(rename = "limits_updated")]
+    LimitsUpdated(Vec<String>),
 }
 
 /// Events that can be received FROM WebSocket clients
@@ -49,6 +82,12 @@ pub enum InboundEvent {
     
     /// Request last N lines of logs
     RequestLogs { logs: Vec<String> },
+    
+    /// Search the log buffer: pattern, optional match limit
+    SearchLogs { search_logs: Vec<String> },
+    
+    /// Swap the session token for a freshly issued one
+    RefreshToken { refresh_token: Vec<String> },
 }
 
 /// Container stats data
@@ -60,7 +99,18 @@ pub struct ContainerStats {
     pub network: NetworkStats,
     pub uptime: u64,
     pub state: String,
+    /// Volume usage, from the quota rather than blkio
     pub disk_bytes: u64,
+    pub disk_limit_bytes: u64,
+}
+
+/// Caps sent with `limits_updated`, null when unlimited
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LimitsUpdate {
+    /// Same unit as `memory_limit_bytes` in stats
+    pub memory_limit_bytes: Option<i64>,
+    /// CPU cores, 1.0 is 100 in `cpu_absolute`
+    pub cpu_limit: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,6 +119,38 @@ pub struct NetworkStats {
     pub tx_bytes: u64,
 }
 
+/// A log line matched by `search_logs`
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LogMatch {
+    /// 1-based, counted from the first line the channel saw, so numbers
+    /// stay put while old lines rotate out of the buffer
+    pub line: u64,
+    pub text: String,
+}
+
+/// Compile a search pattern. Patterns that aren't valid regex are matched
+/// as plain substrings, ones that compile too big are refused.
+pub fn compile_search(pattern: &str) -> Result<regex::Regex, String> {
+    if pattern.is_empty() {
+        return Err("Search pattern is empty".to_string());
+    }
+    if pattern.len() > MAX_SEARCH_PATTERN_LEN {
+        return Err(format!("Search pattern is longer than {} characters", MAX_SEARCH_PATTERN_LEN));
+    }
+
+    let build = |pattern: &str| {
+        regex::RegexBuilder::new(pattern)
+            .size_limit(SEARCH_REGEX_SIZE_LIMIT)
+            .dfa_size_limit(SEARCH_REGEX_SIZE_LIMIT)
+            .build()
+    };
+    match build(pattern) {
+        Ok(re) => Ok(re),
+        Err(regex::Error::CompiledTooBig(_)) => Err("Search pattern is too complex".to_string()),
+        Err(_) => build(&regex::escape(pattern)).map_err(|e| e.to_string()),
+    }
+}
+
 /// Container runtime state for tracking running state
 #[derive(Debug, Clone, PartialEq)]
 pub enum ContainerRuntimeState {
@@ -76,15 +158,33 @@ pub enum ContainerRuntimeState {
     Starting,
     Running,
     Stopping,
+    /// Frozen with docker pause, the processes exist but get no CPU
+    Paused,
 }
 
-impl ToString for ContainerRuntimeState {
-    fn to_string(&self) -> String {
-        match self {
-            ContainerRuntimeState::Offline => "offline".to_string(),
-            ContainerRuntimeState::Starting => "starting".to_string(),
-            ContainerRuntimeState::Running => "running".to_string(),
-            ContainerRuntimeState::Stopping => "stopping".to_string(),
+impl std::fmt::Display for ContainerRuntimeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ContainerRuntimeState::Offline => "offline",
+            ContainerRuntimeState::Starting => "starting",
+            ContainerRuntimeState::Running => "running",
+            ContainerRuntimeState::Stopping => "stopping",
+            ContainerRuntimeState::Paused => "paused",
+        })
+    }
+}
+
+impl std::str::FromStr for ContainerRuntimeState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "offline" => Ok(Self::Offline),
+            "starting" => Ok(Self::Starting),
+            "running" => Ok(Self::Running),
+            "stopping" => Ok(Self::Stopping),
+            "paused" => Ok(Self::Paused),
+            other => Err(format!("Unknown runtime state '{}'", other)),
         }
     }
 }
@@ -101,23 +201,48 @@ pub struct ContainerEventChannel {
     pub last_stats: RwLock<Option<ContainerStats>>,
     /// Log buffer (circular, stores last 1000 lines)
     pub log_buffer: RwLock<Vec<String>>,
+    /// Lines ever added to the buffer, for line numbers in search results
+    lines_seen: AtomicU64,
     /// Start pattern (regex or plain text to detect server started)
     pub start_pattern: RwLock<Option<String>>,
     /// Container uptime start timestamp
     pub uptime_start: RwLock<Option<u64>>,
+    /// Where state changes are persisted, with the container's internal ID
+    store: Option<(Arc<ContainerManager>, String)>,
 }
 
 impl ContainerEventChannel {
-    pub fn new(command_tx: mpsc::UnboundedSender<String>) -> Self {
-        let (event_tx, _) = broadcast::channel(1024);
+    /// `buffer_size` events are kept for each subscriber before it starts missing them
+    pub fn new(command_tx: mpsc::UnboundedSender<String>, buffer_size: usize) -> Self {
+        let (event_tx, _) = broadcast::channel(buffer_size);
         Self {
             event_tx,
             command_tx,
             state: RwLock::new(ContainerRuntimeState::Offline),
             last_stats: RwLock::new(None),
-            log_buffer: RwLock::new(Vec::with_capacity(1000)),
+            log_buffer: RwLock::new(Vec::with_capacity(LOG_BUFFER_LINES)),
+            lines_seen: AtomicU64::new(0),
             start_pattern: RwLock::new(None),
             uptime_start: RwLock::new(None),
+            store: None,
+        }
+    }
+
+    /// Channel whose state starts from, and is saved to, the container manager
+    fn with_store(
+        command_tx: mpsc::UnboundedSender<String>,
+        buffer_size: usize,
+        manager: Arc<ContainerManager>,
+        internal_id: &str,
+    ) -> Self {
+        let state = manager
+            .runtime_state(internal_id)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(ContainerRuntimeState::Offline);
+        Self {
+            state: RwLock::new(state),
+            store: Some((manager, internal_id.to_string())),
+            ..Self::new(command_tx, buffer_size)
         }
     }
     
@@ -134,10 +259,11 @@ impl ContainerEventChannel {
     /// Add a log line to the buffer
     pub async fn add_log(&self, line: String) {
         let mut buffer = self.log_buffer.write().await;
-        if buffer.len() >= 1000 {
+        if buffer.len() >= LOG_BUFFER_LINES {
             buffer.remove(0);
         }
         buffer.push(line);
+        self.lines_seen.fetch_add(1, Ordering::Relaxed);
     }
     
     /// Get last N log lines
@@ -147,6 +273,23 @@ impl ContainerEventChannel {
         buffer[start..].to_vec()
     }
     
+    /// Newest `limit` buffered lines matching `re`, oldest first
+    pub async fn search_logs(&self, re: &regex::Regex, limit: usize) -> Vec<LogMatch> {
+        let buffer = self.log_buffer.read().await;
+        // Read under the lock so it agrees with the buffer
+        let first_line = self.lines_seen.load(Ordering::Relaxed) - buffer.len() as u64 + 1;
+        let mut matches: Vec<LogMatch> = buffer
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, text)| re.is_match(text))
+            .take(limit)
+            .map(|(i, text)| LogMatch { line: first_line + i as u64, text: text.clone() })
+            .collect();
+        matches.reverse();
+        matches
+    }
+    
     /// Set the start pattern for detecting when server is ready
     #[allow(unused)]
     pub async fn set_start_pattern(&self, pattern: Option<String>) {
@@ -162,6 +305,13 @@ impl ContainerEventChannel {
     /// Set runtime state
     pub async fn set_state(&self, new_state: ContainerRuntimeState) {
         let mut state = self.state.write().await;
+        if *state == new_state {
+            return;
+        }
+        if let Some((manager, internal_id)) = &self.store
+            && let Err(e) = manager.set_runtime_state(internal_id, &new_state.to_string()) {
+            tracing::warn!("Failed to persist runtime state of {}: {}", internal_id, e);
+        }
         *state = new_state;
     }
 }
@@ -170,14 +320,32 @@ impl ContainerEventChannel {
 pub struct EventHub {
     /// Map of internal_id -> ContainerEventChannel
     channels: DashMap<String, Arc<ContainerEventChannel>>,
+    /// Persists runtime state so it survives daemon restarts
+    state_store: Option<Arc<ContainerManager>>,
+    /// Broadcast capacity of new channels
+    buffer_size: usize,
 }
 
 impl EventHub {
     pub fn new() -> Self {
         Self {
             channels: DashMap::new(),
+            state_store: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
         }
     }
+
+    /// Events each client may fall behind before it starts missing them
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Seed new channels with the last persisted state and save every change
+    pub fn with_state_store(mut self, manager: Arc<ContainerManager>) -> Self {
+        self.state_store = Some(manager);
+        self
+    }
     
     /// Get or create a channel for a container
     pub fn get_or_create_channel(
@@ -191,17 +359,57 @@ impl EventHub {
             (channel.clone(), rx)
         } else {
             let (command_tx, command_rx) = mpsc::unbounded_channel();
-            let channel = Arc::new(ContainerEventChannel::new(command_tx));
+            let channel = Arc::new(match &self.state_store {
+                Some(manager) => {
+                    ContainerEventChannel::with_store(command_tx, self.buffer_size, manager.clone(), internal_id)
+                }
+                None => ContainerEventChannel::new(command_tx, self.buffer_size),
+            });
             self.channels.insert(internal_id.to_string(), channel.clone());
             (channel, command_rx)
         }
     }
     
+    /// Set the runtime state whether or not a channel exists. The channel
+    /// holds it in memory, without one it goes straight to the state store.
+    pub async fn set_runtime_state(&self, internal_id: &str, state: ContainerRuntimeState) {
+        if let Some(channel) = self.get_channel(internal_id) {
+            channel.set_state(state).await;
+        } else if let Some(manager) = &self.state_store
+            && let Err(e) = manager.set_runtime_state(internal_id, &state.to_string()) {
+            tracing::warn!("Failed to persist runtime state of {}: {}", internal_id, e);
+        }
+    }
+    
+    /// `starting` once Docker started the container, unless a start pattern
+    /// already matched and moved it to `running`
+    pub async fn mark_started(&self, internal_id: &str) {
+        let current = match self.get_channel(internal_id) {
+            Some(channel) => Some(channel.get_state().await),
+            None => self.state_store.as_ref()
+                .and_then(|manager| manager.runtime_state(internal_id))
+                .and_then(|s| s.parse().ok()),
+        };
+        if current != Some(ContainerRuntimeState::Running) {
+            self.set_runtime_state(internal_id, ContainerRuntimeState::Starting).await;
+        }
+    }
+    
     /// Get an existing channel (if any)
     pub fn get_channel(&self, internal_id: &str) -> Option<Arc<ContainerEventChannel>> {
         self.channels.get(internal_id).map(|c| c.clone())
     }
     
+    /// Number of containers with an event channel
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+    
+    /// Number of connected WebSocket subscribers across all channels
+    pub fn subscriber_count(&self) -> usize {
+        self.channels.iter().map(|c| c.event_tx.receiver_count()).sum()
+    }
+    
     /// Remove a channel
     #[allow(unused)]
     pub fn remove_channel(&self, internal_id: &str) {
@@ -218,11 +426,10 @@ impl EventHub {
             let state = channel.get_state().await;
             if state == ContainerRuntimeState::Starting {
                 let pattern = channel.start_pattern.read().await;
-                if let Some(ref pat) = *pattern {
-                    if line.contains(pat) || Self::match_pattern(pat, line) {
-                        channel.set_state(ContainerRuntimeState::Running).await;
-                        let _ = channel.event_tx.send(OutboundEvent::Event(vec!["running".to_string()]));
-                    }
+                if let Some(ref pat) = *pattern
+                    && (line.contains(pat) || Self::match_pattern(pat, line)) {
+                    channel.set_state(ContainerRuntimeState::Running).await;
+                    let _ = channel.event_tx.send(OutboundEvent::Event(vec!["running".to_string()]));
                 }
             }
             
@@ -273,6 +480,28 @@ impl EventHub {
         }
     }
     
+    /// Broadcast that the container was OOM killed
+    pub async fn broadcast_oom_killed(&self, internal_id: &str, exit_code: i64) {
+        if let Some(channel) = self.channels.get(internal_id) {
+            let _ = channel.event_tx.send(OutboundEvent::OomKilled(vec![exit_code.to_string()]));
+        }
+    }
+    
+    /// Broadcast the stage and progress of a transfer to another node
+    pub async fn broadcast_transfer_progress(&self, internal_id: &str, stage: &str, progress: u8) {
+        if let Some(channel) = self.channels.get(internal_id) {
+            let _ = channel.event_tx.send(OutboundEvent::TransferProgress(vec![stage.to_string(), progress.to_string()]));
+        }
+    }
+    
+    /// Broadcast new memory/CPU caps so graphs can rescale without a reconnect
+    pub async fn broadcast_limits_updated(&self, internal_id: &str, limits: LimitsUpdate) {
+        if let Some(channel) = self.channels.get(internal_id) {
+            let limits_json = serde_json::to_string(&limits).unwrap_or_default();
+            let _ = channel.event_tx.send(OutboundEvent::LimitsUpdated(vec![limits_json]));
+        }
+    }
+    
     /// Send logs response
     pub async fn send_logs(&self, internal_id: &str, count: usize) {
         if let Some(channel) = self.channels.get(internal_id) {
@@ -281,6 +510,28 @@ impl EventHub {
         }
     }
     
+    /// Search the log buffer and send the matches, or a daemon message when
+    /// the pattern is refused
+    pub async fn send_search_results(&self, internal_id: &str, pattern: &str, limit: Option<usize>) {
+        let Some(channel) = self.get_channel(internal_id) else {
+            return;
+        };
+        let re = match compile_search(pattern) {
+            Ok(re) => re,
+            Err(e) => {
+                let _ = channel.event_tx.send(OutboundEvent::DaemonMessage(vec![format!("Log search failed: {}", e)]));
+                return;
+            }
+        };
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, LOG_BUFFER_LINES);
+        let matches = channel.search_logs(&re, limit).await;
+        let args = matches
+            .iter()
+            .map(|m| serde_json::to_string(m).unwrap_or_default())
+            .collect();
+        let _ = channel.event_tx.send(OutboundEvent::SearchResults(args));
+    }
+    
     /// Check if stats changed enough to warrant sending
     fn stats_changed(prev: &ContainerStats, new: &ContainerStats) -> bool {
         // Always send if state changed
@@ -294,30 +545,23 @@ impl EventHub {
         }
         
         // Send if memory changed by more than 1MB
-        let mem_diff = if prev.memory_bytes > new.memory_bytes {
-            prev.memory_bytes - new.memory_bytes
-        } else {
-            new.memory_bytes - prev.memory_bytes
-        };
+        let mem_diff = prev.memory_bytes.abs_diff(new.memory_bytes);
         if mem_diff > 1_048_576 {
             return true;
         }
         
         // Send if network changed by more than 10KB
-        let net_rx_diff = if prev.network.rx_bytes > new.network.rx_bytes {
-            prev.network.rx_bytes - new.network.rx_bytes
-        } else {
-            new.network.rx_bytes - prev.network.rx_bytes
-        };
-        let net_tx_diff = if prev.network.tx_bytes > new.network.tx_bytes {
-            prev.network.tx_bytes - new.network.tx_bytes
-        } else {
-            new.network.tx_bytes - prev.network.tx_bytes
-        };
+        let net_rx_diff = prev.network.rx_bytes.abs_diff(new.network.rx_bytes);
+        let net_tx_diff = prev.network.tx_bytes.abs_diff(new.network.tx_bytes);
         if net_rx_diff > 10_240 || net_tx_diff > 10_240 {
             return true;
         }
         
+        // Send if disk usage changed by more than 1MB or the quota was resized
+        if prev.disk_bytes.abs_diff(new.disk_bytes) > 1_048_576 || prev.disk_limit_bytes != new.disk_limit_bytes {
+            return true;
+        }
+        
         false
     }
     
@@ -336,3 +580,31 @@ impl Default for EventHub {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_search_logs() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let channel = ContainerEventChannel::new(command_tx, DEFAULT_BUFFER_SIZE);
+        for i in 0..LOG_BUFFER_LINES + 10 {
+            let level = if i % 100 == 0 { "ERROR" } else { "INFO" };
+            channel.add_log(format!("[{}] line {}", level, i)).await;
+        }
+
+        let re = compile_search(r"ERROR\] line \d+").unwrap();
+        let matches = channel.search_logs(&re, 2).await;
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1], LogMatch { line: 1001, text: "[ERROR] line 1000".to_string() });
+        assert_eq!(matches[0].line, 901);
+
+        // Not valid regex, matched literally
+        let re = compile_search("[ERROR").unwrap();
+        assert_eq!(channel.search_logs(&re, 1000).await.len(), 10);
+
+        assert!(compile_search(r"(\w{500}){500}").is_err());
+        assert!(compile_search(&"a".repeat(MAX_SEARCH_PATTERN_LEN + 1)).is_err());
+    }
+}
@@ -6,23 +6,71 @@ use axum::{
 
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade, CloseFrame},
-        Path, Query, State,
+        ConnectInfo, Path, Query, State,
     },
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
  #[allow(unused)]
 use tracing::{debug, error, info, warn};
  #[allow(unused)]
 use super::event_hub::{EventHub, InboundEvent, OutboundEvent, ContainerRuntimeState};
 use super::console::ConsoleStreamer;
 use super::stats::StatsCollector;
+use crate::auth::rate_limit::RateLimiter;
 use crate::auth::tokens::TokenManager;
+use crate::config::config::WebSocketConfig;
 use crate::container::manager::ContainerManager;
-use crate::container::power::{PowerManager, PowerAction};
+use crate::container::power::{PowerManager, PowerAction, PowerBusy};
+use crate::daemon::shutdown::ShutdownSignal;
+
+/// How often an idle connection re-checks its token, so revocation takes effect
+const TOKEN_REVALIDATE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often a client may fall behind the event buffer before it is dropped
+#[derive(Debug, Clone, Copy)]
+pub struct LagLimit {
+    pub max_lags: u32,
+    pub window: Duration,
+}
+
+impl LagLimit {
+    pub fn from_config(config: &WebSocketConfig) -> Self {
+        Self {
+            max_lags: config.max_lags,
+            window: Duration::from_secs(config.lag_window_secs),
+        }
+    }
+}
+
+/// Lags of one connection inside the sliding window
+struct LagTracker {
+    limit: LagLimit,
+    lags: VecDeque<Instant>,
+}
+
+impl LagTracker {
+    fn new(limit: LagLimit) -> Self {
+        Self { limit, lags: VecDeque::new() }
+    }
+
+    /// Note a lag at `now`, true once the client lagged too often to keep
+    fn record(&mut self, now: Instant) -> bool {
+        while self.lags.front().is_some_and(|t| now.duration_since(*t) >= self.limit.window) {
+            self.lags.pop_front();
+        }
+        self.lags.push_back(now);
+        self.lags.len() >= self.limit.max_lags as usize
+    }
+}
 
 #[derive(Deserialize)]
 pub struct WebSocketQuery {
@@ -38,6 +86,10 @@ pub struct WebSocketState {
     pub console_streamer: Arc<ConsoleStreamer>,
     pub stats_collector: Arc<StatsCollector>,
     pub token_manager: Arc<TokenManager>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub shutdown: ShutdownSignal,
+    /// When a client that keeps falling behind is disconnected
+    pub lag_limit: LagLimit,
 }
 
 /// Handle WebSocket upgrade request
@@ -45,17 +97,27 @@ pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Path(internal_id): Path<String>,
     Query(query): Query<WebSocketQuery>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     State(state): State<WebSocketState>,
 ) -> Response {
     tracing::info!("WebSocket upgrade request for container: {}", internal_id);
     
+    if state.rate_limiter.is_limited(addr.ip()) {
+        tracing::warn!("Rate limited WebSocket upgrade from {}", addr.ip());
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many failed authentication attempts"
+        ).into_response();
+    }
+    
     // Validate token
-    match state.token_manager.validate_token(&query.token, true) {
+    match state.token_manager.validate_container_token(&query.token, &internal_id, true) {
         Ok(true) => {
             tracing::info!("Token validated for WebSocket connection: {}", internal_id);
             ws.on_upgrade(move |socket| handle_socket(socket, internal_id, state, query.token))
         }
         Ok(false) => {
+            state.rate_limiter.record_failure(addr.ip());
             tracing::warn!("Invalid or expired token for WebSocket: {}", internal_id);
             (
                 StatusCode::UNAUTHORIZED,
@@ -109,10 +171,14 @@ async fn handle_socket(socket: WebSocket, internal_id: String, state: WebSocketS
     // Split the socket
     let (mut sender, mut receiver) = socket.split();
     
+    // Token the send task checks against, swappable via `refresh_token`
+    let session_token = Arc::new(RwLock::new(token));
+    
     // Clone state for the receiver task
     let state_recv = state.clone();
     let internal_id_recv = internal_id.clone();
     let channel_recv = channel.clone();
+    let session_token_recv = session_token.clone();
     
     // Spawn task to handle incoming messages
     let recv_task = tokio::spawn(async move {
@@ -129,6 +195,7 @@ async fn handle_socket(socket: WebSocket, internal_id: String, state: WebSocketS
                                 &internal_id_recv,
                                 &state_recv,
                                 &channel_recv,
+                                &session_token_recv,
                             ).await;
                         }
                         Err(e) => {
@@ -157,13 +224,54 @@ async fn handle_socket(socket: WebSocket, internal_id: String, state: WebSocketS
     
     // Spawn task to handle outgoing messages
     let token_manager_send = state.token_manager.clone();
-    let token_clone = token.clone();
+    let session_token_send = session_token.clone();
+    let internal_id_send = internal_id.clone();
+    let mut shutdown = state.shutdown.clone();
+    let mut lags = LagTracker::new(state.lag_limit);
     let send_task = tokio::spawn(async move {
-        while let Ok(event) = event_rx.recv().await {
+        // Revoked tokens are caught here even when no events are flowing
+        let mut revalidate = tokio::time::interval(TOKEN_REVALIDATE_INTERVAL);
+        revalidate.tick().await;
+        
+        loop {
+            let event = tokio::select! {
+                received = event_rx.recv() => match received {
+                    Ok(event) => Some(event),
+                    // The buffer overran, the client only misses the oldest
+                    // events and is told so, unless it keeps falling behind
+                    Err(RecvError::Lagged(skipped)) => {
+                        if lags.record(Instant::now()) {
+                            tracing::warn!("WebSocket client for {} keeps falling behind, closing", internal_id_send);
+                            // 1008 "policy violation", reconnecting starts from a fresh buffer
+                            let _ = sender.send(Message::Close(Some(CloseFrame {
+                                code: 1008,
+                                reason: "Connection too slow".into(),
+                            }))).await;
+                            break;
+                        }
+                        Some(OutboundEvent::DaemonMessage(vec![format!(
+                            "{} console lines dropped due to slow connection",
+                            skipped
+                        )]))
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = revalidate.tick() => None,
+                _ = shutdown.recv() => {
+                    // 1001 "going away" tells clients to reconnect later
+                    let _ = sender.send(Message::Close(Some(CloseFrame {
+                        code: 1001,
+                        reason: "Server shutting down".into(),
+                    }))).await;
+                    break;
+                }
+            };
+            
             // Check if token is still valid
-            match token_manager_send.validate_token(&token_clone, false) {
+            let current_token = session_token_send.read().await.clone();
+            match token_manager_send.validate_container_token(&current_token, &internal_id_send, false) {
                 Ok(false) | Err(_) => {
-                    tracing::warn!("Token expired during WebSocket connection, closing");
+                    tracing::warn!("Token expired or revoked during WebSocket connection, closing");
                     break;
                 }
                 Ok(true) => {
@@ -171,6 +279,10 @@ async fn handle_socket(socket: WebSocket, internal_id: String, state: WebSocketS
                 }
             }
             
+            let Some(event) = event else {
+                continue;
+            };
+            
             let json = match serde_json::to_string(&event) {
                 Ok(j) => j,
                 Err(e) => {
@@ -179,7 +291,7 @@ async fn handle_socket(socket: WebSocket, internal_id: String, state: WebSocketS
                 }
             };
             
-            if sender.send(Message::Text(json.into())).await.is_err() {
+            if sender.send(Message::Text(json)).await.is_err() {
                 // Client disconnected
                 break;
             }
@@ -205,6 +317,7 @@ async fn handle_inbound_event(
     internal_id: &str,
     state: &WebSocketState,
     channel: &Arc<super::event_hub::ContainerEventChannel>,
+    session_token: &Arc<RwLock<String>>,
 ) {
     match event {
         InboundEvent::Power { power: args } => {
@@ -223,6 +336,11 @@ async fn handle_inbound_event(
                     state.event_hub.broadcast_event(internal_id, "starting").await;
                     Some(PowerAction::Start)
                 }
+                "stop" => {
+                    channel.set_state(ContainerRuntimeState::Stopping).await;
+                    state.event_hub.broadcast_event(internal_id, "stopping").await;
+                    Some(PowerAction::Stop)
+                }
                 "kill" => {
                     channel.set_state(ContainerRuntimeState::Stopping).await;
                     state.event_hub.broadcast_event(internal_id, "stopping").await;
@@ -233,6 +351,8 @@ async fn handle_inbound_event(
                     state.event_hub.broadcast_event(internal_id, "stopping").await;
                     Some(PowerAction::Restart)
                 }
+                "pause" => Some(PowerAction::Pause),
+                "resume" | "unpause" => Some(PowerAction::Unpause),
                 _ => {
                     tracing::warn!("Unknown power action: {}", action);
                     None
@@ -242,6 +362,8 @@ async fn handle_inbound_event(
             if let Some(pa) = power_action {
                 match state.power.execute_action(internal_id.to_string(), pa).await {
                     Ok(_) => {},
+                    // The power manager already announced `busy` to every client
+                    Err(e) if e.downcast_ref::<PowerBusy>().is_some() => {},
                     Err(e) => {
                         let error_msg = format!("Power action failed: {}", e);
                         tracing::error!("{}", error_msg);
@@ -266,13 +388,48 @@ async fn handle_inbound_event(
         }
         
         InboundEvent::RequestLogs { logs: args } => {
-            let count: usize = args.get(0)
+            let count: usize = args.first()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(50);
             
             tracing::info!("Requesting {} logs for {}", count, internal_id);
             state.event_hub.send_logs(internal_id, count).await;
         }
+        
+        InboundEvent::SearchLogs { search_logs: args } => {
+            let Some(pattern) = args.first() else {
+                tracing::warn!("SearchLogs with no pattern");
+                return;
+            };
+            let limit = args.get(1).and_then(|s| s.parse().ok());
+            
+            tracing::info!("Searching logs of {} for {:?}", internal_id, pattern);
+            state.event_hub.send_search_results(internal_id, pattern, limit).await;
+        }
+        
+        InboundEvent::RefreshToken { refresh_token: args } => {
+            let new_token = match args.first() {
+                Some(t) => t,
+                None => {
+                    tracing::warn!("RefreshToken with no token");
+                    return;
+                }
+            };
+            
+            // Don't consume single-use tokens here, the send task keeps checking it
+            match state.token_manager.validate_container_token(new_token, internal_id, false) {
+                Ok(true) => {
+                    *session_token.write().await = new_token.clone();
+                    tracing::info!("Session token refreshed for {}", internal_id);
+                }
+                Ok(false) => {
+                    tracing::warn!("Rejected invalid refresh token for {}", internal_id);
+                }
+                Err(e) => {
+                    tracing::error!("Token validation error during refresh: {}", e);
+                }
+            }
+        }
     }
 }
 
@@ -284,3 +441,21 @@ pub async fn notify_installing(event_hub: &EventHub, internal_id: &str) {
 pub async fn notify_installed(event_hub: &EventHub, internal_id: &str) {
     event_hub.broadcast_event(internal_id, "installed").await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lag_tracker() {
+        let mut lags = LagTracker::new(LagLimit { max_lags: 3, window: Duration::from_secs(60) });
+        let start = Instant::now();
+
+        assert!(!lags.record(start));
+        assert!(!lags.record(start + Duration::from_secs(10)));
+        // The first lag left the window, only two remain
+        assert!(!lags.record(start + Duration::from_secs(65)));
+        // Third within a minute of the one at 10s
+        assert!(lags.record(start + Duration::from_secs(69)));
+    }
+}
@@ -8,14 +8,18 @@
 //! - `stats` - Container resource stats (CPU, memory, network, uptime)
 //! - `console output` - Console output from container
 //! - `console duplicate` - Duplicate line count
-//! - `event` - Lifecycle events (installing, installed, exit, starting, running, stopping)
+//! - `event` - Lifecycle events (installing, installed, exit, starting, running, stopping, paused)
+//! - `oom_killed` - Container was killed by the OOM killer (exit code)
+//! - `limits_updated` - New memory/CPU caps after a resources update
 //! - `daemon_message` - Daemon messages (Container stopped, etc.)
 //! - `logs` - Response to logs request
 //! 
 //! ### Inbound (client -> server)
-//! - `power` - Power actions (start, kill, restart)
+//! - `power` - Power actions (start, stop, kill, restart, pause, resume)
 //! - `send command` - Send command to container stdin
 //! - `logs` - Request last N lines of logs
+//! - `refresh_token` - Swap the session token without reconnecting
+//!
 //!  Need this to write the docs
 
 pub mod event_hub;
@@ -23,7 +27,7 @@ pub mod console;
 pub mod stats;
 pub mod handler;
 #[allow(unused)]
-pub use event_hub::{EventHub, OutboundEvent, InboundEvent, ContainerStats, ContainerRuntimeState};
+pub use event_hub::{EventHub, OutboundEvent, InboundEvent, ContainerStats, ContainerRuntimeState, LimitsUpdate};
 pub use console::ConsoleStreamer;
 pub use stats::StatsCollector;
-pub use handler::{ws_handler, WebSocketState, notify_installing, notify_installed};
+pub use handler::{ws_handler, WebSocketState, LagLimit, notify_installing, notify_installed};
@@ -2,64 +2,209 @@
 //! 
 //! Collects real-time stats from Docker containers and broadcasts changes.
 
-use bollard::container::StatsOptions;
+use bollard::container::{Stats, StatsOptions};
 use bollard::Docker;
 use futures::StreamExt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 //use tokio::sync::RwLock;
 use tracing::{debug, warn};
 
-use super::event_hub::{ContainerStats, EventHub, NetworkStats};
+use super::event_hub::{ContainerEventChannel, ContainerRuntimeState, ContainerStats, EventHub, NetworkStats};
 use crate::container::manager::ContainerManager;
+use crate::daemon::shutdown::ShutdownSignal;
+use crate::error::LightdError;
+use crate::filesystem::quota::QuotaManager;
+
+/// Volume usage is read with `df`, too slow to run for every stats sample
+const DISK_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Last quota reading of a volume, refreshed every `DISK_REFRESH_INTERVAL`
+struct DiskUsageCache {
+    quota_manager: Option<Arc<QuotaManager>>,
+    volume_id: String,
+    read_at: Option<Instant>,
+    used_bytes: u64,
+    limit_bytes: u64,
+}
+
+impl DiskUsageCache {
+    fn new(quota_manager: Option<Arc<QuotaManager>>, volume_id: String) -> Self {
+        Self {
+            quota_manager,
+            volume_id,
+            read_at: None,
+            used_bytes: 0,
+            limit_bytes: 0,
+        }
+    }
+    
+    /// Used and total bytes, the previous reading is kept if `df` fails
+    async fn get(&mut self) -> (u64, u64) {
+        let Some(quota_manager) = &self.quota_manager else {
+            return (0, 0);
+        };
+        if self.read_at.is_some_and(|at| at.elapsed() < DISK_REFRESH_INTERVAL) {
+            return (self.used_bytes, self.limit_bytes);
+        }
+        
+        self.read_at = Some(Instant::now());
+        match quota_manager.get_quota_usage(&self.volume_id).await {
+            Ok(quota) => {
+                self.used_bytes = quota.used_mb * 1024 * 1024;
+                self.limit_bytes = quota.size_mb * 1024 * 1024;
+            }
+            Err(e) => debug!("Failed to read disk usage of volume {}: {}", self.volume_id, e),
+        }
+        (self.used_bytes, self.limit_bytes)
+    }
+}
+
+/// CPU use between the sample and the one before it, 100 per busy core
+pub fn cpu_percent(stats: &Stats) -> f64 {
+    let cpu_delta = stats.cpu_stats.cpu_usage.total_usage as i64 - stats.precpu_stats.cpu_usage.total_usage as i64;
+    let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as i64
+        - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as i64;
+
+    if system_delta > 0 && cpu_delta > 0 {
+        let num_cpus = stats.cpu_stats.online_cpus.unwrap_or(1) as f64;
+        (cpu_delta as f64 / system_delta as f64) * num_cpus * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Bytes received and sent over all of the container's interfaces
+fn network_totals(stats: &Stats) -> NetworkStats {
+    let (rx_bytes, tx_bytes) = stats.networks.as_ref().map_or((0, 0), |networks| {
+        networks.values().fold((0u64, 0u64), |acc, net| (acc.0 + net.rx_bytes, acc.1 + net.tx_bytes))
+    });
+    NetworkStats { rx_bytes, tx_bytes }
+}
 
 /// Stats collector that monitors container resources
 pub struct StatsCollector {
     docker: Arc<Docker>,
     manager: Arc<ContainerManager>,
     event_hub: Arc<EventHub>,
+    shutdown: ShutdownSignal,
+    quota_manager: Option<Arc<QuotaManager>>,
 }
 
 impl StatsCollector {
     pub fn new(
         manager: Arc<ContainerManager>,
         event_hub: Arc<EventHub>,
+        shutdown: ShutdownSignal,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let docker = Arc::new(Docker::connect_with_local_defaults()?);
+        let docker = Arc::new(crate::container::docker::client()?);
         
         Ok(Self {
             docker,
             manager,
             event_hub,
+            shutdown,
+            quota_manager: None,
         })
     }
     
+    /// Report volume usage against its quota as the disk stats
+    pub fn with_quota_manager(mut self, quota_manager: Arc<QuotaManager>) -> Self {
+        self.quota_manager = Some(quota_manager);
+        self
+    }
+    
+    /// One stats reading without a WebSocket, None when the container doesn't exist.
+    /// Docker takes a second sample for the CPU delta, so this takes about a second.
+    pub async fn snapshot(&self, internal_id: &str) -> Result<Option<ContainerStats>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(state) = self.manager.get_container(internal_id).await? else {
+            return Ok(None);
+        };
+        let container_id = state.container_id.ok_or("Container not ready")?;
+        let info = self.docker.inspect_container(&container_id, None).await?;
+        let docker_state = info.state.unwrap_or_default();
+
+        let runtime_state = match self.event_hub.get_channel(internal_id) {
+            Some(channel) => channel.get_state().await,
+            None if docker_state.paused == Some(true) => ContainerRuntimeState::Paused,
+            None if docker_state.running == Some(true) => ContainerRuntimeState::Running,
+            None => ContainerRuntimeState::Offline,
+        };
+        let (disk_bytes, disk_limit_bytes) = DiskUsageCache::new(self.quota_manager.clone(), state.volume_id).get().await;
+        let memory_limit = state.limits.memory.unwrap_or(0) as u64;
+
+        if docker_state.running != Some(true) {
+            return Ok(Some(ContainerStats {
+                memory_bytes: 0,
+                memory_limit_bytes: memory_limit,
+                cpu_absolute: 0.0,
+                network: NetworkStats { rx_bytes: 0, tx_bytes: 0 },
+                uptime: 0,
+                state: runtime_state.to_string(),
+                disk_bytes,
+                disk_limit_bytes,
+            }));
+        }
+
+        // one_shot would skip the second sample and leave precpu empty
+        let mut stream = self.docker.stats(&container_id, Some(StatsOptions {
+            stream: false,
+            one_shot: false,
+        }));
+        let stats = stream.next().await.ok_or("Docker returned no stats")??;
+
+        let uptime = docker_state.started_at
+            .and_then(|ts| chrono::DateTime::parse_from_rfc3339(&ts).ok())
+            .map(|started| (chrono::Utc::now().timestamp() - started.timestamp()).max(0) as u64)
+            .unwrap_or(0);
+        let cpu_absolute = if runtime_state == ContainerRuntimeState::Paused { 0.0 } else { cpu_percent(&stats) };
+
+        Ok(Some(ContainerStats {
+            memory_bytes: stats.memory_stats.usage.unwrap_or(0),
+            memory_limit_bytes: stats.memory_stats.limit.unwrap_or(memory_limit),
+            cpu_absolute: (cpu_absolute * 100.0).round() / 100.0,
+            network: network_totals(&stats),
+            uptime,
+            state: runtime_state.to_string(),
+            disk_bytes,
+            disk_limit_bytes,
+        }))
+    }
+    
     /// Start collecting stats for a container
     pub async fn start_collecting(&self, internal_id: String) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Get container state
         let state = self.manager.get_container(&internal_id).await?
-            .ok_or("Container not found")?;
+            .ok_or(LightdError::NotFound("Container"))?;
         
         let container_id = state.container_id.ok_or("Container not ready")?;
         let memory_limit = state.limits.memory.unwrap_or(0) as u64;
+        let disk = DiskUsageCache::new(self.quota_manager.clone(), state.volume_id.clone());
         
         let docker = self.docker.clone();
         let event_hub = self.event_hub.clone();
         let internal_id_clone = internal_id.clone();
+        let mut shutdown = self.shutdown.clone();
         
         // Get or create the channel
         let (channel, _) = event_hub.get_or_create_channel(&internal_id);
         
         // Spawn the stats collection task
         tokio::spawn(async move {
-            Self::collect_stats_loop(
-                docker,
-                container_id,
-                internal_id_clone,
-                event_hub,
-                channel,
-                memory_limit,
-            ).await;
+            tokio::select! {
+                _ = Self::collect_stats_loop(
+                    docker,
+                    container_id,
+                    internal_id_clone.clone(),
+                    event_hub,
+                    channel,
+                    memory_limit,
+                    disk,
+                ) => {}
+                _ = shutdown.recv() => {
+                    debug!("Stats collector stopped for {}", internal_id_clone);
+                }
+            }
         });
         
         Ok(())
@@ -71,8 +216,9 @@ impl StatsCollector {
         container_id: String,
         internal_id: String,
         event_hub: Arc<EventHub>,
-        channel: Arc<super::event_hub::ContainerEventChannel>,
+        channel: Arc<ContainerEventChannel>,
         memory_limit: u64,
+        mut disk: DiskUsageCache,
     ) {
         tracing::info!("Starting stats collector for container {}", internal_id);
         
@@ -81,6 +227,8 @@ impl StatsCollector {
         let mut last_cpu_total: Option<u64> = None;
         #[allow(unused)]
         let mut last_system_cpu: Option<u64> = None;
+        // State from before stats became unavailable, restored once they resume
+        let mut unavailable: Option<ContainerRuntimeState> = None;
         
         loop {
             // Check if container exists
@@ -88,6 +236,7 @@ impl StatsCollector {
                 Ok(info) => info,
                 Err(e) => {
                     debug!("Container {} not found: {}", internal_id, e);
+                    Self::mark_unavailable(&event_hub, &channel, &internal_id, "container not found", &mut unavailable).await;
                     tokio::time::sleep(backoff).await;
                     backoff = (backoff * 2).min(Duration::from_secs(5));
                     continue;
@@ -99,6 +248,15 @@ impl StatsCollector {
                 .and_then(|s| s.running)
                 .unwrap_or(false);
             
+            // Paused before we attached, e.g. across a daemon restart
+            let is_paused = container_info.state
+                .as_ref()
+                .and_then(|s| s.paused)
+                .unwrap_or(false);
+            if is_paused && channel.get_state().await != ContainerRuntimeState::Paused {
+                channel.set_state(ContainerRuntimeState::Paused).await;
+            }
+            
             if !is_running {
                 debug!("Container {} not running", internal_id);
                 tokio::time::sleep(backoff).await;
@@ -119,35 +277,17 @@ impl StatsCollector {
             while let Some(result) = stats_stream.next().await {
                 match result {
                     Ok(stats) => {
-                        // Calculate CPU percentage
-                        let cpu_total = stats.cpu_stats.cpu_usage.total_usage;
-                        let precpu_total = stats.precpu_stats.cpu_usage.total_usage;
+                        if let Some(previous) = unavailable.take() {
+                            Self::mark_resumed(&event_hub, &channel, &internal_id, previous).await;
+                        }
                         
-                        let cpu_absolute = {
-                            let cpu_delta = cpu_total as i64 - precpu_total as i64;
-                            let system_delta = stats.cpu_stats.system_cpu_usage.unwrap_or(0) as i64 
-                                - stats.precpu_stats.system_cpu_usage.unwrap_or(0) as i64;
-                            
-                            if system_delta > 0 && cpu_delta > 0 {
-                                let num_cpus = stats.cpu_stats.online_cpus.unwrap_or(1) as f64;
-                                (cpu_delta as f64 / system_delta as f64) * num_cpus * 100.0
-                            } else {
-                                0.0
-                            }
-                        };
+                        let cpu_absolute = cpu_percent(&stats);
                         
                         // Get memory stats
                         let memory_bytes = stats.memory_stats.usage.unwrap_or(0);
                         let memory_limit_bytes = stats.memory_stats.limit.unwrap_or(memory_limit);
                         
-                        // Get network stats
-                        let (rx_bytes, tx_bytes) = if let Some(networks) = &stats.networks {
-                            networks.values().fold((0u64, 0u64), |acc, net| {
-                                (acc.0 + net.rx_bytes, acc.1 + net.tx_bytes)
-                            })
-                        } else {
-                            (0, 0)
-                        };
+                        let network = network_totals(&stats);
                         
                         // Calculate uptime
                         let uptime = {
@@ -163,21 +303,24 @@ impl StatsCollector {
                             }
                         };
                         
+                        let (disk_bytes, disk_limit_bytes) = disk.get().await;
+                        
                         // Get state
-                        let state_str = channel.get_state().await.to_string();
+                        let runtime_state = channel.get_state().await;
+                        // A frozen container uses no CPU, don't repeat the last sample's delta
+                        let cpu_absolute = if runtime_state == ContainerRuntimeState::Paused { 0.0 } else { cpu_absolute };
+                        let state_str = runtime_state.to_string();
                         
                         // Build stats object
                         let container_stats = ContainerStats {
                             memory_bytes,
                             memory_limit_bytes,
                             cpu_absolute: (cpu_absolute * 100.0).round() / 100.0, // Round to 2 decimals
-                            network: NetworkStats {
-                                rx_bytes,
-                                tx_bytes,
-                            },
+                            network,
                             uptime,
                             state: state_str,
-                            disk_bytes: 0, // TODO: Implement disk stats
+                            disk_bytes,
+                            disk_limit_bytes,
                         };
                         
                         // Broadcast (with change detection)
@@ -185,6 +328,7 @@ impl StatsCollector {
                     }
                     Err(e) => {
                         warn!("Stats error for {}: {}", internal_id, e);
+                        Self::mark_unavailable(&event_hub, &channel, &internal_id, &e.to_string(), &mut unavailable).await;
                         break;
                     }
                 }
@@ -197,4 +341,39 @@ impl StatsCollector {
             tokio::time::sleep(Duration::from_millis(500)).await;
         }
     }
+    
+    /// Tell clients stats stopped and mark the container offline, once per outage.
+    /// The last stats are dropped so the first value after resuming is sent.
+    async fn mark_unavailable(
+        event_hub: &EventHub,
+        channel: &ContainerEventChannel,
+        internal_id: &str,
+        reason: &str,
+        unavailable: &mut Option<ContainerRuntimeState>,
+    ) {
+        if unavailable.is_some() {
+            return;
+        }
+        
+        *unavailable = Some(channel.get_state().await);
+        channel.set_state(ContainerRuntimeState::Offline).await;
+        *channel.last_stats.write().await = None;
+        
+        event_hub.broadcast_daemon_message(internal_id, &format!("Stats unavailable: {}", reason)).await;
+    }
+    
+    /// Undo `mark_unavailable` unless something else changed the state meanwhile
+    async fn mark_resumed(
+        event_hub: &EventHub,
+        channel: &ContainerEventChannel,
+        internal_id: &str,
+        previous: ContainerRuntimeState,
+    ) {
+        if channel.get_state().await == ContainerRuntimeState::Offline {
+            channel.set_state(previous).await;
+        }
+        
+        tracing::info!("Stats resumed for {}", internal_id);
+        event_hub.broadcast_daemon_message(internal_id, "Stats resumed").await;
+    }
 }